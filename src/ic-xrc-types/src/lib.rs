@@ -1,7 +1,8 @@
-use candid::{CandidType, Deserialize};
+use candid::{CandidType, Deserialize, Principal};
+use serde::Serialize;
 
 /// The enum defining the different asset classes.
-#[derive(CandidType, Clone, Debug, Deserialize, PartialEq)]
+#[derive(CandidType, Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub enum AssetClass {
     /// The cryptocurrency asset class.
     Cryptocurrency,
@@ -10,7 +11,7 @@ pub enum AssetClass {
 }
 
 /// Exchange rates are derived for pairs of assets captured in this struct.
-#[derive(CandidType, Clone, Debug, Deserialize, PartialEq)]
+#[derive(CandidType, Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct Asset {
     /// The symbol/code of the asset.
     pub symbol: String,
@@ -34,8 +35,84 @@ pub struct GetExchangeRateRequest {
     pub timestamp: Option<u64>,
 }
 
+/// Quality requirements that the returned rate must meet. If a requirement is not met,
+/// an error is returned instead of the rate.
+#[derive(CandidType, Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct ExchangeRateOptions {
+    /// The minimum number of rates that must be received for each leg of the pair,
+    /// i.e., for the base asset and for the quote asset. Legs that do not require any
+    /// sources, such as USDT or USD, are exempt.
+    pub min_num_received_rates: Option<u64>,
+    /// The maximum standard deviation of the received rates, scaled by the factor
+    /// `10^decimals` as reported in the metadata.
+    pub max_standard_deviation: Option<u64>,
+    /// The maximum number of days that the forex rates may predate the requested day.
+    /// Forex rates may be older than the requested day, e.g., on weekends or holidays.
+    pub max_forex_staleness_days: Option<u64>,
+    /// Whether cryptocurrency rates that the canister has already cached may be used.
+    /// If false, the rates are retrieved from the exchanges. Defaults to true.
+    pub accept_cached_data: Option<bool>,
+}
+
+/// The type the user sends when requesting a rate with `get_exchange_rate_v2`.
+#[derive(CandidType, Clone, Debug, Deserialize)]
+pub struct GetExchangeRateV2Request {
+    /// The base asset, i.e., the first asset in a currency pair.
+    pub base_asset: Asset,
+    /// The quote asset, i.e., the second asset in a currency pair.
+    pub quote_asset: Asset,
+    /// An optional parameter used to find a rate at a specific time.
+    pub timestamp: Option<u64>,
+    /// Optional quality requirements that the returned rate must meet.
+    pub options: Option<ExchangeRateOptions>,
+}
+
+/// The type the user sends when requesting a series of rates.
+///
+/// Cryptocurrency rates are provided at minute granularity, whereas fiat currency
+/// pairs are provided at day granularity.
+#[derive(CandidType, Clone, Debug, Deserialize)]
+pub struct GetExchangeRateSeriesRequest {
+    /// The base asset, i.e., the first asset in a currency pair.
+    pub base_asset: Asset,
+    /// The quote asset, i.e., the second asset in a currency pair.
+    pub quote_asset: Asset,
+    /// The timestamp in seconds of the first rate in the series.
+    pub start: u64,
+    /// The timestamp in seconds of the last rate in the series (inclusive).
+    pub end: u64,
+    /// The number of seconds between two consecutive rates in the series.
+    pub step: u64,
+}
+
+/// The type the user sends when requesting a time-weighted average price (TWAP).
+///
+/// The rate is averaged over the minutes of the window ending at the given timestamp
+/// instead of being the median of a single minute.
+#[derive(CandidType, Clone, Debug, Deserialize)]
+pub struct GetTwapExchangeRateRequest {
+    /// The base asset, i.e., the first asset in a currency pair.
+    pub base_asset: Asset,
+    /// The quote asset, i.e., the second asset in a currency pair.
+    pub quote_asset: Asset,
+    /// An optional parameter used to find a rate at a specific time. The window ends
+    /// at the minute of this timestamp.
+    pub timestamp: Option<u64>,
+    /// The number of minutes over which the rate is averaged, e.g., 5, 15 or 60.
+    pub window_minutes: u64,
+}
+
+/// The type the user sends when requesting a certified exchange rate.
+#[derive(CandidType, Clone, Debug, Deserialize)]
+pub struct GetCertifiedExchangeRateRequest {
+    /// The base asset, i.e., the first asset in a currency pair.
+    pub base_asset: Asset,
+    /// The quote asset, i.e., the second asset in a currency pair.
+    pub quote_asset: Asset,
+}
+
 /// Metadata information to give background on how the rate was determined.
-#[derive(CandidType, Clone, Debug, Deserialize, PartialEq)]
+#[derive(CandidType, Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct ExchangeRateMetadata {
     /// The scaling factor for the exchange rate and the standard deviation.
     pub decimals: u32,
@@ -51,11 +128,27 @@ pub struct ExchangeRateMetadata {
     pub standard_deviation: u64,
     /// The timestamp of the beginning of the day for which the forex rates were retrieved, if any.
     pub forex_timestamp: Option<u64>,
+    /// The number of minutes over which the rate was averaged if a time-weighted average price
+    /// was requested.
+    pub twap_window_minutes: Option<u64>,
+    /// The number of received rates for the base asset that were discarded as outliers or
+    /// because they were invalid.
+    pub base_asset_num_discarded_rates: Option<usize>,
+    /// The number of received rates for the quote asset that were discarded as outliers or
+    /// because they were invalid.
+    pub quote_asset_num_discarded_rates: Option<usize>,
+    /// The symbols of the assets through which the base asset was priced against USDT, starting
+    /// with the base asset and ending with USDT, e.g., `["ABC", "BTC", "USDT"]` if the rate
+    /// was derived from the ABC/BTC and BTC/USDT markets. Only set for cryptocurrencies.
+    pub base_asset_route: Option<Vec<String>>,
+    /// The symbols of the assets through which the quote asset was priced against USDT.
+    /// Only set for cryptocurrencies.
+    pub quote_asset_route: Option<Vec<String>>,
 }
 
 /// When a rate is determined, this struct is used to present the information
 /// to the user.
-#[derive(CandidType, Clone, Debug, Deserialize, PartialEq)]
+#[derive(CandidType, Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct ExchangeRate {
     /// The base asset.
     pub base_asset: Asset,
@@ -69,6 +162,48 @@ pub struct ExchangeRate {
     pub metadata: ExchangeRateMetadata,
 }
 
+/// A rate reported by a single source.
+#[derive(CandidType, Clone, Debug, Deserialize, PartialEq)]
+pub struct SourceRate {
+    /// The name of the source, e.g., the exchange that provided the rate.
+    pub source: String,
+    /// The rate scaled by the factor `10^9`.
+    pub rate: u64,
+}
+
+/// The individual source rates behind an [ExchangeRateV2].
+#[derive(CandidType, Clone, Debug, Deserialize, PartialEq)]
+pub struct ExchangeRateSources {
+    /// The rates of the base asset against USDT, one per exchange. Empty if the
+    /// base asset is not a cryptocurrency.
+    pub base_asset_rates: Vec<SourceRate>,
+    /// The rates of the quote asset against USDT, one per exchange. Empty if the
+    /// quote asset is not a cryptocurrency.
+    pub quote_asset_rates: Vec<SourceRate>,
+    /// The stablecoin that was used to approximate USD, if any. The rate is the
+    /// median rate of the stablecoin against USDT.
+    pub stablecoin: Option<SourceRate>,
+    /// The forex sources that provided rates for the day of the forex rates.
+    pub forex_sources: Vec<String>,
+}
+
+/// An [ExchangeRate] extended with the individual source rates behind the median rate.
+#[derive(CandidType, Clone, Debug, Deserialize, PartialEq)]
+pub struct ExchangeRateV2 {
+    /// The base asset.
+    pub base_asset: Asset,
+    /// The quote asset.
+    pub quote_asset: Asset,
+    /// The timestamp associated with the returned rate.
+    pub timestamp: u64,
+    /// The median rate from the received rates, scaled by the factor `10^decimals` in the metadata.
+    pub rate: u64,
+    /// Metadata providing additional information about the exchange rate calculation.
+    pub metadata: ExchangeRateMetadata,
+    /// The individual source rates behind the rate.
+    pub sources: ExchangeRateSources,
+}
+
 /// Returned to the user when something goes wrong retrieving the exchange rate.
 #[derive(CandidType, Clone, Debug, Deserialize)]
 pub enum ExchangeRateError {
@@ -104,6 +239,20 @@ pub enum ExchangeRateError {
     Other(OtherError),
 }
 
+/// The latest rate of an asset pair together with the data needed to verify it.
+#[derive(CandidType, Clone, Debug, Deserialize, PartialEq)]
+pub struct CertifiedExchangeRate {
+    /// The latest rate of the asset pair.
+    pub rate: ExchangeRate,
+    /// The certificate of the canister's certified data.
+    #[serde(with = "serde_bytes")]
+    pub certificate: Vec<u8>,
+    /// The CBOR-encoded hash tree proving that the candid-encoded rate is stored
+    /// in the canister's certified data at the path `["rates", "<base>/<quote>"]`.
+    #[serde(with = "serde_bytes")]
+    pub witness: Vec<u8>,
+}
+
 /// Used to provide details for the [ExchangeRateError::Other] variant field.
 #[derive(CandidType, Clone, Debug, Deserialize)]
 pub struct OtherError {
@@ -113,5 +262,402 @@ pub struct OtherError {
     pub description: String,
 }
 
+/// A cryptocurrency that the canister can currently price.
+#[derive(CandidType, Clone, Debug, Deserialize, PartialEq)]
+pub struct SupportedCryptocurrency {
+    /// The symbol of the cryptocurrency.
+    pub symbol: String,
+    /// The exchanges that currently list the cryptocurrency.
+    pub exchanges: Vec<String>,
+}
+
+/// A fiat currency that the canister can currently price.
+#[derive(CandidType, Clone, Debug, Deserialize, PartialEq)]
+pub struct SupportedFiatCurrency {
+    /// The symbol of the fiat currency.
+    pub symbol: String,
+    /// The number of forex sources that provided a rate for the fiat currency.
+    pub num_sources: u64,
+}
+
+/// The assets that the canister can currently price, returned by `list_supported_assets`.
+#[derive(CandidType, Clone, Debug, Deserialize, PartialEq)]
+pub struct SupportedAssets {
+    /// The cryptocurrencies, ordered by symbol.
+    pub cryptocurrencies: Vec<SupportedCryptocurrency>,
+    /// The fiat currencies, ordered by symbol. The rates of all fiat currencies
+    /// are quoted against USD, which is therefore always supported.
+    pub fiat_currencies: Vec<SupportedFiatCurrency>,
+    /// The timestamp of the beginning of the day of the latest forex rates.
+    pub forex_timestamp: Option<u64>,
+}
+
+/// The estimated cost of a `get_exchange_rate` request, given the current state of the canister.
+#[derive(CandidType, Clone, Debug, Deserialize, PartialEq)]
+pub struct ExchangeRateCostEstimate {
+    /// The number of cycles that must be attached to the call.
+    pub cycles_required: u128,
+    /// The number of cycles that would be charged.
+    pub fee: u128,
+    /// The number of attached cycles that would be refunded.
+    pub refund: u128,
+    /// The number of rates that would be retrieved with HTTPS outcalls.
+    pub num_outbound_rates_needed: u64,
+}
+
+/// The tuning values of the canister that NNS Governance can change at runtime
+/// through `update_config`.
+#[derive(CandidType, Clone, Debug, Deserialize, PartialEq)]
+pub struct Config {
+    /// Rates deviating from the median by more than `median / rate_deviation_divisor`
+    /// are considered inconsistent.
+    pub rate_deviation_divisor: u64,
+    /// Rates deviating from the median by more than
+    /// `median / max_relative_difference_divisor` are discarded.
+    pub max_relative_difference_divisor: u64,
+    /// The maximum number of concurrent outbound rate requests before callers are rate limited.
+    pub request_counter_limit: u64,
+    /// The minimum number of forex sources needed to report a fiat currency rate.
+    pub min_forex_sources_to_report: u64,
+    /// The maximum number of days to go back when looking up forex rates.
+    pub max_forex_days_to_go_back: u64,
+    /// The minimum number of markets an exchange listing must contain to be accepted.
+    pub min_total_markets: u64,
+    /// The age in seconds after which an exchange listing is no longer trusted.
+    pub max_listing_staleness_secs: u64,
+    /// The maximum number of entries in the exchange rate cache.
+    pub max_cache_size: u64,
+    /// The names of the exchanges that must not be queried, e.g., `Coinbase`. If absent, every
+    /// exchange is queried, as in the default configuration.
+    pub disabled_exchanges: Option<Vec<String>>,
+    /// The names of the forex sources that must not be queried, e.g., `EuropeanCentralBank`.
+    /// If absent, every forex source is queried, as in the default configuration.
+    pub disabled_forex_sources: Option<Vec<String>>,
+    /// The principals that are not charged the standard fee. If absent, the CMC, the NNS dapp
+    /// and NNS Governance are free of charge, which is also the list of the default
+    /// configuration.
+    pub privileged_principals: Option<Vec<PrivilegedPrincipal>>,
+    /// The cryptocurrencies whose pairs with fiat currencies and USDT bypass the rate limiting.
+    /// If absent, BTC, ETH, ICP and USDT are privileged, which is also the list of the default
+    /// configuration.
+    pub privileged_crypto_assets: Option<Vec<String>>,
+    /// The filter used to discard outliers among the received cryptocurrency rates.
+    /// If absent, [OutlierFilter::RelativeDifference] is used, as in the default configuration.
+    pub crypto_outlier_filter: Option<OutlierFilter>,
+    /// The filter used to discard outliers among the received fiat currency rates.
+    /// If absent, [OutlierFilter::RelativeDifference] is used, as in the default configuration.
+    pub fiat_outlier_filter: Option<OutlierFilter>,
+    /// Determines how the received cryptocurrency rates are aggregated into a single rate.
+    /// If absent, [RateAggregationMethod::Median] is used, as in the default configuration.
+    pub crypto_rate_aggregation: Option<RateAggregationMethod>,
+    /// Determines how the rates of exchanges with a poor reputation are aggregated.
+    /// If absent, [ExchangeReputationPolicy::Ignore] is used, as in the default configuration.
+    pub exchange_reputation_policy: Option<ExchangeReputationPolicy>,
+    /// Determines what happens if the stablecoins used to derive the USDT/USD rate disagree.
+    /// If absent, [StablecoinDepegPolicy::Ignore] is used and the disagreement is not checked.
+    /// Unlike an absent policy, the default configuration rejects rates if no majority of the
+    /// stablecoins is within 200 basis points of the chosen stablecoin.
+    pub stablecoin_depeg_policy: Option<StablecoinDepegPolicy>,
+    /// The weighted basket currencies that are computed from the collected rates. A symbol may
+    /// have several definitions with different effective dates. If absent when the
+    /// configuration is set, the default baskets, i.e., CXDR with the IMF SDR weights, are
+    /// stored and used, as in the default configuration.
+    pub baskets: Option<Vec<Basket>>,
+}
+
+/// Determines which of the received rates of an asset are discarded as outliers.
+#[derive(CandidType, Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+pub enum OutlierFilter {
+    /// Rates deviating from the median by more than
+    /// `median / max_relative_difference_divisor` are discarded.
+    RelativeDifference,
+    /// Rates deviating from the median by more than `threshold_percent` percent of the
+    /// median absolute deviation (MAD) of the rates are discarded.
+    MedianAbsoluteDeviation {
+        /// The permitted deviation in percent of the MAD, e.g., 300 for three times the MAD.
+        threshold_percent: u64,
+    },
+    /// Rates below the first quartile or above the third quartile by more than
+    /// `threshold_percent` percent of the interquartile range (IQR) are discarded.
+    InterquartileRange {
+        /// The permitted distance in percent of the IQR, e.g., 150 for Tukey's fences.
+        threshold_percent: u64,
+    },
+}
+
+/// Determines how the rates received from the exchanges are aggregated into a single rate.
+#[derive(CandidType, Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+pub enum RateAggregationMethod {
+    /// The rate is the median of the received rates.
+    Median,
+    /// The rate is the median of the received rates weighted by the volume traded on each
+    /// exchange. If an exchange does not report a volume, the plain median is used instead.
+    VolumeWeightedMedian,
+}
+
+/// Determines how the rates of an exchange are aggregated if its reputation score, the rolling
+/// average deviation of its rates from the median rate in basis points, exceeds a threshold.
+#[derive(CandidType, Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+pub enum ExchangeReputationPolicy {
+    /// The reputation scores are tracked but do not affect the rates.
+    Ignore,
+    /// The rates of exchanges whose score exceeds `max_score_bps` are discarded.
+    Exclude {
+        /// The highest score in basis points at which the rates of an exchange are used.
+        max_score_bps: u64,
+    },
+    /// The weights of exchanges whose score exceeds `max_score_bps` are scaled down to
+    /// `weight_percent` percent, reducing their influence on the weighted median rate.
+    DownWeight {
+        /// The highest score in basis points at which the weight of an exchange is not scaled.
+        max_score_bps: u64,
+        /// The percentage of the weight that is retained, between 0 and 100.
+        weight_percent: u64,
+    },
+}
+
+/// Determines what happens if the stablecoins used to derive the USDT/USD rate disagree, i.e.,
+/// if no majority of their rates lies within `max_deviation_bps` basis points of the rate of
+/// the chosen stablecoin.
+#[derive(CandidType, Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+pub enum StablecoinDepegPolicy {
+    /// The stablecoin rates are not checked.
+    Ignore,
+    /// The disagreement is logged and counted in the metrics, but the rate is still used.
+    Flag {
+        /// The largest permitted deviation from the chosen stablecoin in basis points.
+        max_deviation_bps: u64,
+    },
+    /// The request fails with a stablecoin depeg error.
+    Reject {
+        /// The largest permitted deviation from the chosen stablecoin in basis points.
+        max_deviation_bps: u64,
+    },
+}
+
+/// An asset contained in a basket currency.
+#[derive(CandidType, Clone, Debug, Deserialize, PartialEq)]
+pub struct BasketComponent {
+    /// The fiat currency or cryptocurrency.
+    pub asset: Asset,
+    /// The amount of the asset contained in one unit of the basket, multiplied by 1_000_000.
+    pub weight_per_million: u64,
+}
+
+/// A currency whose USD rate is the weighted sum of the USD rates of its components, e.g.,
+/// CXDR, which mirrors the IMF SDR basket.
+#[derive(CandidType, Clone, Debug, Deserialize, PartialEq)]
+pub struct Basket {
+    /// The symbol under which the basket can be requested as a fiat currency.
+    pub symbol: String,
+    /// The assets contained in the basket.
+    pub components: Vec<BasketComponent>,
+    /// The UNIX timestamp in seconds of the first collection day for which this definition
+    /// is used. It is superseded by the definition of the same symbol with the next later
+    /// effective date.
+    pub effective_from: u64,
+}
+
+/// Determines what a principal is charged for its requests.
+#[derive(CandidType, Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+pub enum CallerTier {
+    /// Requests are free of charge and no cycles need to be attached. Cached rates are only
+    /// used if they are based on sufficiently many sources.
+    Free,
+    /// Requests are charged half of the standard fee.
+    Discounted,
+    /// Requests are charged the standard fee.
+    Standard,
+}
+
+/// A principal together with its tier.
+#[derive(CandidType, Clone, Debug, Deserialize, PartialEq)]
+pub struct PrivilegedPrincipal {
+    /// The principal of the caller.
+    pub principal: Principal,
+    /// The tier of the caller.
+    pub tier: CallerTier,
+}
+
+/// Returned when a call to `update_config` fails.
+#[derive(CandidType, Clone, Debug, Deserialize, PartialEq)]
+pub enum UpdateConfigError {
+    /// Returned when the caller is not NNS Governance.
+    NotAuthorized,
+    /// Returned when the provided configuration contains an invalid value.
+    InvalidConfig(String),
+}
+
+/// The number of calls to a source that ended with the given outcome since the last upgrade.
+#[derive(CandidType, Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct OutcomeCount {
+    /// The outcome of the calls, e.g., `success` or `http_error`.
+    pub outcome: String,
+    /// The number of calls.
+    pub count: u64,
+}
+
+/// The health of one kind of call to an exchange.
+#[derive(CandidType, Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ExchangeCallStatus {
+    /// The kind of call, i.e., `crypto` or `stablecoin`.
+    pub kind: String,
+    /// The timestamp in seconds of the last call that returned a usable rate.
+    pub last_success_seconds: Option<u64>,
+    /// The number of calls per outcome.
+    pub outcomes: Vec<OutcomeCount>,
+}
+
+/// The health of an exchange.
+#[derive(CandidType, Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ExchangeStatus {
+    /// The name of the exchange.
+    pub name: String,
+    /// Whether the exchange can be called with the build features of the canister.
+    pub available: bool,
+    /// Whether the exchange was not disabled through the configuration.
+    pub enabled: bool,
+    /// The health of the calls per kind.
+    pub calls: Vec<ExchangeCallStatus>,
+    /// The timestamp in seconds of the last accepted listing of the exchange.
+    pub listing_last_success_seconds: Option<u64>,
+    /// The number of markets in the last accepted listing of the exchange.
+    pub listing_total_markets: Option<u64>,
+}
+
+/// The health of a forex source.
+#[derive(CandidType, Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ForexStatus {
+    /// The name of the forex source.
+    pub name: String,
+    /// Whether the forex source can be called with the build features of the canister.
+    pub available: bool,
+    /// Whether the forex source was not disabled through the configuration.
+    pub enabled: bool,
+    /// The timestamp in seconds of the last call that returned rates.
+    pub last_success_seconds: Option<u64>,
+    /// The number of calls per outcome.
+    pub outcomes: Vec<OutcomeCount>,
+}
+
+/// A cryptocurrency rate that is currently being retrieved with HTTPS outcalls.
+#[derive(CandidType, Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct InflightRequest {
+    /// The symbol of the cryptocurrency.
+    pub symbol: String,
+    /// The timestamp of the rate in seconds.
+    pub timestamp: u64,
+}
+
+/// The state and the health of the canister, returned by `get_status`.
+#[derive(CandidType, Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Status {
+    /// The health of every exchange.
+    pub exchanges: Vec<ExchangeStatus>,
+    /// The health of every forex source.
+    pub forex_sources: Vec<ForexStatus>,
+    /// The timestamp in seconds of the last run of the periodic forex task.
+    pub periodic_forex_run_last_seconds: Option<u64>,
+    /// The number of cryptocurrency rates in the cache.
+    pub cache_size: u64,
+    /// The number of HTTPS outcalls that are currently counted towards the rate limit.
+    pub request_counter: u64,
+    /// The cryptocurrency rates that are currently being retrieved.
+    pub inflight_requests: Vec<InflightRequest>,
+    /// The features the canister was built with, e.g., `ipv4-support`.
+    pub features: Vec<String>,
+}
+
+/// A request to the canister that was recorded in the request log.
+#[derive(CandidType, Clone, Debug, Deserialize)]
+pub struct RequestLogEntry {
+    /// The time in seconds at which the request was received.
+    pub timestamp: u64,
+    /// The caller of the request.
+    pub caller: Principal,
+    /// Whether the caller is a privileged principal.
+    pub privileged: bool,
+    /// The request.
+    pub request: GetExchangeRateRequest,
+    /// The result that was returned.
+    pub result: GetExchangeRateResult,
+}
+
+/// The criteria an entry in the request log must fulfill to be returned. A criterion
+/// that is not set matches every entry.
+#[derive(CandidType, Clone, Debug, Default, Deserialize)]
+pub struct RequestLogFilter {
+    /// Only return requests of this caller.
+    pub caller: Option<Principal>,
+    /// Only return requests with this base asset symbol.
+    pub base_asset_symbol: Option<String>,
+    /// Only return requests with this quote asset symbol.
+    pub quote_asset_symbol: Option<String>,
+    /// Only return requests of privileged (`true`) or non-privileged (`false`) callers.
+    pub privileged: Option<bool>,
+    /// Only return requests that failed with this [ExchangeRateError] variant, e.g., `Pending`.
+    pub error: Option<String>,
+    /// Only return requests received at or after this time in seconds.
+    pub start_timestamp: Option<u64>,
+    /// Only return requests received at or before this time in seconds.
+    pub end_timestamp: Option<u64>,
+}
+
+/// The argument of a `get_request_log` request.
+#[derive(CandidType, Clone, Debug, Deserialize)]
+pub struct GetRequestLogRequest {
+    /// The number of matching entries to skip, starting with the most recent entry.
+    pub offset: u64,
+    /// The maximum number of entries to return.
+    pub limit: u64,
+    /// The criteria the returned entries must fulfill.
+    pub filter: RequestLogFilter,
+}
+
+/// Returned when a call to `get_request_log` fails.
+#[derive(CandidType, Clone, Debug, Deserialize, PartialEq)]
+pub enum GetRequestLogError {
+    /// Returned when the caller is neither NNS Governance nor a controller of the canister.
+    NotAuthorized,
+}
+
+/// A page of the request log, returned by `get_request_log`.
+#[derive(CandidType, Clone, Debug, Deserialize)]
+pub struct RequestLogPage {
+    /// The matching entries, starting with the most recent entry.
+    pub entries: Vec<RequestLogEntry>,
+    /// The total number of matching entries.
+    pub total: u64,
+}
+
 /// Short-hand for returning the result of a `get_exchange_rate` request.
 pub type GetExchangeRateResult = Result<ExchangeRate, ExchangeRateError>;
+
+/// Short-hand for returning the result of a `get_exchange_rate_v2` request.
+pub type GetExchangeRateV2Result = Result<ExchangeRateV2, ExchangeRateError>;
+
+/// Short-hand for returning the result of a `get_certified_exchange_rate` request.
+pub type GetCertifiedExchangeRateResult = Result<CertifiedExchangeRate, ExchangeRateError>;
+
+/// Short-hand for the argument of a `get_exchange_rates` request: the rates
+/// to retrieve in a single call.
+pub type GetExchangeRatesRequest = Vec<GetExchangeRateRequest>;
+
+/// Short-hand for returning the result of a `get_exchange_rates` request.
+///
+/// The outer error is returned when the batch as a whole is rejected (e.g.,
+/// not enough cycles were attached). Otherwise, the vector contains one result
+/// per request, in the order the requests were provided.
+pub type GetExchangeRatesResult = Result<Vec<GetExchangeRateResult>, ExchangeRateError>;
+
+/// Short-hand for returning the result of a `get_exchange_rate_series` request.
+///
+/// The rates are ordered by timestamp. Points in the series for which no rate
+/// could be determined are omitted.
+pub type GetExchangeRateSeriesResult = Result<Vec<ExchangeRate>, ExchangeRateError>;
+
+/// Short-hand for returning the result of an `update_config` request.
+pub type UpdateConfigResult = Result<(), UpdateConfigError>;
+
+/// Short-hand for returning the result of a `get_request_log` request.
+pub type GetRequestLogResult = Result<RequestLogPage, GetRequestLogError>;
@@ -79,6 +79,11 @@ mod test {
                             quote_asset_num_received_rates: 1,
                             standard_deviation: 1,
                             forex_timestamp: Some(1_669_755_360),
+                            twap_window_minutes: None,
+                            base_asset_num_discarded_rates: None,
+                            quote_asset_num_discarded_rates: None,
+                            base_asset_route: None,
+                            quote_asset_route: None,
                         },
                     }),
                 };
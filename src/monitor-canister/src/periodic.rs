@@ -303,6 +303,11 @@ mod test {
                 quote_asset_num_received_rates: 6,
                 standard_deviation: 1,
                 forex_timestamp: Some(timestamp_secs),
+                twap_window_minutes: None,
+                base_asset_num_discarded_rates: None,
+                quote_asset_num_discarded_rates: None,
+                base_asset_route: None,
+                quote_asset_route: None,
             },
         };
         let xrc = Arc::new(
@@ -545,6 +550,11 @@ mod test {
                 quote_asset_num_received_rates: 6,
                 standard_deviation: 1,
                 forex_timestamp: Some(0),
+                twap_window_minutes: None,
+                base_asset_num_discarded_rates: None,
+                quote_asset_num_discarded_rates: None,
+                base_asset_route: None,
+                quote_asset_route: None,
             },
         }
     }
@@ -30,6 +30,7 @@ impl Storable for Config {
 }
 
 #[derive(CandidType, Deserialize)]
+#[allow(clippy::large_enum_variant)]
 pub enum EntryResult {
     Rate(ExchangeRate),
     RateError(ExchangeRateError),
@@ -30,7 +30,7 @@ use crate::{
 /// 0. The XRC retrieves rates from the mock forex sources and normalizes them to USD,
 ///    collecting the EUR/USD rate (see xrc/forex.rs).
 /// 1. The XRC retrieves the BTC/USDT rates from the mock exchange responses.
-/// 2. The XRC retrieves the stablecoin rates (USDS and USDC, each quoted in USDT) from the mock exchanges.
+/// 2. The XRC retrieves the stablecoin rates (USDC, USDS, DAI, FDUSD and PYUSD, each quoted in USDT, where listed) from the mock exchanges.
 /// 3. The XRC determines the USDT/USD rate.
 /// 4. The XRC multiplies the USDT/USD rate with the BTC/USDT rate to get the BTC/USD rate.
 /// 5. The XRC divides BTC/USD by the forex rate EUR/USD (inverting EUR/USD to USD/EUR and multiplying) to get BTC/EUR.
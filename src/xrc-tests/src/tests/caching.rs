@@ -88,6 +88,11 @@ fn caching() {
             quote_asset_num_received_rates: 9,
             standard_deviation: 3_178_330,
             forex_timestamp: None,
+            twap_window_minutes: None,
+            base_asset_num_discarded_rates: None,
+            quote_asset_num_discarded_rates: None,
+            base_asset_route: None,
+            quote_asset_route: None,
         },
     };
 
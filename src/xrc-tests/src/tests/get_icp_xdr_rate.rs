@@ -21,7 +21,7 @@ use crate::{
 /// 0. The XRC retrieves rates from the mock forex sources, normalizes them to USD,
 ///    and computes the CXDR/USD rate (see xrc/forex.rs).
 /// 1. The XRC retrieves the ICP/USDT rates from the mock exchange responses.
-/// 2. The XRC retrieves the stablecoin rates (USDS and USDC, each quoted in USDT) from the mock exchanges.
+/// 2. The XRC retrieves the stablecoin rates (USDC, USDS, DAI, FDUSD and PYUSD, each quoted in USDT, where listed) from the mock exchanges.
 /// 3. The XRC determines if USDT has not depegged. If it has not depegged, it returns the USDT/USD rate.
 /// 4. The XRC multiplies the USDT/USD rate with the ICP/USDT rate to get the ICP/USD rate.
 /// 5. The XRC divides ICP/USD by the forex rate CXDR/USD (inverting CXDR/USD to USD/CXDR and multiplying) to get ICP/CXDR.
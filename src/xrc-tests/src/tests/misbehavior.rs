@@ -50,7 +50,7 @@ const FIAT_PAIR_COMMON_DATASET_STD_DEV: u64 = 396_623_626;
 /// 0. The XRC retrieves rates from the mock forex sources and normalizes them to USD,
 ///    collecting the EUR/USD rate (see xrc/forex.rs).
 /// 1. The XRC retrieves the BTC/USDT rates from the mock exchange responses (outliers filtered as above).
-/// 2. The XRC retrieves the stablecoin rates (USDS and USDC, each quoted in USDT) and determines the USDT/USD rate.
+/// 2. The XRC retrieves the stablecoin rates (USDC, USDS, DAI, FDUSD and PYUSD, each quoted in USDT, where listed) and determines the USDT/USD rate.
 /// 3. The XRC multiplies USDT/USD by BTC/USDT to get BTC/USD.
 /// 4. The XRC divides BTC/USD by the forex rate EUR/USD (inverting EUR/USD to USD/EUR and multiplying) to get BTC/EUR.
 /// 5. The XRC returns the median rate and the standard deviation of the BTC/EUR rates.
@@ -171,6 +171,11 @@ fn misbehavior() {
                 quote_asset_num_received_rates: NUM_EXCHANGES,
                 standard_deviation: 3_644_799,
                 forex_timestamp: None,
+                twap_window_minutes: None,
+                base_asset_num_discarded_rates: None,
+                quote_asset_num_discarded_rates: None,
+                base_asset_route: None,
+                quote_asset_route: None,
             },
         };
 
@@ -202,6 +207,11 @@ fn misbehavior() {
                 quote_asset_num_received_rates: NUM_FOREX_SOURCES,
                 standard_deviation: 2_408_021_784,
                 forex_timestamp: Some(yesterday_timestamp_seconds),
+                twap_window_minutes: None,
+                base_asset_num_discarded_rates: None,
+                quote_asset_num_discarded_rates: None,
+                base_asset_route: None,
+                quote_asset_route: None,
             },
         };
 
@@ -236,6 +246,11 @@ fn misbehavior() {
                 quote_asset_num_received_rates: NUM_EXCHANGES,
                 standard_deviation: 1_304_018,
                 forex_timestamp: Some(yesterday_timestamp_seconds),
+                twap_window_minutes: None,
+                base_asset_num_discarded_rates: None,
+                quote_asset_num_discarded_rates: None,
+                base_asset_route: None,
+                quote_asset_route: None,
             },
         };
 
@@ -277,6 +292,11 @@ fn misbehavior() {
                 quote_asset_num_received_rates: NUM_FOREX_SOURCES,
                 standard_deviation: 7_313_975_259,
                 forex_timestamp: Some(yesterday_timestamp_seconds),
+                twap_window_minutes: None,
+                base_asset_num_discarded_rates: None,
+                quote_asset_num_discarded_rates: None,
+                base_asset_route: None,
+                quote_asset_route: None,
             },
         };
 
@@ -1,39 +1,85 @@
 mod dashboard;
+mod json;
 mod metrics;
+mod status;
 #[cfg(test)]
 pub(crate) mod test;
 
 pub use dashboard::get_dashboard;
+pub use json::{
+    get_cache_json, get_forex_json, get_listings_json, get_rate_json, get_requests_json,
+    get_status_json,
+};
 pub use metrics::get_metrics;
+pub use status::get_status;
 
 use ic_xrc_types::{
-    Asset, AssetClass, ExchangeRateError, GetExchangeRateRequest, GetExchangeRateResult,
+    Asset, AssetClass, CertifiedExchangeRate, Config, ExchangeRate, ExchangeRateCostEstimate,
+    ExchangeRateError, ExchangeRateOptions, ExchangeRateSources, ExchangeRateV2,
+    GetCertifiedExchangeRateRequest, GetCertifiedExchangeRateResult, GetExchangeRateRequest,
+    GetExchangeRateResult, GetExchangeRateSeriesRequest, GetExchangeRateSeriesResult,
+    GetExchangeRateV2Request, GetExchangeRateV2Result, GetExchangeRatesRequest,
+    GetExchangeRatesResult, GetRequestLogError, GetRequestLogRequest, GetRequestLogResult,
+    GetTwapExchangeRateRequest, RateAggregationMethod, SourceRate, SupportedAssets,
+    SupportedCryptocurrency, SupportedFiatCurrency, UpdateConfigResult,
 };
 
 use crate::cache::ExchangeRateCache;
+use crate::certification;
+use crate::config;
 use crate::environment::ChargeCyclesError;
+use crate::exchanges::ExtractedRate;
 use crate::{
-    add_labeled_counter, call_exchange,
-    environment::{CanisterEnvironment, ChargeOption, Environment},
-    inflight::{is_inflight, with_inflight_tracking},
+    add_labeled_counter, call_exchange, call_exchange_window,
+    environment::{calculate_fee_for_caller, CanisterEnvironment, ChargeOption, Environment},
+    inflight::{is_inflight, with_inflight_tracking, with_inflight_tracking_for_keys},
     rate_limiting::{is_rate_limited, with_request_counter},
-    stablecoin, utils, with_cache_mut, with_forex_rate_store, with_listing_store, CallExchangeArgs,
-    CallExchangeError,
-    Exchange, ExchangeCallKind, LabelKey, MetricCounter, MetricName, QueriedExchangeRate, DECIMALS,
-    EXCHANGES, LOG_PREFIX, ONE_MINUTE_SECONDS, USD, USDC, USDS, USDT,
+    reputation, stablecoin, utils, with_cache_mut, with_certified_rates, with_config,
+    with_forex_rate_collector, with_forex_rate_store, with_listing_store, CallExchangeArgs,
+    CallExchangeError, Exchange, ExchangeCallKind, LabelKey, MetricCounter, MetricName,
+    QueriedExchangeRate, QueriedSourceRates, RateAggregation, BTC, DAI, DECIMALS, ETH, EXCHANGES,
+    FDUSD, LOG_PREFIX, ONE_DAY_SECONDS, ONE_MINUTE_SECONDS, PYUSD, RATE_UNIT, USD, USDC, USDS,
+    USDT, XRC_REQUEST_CYCLES_COST,
 };
 use crate::{errors, request_log, NONPRIVILEGED_REQUEST_LOG, PRIVILEGED_REQUEST_LOG};
 use async_trait::async_trait;
 use candid::Principal;
-use futures::future::join_all;
+use futures::future::{join, join_all};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// The expected base rates for stablecoins. With two stablecoins, a depeg of one of them
+/// cannot be told apart from a depeg of the other, so the basket is widened to further
+/// stablecoins; see [stablecoin::get_stablecoin_rate]. The USDT/USD rate is always derived from
+/// the rates of the whole basket.
+pub(crate) const STABLECOIN_BASES: &[&str] = &[USDC, USDS, DAI, FDUSD, PYUSD];
+
+/// The maximum number of requests that may be sent in a single `get_exchange_rates` call.
+const MAX_NUM_REQUESTS_PER_BATCH: usize = 50;
+
+/// The maximum number of intervals (minutes for pairs involving a cryptocurrency, days for
+/// fiat pairs) that a series requested with `get_exchange_rate_series` may span.
+const MAX_SERIES_SPAN: u64 = 60;
 
-/// The expected base rates for stablecoins.
-const STABLECOIN_BASES: &[&str] = &[USDC, USDS];
+/// The maximum number of minutes over which a rate requested with `get_twap_exchange_rate`
+/// may be averaged.
+const MAX_TWAP_WINDOW_MINUTES: u64 = 60;
 
 /// A cached rate is only used for privileged canisters if there are at least this many source rates.
 const MIN_NUM_RATES_FOR_PRIVILEGED_CANISTERS: usize =
     if cfg!(feature = "ipv4-support") { 3 } else { 2 };
 
+/// The weight of every rate if the rates are not weighted by volume. It is large enough for the
+/// weights to remain distinct when the reputation policy scales them down.
+const NEUTRAL_RATE_WEIGHT: u64 = RATE_UNIT;
+
+/// The assets through which a cryptocurrency is priced against USDT if too few exchanges list it
+/// against USDT, in order of preference.
+const INTERMEDIATE_ASSETS: &[&str] = &[BTC, ETH, USDC];
+
+/// A cryptocurrency is priced through an intermediate asset if fewer exchanges list it against
+/// USDT, i.e., if a directly queried rate could not be used for privileged canisters.
+const MIN_DIRECT_USDT_SOURCES: usize = MIN_NUM_RATES_FOR_PRIVILEGED_CANISTERS;
+
 #[derive(Clone, Debug)]
 struct QueriedExchangeRateWithFailedExchanges {
     queried_exchange_rate: QueriedExchangeRate,
@@ -46,6 +92,7 @@ trait CallExchanges {
         &self,
         exchanges: &[&Exchange],
         asset: &Asset,
+        route: &UsdtRateRoute,
         timestamp: u64,
     ) -> Result<QueriedExchangeRateWithFailedExchanges, CallExchangeError>;
 
@@ -55,6 +102,23 @@ trait CallExchanges {
         symbols: &[&str],
         timestamp: u64,
     ) -> Vec<Result<QueriedExchangeRateWithFailedExchanges, CallExchangeError>>;
+
+    async fn get_cryptocurrency_usdt_rate_series(
+        &self,
+        exchanges: &[&Exchange],
+        asset: &Asset,
+        route: &UsdtRateRoute,
+        start: u64,
+        end: u64,
+    ) -> Result<RateSeries, CallExchangeError>;
+
+    async fn get_stablecoin_rate_series(
+        &self,
+        exchanges: &[&Exchange],
+        symbol: &str,
+        start: u64,
+        end: u64,
+    ) -> Result<RateSeries, CallExchangeError>;
 }
 
 struct CallExchangesImpl;
@@ -65,85 +129,67 @@ impl CallExchanges for CallExchangesImpl {
         &self,
         exchanges: &[&Exchange],
         asset: &Asset,
+        route: &UsdtRateRoute,
         timestamp: u64,
     ) -> Result<QueriedExchangeRateWithFailedExchanges, CallExchangeError> {
-        // Query only the exchanges that currently list this base against USDT,
-        // per the discovered listings (fail-open on a missing/stale listing).
-        // This avoids querying delisted/unlisted pairs that would only error,
-        // and keeps the reported queried-source count honest by excluding the
-        // skipped exchanges from `num_queried_sources` below.
         let now_secs = utils::time_secs();
-        let queried = exchanges_listing_base_against_usdt(exchanges, &asset.symbol, now_secs);
-        let futures = queried.iter().map(|exchange| {
-            call_exchange(
-                exchange,
-                CallExchangeArgs {
-                    timestamp,
-                    quote_asset: usdt_asset(),
-                    base_asset: asset.clone(),
-                },
-                ExchangeCallKind::Crypto,
-            )
-        });
-        let results = join_all(futures).await;
-
-        let mut rates = vec![];
-        let mut failed_exchanges = vec![];
-        for result in results {
-            match result {
-                Ok(rate) => rates.push(rate),
+        // If too few exchanges list the asset against USDT, it is priced through the
+        // intermediate asset chosen when the request was prepared. If that fails, the
+        // exchanges listing it against a USD-like quote asset are still queried directly.
+        let mut is_fallback = false;
+        if let UsdtRateRoute::Intermediate(route) = route {
+            match get_routed_usdt_rate(exchanges, route, asset, timestamp, now_secs).await {
+                Ok(response) => return Ok(response),
                 Err(err) => {
                     ic_cdk::println!(
-                        "{} Timestamp: {}, Asset: {:?}, Error: {}",
+                        "{} Timestamp: {}, Asset: {:?}, Route through {} failed: {}",
                         LOG_PREFIX,
                         timestamp,
                         asset,
+                        route.intermediate,
                         err,
                     );
-
-                    if let CallExchangeError::Http { exchange, error: _ } = err {
-                        if let Some(exchange) = exchanges.iter().find(|e| e.name() == exchange) {
-                            failed_exchanges.push((*exchange).clone());
-                        } else {
-                            ic_cdk::println!(
-                                "{} Exchange not found for failed exchanges: {} @ {}",
-                                LOG_PREFIX,
-                                exchange,
-                                timestamp
-                            );
-                        }
+                    if !route.has_fallback {
+                        return Err(err);
                     }
+                    is_fallback = true;
                 }
             }
         }
 
-        if rates.is_empty() {
-            return Err(CallExchangeError::NoRatesFound);
-        }
-
-        let queried_exchange_rate = QueriedExchangeRate::new(
-            asset.clone(),
-            usdt_asset(),
-            timestamp,
-            &rates,
-            queried.len(),
-            rates.len(),
-            None,
-        );
-
-        // The raw rates may all be filtered out (e.g. every source reported a
-        // zero or otherwise invalid price), leaving an empty post-filter rate.
-        // Such a rate must never be cached or returned: its median is zero, so a
-        // later cache-only request could otherwise be served a successful zero
-        // rate.
-        if queried_exchange_rate.rates.is_empty() {
-            return Err(CallExchangeError::NoRatesFound);
-        }
-
-        Ok(QueriedExchangeRateWithFailedExchanges {
-            queried_exchange_rate,
-            failed_exchanges,
-        })
+        // Query only the exchanges that currently list this base against a USD-like
+        // quote asset, per the discovered listings (fail-open on a missing/stale listing).
+        // This avoids querying delisted/unlisted pairs that would only error,
+        // and keeps the reported queried-source count honest by excluding the
+        // skipped exchanges from `num_queried_sources`.
+        let queried = exchanges_quoting_base_in_usd(exchanges, &asset.symbol, now_secs);
+        // The direct rate of a routed asset and the stablecoin rates needed to convert it are
+        // not counted when the request is admitted, so they are counted once the route failed.
+        let num_fallback_rates_needed = if is_fallback {
+            let quotes = queried
+                .iter()
+                .map(|(_, quote)| *quote)
+                .filter(|quote| *quote != USDT)
+                .collect();
+            1usize.saturating_add(get_missed_conversion_stablecoins(&quotes, timestamp).len())
+        } else {
+            0
+        };
+        let mut response = with_request_counter(
+            num_fallback_rates_needed,
+            get_exchange_pair_rate(
+                exchanges,
+                &queried,
+                asset,
+                &usdt_asset(),
+                timestamp,
+                now_secs,
+            ),
+        )
+        .await?;
+        response.queried_exchange_rate.base_asset_route =
+            Some(vec![asset.symbol.clone(), USDT.to_string()]);
+        Ok(response)
     }
 
     async fn get_stablecoin_rates(
@@ -159,6 +205,124 @@ impl CallExchanges for CallExchangesImpl {
         )
         .await
     }
+
+    async fn get_cryptocurrency_usdt_rate_series(
+        &self,
+        exchanges: &[&Exchange],
+        asset: &Asset,
+        route: &UsdtRateRoute,
+        start: u64,
+        end: u64,
+    ) -> Result<RateSeries, CallExchangeError> {
+        let now_secs = utils::time_secs();
+        // The exchanges are selected as for a single rate, see
+        // [CallExchanges::get_cryptocurrency_usdt_rate].
+        let mut is_fallback = false;
+        if let UsdtRateRoute::Intermediate(route) = route {
+            match get_routed_usdt_rate_series(exchanges, route, asset, start, end, now_secs).await {
+                Ok(series) => return Ok(series),
+                Err(err) => {
+                    ic_cdk::println!(
+                        "{} Window: {}-{}, Asset: {:?}, Route through {} failed: {}",
+                        LOG_PREFIX,
+                        start,
+                        end,
+                        asset,
+                        route.intermediate,
+                        err,
+                    );
+                    if !route.has_fallback {
+                        return Err(err);
+                    }
+                    is_fallback = true;
+                }
+            }
+        }
+
+        let queried = window_exchanges_quoting_base_in_usd(exchanges, &asset.symbol, now_secs);
+        let num_fallback_windows_needed = if is_fallback {
+            let quotes = queried
+                .iter()
+                .map(|(_, quote)| *quote)
+                .filter(|quote| *quote != USDT)
+                .collect();
+            1usize.saturating_add(get_missed_conversion_stablecoins(&quotes, end).len())
+        } else {
+            0
+        };
+        with_request_counter(
+            num_fallback_windows_needed,
+            get_exchange_pair_rate_series(exchanges, &queried, asset, &usdt_asset(), start, end),
+        )
+        .await
+    }
+
+    async fn get_stablecoin_rate_series(
+        &self,
+        exchanges: &[&Exchange],
+        symbol: &str,
+        start: u64,
+        end: u64,
+    ) -> Result<RateSeries, CallExchangeError> {
+        let mut futures = vec![];
+        for exchange in exchanges
+            .iter()
+            .filter(|exchange| exchange.supports_windows())
+        {
+            let maybe_pair = exchange
+                .supported_stablecoin_pairs()
+                .iter()
+                .find(|pair| pair.0 == symbol || pair.1 == symbol);
+
+            let (base_symbol, quote_symbol) = match maybe_pair {
+                Some(pair) => pair,
+                None => continue,
+            };
+
+            // As in `call_exchange_for_stablecoin`, inverse pairs are inverted so that
+            // USDT is the quote asset.
+            let invert = *base_symbol == USDT;
+            futures.push(async move {
+                let result = call_exchange_window(
+                    exchange,
+                    base_symbol,
+                    quote_symbol,
+                    start,
+                    end,
+                    ExchangeCallKind::Stablecoin,
+                )
+                .await;
+                if invert {
+                    result.map(|candles| {
+                        candles
+                            .into_iter()
+                            .filter_map(|(timestamp, rate)| {
+                                utils::checked_invert_rate(rate.into(), DECIMALS)
+                                    .map(|rate| (timestamp, rate))
+                            })
+                            .collect()
+                    })
+                } else {
+                    result
+                }
+            });
+        }
+
+        let num_queried_sources = futures.len();
+        let results = join_all(futures).await;
+        let asset = Asset {
+            symbol: symbol.to_string(),
+            class: AssetClass::Cryptocurrency,
+        };
+        rate_series_from_candles(
+            asset,
+            usdt_asset(),
+            results,
+            num_queried_sources,
+            start,
+            end,
+        )
+    }
 }
 
 /// Provides an [Asset] that corresponds to the USDT cryptocurrency stablecoin.
@@ -181,133 +345,2524 @@ pub fn usd_asset() -> Asset {
 fn get_available_exchanges() -> Vec<&'static Exchange> {
     EXCHANGES
         .iter()
-        .filter(|e| e.is_available())
+        .filter(|e| e.is_available() && e.is_enabled())
         .collect::<Vec<_>>()
 }
 
-/// Returns the subset of `exchanges` to query for `base`/USDT according to the
-/// discovered listings at `now_secs`. An exchange is kept when its listing
-/// contains `base`, and fail-open when it has no listing or a stale one (see
-/// `ListingStore::should_query`).
-fn exchanges_listing_base_against_usdt<'a>(
+/// Returns the subset of `exchanges` to query for `base` against USDT, each paired with the
+/// USD-like quote asset to query it with (see `ListingStore::select_usd_quote`). Prints in
+/// quotes other than USDT are converted into USDT, see [get_usd_quote_conversion_rates].
+fn exchanges_quoting_base_in_usd<'a>(
     exchanges: &[&'a Exchange],
     base: &str,
     now_secs: u64,
-) -> Vec<&'a Exchange> {
+) -> Vec<(&'a Exchange, &'static str)> {
     with_listing_store(|store| {
         exchanges
             .iter()
-            .filter(|exchange| store.should_query(exchange.name(), base, now_secs))
-            .copied()
+            .filter_map(|exchange| {
+                store
+                    .select_usd_quote(
+                        exchange.name(),
+                        base,
+                        exchange.supported_usd_quote_assets(),
+                        now_secs,
+                    )
+                    .map(|quote| (*exchange, quote))
+            })
             .collect()
     })
 }
 
-/// This function retrieves the requested rate from the exchanges. The median rate of all collected
-/// rates is used as the exchange rate and a set of metadata is returned giving information on
-/// how the rate was retrieved.
-pub async fn get_exchange_rate(request: GetExchangeRateRequest) -> GetExchangeRateResult {
-    let env = CanisterEnvironment::new();
-    let timestamp = env.time_secs();
-    let caller = env.caller();
-    let call_exchanges_impl = CallExchangesImpl;
+/// Returns the exchanges of [exchanges_quoting_base_in_usd] whose candle endpoint takes a time
+/// range, i.e., that can serve a whole window of rates with a single outcall.
+fn window_exchanges_quoting_base_in_usd<'a>(
+    exchanges: &[&'a Exchange],
+    base: &str,
+    now_secs: u64,
+) -> Vec<(&'a Exchange, &'static str)> {
+    exchanges_quoting_base_in_usd(exchanges, base, now_secs)
+        .into_iter()
+        .filter(|(exchange, _)| exchange.supports_windows())
+        .collect()
+}
 
-    // Record metrics
-    let is_caller_privileged = utils::is_caller_privileged(&caller);
+/// Returns the intermediate asset through which `base` is priced against USDT together with
+/// the exchanges whose fresh listing contains `base` quoted in it, if fewer than
+/// [MIN_DIRECT_USDT_SOURCES] exchanges would be queried for `base` against USDT and more exchanges list
+/// `base` against the intermediate asset. Among the [INTERMEDIATE_ASSETS], the one listed by
+/// the most exchanges is chosen, with ties going to the preferred one.
+fn select_intermediate_asset<'a>(
+    exchanges: &[&'a Exchange],
+    base: &str,
+    now_secs: u64,
+) -> Option<(&'static str, Vec<(&'a Exchange, &'static str)>)> {
+    let num_direct_sources = exchanges_quoting_base_in_usd(exchanges, base, now_secs).len();
+    if base == USDT || num_direct_sources >= MIN_DIRECT_USDT_SOURCES {
+        return None;
+    }
 
-    MetricCounter::GetExchangeRateRequest.increment();
-    if is_caller_privileged {
-        MetricCounter::GetExchangeRateRequestFromCmc.increment();
+    with_listing_store(|store| {
+        let mut selected: Option<(&'static str, Vec<(&'a Exchange, &'static str)>)> = None;
+        for intermediate in INTERMEDIATE_ASSETS {
+            if intermediate.eq_ignore_ascii_case(base) {
+                continue;
+            }
+            let listing_exchanges: Vec<_> = exchanges
+                .iter()
+                .filter(|exchange| store.lists_pair(exchange.name(), base, intermediate, now_secs))
+                .map(|exchange| (*exchange, *intermediate))
+                .collect();
+            let num_selected_sources = selected
+                .as_ref()
+                .map_or(num_direct_sources, |(_, selected_exchanges)| {
+                    selected_exchanges.len()
+                });
+            if listing_exchanges.len() > num_selected_sources {
+                selected = Some((intermediate, listing_exchanges));
+            }
+        }
+        selected
+    })
+}
+
+/// The route through which the USDT rate of a cryptocurrency is retrieved. It is chosen before
+/// any outbound calls are made, so that every rate retrieved on the way is counted when the
+/// request is charged and rate limited.
+#[derive(Clone, Debug)]
+enum UsdtRateRoute {
+    /// The cryptocurrency is queried against the USD-like quote assets.
+    Direct,
+    /// The cryptocurrency is priced through an intermediate asset.
+    Intermediate(Box<IntermediateRoute>),
+}
+
+/// A route through an intermediate asset, see [select_intermediate_asset].
+#[derive(Clone, Debug)]
+struct IntermediateRoute {
+    /// The symbol of the intermediate asset.
+    intermediate: &'static str,
+    /// The exchanges that list the cryptocurrency against the intermediate asset.
+    listing_exchanges: Vec<(&'static Exchange, &'static str)>,
+    /// The intermediate/USDT rate if it was found in the cache.
+    cached_intermediate_rate: Option<QueriedExchangeRate>,
+    /// Whether the cryptocurrency is queried directly if the route fails, i.e., whether any
+    /// exchange lists it against a USD-like quote asset. The direct rate is only counted
+    /// against the request counter if the route actually fails.
+    has_fallback: bool,
+}
+
+impl UsdtRateRoute {
+    /// Chooses the route for the cryptocurrency `symbol` at the given timestamp.
+    fn select(
+        exchanges: &[&'static Exchange],
+        symbol: &str,
+        timestamp: u64,
+        now_secs: u64,
+    ) -> Self {
+        match select_intermediate_asset(exchanges, symbol, now_secs) {
+            Some((intermediate, listing_exchanges)) => {
+                UsdtRateRoute::Intermediate(Box::new(IntermediateRoute {
+                    intermediate,
+                    listing_exchanges,
+                    cached_intermediate_rate: with_cache_mut(|cache| {
+                        cache.get(intermediate, timestamp)
+                    }),
+                    has_fallback: !exchanges_quoting_base_in_usd(exchanges, symbol, now_secs)
+                        .is_empty(),
+                }))
+            }
+            None => UsdtRateRoute::Direct,
+        }
+    }
+
+    /// Returns the number of rates that are retrieved from the exchanges on this route: the
+    /// cryptocurrency's rate and, on a route through an intermediate asset, the intermediate
+    /// rate if it is not cached. The direct rate that is retrieved if the route fails is not
+    /// included.
+    fn num_rates_needed(&self) -> usize {
+        match self {
+            UsdtRateRoute::Direct => 1,
+            UsdtRateRoute::Intermediate(route) => {
+                1usize.saturating_add(usize::from(route.cached_intermediate_rate.is_none()))
+            }
+        }
+    }
+
+    /// Returns the symbol of the intermediate asset if its rate is retrieved on this route.
+    fn retrieved_intermediate(&self) -> Option<&'static str> {
+        match self {
+            UsdtRateRoute::Intermediate(route) if route.cached_intermediate_rate.is_none() => {
+                Some(route.intermediate)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the USD-like quote assets other than USDT in which the rates retrieved against
+    /// USDT on this route for the cryptocurrency `symbol` may be quoted. These rates are
+    /// converted into USDT, see [get_usd_quote_conversion_rates]. As for
+    /// [UsdtRateRoute::num_rates_needed], the direct rate retrieved if the route fails is not
+    /// included.
+    fn converted_quotes(
+        &self,
+        exchanges: &[&Exchange],
+        symbol: &str,
+        now_secs: u64,
+    ) -> BTreeSet<&'static str> {
+        let usdt_rate_symbols = match self {
+            UsdtRateRoute::Direct => vec![symbol],
+            UsdtRateRoute::Intermediate(route) => route
+                .cached_intermediate_rate
+                .is_none()
+                .then_some(route.intermediate)
+                .into_iter()
+                .collect(),
+        };
+        usdt_rate_symbols
+            .into_iter()
+            .flat_map(|symbol| exchanges_quoting_base_in_usd(exchanges, symbol, now_secs))
+            .map(|(_, quote)| quote)
+            .filter(|quote| *quote != USDT)
+            .collect()
+    }
+
+    /// Returns the number of windows of rates that are retrieved from the exchanges on this
+    /// route for a series. Intermediate rates are not taken from the cache for a series, so a
+    /// route through an intermediate asset always retrieves two windows. As for
+    /// [UsdtRateRoute::num_rates_needed], the direct window retrieved if the route fails is not
+    /// included.
+    fn num_series_windows_needed(&self) -> usize {
+        match self {
+            UsdtRateRoute::Direct => 1,
+            UsdtRateRoute::Intermediate(_) => 2,
+        }
+    }
+
+    /// Returns the USD-like quote assets other than USDT in which the windows retrieved against
+    /// USDT on this route for the cryptocurrency `symbol` may be quoted, see
+    /// [UsdtRateRoute::converted_quotes].
+    fn series_converted_quotes(
+        &self,
+        exchanges: &[&Exchange],
+        symbol: &str,
+        now_secs: u64,
+    ) -> BTreeSet<&'static str> {
+        let usdt_rate_symbol = match self {
+            UsdtRateRoute::Direct => symbol,
+            UsdtRateRoute::Intermediate(route) => route.intermediate,
+        };
+        window_exchanges_quoting_base_in_usd(exchanges, usdt_rate_symbol, now_secs)
+            .into_iter()
+            .map(|(_, quote)| quote)
+            .filter(|quote| *quote != USDT)
+            .collect()
+    }
+}
+
+/// Retrieves the series of `asset` against USDT through the intermediate asset of the `route`,
+/// see [get_routed_usdt_rate]: the `asset`/intermediate rates of every minute are multiplied with
+/// the intermediate/USDT rates of the same minute.
+async fn get_routed_usdt_rate_series(
+    exchanges: &[&Exchange],
+    route: &IntermediateRoute,
+    asset: &Asset,
+    start: u64,
+    end: u64,
+    now_secs: u64,
+) -> Result<RateSeries, CallExchangeError> {
+    let intermediate = route.intermediate;
+    let intermediate_asset = Asset {
+        symbol: intermediate.to_string(),
+        class: AssetClass::Cryptocurrency,
+    };
+    let listing_exchanges = route
+        .listing_exchanges
+        .iter()
+        .filter(|(exchange, _)| exchanges.contains(exchange) && exchange.supports_windows())
+        .map(|(exchange, quote)| (*exchange, *quote))
+        .collect::<Vec<_>>();
+    let queried = window_exchanges_quoting_base_in_usd(exchanges, intermediate, now_secs);
+    let (pair_series, intermediate_series) = join(
+        get_exchange_pair_rate_series(
+            exchanges,
+            &listing_exchanges,
+            asset,
+            &intermediate_asset,
+            start,
+            end,
+        ),
+        get_exchange_pair_rate_series(
+            exchanges,
+            &queried,
+            &intermediate_asset,
+            &usdt_asset(),
+            start,
+            end,
+        ),
+    )
+    .await;
+    let intermediate_series = intermediate_series?;
+
+    let series = pair_series?
+        .into_iter()
+        .filter_map(|(timestamp, pair_rate)| {
+            let mut rate = pair_rate * intermediate_series.get(&timestamp)?.clone();
+            rate.base_asset_route = Some(vec![
+                asset.symbol.clone(),
+                intermediate.to_string(),
+                USDT.to_string(),
+            ]);
+            Some((timestamp, rate))
+        })
+        .filter(|(_, rate)| !rate.rates.is_empty())
+        .collect::<RateSeries>();
+    if series.is_empty() {
+        return Err(CallExchangeError::NoRatesFound);
+    }
+    Ok(series)
+}
+
+/// Retrieves the windows of rates of `base_asset` from each of the `queried` exchanges against the
+/// quote asset it is paired with and aggregates the rates of every minute, see
+/// [rate_series_from_candles]. As in [get_exchange_pair_rate], rates received against another
+/// quote asset than `quote_asset` are converted into `quote_asset` (USDT), using the conversion
+/// rates at the end of the window.
+async fn get_exchange_pair_rate_series(
+    exchanges: &[&Exchange],
+    queried: &[(&Exchange, &str)],
+    base_asset: &Asset,
+    quote_asset: &Asset,
+    start: u64,
+    end: u64,
+) -> Result<RateSeries, CallExchangeError> {
+    let futures = queried.iter().map(|(exchange, quote)| {
+        call_exchange_window(
+            exchange,
+            &base_asset.symbol,
+            quote,
+            start,
+            end,
+            ExchangeCallKind::Crypto,
+        )
+    });
+    let other_quotes: BTreeSet<&str> = queried
+        .iter()
+        .map(|(_, quote)| *quote)
+        .filter(|quote| *quote != quote_asset.symbol)
+        .collect();
+    let (results, conversion_rates) = join(
+        join_all(futures),
+        get_usd_quote_conversion_rates(exchanges, &other_quotes, end),
+    )
+    .await;
+
+    let mut converted_results = vec![];
+    for ((exchange, quote), result) in queried.iter().zip(results) {
+        if *quote == quote_asset.symbol {
+            converted_results.push(result);
+            continue;
+        }
+        let converted_result = match conversion_rates.get(quote) {
+            Some(conversion_rate) => result.map(|candles| {
+                candles
+                    .into_iter()
+                    .map(|(timestamp, rate)| {
+                        (timestamp, convert_usd_quoted_rate(rate, *conversion_rate))
+                    })
+                    .collect()
+            }),
+            None => Err(CallExchangeError::NoData {
+                exchange: exchange.to_string(),
+            }),
+        };
+        converted_results.push(converted_result);
+    }
+    rate_series_from_candles(
+        base_asset.clone(),
+        quote_asset.clone(),
+        converted_results,
+        queried.len(),
+        start,
+        end,
+    )
+}
+
+/// Prices `asset` against USDT through the intermediate asset of the `route`: the
+/// `asset`/intermediate rate is retrieved from the listing exchanges of the route and multiplied
+/// with the intermediate/USDT rate, which is retrieved unless it was cached when the route was
+/// chosen. The route is recorded in the rate.
+async fn get_routed_usdt_rate(
+    exchanges: &[&Exchange],
+    route: &IntermediateRoute,
+    asset: &Asset,
+    timestamp: u64,
+    now_secs: u64,
+) -> Result<QueriedExchangeRateWithFailedExchanges, CallExchangeError> {
+    let intermediate = route.intermediate;
+    let intermediate_asset = Asset {
+        symbol: intermediate.to_string(),
+        class: AssetClass::Cryptocurrency,
+    };
+    // Exchanges that failed earlier in the same request are not queried again.
+    let listing_exchanges = route
+        .listing_exchanges
+        .iter()
+        .filter(|(exchange, _)| exchanges.contains(exchange))
+        .map(|(exchange, quote)| (*exchange, *quote))
+        .collect::<Vec<_>>();
+    let intermediate_rate_future = async {
+        if let Some(queried_exchange_rate) = route.cached_intermediate_rate.clone() {
+            return Ok(QueriedExchangeRateWithFailedExchanges {
+                queried_exchange_rate,
+                failed_exchanges: vec![],
+            });
+        }
+        let queried = exchanges_quoting_base_in_usd(exchanges, intermediate, now_secs);
+        let response = get_exchange_pair_rate(
+            exchanges,
+            &queried,
+            &intermediate_asset,
+            &usdt_asset(),
+            timestamp,
+            now_secs,
+        )
+        .await?;
+        with_cache_mut(|cache| cache.insert(&response.queried_exchange_rate));
+        Ok(response)
+    };
+    let (pair_response, intermediate_response) = join(
+        get_exchange_pair_rate(
+            exchanges,
+            &listing_exchanges,
+            asset,
+            &intermediate_asset,
+            timestamp,
+            now_secs,
+        ),
+        intermediate_rate_future,
+    )
+    .await;
+    let pair_response = pair_response?;
+    let intermediate_response = intermediate_response?;
+
+    let mut queried_exchange_rate =
+        pair_response.queried_exchange_rate * intermediate_response.queried_exchange_rate;
+    if queried_exchange_rate.rates.is_empty() {
+        return Err(CallExchangeError::NoRatesFound);
+    }
+    queried_exchange_rate.base_asset_route = Some(vec![
+        asset.symbol.clone(),
+        intermediate.to_string(),
+        USDT.to_string(),
+    ]);
+    Ok(QueriedExchangeRateWithFailedExchanges {
+        queried_exchange_rate,
+        failed_exchanges: pair_response
+            .failed_exchanges
+            .into_iter()
+            .chain(intermediate_response.failed_exchanges)
+            .collect(),
+    })
+}
+
+/// Queries each of the `queried` exchanges for the rate of `base_asset` against the quote asset
+/// it is paired with and aggregates the received rates. Rates received against another quote
+/// asset than `quote_asset`, which must be USD-like quote assets, are converted into
+/// `quote_asset` (USDT). The deviations of the received rates are recorded in the exchanges'
+/// reputation, and the reputation policy is applied before the rates are aggregated.
+async fn get_exchange_pair_rate(
+    exchanges: &[&Exchange],
+    queried: &[(&Exchange, &str)],
+    base_asset: &Asset,
+    quote_asset: &Asset,
+    timestamp: u64,
+    now_secs: u64,
+) -> Result<QueriedExchangeRateWithFailedExchanges, CallExchangeError> {
+    let futures = queried.iter().map(|(exchange, quote)| {
+        call_exchange(
+            exchange,
+            CallExchangeArgs {
+                timestamp,
+                quote_asset: Asset {
+                    symbol: quote.to_string(),
+                    class: AssetClass::Cryptocurrency,
+                },
+                base_asset: base_asset.clone(),
+            },
+            ExchangeCallKind::Crypto,
+        )
+    });
+    let other_quotes: BTreeSet<&str> = queried
+        .iter()
+        .map(|(_, quote)| *quote)
+        .filter(|quote| *quote != quote_asset.symbol)
+        .collect();
+    let (results, conversion_rates) = join(
+        join_all(futures),
+        get_usd_quote_conversion_rates(exchanges, &other_quotes, timestamp),
+    )
+    .await;
+
+    let mut rates = vec![];
+    let mut volumes = vec![];
+    let mut source_rates = vec![];
+    let mut failed_exchanges = vec![];
+    let mut num_unconverted_rates: usize = 0;
+    for ((exchange, quote), result) in queried.iter().zip(results) {
+        match result {
+            Ok(ExtractedRate { rate, volume }) => {
+                let rate = if *quote == quote_asset.symbol {
+                    rate
+                } else if let Some(conversion_rate) = conversion_rates.get(quote) {
+                    convert_usd_quoted_rate(rate, *conversion_rate)
+                } else {
+                    ic_cdk::println!(
+                        "{} Timestamp: {}, Asset: {:?}, No conversion rate for the {} rate of {}",
+                        LOG_PREFIX,
+                        timestamp,
+                        base_asset,
+                        quote,
+                        exchange.name(),
+                    );
+                    num_unconverted_rates = num_unconverted_rates.saturating_add(1);
+                    continue;
+                };
+                rates.push(rate);
+                volumes.push(volume);
+                source_rates.push((exchange.name().to_string(), rate));
+            }
+            Err(err) => {
+                ic_cdk::println!(
+                    "{} Timestamp: {}, Asset: {:?}, Error: {}",
+                    LOG_PREFIX,
+                    timestamp,
+                    base_asset,
+                    err,
+                );
+
+                if let CallExchangeError::Http { exchange, error: _ } = err {
+                    if let Some(exchange) = exchanges.iter().find(|e| e.name() == exchange) {
+                        failed_exchanges.push((*exchange).clone());
+                    } else {
+                        ic_cdk::println!(
+                            "{} Exchange not found for failed exchanges: {} @ {}",
+                            LOG_PREFIX,
+                            exchange,
+                            timestamp
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    if rates.is_empty() {
+        return Err(CallExchangeError::NoRatesFound);
+    }
+
+    // The deviations of all received rates are recorded before the reputation policy is
+    // applied, so that an exchange whose rates are discarded can regain its reputation.
+    let num_received_rates = rates.len().saturating_add(num_unconverted_rates);
+    reputation::record_deviations(&base_asset.symbol, &source_rates, now_secs);
+    let mut weights = get_rate_weights(&volumes);
+    let excluded_exchanges = reputation::apply_policy(
+        &base_asset.symbol,
+        &mut source_rates,
+        &mut rates,
+        &mut weights,
+    );
+    if !excluded_exchanges.is_empty() {
+        ic_cdk::println!(
+            "{} Timestamp: {}, Asset: {:?}, Excluded exchanges with a poor reputation: {:?}",
+            LOG_PREFIX,
+            timestamp,
+            base_asset,
+            excluded_exchanges,
+        );
+    }
+
+    let mut queried_exchange_rate = QueriedExchangeRate::new(
+        base_asset.clone(),
+        quote_asset.clone(),
+        timestamp,
+        &rates,
+        queried.len(),
+        num_received_rates,
+        None,
+        // Equal weights are aggregated with the plain median.
+        if weights.windows(2).all(|pair| pair[0] == pair[1]) {
+            RateAggregation::Median
+        } else {
+            RateAggregation::WeightedMedian(&weights)
+        },
+    );
+    // The excluded rates and the rates that could not be converted into USDT count as
+    // discarded rates.
+    for num_discarded_rates in [
+        &mut queried_exchange_rate.base_asset_num_discarded_rates,
+        &mut queried_exchange_rate.quote_asset_num_discarded_rates,
+    ] {
+        *num_discarded_rates =
+            num_discarded_rates.map(|num| num + excluded_exchanges.len() + num_unconverted_rates);
+    }
+    // Keep the exchange names of the rates that were retained so that the
+    // rate can be traced back to its sources.
+    source_rates.retain(|(_, rate)| queried_exchange_rate.rates.contains(rate));
+    queried_exchange_rate.source_rates = Some(QueriedSourceRates {
+        base_asset: source_rates,
+        ..Default::default()
+    });
+
+    // The raw rates may all be filtered out (e.g. every source reported a
+    // zero or otherwise invalid price), leaving an empty post-filter rate.
+    // Such a rate must never be cached or returned: its median is zero, so a
+    // later cache-only request could otherwise be served a successful zero
+    // rate.
+    if queried_exchange_rate.rates.is_empty() {
+        return Err(CallExchangeError::NoRatesFound);
+    }
+
+    Ok(QueriedExchangeRateWithFailedExchanges {
+        queried_exchange_rate,
+        failed_exchanges,
+    })
+}
+
+/// Returns the stablecoins whose rates are needed to convert rates quoted in the given USD-like
+/// quote assets into USDT: the USDC rate for USDC-quoted rates and the rates of all
+/// [STABLECOIN_BASES] for USD-quoted rates.
+fn get_conversion_stablecoins(quotes: &BTreeSet<&str>) -> Vec<&'static str> {
+    STABLECOIN_BASES
+        .iter()
+        .copied()
+        .filter(|symbol| quotes.contains(USD) || (quotes.contains(USDC) && *symbol == USDC))
+        .collect()
+}
+
+/// Returns the stablecoins whose rates must be retrieved from the exchanges to convert rates
+/// quoted in the given USD-like quote assets into USDT at the given timestamp, i.e., the
+/// conversion stablecoins that are not cached, see [get_usd_quote_conversion_rates].
+fn get_missed_conversion_stablecoins(
+    quotes: &BTreeSet<&str>,
+    timestamp: u64,
+) -> BTreeSet<&'static str> {
+    with_cache_mut(|cache| {
+        get_conversion_stablecoins(quotes)
+            .into_iter()
+            .filter(|symbol| cache.get(symbol, timestamp).is_none())
+            .collect()
+    })
+}
+
+/// Returns the stablecoins whose rates must be retrieved from the exchanges to convert the
+/// rates retrieved on the routes of the given cryptocurrencies into USDT.
+fn get_missed_route_stablecoins(
+    exchanges: &[&Exchange],
+    routes: &[(&str, &UsdtRateRoute)],
+    timestamp: u64,
+    now_secs: u64,
+) -> BTreeSet<&'static str> {
+    let quotes = routes
+        .iter()
+        .flat_map(|(symbol, route)| route.converted_quotes(exchanges, symbol, now_secs))
+        .collect();
+    get_missed_conversion_stablecoins(&quotes, timestamp)
+}
+
+/// Retrieves the rates that convert rates quoted in the given USD-like quote assets into USDT,
+/// scaled by `RATE_UNIT`. The stablecoin rates are taken from the cache if possible and
+/// retrieved and cached otherwise. A USDC-quoted rate is converted with the USDC/USDT rate and
+/// a USD-quoted rate with the USD/USDT rate, which is derived from the rates of the
+/// [STABLECOIN_BASES] as for cryptocurrency/fiat pairs, including the depeg check (see
+/// [stablecoin::get_stablecoin_rate]). Quote assets for which no conversion rate can be
+/// determined are omitted.
+async fn get_usd_quote_conversion_rates<'a>(
+    exchanges: &[&Exchange],
+    quotes: &BTreeSet<&'a str>,
+    timestamp: u64,
+) -> BTreeMap<&'a str, u64> {
+    let missed_stablecoins = get_missed_conversion_stablecoins(quotes, timestamp);
+    join_all(missed_stablecoins.iter().map(|symbol| async move {
+        if let Ok(response) = get_stablecoin_rate(exchanges, symbol, timestamp).await {
+            with_cache_mut(|cache| cache.insert(&response.queried_exchange_rate));
+        }
+    }))
+    .await;
+    let stablecoin_rates = with_cache_mut(|cache| {
+        get_conversion_stablecoins(quotes)
+            .into_iter()
+            .filter_map(|symbol| cache.get(symbol, timestamp))
+            .collect::<Vec<_>>()
+    });
+
+    let mut conversion_rates = BTreeMap::new();
+    for quote in quotes {
+        let conversion_rate = match *quote {
+            USDC => stablecoin_rates
+                .iter()
+                .find(|rate| rate.base_asset.symbol == USDC)
+                .cloned(),
+            USD => stablecoin::get_stablecoin_rate(&stablecoin_rates, &usd_asset())
+                .ok()
+                .map(|usdt_usd_rate| usdt_usd_rate.inverted()),
+            _ => None,
+        }
+        .map(ExchangeRate::from)
+        .filter(|rate| rate.rate > 0 && rate.metadata.decimals == DECIMALS);
+        if let Some(conversion_rate) = conversion_rate {
+            conversion_rates.insert(*quote, conversion_rate.rate);
+        }
+    }
+    conversion_rates
+}
+
+/// Returns the weights with which the rates received from the exchanges are aggregated, given
+/// the traded volumes in the same order. The volumes are only used if the configured
+/// [RateAggregationMethod] is the volume-weighted median and every exchange reported a positive
+/// volume, as some exchanges report no volume for a candle and their rates would otherwise be
+/// ignored. Otherwise, every rate has the same neutral weight.
+fn get_rate_weights(volumes: &[u64]) -> Vec<u64> {
+    let aggregation_method = with_config(|config| config.crypto_rate_aggregation)
+        .unwrap_or(RateAggregationMethod::Median);
+    if aggregation_method == RateAggregationMethod::VolumeWeightedMedian
+        && volumes.iter().all(|volume| *volume > 0)
+    {
+        volumes.to_vec()
+    } else {
+        vec![NEUTRAL_RATE_WEIGHT; volumes.len()]
+    }
+}
+
+/// Converts a rate quoted in a USD-like quote asset into USDT using the given conversion rate.
+fn convert_usd_quoted_rate(rate: u64, conversion_rate: u64) -> u64 {
+    let converted_rate = rate as u128 * conversion_rate as u128 / RATE_UNIT as u128;
+    u64::try_from(converted_rate).unwrap_or(u64::MAX)
+}
+
+/// Returns an "asset not listed" error if every given exchange has a fresh listing that does not
+/// contain `symbol` and no exchange lists it against an intermediate asset, i.e., if querying the
+/// exchanges for the `symbol`/USDT rate is known to fail.
+fn check_asset_is_listed(
+    exchanges: &[&Exchange],
+    symbol: &str,
+    now_secs: u64,
+) -> Result<(), ExchangeRateError> {
+    if is_asset_listed(exchanges, symbol, now_secs) {
+        Ok(())
+    } else {
+        Err(errors::asset_not_listed_error(symbol))
+    }
+}
+
+/// Returns false if querying the exchanges for the `symbol`/USDT rate is known to fail, see
+/// [check_asset_is_listed].
+fn is_asset_listed(exchanges: &[&Exchange], symbol: &str, now_secs: u64) -> bool {
+    symbol == USDT
+        || !exchanges_quoting_base_in_usd(exchanges, symbol, now_secs).is_empty()
+        || select_intermediate_asset(exchanges, symbol, now_secs).is_some()
+}
+
+/// This function retrieves the requested rate from the exchanges. The median rate of all collected
+/// rates is used as the exchange rate and a set of metadata is returned giving information on
+/// how the rate was retrieved.
+pub async fn get_exchange_rate(request: GetExchangeRateRequest) -> GetExchangeRateResult {
+    let env = CanisterEnvironment::new();
+    let timestamp = env.time_secs();
+    let caller = env.caller();
+    let call_exchanges_impl = CallExchangesImpl;
+
+    let is_caller_privileged = utils::is_caller_privileged(&caller);
+
+    let result = get_exchange_rate_internal(&env, &call_exchanges_impl, &request).await;
+    record_request(&caller, timestamp, is_caller_privileged, &request, &result);
+    result
+}
+
+/// This function retrieves the requested rate in the same way as [get_exchange_rate] and
+/// additionally returns the individual source rates behind the median rate. The request may
+/// contain quality requirements that the rate must meet.
+pub async fn get_exchange_rate_v2(request: GetExchangeRateV2Request) -> GetExchangeRateV2Result {
+    let env = CanisterEnvironment::new();
+    let timestamp = env.time_secs();
+    let caller = env.caller();
+    let call_exchanges_impl = CallExchangesImpl;
+
+    let is_caller_privileged = utils::is_caller_privileged(&caller);
+
+    let result = get_exchange_rate_v2_internal(&env, &call_exchanges_impl, &request).await;
+    let recorded_result = result.clone().map(exchange_rate_from_v2);
+    record_request(
+        &caller,
+        timestamp,
+        is_caller_privileged,
+        &request_from_v2(&request),
+        &recorded_result,
+    );
+    result
+}
+
+/// This function retrieves the requested rates in a single call. Every distinct rate needed by
+/// the requests is retrieved from the exchanges at most once, and the cycles are charged for the
+/// batch as a whole. One result is returned per request, in the order of the requests.
+pub async fn get_exchange_rates(requests: GetExchangeRatesRequest) -> GetExchangeRatesResult {
+    let env = CanisterEnvironment::new();
+    let timestamp = env.time_secs();
+    let caller = env.caller();
+    let call_exchanges_impl = CallExchangesImpl;
+    let is_caller_privileged = utils::is_caller_privileged(&caller);
+
+    let result = get_exchange_rates_internal(&env, &call_exchanges_impl, &requests).await;
+    match result {
+        Ok(ref results) => {
+            for (request, result) in requests.iter().zip(results) {
+                record_request(&caller, timestamp, is_caller_privileged, request, result);
+            }
+        }
+        Err(ref error) => {
+            let result = Err(error.clone());
+            for request in &requests {
+                record_request(&caller, timestamp, is_caller_privileged, request, &result);
+            }
+        }
+    }
+    result
+}
+
+/// Records the metrics and the request log entry of a single request.
+fn record_request(
+    caller: &Principal,
+    timestamp: u64,
+    is_caller_privileged: bool,
+    request: &GetExchangeRateRequest,
+    result: &GetExchangeRateResult,
+) {
+    MetricCounter::GetExchangeRateRequest.increment();
+    if is_caller_privileged {
+        MetricCounter::GetExchangeRateRequestFromCmc.increment();
+    }
+
+    if is_caller_privileged {
+        request_log::log(&PRIVILEGED_REQUEST_LOG, caller, timestamp, request, result);
+    } else {
+        request_log::log(
+            &NONPRIVILEGED_REQUEST_LOG,
+            caller,
+            timestamp,
+            request,
+            result,
+        );
+    }
+
+    if let Err(ref error) = result {
+        MetricCounter::ErrorsReturned.increment();
+
+        if is_caller_privileged {
+            MetricCounter::ErrorsReturnedToCmc.increment();
+        }
+
+        match error {
+            ExchangeRateError::Pending => MetricCounter::PendingErrorsReturned.increment(),
+            ExchangeRateError::CryptoBaseAssetNotFound
+            | ExchangeRateError::CryptoQuoteAssetNotFound => {
+                MetricCounter::CryptoAssetRelatedErrorsReturned.increment()
+            }
+            ExchangeRateError::StablecoinRateNotFound
+            | ExchangeRateError::StablecoinRateTooFewRates
+            | ExchangeRateError::StablecoinRateZeroRate => {
+                MetricCounter::StablecoinErrorsReturned.increment()
+            }
+            ExchangeRateError::ForexInvalidTimestamp
+            | ExchangeRateError::ForexBaseAssetNotFound
+            | ExchangeRateError::ForexQuoteAssetNotFound
+            | ExchangeRateError::ForexAssetsNotFound => {
+                MetricCounter::ForexAssetRelatedErrorsReturned.increment()
+            }
+            ExchangeRateError::RateLimited => MetricCounter::RateLimitedErrors.increment(),
+            ExchangeRateError::NotEnoughCycles => MetricCounter::CycleRelatedErrors.increment(),
+            ExchangeRateError::InconsistentRatesReceived => {
+                MetricCounter::InconsistentRatesErrorsReturned.increment()
+            }
+            ExchangeRateError::AnonymousPrincipalNotAllowed | ExchangeRateError::Other(_) => {}
+        };
+    }
+}
+
+async fn get_queried_exchange_rate_internal(
+    env: &impl Environment,
+    call_exchanges_impl: &impl CallExchanges,
+    request: &GetExchangeRateRequest,
+    options: &ExchangeRateOptions,
+) -> Result<QueriedExchangeRate, ExchangeRateError> {
+    let caller = env.caller();
+    if utils::is_caller_anonymous(&caller) {
+        return Err(ExchangeRateError::AnonymousPrincipalNotAllowed);
+    }
+
+    if !utils::is_caller_privileged(&caller) && !env.has_enough_cycles() {
+        return Err(ExchangeRateError::NotEnoughCycles);
+    }
+
+    let sanitized_request = utils::sanitize_request(request);
+    // Route the call based on the provided asset types.
+    let result = route_request(env, call_exchanges_impl, &sanitized_request, options).await;
+
+    if let Err(ref error) = result {
+        let timestamp = utils::get_normalized_timestamp(env, &sanitized_request);
+        ic_cdk::println!(
+            "{} Caller: {} Timestamp: {} Request: {:?} Error: {:?}",
+            LOG_PREFIX,
+            caller,
+            timestamp,
+            sanitized_request,
+            error
+        );
+    }
+
+    if let Ok(ref rate) = result {
+        certification::certify_rate(env, &rate.clone().into());
+    }
+
+    result
+}
+
+async fn get_exchange_rate_internal(
+    env: &impl Environment,
+    call_exchanges_impl: &impl CallExchanges,
+    request: &GetExchangeRateRequest,
+) -> GetExchangeRateResult {
+    // If the result is successful, convert from a `QueriedExchangeRate` to `candid::ExchangeRate`.
+    get_queried_exchange_rate_internal(
+        env,
+        call_exchanges_impl,
+        request,
+        &ExchangeRateOptions::default(),
+    )
+    .await
+    .map(|r| r.into())
+}
+
+async fn get_exchange_rate_v2_internal(
+    env: &impl Environment,
+    call_exchanges_impl: &impl CallExchanges,
+    request: &GetExchangeRateV2Request,
+) -> GetExchangeRateV2Result {
+    let options = request.options.clone().unwrap_or_default();
+    get_queried_exchange_rate_internal(
+        env,
+        call_exchanges_impl,
+        &request_from_v2(request),
+        &options,
+    )
+    .await
+    .map(exchange_rate_v2_from_queried)
+}
+
+/// Drops the options of a [GetExchangeRateV2Request], e.g., to record it in the request log.
+fn request_from_v2(request: &GetExchangeRateV2Request) -> GetExchangeRateRequest {
+    GetExchangeRateRequest {
+        base_asset: request.base_asset.clone(),
+        quote_asset: request.quote_asset.clone(),
+        timestamp: request.timestamp,
+    }
+}
+
+/// Converts a [QueriedExchangeRate] into an [ExchangeRateV2], looking up the forex sources
+/// for the day of the forex rates in the forex rate collector.
+fn exchange_rate_v2_from_queried(rate: QueriedExchangeRate) -> ExchangeRateV2 {
+    let mut forex_sources = rate
+        .forex_timestamp
+        .and_then(|timestamp| {
+            with_forex_rate_collector(|collector| collector.get_sources(timestamp))
+        })
+        .unwrap_or_default();
+    forex_sources.sort();
+    let source_rates = rate.source_rates.clone().unwrap_or_default();
+    let into_source_rates = |rates: Vec<(String, u64)>| {
+        rates
+            .into_iter()
+            .map(|(source, rate)| SourceRate { source, rate })
+            .collect()
+    };
+    let sources = ExchangeRateSources {
+        base_asset_rates: into_source_rates(source_rates.base_asset),
+        quote_asset_rates: into_source_rates(source_rates.quote_asset),
+        stablecoin: source_rates
+            .stablecoin
+            .map(|(source, rate)| SourceRate { source, rate }),
+        forex_sources,
+    };
+    let rate = ExchangeRate::from(rate);
+    ExchangeRateV2 {
+        base_asset: rate.base_asset,
+        quote_asset: rate.quote_asset,
+        timestamp: rate.timestamp,
+        rate: rate.rate,
+        metadata: rate.metadata,
+        sources,
+    }
+}
+
+/// Drops the source rates of an [ExchangeRateV2], e.g., to record it in the request log.
+fn exchange_rate_from_v2(rate: ExchangeRateV2) -> ExchangeRate {
+    ExchangeRate {
+        base_asset: rate.base_asset,
+        quote_asset: rate.quote_asset,
+        timestamp: rate.timestamp,
+        rate: rate.rate,
+        metadata: rate.metadata,
+    }
+}
+
+async fn get_exchange_rates_internal(
+    env: &impl Environment,
+    call_exchanges_impl: &impl CallExchanges,
+    requests: &[GetExchangeRateRequest],
+) -> GetExchangeRatesResult {
+    let caller = env.caller();
+    if utils::is_caller_anonymous(&caller) {
+        return Err(ExchangeRateError::AnonymousPrincipalNotAllowed);
+    }
+
+    let is_caller_privileged = utils::is_caller_privileged(&caller);
+    if !is_caller_privileged && !env.has_enough_cycles() {
+        return Err(ExchangeRateError::NotEnoughCycles);
+    }
+
+    if requests.len() > MAX_NUM_REQUESTS_PER_BATCH {
+        return Err(errors::too_many_requests_in_batch_error(
+            requests.len(),
+            MAX_NUM_REQUESTS_PER_BATCH,
+        ));
+    }
+
+    // Determine the rates that are missing from the cache for the whole batch. Requests are
+    // admitted in order into chunks whose rates fit into the request counter budget, and a
+    // request that does not fit into the current chunk starts the next one. Only a request
+    // whose rates do not fit into the budget on their own is rate limited.
+    // Every distinct rate is attributed to the first request that needs it.
+    let mut chunks = vec![BatchRatesNeeded::default()];
+    let mut outbound_rates_needed = vec![];
+    let plans = requests
+        .iter()
+        .map(|request| {
+            let request = utils::sanitize_request(request);
+            let (rates_needed, planned_chunks) =
+                chunks.split_last_mut().expect("There is always a chunk.");
+            let mut num_rates_needed_before = rates_needed.len();
+            let mut plan = plan_batch_request(env, &request, planned_chunks, rates_needed);
+            if matches!(plan, Err(ExchangeRateError::RateLimited)) && !rates_needed.is_empty() {
+                chunks.push(BatchRatesNeeded::default());
+                let (rates_needed, planned_chunks) =
+                    chunks.split_last_mut().expect("There is always a chunk.");
+                num_rates_needed_before = 0;
+                plan = plan_batch_request(env, &request, planned_chunks, rates_needed);
+            }
+            if plan.is_ok() {
+                let rates_needed = chunks.last().expect("There is always a chunk.");
+                outbound_rates_needed.push(rates_needed.len() - num_rates_needed_before);
+            }
+            plan
+        })
+        .collect::<Vec<_>>();
+
+    if !is_caller_privileged {
+        let num_valid_requests = outbound_rates_needed.len();
+        env.charge_cycles(ChargeOption::Batch {
+            num_valid_requests,
+            num_invalid_requests: plans.len().saturating_sub(num_valid_requests),
+            outbound_rates_needed,
+        })?;
+    }
+
+    // The chunks are retrieved one after the other, so that the outbound calls of the batch
+    // never exceed the request counter budget.
+    let mut prefetched_rates = PrefetchedRates::default();
+    for rates_needed in chunks.iter().filter(|chunk| !chunk.is_empty()) {
+        with_inflight_tracking_for_keys(
+            rates_needed.inflight_keys(),
+            with_request_counter(
+                rates_needed.len(),
+                prefetch_rates(call_exchanges_impl, rates_needed, &mut prefetched_rates),
+            ),
+        )
+        .await;
+    }
+
+    // Every missing rate has been retrieved by now. Routing the requests composes the rates
+    // using the cache and the prefetched rates without issuing any further outbound calls.
+    let batch_env = BatchEnvironment { env };
+    let mut results = Vec::with_capacity(plans.len());
+    for plan in plans {
+        let result = match plan {
+            Ok(request) => route_request(
+                &batch_env,
+                &prefetched_rates,
+                &request,
+                &ExchangeRateOptions::default(),
+            )
+            .await
+            .map(|rate| rate.into()),
+            Err(error) => Err(error),
+        };
+
+        match result {
+            Ok(ref rate) => certification::certify_rate(env, rate),
+            Err(ref error) => {
+                ic_cdk::println!(
+                    "{} Caller: {} Batch request error: {:?}",
+                    LOG_PREFIX,
+                    caller,
+                    error
+                );
+            }
+        }
+
+        results.push(result);
+    }
+
+    Ok(results)
+}
+
+/// The distinct symbol-timestamp pairs that need to be retrieved from the exchanges for a batch
+/// of requests.
+#[derive(Default)]
+struct BatchRatesNeeded {
+    /// The cryptocurrency/USDT rates and the routes through which they are retrieved.
+    cryptocurrencies: BTreeMap<(String, u64), UsdtRateRoute>,
+    /// The stablecoin/USDT rates.
+    stablecoins: BTreeSet<(String, u64)>,
+}
+
+impl BatchRatesNeeded {
+    /// The number of rates needed for the batch, counting every rate retrieved on the routes
+    /// of the cryptocurrencies.
+    fn len(&self) -> usize {
+        self.cryptocurrencies
+            .values()
+            .fold(self.stablecoins.len(), |len, route| {
+                len.saturating_add(route.num_rates_needed())
+            })
+    }
+
+    /// Returns the symbol-timestamp pairs that are retrieved for the batch, including the
+    /// intermediate assets of the routes.
+    fn inflight_keys(&self) -> Vec<(String, u64)> {
+        self.cryptocurrencies
+            .iter()
+            .flat_map(|((symbol, timestamp), route)| {
+                std::iter::once((symbol.clone(), *timestamp)).chain(
+                    route
+                        .retrieved_intermediate()
+                        .map(|intermediate| (intermediate.to_string(), *timestamp)),
+                )
+            })
+            .collect()
+    }
+
+    /// Returns true if no rates are needed for the batch.
+    fn is_empty(&self) -> bool {
+        self.cryptocurrencies.is_empty() && self.stablecoins.is_empty()
+    }
+
+    /// Returns true if the cryptocurrency or stablecoin rate with the given key is needed.
+    fn contains(&self, key: &(String, u64)) -> bool {
+        self.cryptocurrencies.contains_key(key) || self.stablecoins.contains(key)
+    }
+}
+
+/// Validates a sanitized request of a batch and adds the rates it needs to `rates_needed`,
+/// unless they are already retrieved for one of the `planned_chunks`. On success, the request
+/// is returned with its normalized timestamp set so that routing it later uses the same
+/// timestamp that was planned for.
+fn plan_batch_request(
+    env: &impl Environment,
+    request: &GetExchangeRateRequest,
+    planned_chunks: &[BatchRatesNeeded],
+    rates_needed: &mut BatchRatesNeeded,
+) -> Result<GetExchangeRateRequest, ExchangeRateError> {
+    let requested_timestamp = get_normalized_timestamp(env, request);
+    let current_timestamp = env.time_secs();
+    let caller = env.caller();
+
+    let (cryptocurrencies, needs_stablecoins, forex_result) =
+        match (&request.base_asset.class, &request.quote_asset.class) {
+            (AssetClass::Cryptocurrency, AssetClass::Cryptocurrency) => (
+                vec![&request.base_asset.symbol, &request.quote_asset.symbol],
+                false,
+                Ok(()),
+            ),
+            (AssetClass::Cryptocurrency, AssetClass::FiatCurrency) => (
+                vec![&request.base_asset.symbol],
+                true,
+                with_forex_rate_store(|store| {
+                    store.get(
+                        requested_timestamp.value,
+                        current_timestamp,
+                        &request.quote_asset.symbol,
+                        USD,
+                    )
+                })
+                .map(|_| ())
+                .map_err(|err| match ExchangeRateError::from(err) {
+                    ExchangeRateError::ForexBaseAssetNotFound => {
+                        ExchangeRateError::ForexQuoteAssetNotFound
+                    }
+                    err => err,
+                }),
+            ),
+            (AssetClass::FiatCurrency, AssetClass::Cryptocurrency) => (
+                vec![&request.quote_asset.symbol],
+                true,
+                with_forex_rate_store(|store| {
+                    store.get(
+                        requested_timestamp.value,
+                        current_timestamp,
+                        &request.base_asset.symbol,
+                        USD,
+                    )
+                })
+                .map(|_| ())
+                .map_err(|err| invert_exchange_rate_error_for_fiat_crypto_pair(err.into())),
+            ),
+            (AssetClass::FiatCurrency, AssetClass::FiatCurrency) => (
+                vec![],
+                false,
+                with_forex_rate_store(|store| {
+                    store.get(
+                        requested_timestamp.value,
+                        current_timestamp,
+                        &request.base_asset.symbol,
+                        &request.quote_asset.symbol,
+                    )
+                })
+                .map_err(ExchangeRateError::from)
+                .and_then(QueriedExchangeRate::validate)
+                .map(|_| ()),
+            ),
+        };
+
+    let (missed_cryptocurrencies, missed_stablecoins) = with_cache_mut(|cache| {
+        let missed_cryptocurrencies = cryptocurrencies
+            .into_iter()
+            .filter(|symbol| {
+                get_rate_from_cache(
+                    cache,
+                    &caller,
+                    symbol,
+                    requested_timestamp.value,
+                    &ExchangeRateOptions::default(),
+                )
+                .is_none()
+            })
+            .map(|symbol| (symbol.clone(), requested_timestamp.value))
+            .collect::<Vec<_>>();
+        // The USDT/USD rate is derived from the whole basket, so every missed stablecoin rate
+        // is retrieved.
+        let missed_stablecoins = STABLECOIN_BASES
+            .iter()
+            .filter(|symbol| {
+                needs_stablecoins && cache.get(symbol, requested_timestamp.value).is_none()
+            })
+            .map(|symbol| (symbol.to_string(), requested_timestamp.value))
+            .collect::<Vec<_>>();
+        (missed_cryptocurrencies, missed_stablecoins)
+    });
+
+    // The request is validated against the rates needed by the batch so far, including its own,
+    // as they all share the same request counter budget.
+    let exchanges = get_available_exchanges();
+    let missed_cryptocurrencies = missed_cryptocurrencies
+        .into_iter()
+        .map(|(symbol, timestamp)| {
+            let route = UsdtRateRoute::select(&exchanges, &symbol, timestamp, current_timestamp);
+            ((symbol, timestamp), route)
+        })
+        .collect::<Vec<_>>();
+    // The stablecoin rates needed to convert rates quoted in USDC or USD are retrieved with the
+    // other stablecoin rates.
+    let routes = missed_cryptocurrencies
+        .iter()
+        .map(|((symbol, _), route)| (symbol.as_str(), route))
+        .collect::<Vec<_>>();
+    let mut missed_stablecoins = missed_stablecoins;
+    for symbol in get_missed_route_stablecoins(
+        &exchanges,
+        &routes,
+        requested_timestamp.value,
+        current_timestamp,
+    ) {
+        let key = (symbol.to_string(), requested_timestamp.value);
+        if !missed_stablecoins.contains(&key) {
+            missed_stablecoins.push(key);
+        }
+    }
+    // The rates that are retrieved for an earlier chunk are cached by the time this chunk is
+    // retrieved, so they are not needed again.
+    let is_planned = |key: &(String, u64)| planned_chunks.iter().any(|chunk| chunk.contains(key));
+    let missed_stablecoins = missed_stablecoins
+        .into_iter()
+        .filter(|key| !is_planned(key))
+        .collect::<Vec<_>>();
+    let missed_symbols = missed_cryptocurrencies
+        .iter()
+        .map(|((symbol, _), _)| symbol.clone())
+        .collect::<Vec<_>>();
+    let missed_cryptocurrencies = missed_cryptocurrencies
+        .into_iter()
+        .filter(|(key, _)| !is_planned(key))
+        .collect::<Vec<_>>();
+    let num_rates_needed = if missed_cryptocurrencies.is_empty() && missed_stablecoins.is_empty() {
+        0
+    } else {
+        let num_new_rates_needed = missed_cryptocurrencies
+            .iter()
+            .filter(|(key, _)| !rates_needed.cryptocurrencies.contains_key(key))
+            .fold(0usize, |num_rates_needed, (_, route)| {
+                num_rates_needed.saturating_add(route.num_rates_needed())
+            })
+            .saturating_add(
+                missed_stablecoins
+                    .iter()
+                    .filter(|key| !rates_needed.stablecoins.contains(*key))
+                    .count(),
+            );
+        rates_needed.len().saturating_add(num_new_rates_needed)
+    };
+
+    let missed_symbols = missed_symbols
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>();
+    validate_request(
+        env,
+        request,
+        num_rates_needed,
+        &missed_symbols,
+        &requested_timestamp,
+    )?;
+    forex_result?;
+
+    for (key, route) in missed_cryptocurrencies {
+        rates_needed.cryptocurrencies.entry(key).or_insert(route);
+    }
+    rates_needed.stablecoins.extend(missed_stablecoins);
+
+    Ok(GetExchangeRateRequest {
+        timestamp: Some(requested_timestamp.value),
+        ..request.clone()
+    })
+}
+
+/// Retrieves all of the rates needed by a chunk of a batch concurrently, caches the retrieved
+/// rates and adds them to `prefetched_rates`. The stablecoin rates are retrieved first so that the rates quoted in USDC or USD can be
+/// converted into USDT with the cached stablecoin rates.
+async fn prefetch_rates(
+    call_exchanges_impl: &impl CallExchanges,
+    rates_needed: &BatchRatesNeeded,
+    prefetched_rates: &mut PrefetchedRates,
+) {
+    let exchanges = get_available_exchanges();
+
+    let mut stablecoin_symbols_by_timestamp: BTreeMap<u64, Vec<&str>> = BTreeMap::new();
+    for (symbol, timestamp) in &rates_needed.stablecoins {
+        stablecoin_symbols_by_timestamp
+            .entry(*timestamp)
+            .or_default()
+            .push(symbol);
+    }
+    let stablecoin_futures = stablecoin_symbols_by_timestamp
+        .iter()
+        .map(|(timestamp, symbols)| {
+            call_exchanges_impl.get_stablecoin_rates(&exchanges, symbols, *timestamp)
+        });
+    let stablecoin_results = join_all(stablecoin_futures).await;
+    for ((timestamp, symbols), results) in stablecoin_symbols_by_timestamp
+        .iter()
+        .zip(stablecoin_results)
+    {
+        for (symbol, result) in symbols.iter().zip(results) {
+            prefetched_rates.insert_stablecoin_rate((symbol.to_string(), *timestamp), result);
+        }
+    }
+
+    let cryptocurrency_futures = rates_needed.cryptocurrencies.iter().map(|(key, route)| {
+        let (symbol, timestamp) = key;
+        let asset = Asset {
+            symbol: symbol.clone(),
+            class: AssetClass::Cryptocurrency,
+        };
+        let exchanges = &exchanges;
+        async move {
+            call_exchanges_impl
+                .get_cryptocurrency_usdt_rate(exchanges, &asset, route, *timestamp)
+                .await
+        }
+    });
+    let cryptocurrency_results = join_all(cryptocurrency_futures).await;
+    for (key, result) in rates_needed
+        .cryptocurrencies
+        .keys()
+        .zip(cryptocurrency_results)
+    {
+        prefetched_rates.insert_cryptocurrency_rate(key.clone(), result);
+    }
+}
+
+type PrefetchedRateResult = Result<QueriedExchangeRateWithFailedExchanges, CallExchangeError>;
+
+/// The rates retrieved up front for a batch of requests. It is used in place of
+/// [CallExchangesImpl] when routing the requests of a batch so that every distinct rate
+/// is only retrieved once.
+#[derive(Default)]
+struct PrefetchedRates {
+    cryptocurrency_usdt_rates: BTreeMap<(String, u64), PrefetchedRateResult>,
+    stablecoin_rates: BTreeMap<(String, u64), PrefetchedRateResult>,
+}
+
+impl PrefetchedRates {
+    fn insert_cryptocurrency_rate(&mut self, key: (String, u64), result: PrefetchedRateResult) {
+        log_and_cache_prefetched_rate(&key, &result);
+        self.cryptocurrency_usdt_rates.insert(key, result);
+    }
+
+    fn insert_stablecoin_rate(&mut self, key: (String, u64), result: PrefetchedRateResult) {
+        log_and_cache_prefetched_rate(&key, &result);
+        self.stablecoin_rates.insert(key, result);
+    }
+}
+
+fn log_and_cache_prefetched_rate(key: &(String, u64), result: &PrefetchedRateResult) {
+    match result {
+        Ok(response) => with_cache_mut(|cache| {
+            cache.insert(&response.queried_exchange_rate);
+        }),
+        Err(error) => {
+            ic_cdk::println!(
+                "{} Error while retrieving {} rates @ {}: {}",
+                LOG_PREFIX,
+                key.0,
+                key.1,
+                error
+            );
+        }
+    }
+}
+
+#[async_trait]
+impl CallExchanges for PrefetchedRates {
+    async fn get_cryptocurrency_usdt_rate(
+        &self,
+        _exchanges: &[&Exchange],
+        asset: &Asset,
+        _route: &UsdtRateRoute,
+        timestamp: u64,
+    ) -> Result<QueriedExchangeRateWithFailedExchanges, CallExchangeError> {
+        self.cryptocurrency_usdt_rates
+            .get(&(asset.symbol.clone(), timestamp))
+            .cloned()
+            .unwrap_or(Err(CallExchangeError::NoRatesFound))
+    }
+
+    async fn get_stablecoin_rates(
+        &self,
+        _exchanges: &[&Exchange],
+        symbols: &[&str],
+        timestamp: u64,
+    ) -> Vec<Result<QueriedExchangeRateWithFailedExchanges, CallExchangeError>> {
+        symbols
+            .iter()
+            .map(|symbol| {
+                self.stablecoin_rates
+                    .get(&(symbol.to_string(), timestamp))
+                    .cloned()
+                    .unwrap_or(Err(CallExchangeError::NoRatesFound))
+            })
+            .collect()
+    }
+
+    // Series are never prefetched as they are not part of a batch.
+    async fn get_cryptocurrency_usdt_rate_series(
+        &self,
+        _exchanges: &[&Exchange],
+        _asset: &Asset,
+        _route: &UsdtRateRoute,
+        _start: u64,
+        _end: u64,
+    ) -> Result<RateSeries, CallExchangeError> {
+        Err(CallExchangeError::NoRatesFound)
+    }
+
+    async fn get_stablecoin_rate_series(
+        &self,
+        _exchanges: &[&Exchange],
+        _symbol: &str,
+        _start: u64,
+        _end: u64,
+    ) -> Result<RateSeries, CallExchangeError> {
+        Err(CallExchangeError::NoRatesFound)
+    }
+}
+
+/// The environment used when routing the requests of a batch. The cycles for the whole
+/// batch are charged up front, so charging cycles for an individual request is a no-op.
+struct BatchEnvironment<'a, E: Environment> {
+    env: &'a E,
+}
+
+impl<E: Environment> Environment for BatchEnvironment<'_, E> {
+    fn caller(&self) -> Principal {
+        self.env.caller()
+    }
+
+    fn time_secs(&self) -> u64 {
+        self.env.time_secs()
+    }
+
+    fn cycles_available(&self) -> u128 {
+        self.env.cycles_available()
+    }
+
+    fn accept_cycles(&self, max_amount: u128) -> u128 {
+        self.env.accept_cycles(max_amount)
+    }
+
+    fn charge_cycles(&self, _option: ChargeOption) -> Result<(), ChargeCyclesError> {
+        Ok(())
+    }
+}
+
+/// This function returns the requested rate using only the rates that are already in the cache
+/// and the forex rate store. No outbound calls are made and no cycles are charged. If a needed
+/// rate is not available, a "not cached" error is returned.
+pub fn get_cached_exchange_rate(request: GetExchangeRateRequest) -> GetExchangeRateResult {
+    let env = CanisterEnvironment::new();
+    get_cached_exchange_rate_internal(&env, &request)
+}
+
+fn get_cached_exchange_rate_internal(
+    env: &impl Environment,
+    request: &GetExchangeRateRequest,
+) -> GetExchangeRateResult {
+    let request = utils::sanitize_request(request);
+    let requested_timestamp = utils::get_normalized_timestamp(env, &request);
+    let current_timestamp = env.time_secs();
+    if requested_timestamp > current_timestamp {
+        return Err(errors::timestamp_is_in_future_error(
+            requested_timestamp,
+            current_timestamp,
+        ));
+    }
+
+    if request.base_asset.symbol.is_empty() {
+        return Err(errors::base_asset_symbol_invalid_error());
+    }
+
+    if request.quote_asset.symbol.is_empty() {
+        return Err(errors::quote_asset_symbol_invalid_error());
+    }
+
+    let result = get_cached_rate(env, &request, requested_timestamp);
+    // If no timestamp is provided, the rates for the current minute may still be in the process of
+    // being retrieved, so the rates from the previous minute are used instead.
+    let result = match result {
+        Err(ExchangeRateError::Other(ref error))
+            if error.code == errors::RATE_NOT_CACHED_ERROR_CODE && request.timestamp.is_none() =>
+        {
+            get_cached_rate(
+                env,
+                &request,
+                requested_timestamp.saturating_sub(ONE_MINUTE_SECONDS),
+            )
+            .or(result)
+        }
+        _ => result,
+    };
+
+    result.map(|rate| rate.into())
+}
+
+/// Composes the rate for a sanitized request from the cache and the forex rate store at the
+/// given timestamp.
+fn get_cached_rate(
+    env: &impl Environment,
+    request: &GetExchangeRateRequest,
+    timestamp: u64,
+) -> Result<QueriedExchangeRate, ExchangeRateError> {
+    let caller = env.caller();
+    let current_timestamp = env.time_secs();
+    let get_crypto_usdt_rate = |symbol: &str| {
+        with_cache_mut(|cache| {
+            get_rate_from_cache(
+                cache,
+                &caller,
+                symbol,
+                timestamp,
+                &ExchangeRateOptions::default(),
+            )
+        })
+        .ok_or_else(|| errors::rate_not_cached_error(symbol, timestamp))
+    };
+    let get_crypto_usd_rate = |symbol: &str| -> Result<QueriedExchangeRate, ExchangeRateError> {
+        let crypto_usdt_rate = get_crypto_usdt_rate(symbol)?;
+        // Stablecoins without a cached rate are skipped as long as sufficiently many other
+        // stablecoin rates are cached.
+        let stablecoin_rates = with_cache_mut(|cache| {
+            STABLECOIN_BASES
+                .iter()
+                .filter_map(|symbol| cache.get(symbol, timestamp))
+                .collect::<Vec<_>>()
+        });
+        if stablecoin_rates.len() < stablecoin::MIN_NUM_STABLECOIN_RATES {
+            let missed_symbol = STABLECOIN_BASES
+                .iter()
+                .find(|symbol| {
+                    !stablecoin_rates
+                        .iter()
+                        .any(|rate| rate.base_asset.symbol == **symbol)
+                })
+                .unwrap_or(&USDC);
+            return Err(errors::rate_not_cached_error(missed_symbol, timestamp));
+        }
+        let stablecoin_rate = stablecoin::get_stablecoin_rate(&stablecoin_rates, &usd_asset())
+            .map_err(ExchangeRateError::from)?;
+        Ok(crypto_usdt_rate * stablecoin_rate)
+    };
+    let get_forex_usd_rate = |symbol: &str| {
+        with_forex_rate_store(|store| store.get(timestamp, current_timestamp, symbol, USD))
+            .map_err(ExchangeRateError::from)
+    };
+
+    match (&request.base_asset.class, &request.quote_asset.class) {
+        (AssetClass::Cryptocurrency, AssetClass::Cryptocurrency) => {
+            let base_rate = get_crypto_usdt_rate(&request.base_asset.symbol)?;
+            let quote_rate = get_crypto_usdt_rate(&request.quote_asset.symbol)?;
+            (base_rate / quote_rate).validate()
+        }
+        (AssetClass::Cryptocurrency, AssetClass::FiatCurrency) => {
+            let forex_rate =
+                get_forex_usd_rate(&request.quote_asset.symbol).map_err(|err| match err {
+                    ExchangeRateError::ForexBaseAssetNotFound => {
+                        ExchangeRateError::ForexQuoteAssetNotFound
+                    }
+                    _ => err,
+                })?;
+            let crypto_usd_base_rate = get_crypto_usd_rate(&request.base_asset.symbol)?;
+            (crypto_usd_base_rate / forex_rate).validate()
+        }
+        (AssetClass::FiatCurrency, AssetClass::Cryptocurrency) => {
+            let forex_rate = get_forex_usd_rate(&request.base_asset.symbol)?;
+            let crypto_usd_quote_rate = get_crypto_usd_rate(&request.quote_asset.symbol)?;
+            (crypto_usd_quote_rate / forex_rate)
+                .validate()
+                .map(|rate| rate.inverted())
+        }
+        (AssetClass::FiatCurrency, AssetClass::FiatCurrency) => with_forex_rate_store(|store| {
+            store.get(
+                timestamp,
+                current_timestamp,
+                &request.base_asset.symbol,
+                &request.quote_asset.symbol,
+            )
+        })
+        .map_err(ExchangeRateError::from)
+        .and_then(QueriedExchangeRate::validate),
+    }
+}
+
+/// This function estimates the cycles that [get_exchange_rate] would charge for the given request
+/// if it were called now. The estimate performs the same cache lookups and checks as the actual
+/// request, so it depends on the cached rates, the listings, and the stored forex rates.
+pub fn estimate_exchange_rate_cost(request: GetExchangeRateRequest) -> ExchangeRateCostEstimate {
+    let env = CanisterEnvironment::new();
+    estimate_exchange_rate_cost_internal(&env, &request)
+}
+
+fn estimate_exchange_rate_cost_internal(
+    env: &impl Environment,
+    request: &GetExchangeRateRequest,
+) -> ExchangeRateCostEstimate {
+    let request = utils::sanitize_request(request);
+    let options = ExchangeRateOptions::default();
+    // The number of rates that must be retrieved and whether cycles are charged at all, in which
+    // case it is also determined whether the request is valid.
+    let (num_rates_needed, maybe_is_valid_request) =
+        match (&request.base_asset.class, &request.quote_asset.class) {
+            (AssetClass::Cryptocurrency, AssetClass::Cryptocurrency) => {
+                let preparation = prepare_cryptocurrency_pair(env, &request, &options);
+                (
+                    preparation.num_rates_needed,
+                    Some(preparation.validation_result.is_ok()),
+                )
+            }
+            (AssetClass::Cryptocurrency, AssetClass::FiatCurrency)
+            | (AssetClass::FiatCurrency, AssetClass::Cryptocurrency) => {
+                let request = if request.base_asset.class == AssetClass::FiatCurrency {
+                    invert_assets_in_request(&request)
+                } else {
+                    request.clone()
+                };
+                let preparation = prepare_crypto_base_fiat_quote_pair(env, &request, &options);
+                (
+                    preparation.num_rates_needed,
+                    Some(
+                        preparation.validation_result.is_ok()
+                            && preparation.forex_rate_result.is_ok(),
+                    ),
+                )
+            }
+            // Fiat pairs that fail the validation are not charged.
+            (AssetClass::FiatCurrency, AssetClass::FiatCurrency) => (
+                0,
+                get_fiat_pair_rate(env, &request, &options)
+                    .ok()
+                    .map(|result| result.is_ok()),
+            ),
+        };
+
+    let fee = match maybe_is_valid_request {
+        Some(is_valid_request) => calculate_fee_for_caller(
+            get_charge_option(num_rates_needed, is_valid_request),
+            &env.caller(),
+        ),
+        None => 0,
+    };
+    let cycles_required = if utils::is_caller_privileged(&env.caller()) {
+        0
+    } else {
+        XRC_REQUEST_CYCLES_COST
+    };
+
+    ExchangeRateCostEstimate {
+        cycles_required,
+        fee,
+        refund: cycles_required.saturating_sub(fee),
+        num_outbound_rates_needed: num_rates_needed as u64,
+    }
+}
+
+/// This function returns the latest certified rate of the requested asset pair together with
+/// the certificate and a witness, which allow the caller to verify the rate. Only the rates of
+/// privileged asset pairs that were retrieved by earlier update calls are certified.
+pub fn get_certified_exchange_rate(
+    request: GetCertifiedExchangeRateRequest,
+) -> GetCertifiedExchangeRateResult {
+    let env = CanisterEnvironment::new();
+    get_certified_exchange_rate_internal(&env, &request)
+}
+
+fn get_certified_exchange_rate_internal(
+    env: &impl Environment,
+    request: &GetCertifiedExchangeRateRequest,
+) -> GetCertifiedExchangeRateResult {
+    let request = utils::sanitize_request(&GetExchangeRateRequest {
+        base_asset: request.base_asset.clone(),
+        quote_asset: request.quote_asset.clone(),
+        timestamp: None,
+    });
+    if request.base_asset.symbol.is_empty() {
+        return Err(errors::base_asset_symbol_invalid_error());
+    }
+
+    if request.quote_asset.symbol.is_empty() {
+        return Err(errors::quote_asset_symbol_invalid_error());
+    }
+
+    let certificate = env
+        .data_certificate()
+        .ok_or_else(errors::certificate_not_available_error)?;
+    let (rate, witness) =
+        with_certified_rates(|rates| rates.get(&request.base_asset, &request.quote_asset))
+            .ok_or_else(|| {
+                errors::rate_not_certified_error(
+                    &request.base_asset.symbol,
+                    &request.quote_asset.symbol,
+                )
+            })?;
+
+    Ok(CertifiedExchangeRate {
+        rate,
+        certificate,
+        witness: certification::encode_witness(&witness),
+    })
+}
+
+/// This function returns the assets that the canister can currently price: the cryptocurrencies
+/// listed by the available exchanges, according to their latest listings, and the fiat
+/// currencies of the latest day in the forex rate store.
+pub fn list_supported_assets() -> SupportedAssets {
+    let env = CanisterEnvironment::new();
+    list_supported_assets_internal(&env)
+}
+
+fn list_supported_assets_internal(env: &impl Environment) -> SupportedAssets {
+    let exchanges = get_available_exchanges()
+        .iter()
+        .map(|exchange| exchange.name())
+        .collect::<Vec<_>>();
+    let mut listed_bases =
+        with_listing_store(|store| store.listed_bases(&exchanges, env.time_secs()));
+    // Every exchange is queried against USDT, so the USDT rate is always known.
+    listed_bases.insert(
+        USDT.to_string(),
+        exchanges
+            .iter()
+            .map(|exchange| exchange.to_string())
+            .collect(),
+    );
+    let cryptocurrencies = listed_bases
+        .into_iter()
+        .map(|(symbol, exchanges)| SupportedCryptocurrency { symbol, exchanges })
+        .collect();
+
+    let (forex_timestamp, fiat_currencies) =
+        with_forex_rate_store(|store| match store.get_latest() {
+            Some((timestamp, rates)) => (
+                Some(timestamp),
+                rates
+                    .iter()
+                    .map(|(symbol, rate)| SupportedFiatCurrency {
+                        symbol: symbol.clone(),
+                        num_sources: rate.base_asset_num_received_rates as u64,
+                    })
+                    .collect(),
+            ),
+            None => (None, vec![]),
+        });
+
+    SupportedAssets {
+        cryptocurrencies,
+        fiat_currencies,
+        forex_timestamp,
+    }
+}
+
+/// This function returns the current configuration of the canister's tuning values.
+pub fn get_config() -> Config {
+    config::get_config()
+}
+
+/// This function replaces the configuration of the canister's tuning values.
+/// Only NNS Governance may update the configuration.
+pub fn update_config(config: Config) -> UpdateConfigResult {
+    let env = CanisterEnvironment::new();
+    update_config_internal(&env, config)
+}
+
+fn update_config_internal(env: &impl Environment, config: Config) -> UpdateConfigResult {
+    config::update_config(env.caller(), config, env.time_secs())
+}
+
+/// This function returns the entries of the request logs that match the request's filter,
+/// starting with the most recent entry. At most [request_log::MAX_REQUEST_LOG_PAGE_SIZE] entries
+/// are returned. As the entries contain the callers' principals, only NNS Governance and the
+/// controllers of the canister may read the logs.
+pub fn get_request_log(request: GetRequestLogRequest) -> GetRequestLogResult {
+    let env = CanisterEnvironment::new();
+    get_request_log_internal(&env, &request)
+}
+
+fn get_request_log_internal(
+    env: &impl Environment,
+    request: &GetRequestLogRequest,
+) -> GetRequestLogResult {
+    let caller = env.caller();
+    if caller != config::NNS_GOVERNANCE_CANISTER_ID && !env.is_controller(&caller) {
+        return Err(GetRequestLogError::NotAuthorized);
+    }
+    Ok(request_log::get_request_log(request))
+}
+
+/// Rates of a single asset keyed by their timestamp in seconds.
+type RateSeries = BTreeMap<u64, QueriedExchangeRate>;
+
+/// This function retrieves a series of rates for the requested pair. Cryptocurrency rates are
+/// provided at minute granularity and are retrieved with a single outbound call per exchange for
+/// the whole window. Fiat currency rates are provided at day granularity and are read from the
+/// forex rate store.
+pub async fn get_exchange_rate_series(
+    request: GetExchangeRateSeriesRequest,
+) -> GetExchangeRateSeriesResult {
+    let env = CanisterEnvironment::new();
+    let timestamp = env.time_secs();
+    let caller = env.caller();
+    let call_exchanges_impl = CallExchangesImpl;
+
+    let is_caller_privileged = utils::is_caller_privileged(&caller);
+
+    let result = get_exchange_rate_series_internal(&env, &call_exchanges_impl, &request).await;
+    // The series is recorded as a request for the rate at its end with the last rate of the
+    // series as the result. A series that passed validation has at least one point.
+    let recorded_result = result.clone().and_then(|rates| {
+        rates
+            .last()
+            .cloned()
+            .ok_or(ExchangeRateError::CryptoBaseAssetNotFound)
+    });
+    record_request(
+        &caller,
+        timestamp,
+        is_caller_privileged,
+        &GetExchangeRateRequest {
+            base_asset: request.base_asset.clone(),
+            quote_asset: request.quote_asset.clone(),
+            timestamp: Some(request.end),
+        },
+        &recorded_result,
+    );
+    result
+}
+
+async fn get_exchange_rate_series_internal(
+    env: &impl Environment,
+    call_exchanges_impl: &impl CallExchanges,
+    request: &GetExchangeRateSeriesRequest,
+) -> GetExchangeRateSeriesResult {
+    let caller = env.caller();
+    if utils::is_caller_anonymous(&caller) {
+        return Err(ExchangeRateError::AnonymousPrincipalNotAllowed);
+    }
+
+    let is_caller_privileged = utils::is_caller_privileged(&caller);
+    if !is_caller_privileged && !env.has_enough_cycles() {
+        return Err(ExchangeRateError::NotEnoughCycles);
+    }
+
+    // The end of the series is used as the request's timestamp so that the series is never
+    // exempted from rate limiting.
+    let sanitized_request = utils::sanitize_request(&GetExchangeRateRequest {
+        base_asset: request.base_asset.clone(),
+        quote_asset: request.quote_asset.clone(),
+        timestamp: Some(request.end),
+    });
+    let num_windows_needed = get_num_series_windows_needed(&sanitized_request, env.time_secs());
+    let validate_result =
+        validate_series_request(env, &sanitized_request, request, num_windows_needed);
+
+    if !is_caller_privileged {
+        let charge_option = if validate_result.is_ok() {
+            ChargeOption::Series(num_windows_needed)
+        } else {
+            ChargeOption::MinimumFee
+        };
+        env.charge_cycles(charge_option)?;
+    }
+
+    let timestamps = validate_result?;
+    let results = get_rate_series(
+        env,
+        call_exchanges_impl,
+        &sanitized_request,
+        &timestamps,
+        num_windows_needed,
+    )
+    .await?;
+
+    // Points for which no rate could be determined are omitted. If no rate could be determined
+    // at all, the error of the last point is returned.
+    let mut last_error = None;
+    let mut rates = vec![];
+    for result in results {
+        match result {
+            Ok(rate) => rates.push(rate.into()),
+            Err(error) => last_error = Some(error),
+        }
+    }
+
+    match last_error {
+        Some(error) if rates.is_empty() => {
+            ic_cdk::println!(
+                "{} Caller: {} Series request: {:?} Error: {:?}",
+                LOG_PREFIX,
+                caller,
+                request,
+                error
+            );
+            Err(error)
+        }
+        _ => Ok(rates),
+    }
+}
+
+/// This function retrieves the time-weighted average price (TWAP) of the requested pair over the
+/// minutes of the requested window. The rates of all minutes are retrieved like a series, the
+/// median rate of every minute is determined across the exchanges, and the average of these
+/// medians is returned.
+pub async fn get_twap_exchange_rate(request: GetTwapExchangeRateRequest) -> GetExchangeRateResult {
+    let env = CanisterEnvironment::new();
+    let timestamp = env.time_secs();
+    let caller = env.caller();
+    let call_exchanges_impl = CallExchangesImpl;
+
+    let is_caller_privileged = utils::is_caller_privileged(&caller);
+
+    let result = get_twap_exchange_rate_internal(&env, &call_exchanges_impl, &request).await;
+    record_request(
+        &caller,
+        timestamp,
+        is_caller_privileged,
+        &GetExchangeRateRequest {
+            base_asset: request.base_asset.clone(),
+            quote_asset: request.quote_asset.clone(),
+            timestamp: request.timestamp,
+        },
+        &result,
+    );
+    result
+}
+
+async fn get_twap_exchange_rate_internal(
+    env: &impl Environment,
+    call_exchanges_impl: &impl CallExchanges,
+    request: &GetTwapExchangeRateRequest,
+) -> GetExchangeRateResult {
+    let caller = env.caller();
+    if utils::is_caller_anonymous(&caller) {
+        return Err(ExchangeRateError::AnonymousPrincipalNotAllowed);
+    }
+
+    let is_caller_privileged = utils::is_caller_privileged(&caller);
+    if !is_caller_privileged && !env.has_enough_cycles() {
+        return Err(ExchangeRateError::NotEnoughCycles);
+    }
+
+    // As for series, the end of the window is used as the request's timestamp so that the
+    // request is never exempted from rate limiting.
+    let end = utils::get_normalized_timestamp(
+        env,
+        &GetExchangeRateRequest {
+            base_asset: request.base_asset.clone(),
+            quote_asset: request.quote_asset.clone(),
+            timestamp: request.timestamp,
+        },
+    );
+    let sanitized_request = utils::sanitize_request(&GetExchangeRateRequest {
+        base_asset: request.base_asset.clone(),
+        quote_asset: request.quote_asset.clone(),
+        timestamp: Some(end),
+    });
+    let num_windows_needed = get_num_series_windows_needed(&sanitized_request, env.time_secs());
+    let validate_result = validate_twap_request(&sanitized_request, request.window_minutes)
+        .and_then(|_| {
+            let series_request = GetExchangeRateSeriesRequest {
+                base_asset: sanitized_request.base_asset.clone(),
+                quote_asset: sanitized_request.quote_asset.clone(),
+                start: end.saturating_sub(
+                    (request.window_minutes - 1).saturating_mul(ONE_MINUTE_SECONDS),
+                ),
+                end,
+                step: ONE_MINUTE_SECONDS,
+            };
+            validate_series_request(env, &sanitized_request, &series_request, num_windows_needed)
+        });
+
+    if !is_caller_privileged {
+        let charge_option = if validate_result.is_ok() {
+            ChargeOption::Series(num_windows_needed)
+        } else {
+            ChargeOption::MinimumFee
+        };
+        env.charge_cycles(charge_option)?;
+    }
+
+    let timestamps = validate_result?;
+    let results = get_rate_series(
+        env,
+        call_exchanges_impl,
+        &sanitized_request,
+        &timestamps,
+        num_windows_needed,
+    )
+    .await?;
+
+    let result = get_twap_from_rate_series(results, end, request.window_minutes);
+    if let Err(ref error) = result {
+        ic_cdk::println!(
+            "{} Caller: {} TWAP request: {:?} Error: {:?}",
+            LOG_PREFIX,
+            caller,
+            request,
+            error
+        );
+    }
+    result
+}
+
+/// This function validates the window of a TWAP request. A TWAP is only available for pairs
+/// involving a cryptocurrency as fiat currency rates have day granularity.
+fn validate_twap_request(
+    sanitized_request: &GetExchangeRateRequest,
+    window_minutes: u64,
+) -> Result<(), ExchangeRateError> {
+    if window_minutes == 0 || window_minutes > MAX_TWAP_WINDOW_MINUTES {
+        return Err(errors::invalid_twap_error(&format!(
+            "the window must be between 1 and {} minutes",
+            MAX_TWAP_WINDOW_MINUTES
+        )));
+    }
+
+    if sanitized_request.base_asset.class == AssetClass::FiatCurrency
+        && sanitized_request.quote_asset.class == AssetClass::FiatCurrency
+    {
+        return Err(errors::invalid_twap_error(
+            "a cryptocurrency must be involved",
+        ));
+    }
+
+    Ok(())
+}
+
+/// Averages the median rates of the minutes of a TWAP window. Minutes for which no rate could be
+/// determined are skipped. If no rate could be determined at all, the error of the last minute
+/// is returned.
+fn get_twap_from_rate_series(
+    results: Vec<Result<QueriedExchangeRate, ExchangeRateError>>,
+    timestamp: u64,
+    window_minutes: u64,
+) -> GetExchangeRateResult {
+    let mut last_error = None;
+    let mut rates = vec![];
+    for result in results {
+        match result {
+            Ok(rate) => rates.push(rate),
+            Err(error) => last_error = Some(error),
+        }
+    }
+
+    let latest_rate = match rates.last() {
+        Some(rate) => rate.clone(),
+        None => {
+            return Err(last_error.unwrap_or(ExchangeRateError::CryptoBaseAssetNotFound));
+        }
+    };
+
+    // The rates of different minutes may use different numbers of decimals. They are scaled to
+    // the largest number of decimals before they are averaged.
+    let decimals = rates
+        .iter()
+        .map(|rate| rate.decimals.unwrap_or(DECIMALS))
+        .max()
+        .unwrap_or(DECIMALS);
+    let mut minute_rates = rates
+        .iter()
+        .map(|rate| {
+            let scale = 10u64.saturating_pow(decimals - rate.decimals.unwrap_or(DECIMALS));
+            utils::median(&rate.rates).saturating_mul(scale)
+        })
+        .collect::<Vec<_>>();
+    minute_rates.sort();
+    let sum = minute_rates
+        .iter()
+        .map(|rate| u128::from(*rate))
+        .sum::<u128>();
+    let average = sum / minute_rates.len() as u128;
+
+    let mut exchange_rate: ExchangeRate = QueriedExchangeRate {
+        timestamp,
+        rates: minute_rates,
+        decimals: Some(decimals),
+        ..latest_rate
+    }
+    .into();
+    exchange_rate.rate = average as u64;
+    exchange_rate.metadata.twap_window_minutes = Some(window_minutes);
+    Ok(exchange_rate)
+}
+
+/// Retrieves the rates of the given pair at the given timestamps, which must be aligned to the
+/// granularity of the pair. One result is returned per timestamp.
+async fn get_rate_series(
+    env: &impl Environment,
+    call_exchanges_impl: &impl CallExchanges,
+    sanitized_request: &GetExchangeRateRequest,
+    timestamps: &[u64],
+    num_windows_needed: usize,
+) -> Result<Vec<Result<QueriedExchangeRate, ExchangeRateError>>, ExchangeRateError> {
+    let start = *timestamps
+        .first()
+        .expect("A series has at least one timestamp");
+    let end = *timestamps
+        .last()
+        .expect("A series has at least one timestamp");
+    let current_timestamp = env.time_secs();
+    let exchanges = get_available_exchanges();
+    let base_asset = &sanitized_request.base_asset;
+    let quote_asset = &sanitized_request.quote_asset;
+
+    with_request_counter(num_windows_needed, async {
+        match (&base_asset.class, &quote_asset.class) {
+            (AssetClass::Cryptocurrency, AssetClass::Cryptocurrency) => {
+                let (base_series, quote_series) = join(
+                    get_cryptocurrency_usdt_series(
+                        call_exchanges_impl,
+                        &exchanges,
+                        base_asset,
+                        timestamps,
+                        current_timestamp,
+                    ),
+                    get_cryptocurrency_usdt_series(
+                        call_exchanges_impl,
+                        &exchanges,
+                        quote_asset,
+                        timestamps,
+                        current_timestamp,
+                    ),
+                )
+                .await;
+                let base_series =
+                    base_series.map_err(|_| ExchangeRateError::CryptoBaseAssetNotFound)?;
+                let quote_series =
+                    quote_series.map_err(|_| ExchangeRateError::CryptoQuoteAssetNotFound)?;
+                Ok(timestamps
+                    .iter()
+                    .map(|timestamp| {
+                        let base_rate = base_series
+                            .get(timestamp)
+                            .ok_or(ExchangeRateError::CryptoBaseAssetNotFound)?;
+                        let quote_rate = quote_series
+                            .get(timestamp)
+                            .ok_or(ExchangeRateError::CryptoQuoteAssetNotFound)?;
+                        (base_rate.clone() / quote_rate.clone()).validate()
+                    })
+                    .collect::<Vec<_>>())
+            }
+            (AssetClass::Cryptocurrency, AssetClass::FiatCurrency) => {
+                let crypto_usd_series = get_cryptocurrency_usd_series(
+                    call_exchanges_impl,
+                    &exchanges,
+                    base_asset,
+                    timestamps,
+                    start,
+                    end,
+                    current_timestamp,
+                )
+                .await?;
+                Ok(crypto_usd_series
+                    .into_iter()
+                    .zip(timestamps)
+                    .map(|(crypto_usd_rate, timestamp)| {
+                        let forex_rate = with_forex_rate_store(|store| {
+                            store.get(*timestamp, current_timestamp, &quote_asset.symbol, USD)
+                        })
+                        .map_err(|err| {
+                            match ExchangeRateError::from(err) {
+                                ExchangeRateError::ForexBaseAssetNotFound => {
+                                    ExchangeRateError::ForexQuoteAssetNotFound
+                                }
+                                err => err,
+                            }
+                        })?;
+                        (crypto_usd_rate? / forex_rate).validate()
+                    })
+                    .collect::<Vec<_>>())
+            }
+            (AssetClass::FiatCurrency, AssetClass::Cryptocurrency) => {
+                let crypto_usd_series = get_cryptocurrency_usd_series(
+                    call_exchanges_impl,
+                    &exchanges,
+                    quote_asset,
+                    timestamps,
+                    start,
+                    end,
+                    current_timestamp,
+                )
+                .await
+                .map_err(invert_exchange_rate_error_for_fiat_crypto_pair)?;
+                Ok(crypto_usd_series
+                    .into_iter()
+                    .zip(timestamps)
+                    .map(|(crypto_usd_rate, timestamp)| {
+                        let forex_rate = with_forex_rate_store(|store| {
+                            store.get(*timestamp, current_timestamp, &base_asset.symbol, USD)
+                        })
+                        .map_err(ExchangeRateError::from)?;
+                        (crypto_usd_rate? / forex_rate)
+                            .validate()
+                            .map(|rate| rate.inverted())
+                    })
+                    .map(|result| result.map_err(invert_exchange_rate_error_for_fiat_crypto_pair))
+                    .collect::<Vec<_>>())
+            }
+            (AssetClass::FiatCurrency, AssetClass::FiatCurrency) => Ok(timestamps
+                .iter()
+                .map(|timestamp| {
+                    with_forex_rate_store(|store| {
+                        store.get(
+                            *timestamp,
+                            current_timestamp,
+                            &base_asset.symbol,
+                            &quote_asset.symbol,
+                        )
+                    })
+                    .map_err(ExchangeRateError::from)
+                    .and_then(QueriedExchangeRate::validate)
+                })
+                .collect::<Vec<_>>()),
+        }
+    })
+    .await
+}
+
+/// Returns the number of windows of rates that need to be retrieved from the exchanges for
+/// a series of the given pair: the windows on the route of every cryptocurrency other than USDT,
+/// the stablecoin windows of cryptocurrency/fiat pairs, and the stablecoin rates that are not
+/// cached but needed to convert windows quoted in other USD-like quote assets into USDT.
+fn get_num_series_windows_needed(request: &GetExchangeRateRequest, now_secs: u64) -> usize {
+    let exchanges = get_available_exchanges();
+    let end = request.timestamp.unwrap_or(now_secs);
+    let routes = [&request.base_asset, &request.quote_asset]
+        .into_iter()
+        .filter(|asset| asset.class == AssetClass::Cryptocurrency && asset.symbol != USDT)
+        .map(|asset| {
+            let route = UsdtRateRoute::select(&exchanges, &asset.symbol, end, now_secs);
+            (asset.symbol.as_str(), route)
+        })
+        .collect::<Vec<_>>();
+    let num_cryptocurrency_windows = routes
+        .iter()
+        .map(|(_, route)| route.num_series_windows_needed())
+        .fold(0usize, usize::saturating_add);
+    let quotes = routes
+        .iter()
+        .flat_map(|(symbol, route)| route.series_converted_quotes(&exchanges, symbol, now_secs))
+        .collect();
+    let num_windows_needed = num_cryptocurrency_windows
+        .saturating_add(get_missed_conversion_stablecoins(&quotes, end).len());
+    match (&request.base_asset.class, &request.quote_asset.class) {
+        (AssetClass::Cryptocurrency, AssetClass::Cryptocurrency)
+        | (AssetClass::FiatCurrency, AssetClass::FiatCurrency) => num_windows_needed,
+        _ => num_windows_needed.saturating_add(STABLECOIN_BASES.len()),
+    }
+}
+
+/// This function validates a series request and returns the timestamps of the points in the
+/// series. The timestamps are aligned to the granularity of the pair: minutes if a
+/// cryptocurrency is involved, days otherwise.
+fn validate_series_request(
+    env: &impl Environment,
+    sanitized_request: &GetExchangeRateRequest,
+    request: &GetExchangeRateSeriesRequest,
+    num_windows_needed: usize,
+) -> Result<Vec<u64>, ExchangeRateError> {
+    if sanitized_request.base_asset.symbol.is_empty() {
+        return Err(errors::base_asset_symbol_invalid_error());
+    }
+
+    if sanitized_request.quote_asset.symbol.is_empty() {
+        return Err(errors::quote_asset_symbol_invalid_error());
+    }
+
+    if request.step == 0 {
+        return Err(errors::invalid_series_error(
+            "the step must be greater than zero",
+        ));
+    }
+
+    if request.start > request.end {
+        return Err(errors::invalid_series_error(
+            "the start must not be after the end",
+        ));
+    }
+
+    let current_timestamp = env.time_secs();
+    if request.end > current_timestamp {
+        return Err(errors::timestamp_is_in_future_error(
+            request.end,
+            current_timestamp,
+        ));
     }
 
-    let result = get_exchange_rate_internal(&env, &call_exchanges_impl, &request).await;
+    let granularity = match (
+        &sanitized_request.base_asset.class,
+        &sanitized_request.quote_asset.class,
+    ) {
+        (AssetClass::FiatCurrency, AssetClass::FiatCurrency) => ONE_DAY_SECONDS,
+        _ => ONE_MINUTE_SECONDS,
+    };
 
-    if is_caller_privileged {
-        request_log::log(
-            &PRIVILEGED_REQUEST_LOG,
-            &caller,
-            timestamp,
-            &request,
-            &result,
-        );
-    } else {
-        request_log::log(
-            &NONPRIVILEGED_REQUEST_LOG,
-            &caller,
-            timestamp,
-            &request,
-            &result,
-        );
+    if !request.step.is_multiple_of(granularity) {
+        return Err(errors::invalid_series_error(&format!(
+            "the step must be a multiple of {} seconds",
+            granularity
+        )));
     }
 
-    if let Err(ref error) = result {
-        MetricCounter::ErrorsReturned.increment();
-
-        if is_caller_privileged {
-            MetricCounter::ErrorsReturnedToCmc.increment();
-        }
+    let start = (request.start / granularity) * granularity;
+    let end = (request.end / granularity) * granularity;
+    if (end - start) / granularity > MAX_SERIES_SPAN {
+        return Err(errors::invalid_series_error(&format!(
+            "the series may span at most {} intervals of {} seconds",
+            MAX_SERIES_SPAN, granularity
+        )));
+    }
 
-        match error {
-            ExchangeRateError::Pending => MetricCounter::PendingErrorsReturned.increment(),
-            ExchangeRateError::CryptoBaseAssetNotFound
-            | ExchangeRateError::CryptoQuoteAssetNotFound => {
-                MetricCounter::CryptoAssetRelatedErrorsReturned.increment()
-            }
-            ExchangeRateError::StablecoinRateNotFound
-            | ExchangeRateError::StablecoinRateTooFewRates
-            | ExchangeRateError::StablecoinRateZeroRate => {
-                MetricCounter::StablecoinErrorsReturned.increment()
-            }
-            ExchangeRateError::ForexInvalidTimestamp
-            | ExchangeRateError::ForexBaseAssetNotFound
-            | ExchangeRateError::ForexQuoteAssetNotFound
-            | ExchangeRateError::ForexAssetsNotFound => {
-                MetricCounter::ForexAssetRelatedErrorsReturned.increment()
-            }
-            ExchangeRateError::RateLimited => MetricCounter::RateLimitedErrors.increment(),
-            ExchangeRateError::NotEnoughCycles => MetricCounter::CycleRelatedErrors.increment(),
-            ExchangeRateError::InconsistentRatesReceived => {
-                MetricCounter::InconsistentRatesErrorsReturned.increment()
-            }
-            ExchangeRateError::AnonymousPrincipalNotAllowed | ExchangeRateError::Other(_) => {}
-        };
+    // Assets that no exchange lists are rejected before the request counts against the rate
+    // limit.
+    let exchanges = get_available_exchanges();
+    [
+        &sanitized_request.base_asset,
+        &sanitized_request.quote_asset,
+    ]
+    .into_iter()
+    .filter(|asset| asset.class == AssetClass::Cryptocurrency)
+    .try_for_each(|asset| check_asset_is_listed(&exchanges, &asset.symbol, current_timestamp))?;
+
+    if !utils::is_caller_privileged(&env.caller())
+        && is_rate_limited(num_windows_needed, sanitized_request)
+    {
+        return Err(ExchangeRateError::RateLimited);
     }
 
-    result
+    let mut timestamps = vec![];
+    let mut timestamp = start;
+    while timestamp <= end {
+        timestamps.push(timestamp);
+        timestamp = timestamp.saturating_add(request.step);
+    }
+    Ok(timestamps)
 }
 
-async fn get_exchange_rate_internal(
-    env: &impl Environment,
+/// Retrieves the cryptocurrency/USDT rates of the given asset at the given timestamps on the
+/// route chosen as for a single rate, see [UsdtRateRoute::select]. The USDT/USDT rate is taken
+/// from the cache without any outbound calls.
+async fn get_cryptocurrency_usdt_series(
     call_exchanges_impl: &impl CallExchanges,
-    request: &GetExchangeRateRequest,
-) -> GetExchangeRateResult {
-    let caller = env.caller();
-    if utils::is_caller_anonymous(&caller) {
-        return Err(ExchangeRateError::AnonymousPrincipalNotAllowed);
+    exchanges: &[&'static Exchange],
+    asset: &Asset,
+    timestamps: &[u64],
+    now_secs: u64,
+) -> Result<RateSeries, CallExchangeError> {
+    if asset.symbol == USDT {
+        return Ok(with_cache_mut(|cache| {
+            timestamps
+                .iter()
+                .filter_map(|timestamp| cache.get(USDT, *timestamp).map(|rate| (*timestamp, rate)))
+                .collect()
+        }));
     }
 
-    if !utils::is_caller_privileged(&caller) && !env.has_enough_cycles() {
-        return Err(ExchangeRateError::NotEnoughCycles);
+    let start = *timestamps.first().unwrap_or(&0);
+    let end = *timestamps.last().unwrap_or(&0);
+    let route = UsdtRateRoute::select(exchanges, &asset.symbol, end, now_secs);
+    call_exchanges_impl
+        .get_cryptocurrency_usdt_rate_series(exchanges, asset, &route, start, end)
+        .await
+}
+
+/// Retrieves the cryptocurrency/USD rates of the given asset at the given timestamps by
+/// combining the cryptocurrency/USDT rates with the stablecoin rates of the same minute.
+async fn get_cryptocurrency_usd_series(
+    call_exchanges_impl: &impl CallExchanges,
+    exchanges: &[&'static Exchange],
+    asset: &Asset,
+    timestamps: &[u64],
+    start: u64,
+    end: u64,
+    now_secs: u64,
+) -> Result<Vec<Result<QueriedExchangeRate, ExchangeRateError>>, ExchangeRateError> {
+    let (crypto_usdt_series, stablecoin_series) = join(
+        get_cryptocurrency_usdt_series(call_exchanges_impl, exchanges, asset, timestamps, now_secs),
+        join_all(STABLECOIN_BASES.iter().map(|symbol| {
+            call_exchanges_impl.get_stablecoin_rate_series(exchanges, symbol, start, end)
+        })),
+    )
+    .await;
+    let crypto_usdt_series =
+        crypto_usdt_series.map_err(|_| ExchangeRateError::CryptoBaseAssetNotFound)?;
+    let stablecoin_series = STABLECOIN_BASES
+        .iter()
+        .zip(stablecoin_series)
+        .filter_map(|(symbol, result)| match result {
+            Ok(series) => Some(series),
+            Err(error) => {
+                ic_cdk::println!(
+                    "{} Error while retrieving {} rates @ {}-{}: {}",
+                    LOG_PREFIX,
+                    symbol,
+                    start,
+                    end,
+                    error
+                );
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+
+    Ok(timestamps
+        .iter()
+        .map(|timestamp| {
+            let crypto_usdt_rate = crypto_usdt_series
+                .get(timestamp)
+                .ok_or(ExchangeRateError::CryptoBaseAssetNotFound)?;
+            let stablecoin_rates = stablecoin_series
+                .iter()
+                .filter_map(|series| series.get(timestamp).cloned())
+                .collect::<Vec<_>>();
+            let stablecoin_rate = stablecoin::get_stablecoin_rate(&stablecoin_rates, &usd_asset())
+                .map_err(ExchangeRateError::from)?;
+            Ok(crypto_usdt_rate.clone() * stablecoin_rate)
+        })
+        .collect())
+}
+
+/// Groups the candles retrieved from the exchanges by minute and aggregates the rates of every
+/// minute from `start` to `end` into a [QueriedExchangeRate] of `asset` against `quote_asset`.
+fn rate_series_from_candles(
+    asset: Asset,
+    quote_asset: Asset,
+    results: Vec<Result<Vec<(u64, u64)>, CallExchangeError>>,
+    num_queried_sources: usize,
+    start: u64,
+    end: u64,
+) -> Result<RateSeries, CallExchangeError> {
+    let mut rates_by_timestamp: BTreeMap<u64, Vec<u64>> = BTreeMap::new();
+    for result in results {
+        match result {
+            Ok(candles) => {
+                for (timestamp, rate) in candles {
+                    if (start..=end).contains(&timestamp)
+                        && timestamp.is_multiple_of(ONE_MINUTE_SECONDS)
+                    {
+                        rates_by_timestamp.entry(timestamp).or_default().push(rate);
+                    }
+                }
+            }
+            Err(error) => {
+                ic_cdk::println!(
+                    "{} Window: {}-{}, Asset: {:?}, Error: {}",
+                    LOG_PREFIX,
+                    start,
+                    end,
+                    asset,
+                    error,
+                );
+            }
+        }
     }
 
-    let sanitized_request = utils::sanitize_request(request);
-    // Route the call based on the provided asset types.
-    let result = route_request(env, call_exchanges_impl, &sanitized_request).await;
+    let series = rates_by_timestamp
+        .into_iter()
+        .map(|(timestamp, rates)| {
+            let rate = QueriedExchangeRate::new(
+                asset.clone(),
+                quote_asset.clone(),
+                timestamp,
+                &rates,
+                num_queried_sources,
+                rates.len(),
+                None,
+                RateAggregation::Median,
+            );
+            (timestamp, rate)
+        })
+        .filter(|(_, rate)| !rate.rates.is_empty())
+        .collect::<RateSeries>();
 
-    if let Err(ref error) = result {
-        let timestamp = utils::get_normalized_timestamp(env, &sanitized_request);
-        ic_cdk::println!(
-            "{} Caller: {} Timestamp: {} Request: {:?} Error: {:?}",
-            LOG_PREFIX,
-            caller,
-            timestamp,
-            sanitized_request,
-            error
-        );
+    if series.is_empty() {
+        return Err(CallExchangeError::NoRatesFound);
     }
 
-    // If the result is successful, convert from a `QueriedExchangeRate` to `candid::ExchangeRate`.
-    result.map(|r| r.into())
+    Ok(series)
 }
 
 /// This function is used for handling fiat-crypto pairs.
@@ -342,13 +2897,14 @@ async fn route_request(
     env: &impl Environment,
     call_exchanges_impl: &impl CallExchanges,
     request: &GetExchangeRateRequest,
+    options: &ExchangeRateOptions,
 ) -> Result<QueriedExchangeRate, ExchangeRateError> {
     match (&request.base_asset.class, &request.quote_asset.class) {
         (AssetClass::Cryptocurrency, AssetClass::Cryptocurrency) => {
-            handle_cryptocurrency_pair(env, call_exchanges_impl, request).await
+            handle_cryptocurrency_pair(env, call_exchanges_impl, request, options).await
         }
         (AssetClass::Cryptocurrency, AssetClass::FiatCurrency) => {
-            handle_crypto_base_fiat_quote_pair(env, call_exchanges_impl, request)
+            handle_crypto_base_fiat_quote_pair(env, call_exchanges_impl, request, options)
                 .await
                 .map_err(|err| match err {
                     ExchangeRateError::ForexBaseAssetNotFound => {
@@ -359,12 +2915,14 @@ async fn route_request(
         }
         (AssetClass::FiatCurrency, AssetClass::Cryptocurrency) => {
             let inverted_request = invert_assets_in_request(request);
-            handle_crypto_base_fiat_quote_pair(env, call_exchanges_impl, &inverted_request)
+            handle_crypto_base_fiat_quote_pair(env, call_exchanges_impl, &inverted_request, options)
                 .await
                 .map(|rate| rate.inverted())
                 .map_err(invert_exchange_rate_error_for_fiat_crypto_pair)
         }
-        (AssetClass::FiatCurrency, AssetClass::FiatCurrency) => handle_fiat_pair(env, request),
+        (AssetClass::FiatCurrency, AssetClass::FiatCurrency) => {
+            handle_fiat_pair(env, request, options)
+        }
     }
 }
 
@@ -422,21 +2980,27 @@ fn get_normalized_timestamp(
 }
 
 /// This function extracts the exchange rate for the given symbol and timestamp from the cache.
+/// A cached rate is ignored if the caller does not accept cached data or if the rate has fewer
+/// source rates than required.
 fn get_rate_from_cache(
     cache: &mut ExchangeRateCache,
     caller: &Principal,
     symbol: &str,
     timestamp: u64,
+    options: &ExchangeRateOptions,
 ) -> Option<QueriedExchangeRate> {
+    if symbol != USDT && !options.accept_cached_data.unwrap_or(true) {
+        return None;
+    }
+
     let maybe_rate = cache.get(symbol, timestamp);
-    if !utils::is_caller_privileged(caller) {
-        return maybe_rate;
+    let mut min_num_rates = options.min_num_received_rates.unwrap_or(0) as usize;
+    if utils::is_caller_privileged(caller) {
+        min_num_rates = min_num_rates.max(MIN_NUM_RATES_FOR_PRIVILEGED_CANISTERS);
     }
     match maybe_rate {
         Some(ref rate) => {
-            if rate.base_asset.symbol == USDT
-                || rate.rates.len() >= MIN_NUM_RATES_FOR_PRIVILEGED_CANISTERS
-            {
+            if rate.base_asset.symbol == USDT || rate.rates.len() >= min_num_rates {
                 maybe_rate
             } else {
                 None
@@ -466,6 +3030,8 @@ enum ValidateRequestError {
     BaseAssetInvalidSymbol,
     /// The quote asset symbol provided contains invalid characters.
     QuoteAssetInvalidSymbol,
+    /// No exchange lists the cryptocurrency with the given symbol.
+    AssetNotListed(String),
 }
 
 impl From<ValidateRequestError> for ExchangeRateError {
@@ -484,16 +3050,20 @@ impl From<ValidateRequestError> for ExchangeRateError {
             ValidateRequestError::QuoteAssetInvalidSymbol => {
                 errors::quote_asset_symbol_invalid_error()
             }
+            ValidateRequestError::AssetNotListed(symbol) => errors::asset_not_listed_error(&symbol),
         }
     }
 }
 
 /// This function validates a santized request with the given number of rates needed
-/// in order to complete the request.
+/// in order to complete the request. The cryptocurrencies whose rates must be retrieved are
+/// checked against the exchange listings before the rate limit and inflight checks, so that a
+/// request for an unlisted asset fails the same way regardless of the canister's load.
 fn validate_request(
     env: &impl Environment,
     request: &GetExchangeRateRequest,
     num_rates_needed: usize,
+    missed_cryptocurrencies: &[&str],
     requested_timestamp: &NormalizedTimestamp,
 ) -> Result<(), ValidateRequestError> {
     let current_timestamp = env.time_secs();
@@ -512,6 +3082,14 @@ fn validate_request(
         return Err(ValidateRequestError::QuoteAssetInvalidSymbol);
     }
 
+    let exchanges = get_available_exchanges();
+    if let Some(symbol) = missed_cryptocurrencies
+        .iter()
+        .find(|symbol| !is_asset_listed(&exchanges, symbol, current_timestamp))
+    {
+        return Err(ValidateRequestError::AssetNotListed(symbol.to_string()));
+    }
+
     if utils::is_caller_privileged(&env.caller()) {
         return Ok(());
     }
@@ -546,23 +3124,47 @@ fn charge_cycles(
         return Ok(());
     }
 
-    let charge_cycles_option = if is_valid_request {
+    env.charge_cycles(get_charge_option(num_rates_needed, is_valid_request))
+}
+
+/// This function returns the [ChargeOption] for a request with the given number of needed
+/// outbound calls.
+fn get_charge_option(num_rates_needed: usize, is_valid_request: bool) -> ChargeOption {
+    if is_valid_request {
         ChargeOption::OutboundRatesNeeded(num_rates_needed)
     } else {
         ChargeOption::MinimumFee
-    };
+    }
+}
 
-    env.charge_cycles(charge_cycles_option)
+/// The state of a cryptocurrency pair request before any outbound calls are made: the rates
+/// found in the cache, the stablecoin symbols that were missed, the number of rates that must be
+/// retrieved, and the outcome of the checks that determine whether the request is valid.
+struct CryptocurrencyPairPreparation {
+    requested_timestamp: NormalizedTimestamp,
+    exchanges: Vec<&'static Exchange>,
+    maybe_base_rate: Option<QueriedExchangeRate>,
+    maybe_quote_rate: Option<QueriedExchangeRate>,
+    /// The route of the base asset's rate if it is not cached.
+    maybe_base_route: Option<UsdtRateRoute>,
+    /// The route of the quote asset's rate if it is not cached.
+    maybe_quote_route: Option<UsdtRateRoute>,
+    /// The stablecoins whose rates are needed to convert rates quoted in USDC or USD into USDT.
+    missed_stablecoin_symbols: Vec<&'static str>,
+    num_rates_needed: usize,
+    /// The first error found when validating the request, if any.
+    validation_result: Result<(), ExchangeRateError>,
 }
 
-async fn handle_cryptocurrency_pair(
+/// This function looks up the cached rates of a cryptocurrency pair and validates the request.
+/// It is shared by [handle_cryptocurrency_pair] and [estimate_exchange_rate_cost].
+fn prepare_cryptocurrency_pair(
     env: &impl Environment,
-    call_exchanges_impl: &impl CallExchanges,
     request: &GetExchangeRateRequest,
-) -> Result<QueriedExchangeRate, ExchangeRateError> {
+    options: &ExchangeRateOptions,
+) -> CryptocurrencyPairPreparation {
     let requested_timestamp = get_normalized_timestamp(env, request);
-    let mut failed_exchanges = vec![];
-    let mut exchanges = get_available_exchanges();
+    let exchanges = get_available_exchanges();
     let caller = env.caller();
     let (maybe_base_rate, maybe_quote_rate) = with_cache_mut(|cache| {
         (
@@ -571,32 +3173,100 @@ async fn handle_cryptocurrency_pair(
                 &caller,
                 &request.base_asset.symbol,
                 requested_timestamp.value,
+                options,
             ),
             get_rate_from_cache(
                 cache,
                 &caller,
                 &request.quote_asset.symbol,
                 requested_timestamp.value,
+                options,
             ),
         )
     });
 
-    let mut num_rates_needed: usize = 0;
-    if maybe_base_rate.is_none() {
-        num_rates_needed = num_rates_needed.saturating_add(1);
-    }
-
-    if maybe_quote_rate.is_none() {
-        num_rates_needed = num_rates_needed.saturating_add(1);
-    }
+    let now_secs = env.time_secs();
+    let select_route = |symbol: &str| {
+        UsdtRateRoute::select(&exchanges, symbol, requested_timestamp.value, now_secs)
+    };
+    let maybe_base_route = maybe_base_rate
+        .is_none()
+        .then(|| select_route(&request.base_asset.symbol));
+    let maybe_quote_route = maybe_quote_rate
+        .is_none()
+        .then(|| select_route(&request.quote_asset.symbol));
+    let routes = [
+        (request.base_asset.symbol.as_str(), &maybe_base_route),
+        (request.quote_asset.symbol.as_str(), &maybe_quote_route),
+    ]
+    .into_iter()
+    .filter_map(|(symbol, maybe_route)| maybe_route.as_ref().map(|route| (symbol, route)))
+    .collect::<Vec<_>>();
+    // The stablecoin rates needed to convert rates quoted in USDC or USD are retrieved as well.
+    let missed_stablecoin_symbols =
+        get_missed_route_stablecoins(&exchanges, &routes, requested_timestamp.value, now_secs)
+            .into_iter()
+            .collect::<Vec<_>>();
+    let num_rates_needed = routes.iter().fold(
+        missed_stablecoin_symbols.len(),
+        |num_rates_needed, (_, route)| num_rates_needed.saturating_add(route.num_rates_needed()),
+    );
 
-    let validate_request_result =
-        validate_request(env, request, num_rates_needed, &requested_timestamp);
-    charge_cycles(env, num_rates_needed, validate_request_result.is_ok())?;
+    // Assets that no exchange lists are rejected before any outbound calls are made.
+    let missed_cryptocurrencies = [
+        (
+            maybe_base_rate.is_none(),
+            request.base_asset.symbol.as_str(),
+        ),
+        (
+            maybe_quote_rate.is_none(),
+            request.quote_asset.symbol.as_str(),
+        ),
+    ]
+    .into_iter()
+    .filter_map(|(is_rate_needed, symbol)| is_rate_needed.then_some(symbol))
+    .collect::<Vec<_>>();
+    let validate_request_result = validate_request(
+        env,
+        request,
+        num_rates_needed,
+        &missed_cryptocurrencies,
+        &requested_timestamp,
+    );
 
-    if let Err(error) = validate_request_result {
-        return Err(error.into());
+    CryptocurrencyPairPreparation {
+        requested_timestamp,
+        exchanges,
+        maybe_base_rate,
+        maybe_quote_rate,
+        maybe_base_route,
+        maybe_quote_route,
+        missed_stablecoin_symbols,
+        num_rates_needed,
+        validation_result: validate_request_result.map_err(ExchangeRateError::from),
     }
+}
+
+async fn handle_cryptocurrency_pair(
+    env: &impl Environment,
+    call_exchanges_impl: &impl CallExchanges,
+    request: &GetExchangeRateRequest,
+    options: &ExchangeRateOptions,
+) -> Result<QueriedExchangeRate, ExchangeRateError> {
+    let CryptocurrencyPairPreparation {
+        requested_timestamp,
+        mut exchanges,
+        maybe_base_rate,
+        maybe_quote_rate,
+        maybe_base_route,
+        maybe_quote_route,
+        missed_stablecoin_symbols,
+        num_rates_needed,
+        validation_result,
+    } = prepare_cryptocurrency_pair(env, request, options);
+    let mut failed_exchanges = vec![];
+    charge_cycles(env, num_rates_needed, validation_result.is_ok())?;
+    validation_result?;
 
     // We have all of the necessary rates in the cache return the result.
     // Validate the composed result here too, mirroring the fresh path, so a
@@ -604,23 +3274,50 @@ async fn handle_cryptocurrency_pair(
     if num_rates_needed == 0 {
         return (maybe_base_rate.expect("rate should exist")
             / maybe_quote_rate.expect("rate should exist"))
-        .validate();
+        .validate_with_options(options);
     }
 
+    let mut inflight_symbols = vec![
+        request.base_asset.symbol.clone(),
+        request.quote_asset.symbol.clone(),
+    ];
+    inflight_symbols.extend(
+        maybe_base_route
+            .iter()
+            .chain(maybe_quote_route.iter())
+            .filter_map(UsdtRateRoute::retrieved_intermediate)
+            .map(str::to_string),
+    );
     with_inflight_tracking(
-        vec![
-            request.base_asset.symbol.clone(),
-            request.quote_asset.symbol.clone(),
-        ],
+        inflight_symbols,
         requested_timestamp.value,
         with_request_counter(num_rates_needed, async move {
+            // Retrieve and cache the stablecoin rates that convert rates quoted in USDC or USD,
+            // so that they are found in the cache when the cryptocurrency rates are converted.
+            let stablecoin_results = call_exchanges_impl
+                .get_stablecoin_rates(
+                    &exchanges,
+                    &missed_stablecoin_symbols,
+                    requested_timestamp.value,
+                )
+                .await;
+            for response in stablecoin_results.into_iter().flatten() {
+                with_cache_mut(|cache| {
+                    cache.insert(&response.queried_exchange_rate);
+                });
+            }
+
             let base_rate = match maybe_base_rate {
                 Some(base_rate) => base_rate,
                 None => {
+                    let route = maybe_base_route
+                        .as_ref()
+                        .expect("A route should be selected for a missed rate.");
                     let response = call_exchanges_impl
                         .get_cryptocurrency_usdt_rate(
                             &exchanges,
                             &request.base_asset,
+                            route,
                             requested_timestamp.value,
                         )
                         .await
@@ -637,10 +3334,14 @@ async fn handle_cryptocurrency_pair(
             let quote_rate = match maybe_quote_rate {
                 Some(quote_rate) => quote_rate,
                 None => {
+                    let route = maybe_quote_route
+                        .as_ref()
+                        .expect("A route should be selected for a missed rate.");
                     let response = call_exchanges_impl
                         .get_cryptocurrency_usdt_rate(
                             &exchanges,
                             &request.quote_asset,
+                            route,
                             requested_timestamp.value,
                         )
                         .await
@@ -652,71 +3353,145 @@ async fn handle_cryptocurrency_pair(
                     response.queried_exchange_rate
                 }
             };
-            (base_rate / quote_rate).validate()
+            (base_rate / quote_rate).validate_with_options(options)
         }),
     )
     .await
 }
 
-async fn handle_crypto_base_fiat_quote_pair(
+/// The state of a crypto/fiat pair request before any outbound calls are made: the rates found
+/// in the cache, the stablecoin symbols that were missed, the number of rates that must be
+/// retrieved, and the outcome of the checks that determine whether the request is valid.
+struct CryptoBaseFiatQuotePairPreparation {
+    requested_timestamp: NormalizedTimestamp,
+    exchanges: Vec<&'static Exchange>,
+    maybe_crypto_base_rate: Option<QueriedExchangeRate>,
+    /// The route of the cryptocurrency's rate if it is not cached.
+    maybe_crypto_base_route: Option<UsdtRateRoute>,
+    stablecoin_rates: Vec<QueriedExchangeRate>,
+    missed_stablecoin_symbols: Vec<&'static str>,
+    num_rates_needed: usize,
+    /// The first error found when validating the request, if any.
+    validation_result: Result<(), ExchangeRateError>,
+    forex_rate_result: Result<QueriedExchangeRate, ExchangeRateError>,
+}
+
+/// This function looks up the cached rates of a crypto/fiat pair, validates the request and
+/// retrieves the forex rate. It is shared by [handle_crypto_base_fiat_quote_pair] and
+/// [estimate_exchange_rate_cost].
+fn prepare_crypto_base_fiat_quote_pair(
     env: &impl Environment,
-    call_exchanges_impl: &impl CallExchanges,
     request: &GetExchangeRateRequest,
-) -> Result<QueriedExchangeRate, ExchangeRateError> {
+    options: &ExchangeRateOptions,
+) -> CryptoBaseFiatQuotePairPreparation {
     let requested_timestamp = get_normalized_timestamp(env, request);
     let caller = env.caller();
-    let mut failed_exchanges_list = vec![];
-    let mut exchanges = get_available_exchanges();
+    let exchanges = get_available_exchanges();
     let maybe_crypto_base_rate = with_cache_mut(|cache| {
         get_rate_from_cache(
             cache,
             &caller,
             &request.base_asset.symbol,
             requested_timestamp.value,
+            options,
         )
     });
-    let mut num_rates_needed: usize = 0;
-    if maybe_crypto_base_rate.is_none() {
-        num_rates_needed = num_rates_needed.saturating_add(1);
-    }
+    let now_secs = env.time_secs();
+    let maybe_crypto_base_route = maybe_crypto_base_rate.is_none().then(|| {
+        UsdtRateRoute::select(
+            &exchanges,
+            &request.base_asset.symbol,
+            requested_timestamp.value,
+            now_secs,
+        )
+    });
+    let mut num_rates_needed = maybe_crypto_base_route
+        .as_ref()
+        .map_or(0, UsdtRateRoute::num_rates_needed);
 
     // Get stablecoin rates from cache, collecting symbols that were missed.
     let mut missed_stablecoin_symbols = vec![];
     let mut stablecoin_rates = vec![];
+    let accept_cached_data = options.accept_cached_data.unwrap_or(true);
     with_cache_mut(|cache| {
         for symbol in STABLECOIN_BASES {
-            match cache.get(symbol, requested_timestamp.value) {
+            match cache
+                .get(symbol, requested_timestamp.value)
+                .filter(|_| accept_cached_data)
+            {
                 Some(rate) => stablecoin_rates.push(rate.clone()),
                 None => missed_stablecoin_symbols.push(*symbol),
             }
         }
     });
-
+    // The USDT/USD rate is derived from the whole basket, so every missed stablecoin rate is
+    // retrieved. The stablecoin rates needed to convert rates quoted in USDC or USD are among
+    // them.
     num_rates_needed = num_rates_needed.saturating_add(missed_stablecoin_symbols.len());
 
-    let validate_request_result =
-        validate_request(env, request, num_rates_needed, &requested_timestamp);
+    // Assets that no exchange lists are rejected before any outbound calls are made.
+    let missed_cryptocurrencies = if maybe_crypto_base_rate.is_none() {
+        vec![request.base_asset.symbol.as_str()]
+    } else {
+        vec![]
+    };
+    let validate_request_result = validate_request(
+        env,
+        request,
+        num_rates_needed,
+        &missed_cryptocurrencies,
+        &requested_timestamp,
+    );
 
     let forex_rate_result = with_forex_rate_store(|store| {
         let current_timestamp_secs = env.time_secs();
-        store.get(
+        store.get_with_max_staleness(
             requested_timestamp.value,
             current_timestamp_secs,
             &request.quote_asset.symbol,
             USD,
+            options.max_forex_staleness_days,
         )
     })
     .map_err(ExchangeRateError::from);
 
+    CryptoBaseFiatQuotePairPreparation {
+        requested_timestamp,
+        exchanges,
+        maybe_crypto_base_rate,
+        maybe_crypto_base_route,
+        stablecoin_rates,
+        missed_stablecoin_symbols,
+        num_rates_needed,
+        validation_result: validate_request_result.map_err(ExchangeRateError::from),
+        forex_rate_result,
+    }
+}
+
+async fn handle_crypto_base_fiat_quote_pair(
+    env: &impl Environment,
+    call_exchanges_impl: &impl CallExchanges,
+    request: &GetExchangeRateRequest,
+    options: &ExchangeRateOptions,
+) -> Result<QueriedExchangeRate, ExchangeRateError> {
+    let CryptoBaseFiatQuotePairPreparation {
+        requested_timestamp,
+        mut exchanges,
+        maybe_crypto_base_rate,
+        maybe_crypto_base_route,
+        mut stablecoin_rates,
+        missed_stablecoin_symbols,
+        num_rates_needed,
+        validation_result,
+        forex_rate_result,
+    } = prepare_crypto_base_fiat_quote_pair(env, request, options);
+    let mut failed_exchanges_list = vec![];
     charge_cycles(
         env,
         num_rates_needed,
-        validate_request_result.is_ok() && forex_rate_result.is_ok(),
+        validation_result.is_ok() && forex_rate_result.is_ok(),
     )?;
-
-    if let Err(error) = validate_request_result {
-        return Err(error.into());
-    }
+    validation_result?;
     let forex_rate = forex_rate_result?;
 
     // We have all of the necessary rates in the cache; return the result.
@@ -728,11 +3503,18 @@ async fn handle_crypto_base_fiat_quote_pair(
         let stablecoin_rate = stablecoin::get_stablecoin_rate(&stablecoin_rates, &usd_asset())
             .map_err(ExchangeRateError::from)?;
         let crypto_usd_base_rate = crypto_base_rate * stablecoin_rate;
-        return (crypto_usd_base_rate / forex_rate).validate();
+        return (crypto_usd_base_rate / forex_rate).validate_with_options(options);
     }
 
+    let mut inflight_symbols = vec![request.base_asset.symbol.clone()];
+    inflight_symbols.extend(
+        maybe_crypto_base_route
+            .as_ref()
+            .and_then(UsdtRateRoute::retrieved_intermediate)
+            .map(str::to_string),
+    );
     with_inflight_tracking(
-        vec![request.base_asset.symbol.clone()],
+        inflight_symbols,
         requested_timestamp.value,
         with_request_counter(num_rates_needed, async move {
             // Retrieve the missing stablecoin results. For each rate retrieved, cache it and add it to the
@@ -774,10 +3556,14 @@ async fn handle_crypto_base_fiat_quote_pair(
             let crypto_base_rate = match maybe_crypto_base_rate {
                 Some(base_rate) => base_rate,
                 None => {
+                    let route = maybe_crypto_base_route
+                        .as_ref()
+                        .expect("A route should be selected for a missed rate.");
                     let response = call_exchanges_impl
                         .get_cryptocurrency_usdt_rate(
                             &exchanges,
                             &request.base_asset,
+                            route,
                             requested_timestamp.value,
                         )
                         .await
@@ -793,39 +3579,130 @@ async fn handle_crypto_base_fiat_quote_pair(
             let stablecoin_rate = stablecoin::get_stablecoin_rate(&stablecoin_rates, &usd_asset())
                 .map_err(ExchangeRateError::from)?;
             let crypto_usd_base_rate = crypto_base_rate * stablecoin_rate;
-            (crypto_usd_base_rate / forex_rate).validate()
+            (crypto_usd_base_rate / forex_rate).validate_with_options(options)
         }),
     )
     .await
 }
 
+/// Retrieves the USD rates of the given cryptocurrencies at the given timestamp, which are
+/// needed to compute the baskets with cryptocurrency components. Cryptocurrencies whose rates
+/// cannot be retrieved are omitted.
+pub(crate) async fn get_cryptocurrency_usd_rates(
+    symbols: &[String],
+    timestamp: u64,
+) -> BTreeMap<String, Vec<u64>> {
+    get_cryptocurrency_usd_rates_internal(
+        &CallExchangesImpl,
+        symbols,
+        timestamp,
+        utils::time_secs(),
+    )
+    .await
+}
+
+async fn get_cryptocurrency_usd_rates_internal(
+    call_exchanges_impl: &impl CallExchanges,
+    symbols: &[String],
+    timestamp: u64,
+    now_secs: u64,
+) -> BTreeMap<String, Vec<u64>> {
+    let exchanges = get_available_exchanges();
+    let stablecoin_rates = call_exchanges_impl
+        .get_stablecoin_rates(&exchanges, STABLECOIN_BASES, timestamp)
+        .await
+        .into_iter()
+        .filter_map(|result| result.ok())
+        .map(|result| result.queried_exchange_rate)
+        .collect::<Vec<_>>();
+    let stablecoin_rate = match stablecoin::get_stablecoin_rate(&stablecoin_rates, &usd_asset()) {
+        Ok(rate) => rate,
+        Err(error) => {
+            ic_cdk::println!(
+                "{} Error while computing the {} rate @ {}: {}",
+                LOG_PREFIX,
+                USDT,
+                timestamp,
+                error
+            );
+            return BTreeMap::new();
+        }
+    };
+
+    let results = join_all(symbols.iter().map(|symbol| {
+        let asset = Asset {
+            symbol: symbol.clone(),
+            class: AssetClass::Cryptocurrency,
+        };
+        let route = UsdtRateRoute::select(&exchanges, symbol, timestamp, now_secs);
+        let exchanges = &exchanges;
+        async move {
+            call_exchanges_impl
+                .get_cryptocurrency_usdt_rate(exchanges, &asset, &route, timestamp)
+                .await
+        }
+    }))
+    .await;
+
+    symbols
+        .iter()
+        .zip(results)
+        .filter_map(|(symbol, result)| match result {
+            Ok(response) => {
+                let usd_rate = response.queried_exchange_rate * stablecoin_rate.clone();
+                Some((symbol.clone(), usd_rate.rates))
+            }
+            Err(error) => {
+                ic_cdk::println!(
+                    "{} Error while retrieving {} rates @ {}: {}",
+                    LOG_PREFIX,
+                    symbol,
+                    timestamp,
+                    error
+                );
+                None
+            }
+        })
+        .collect()
+}
+
 fn handle_fiat_pair(
     env: &impl Environment,
     request: &GetExchangeRateRequest,
+    options: &ExchangeRateOptions,
 ) -> Result<QueriedExchangeRate, ExchangeRateError> {
-    let requested_timestamp =
-        NormalizedTimestamp::requested_or_current(utils::get_normalized_timestamp(env, request));
-    let current_timestamp = env.time_secs();
-    let validate_result = validate_request(env, request, 0, &requested_timestamp);
-    let result = match validate_result {
-        Ok(_) => with_forex_rate_store(|store| {
-            store.get(
-                requested_timestamp.value,
-                current_timestamp,
-                &request.base_asset.symbol,
-                &request.quote_asset.symbol,
-            )
-        })
-        .map_err(|err| err.into())
-        .and_then(QueriedExchangeRate::validate),
-        Err(error) => return Err(error.into()),
-    };
+    let result = get_fiat_pair_rate(env, request, options).map_err(ExchangeRateError::from)?;
 
     charge_cycles(env, 0, result.is_ok())?;
 
     result
 }
 
+/// This function validates a fiat pair request and looks up the rate in the forex rate store.
+/// The outer error is returned if the request fails the validation, in which case no cycles
+/// are charged. It is shared by [handle_fiat_pair] and [estimate_exchange_rate_cost].
+fn get_fiat_pair_rate(
+    env: &impl Environment,
+    request: &GetExchangeRateRequest,
+    options: &ExchangeRateOptions,
+) -> Result<Result<QueriedExchangeRate, ExchangeRateError>, ValidateRequestError> {
+    let requested_timestamp =
+        NormalizedTimestamp::requested_or_current(utils::get_normalized_timestamp(env, request));
+    let current_timestamp = env.time_secs();
+    validate_request(env, request, 0, &[], &requested_timestamp)?;
+    Ok(with_forex_rate_store(|store| {
+        store.get_with_max_staleness(
+            requested_timestamp.value,
+            current_timestamp,
+            &request.base_asset.symbol,
+            &request.quote_asset.symbol,
+            options.max_forex_staleness_days,
+        )
+    })
+    .map_err(|err| err.into())
+    .and_then(|rate| rate.validate_with_options(options)))
+}
+
 async fn get_stablecoin_rate(
     exchanges: &[&Exchange],
     symbol: &str,
@@ -903,6 +3780,7 @@ async fn get_stablecoin_rate(
             exchanges.len(),
             rates.len(),
             None,
+            RateAggregation::Median,
         ),
         failed_exchanges,
     })
@@ -959,7 +3837,8 @@ async fn call_exchange_for_stablecoin(
         },
         ExchangeCallKind::Stablecoin,
     )
-    .await;
+    .await
+    .map(|extracted_rate| extracted_rate.rate);
 
     // Some stablecoin pairs are the inverse (USDT/USDS) of what is desired (USDS/USDT).
     // To ensure USDT is the quote asset, the rate is inverted.
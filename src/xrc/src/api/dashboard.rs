@@ -4,13 +4,17 @@ use ic_xrc_types::{Asset, GetExchangeRateResult};
 use serde_bytes::ByteBuf;
 
 use crate::{
+    config,
     forex::{ForexRatesCollector, FOREX_SOURCES},
     request_log::RequestLog,
     types::HttpResponse,
-    EXCHANGES, FOREX_RATE_COLLECTOR, NONPRIVILEGED_REQUEST_LOG, PRIVILEGED_CANISTER_IDS,
-    PRIVILEGED_REQUEST_LOG,
+    EXCHANGES, FOREX_RATE_COLLECTOR, NONPRIVILEGED_REQUEST_LOG, PRIVILEGED_REQUEST_LOG,
 };
 
+/// The maximum number of the most recent entries of each request log that are rendered. Older
+/// entries can be retrieved with `get_request_log`.
+const MAX_RENDERED_REQUEST_LOG_ENTRIES: usize = 100;
+
 const DOCUMENT: &str = r#"
 <!DOCTYPE html>
 <html lang=\"en\">
@@ -165,9 +169,14 @@ fn render_metadata() -> String {
         )
         .replace(
             "[PRIVILEGED_CANISTER_IDS]",
-            &PRIVILEGED_CANISTER_IDS
+            &config::privileged_principals()
                 .iter()
-                .map(|id| format!("<tr><td><code>{}</code></td></tr>", id))
+                .map(|privileged_principal| {
+                    format!(
+                        "<tr><td><code>{}</code></td><td>{:?}</td></tr>",
+                        privileged_principal.principal, privileged_principal.tier
+                    )
+                })
                 .collect::<Vec<_>>()
                 .join(""),
         )
@@ -203,7 +212,7 @@ fn render_request_log_entries(log: &'static LocalKey<RefCell<RequestLog>>) -> St
     let rows = log.with(|cell| {
         cell.borrow()
             .entries()
-            .iter()
+            .take(MAX_RENDERED_REQUEST_LOG_ENTRIES)
             .map(|entry| {
                 format!(
                     "<tr><td class='ts-class'>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{:?}</td>{}</tr>",
@@ -0,0 +1,612 @@
+use std::{cell::RefCell, thread::LocalKey};
+
+use chrono::NaiveDate;
+use ic_xrc_types::{
+    Asset, AssetClass, ExchangeRate, ExchangeRateError, GetExchangeRateRequest,
+    GetExchangeRateResult,
+};
+use serde_bytes::ByteBuf;
+use serde_json::{json, Map, Value};
+
+use crate::{
+    environment::{CanisterEnvironment, Environment},
+    errors,
+    request_log::{RequestLog, MAX_REQUEST_LOG_PAGE_SIZE},
+    types::HttpResponse,
+    with_cache, with_forex_rate_store, with_listing_store, NONPRIVILEGED_REQUEST_LOG,
+    PRIVILEGED_REQUEST_LOG,
+};
+
+/// The number of seconds for which clients may cache the latest rate of an asset pair.
+const LATEST_RATE_MAX_AGE_SECS: u64 = 60;
+
+/// The number of seconds for which clients may cache the rate of an asset pair at a given minute.
+const HISTORICAL_RATE_MAX_AGE_SECS: u64 = 3_600;
+
+/// Returns the status of the canister, see [super::get_status].
+pub fn get_status_json() -> HttpResponse {
+    match serde_json::to_value(super::get_status()) {
+        Ok(status) => json_response(200, status),
+        Err(err) => error_response(500, format!("Failed to encode status: {}", err)),
+    }
+}
+
+/// Returns the forex rates of the day given by the `date` query parameter in the format
+/// `YYYY-MM-DD`. If no date is provided, the rates of the most recent day are returned.
+pub fn get_forex_json(query: Option<&str>) -> HttpResponse {
+    let date = query.and_then(|query| get_query_parameter(query, "date"));
+    let timestamp = match date.as_deref().map(parse_date).transpose() {
+        Ok(timestamp) => timestamp,
+        Err(err) => return error_response(400, err),
+    };
+
+    let forex_rates = with_forex_rate_store(|store| {
+        let (timestamp, rates) = match timestamp {
+            Some(timestamp) => (timestamp, store.get_day(timestamp)?),
+            None => store.get_latest()?,
+        };
+        let rates = rates
+            .iter()
+            .map(|(symbol, rate)| {
+                let rate = ExchangeRate::from(rate.clone());
+                (
+                    symbol.clone(),
+                    json!({
+                        "rate": u64_json(rate.rate),
+                        "decimals": rate.metadata.decimals,
+                        "num_sources": rate.metadata.base_asset_num_received_rates,
+                    }),
+                )
+            })
+            .collect::<Map<_, _>>();
+        Some(json!({ "timestamp": timestamp, "rates": rates }))
+    });
+
+    match forex_rates {
+        Some(forex_rates) => json_response(200, forex_rates),
+        None => error_response(404, "No forex rates found.".to_string()),
+    }
+}
+
+/// Returns the last accepted listing of every exchange.
+pub fn get_listings_json() -> HttpResponse {
+    let listings = with_listing_store(|store| {
+        store
+            .iter()
+            .map(|(exchange, listing)| {
+                (
+                    exchange.clone(),
+                    json!({
+                        "bases": listing.bases,
+                        "total_markets": listing.total_markets,
+                        "last_success_secs": listing.last_success_secs,
+                    }),
+                )
+            })
+            .collect::<Map<_, _>>()
+    });
+    json_response(200, Value::Object(listings))
+}
+
+/// Returns the keys of the cached cryptocurrency rates, starting with the most recently used rate.
+pub fn get_cache_json() -> HttpResponse {
+    let keys = with_cache(|cache| {
+        cache
+            .keys()
+            .into_iter()
+            .map(|(symbol, timestamp)| json!({ "symbol": symbol, "timestamp": timestamp }))
+            .collect::<Vec<_>>()
+    });
+    json_response(200, Value::Array(keys))
+}
+
+/// Returns the most recent entries of the privileged and the non-privileged request logs. The
+/// callers are left out as the endpoint is public. Older entries and the callers can be
+/// retrieved with `get_request_log`.
+pub fn get_requests_json() -> HttpResponse {
+    json_response(
+        200,
+        json!({
+            "privileged": request_log_entries(&PRIVILEGED_REQUEST_LOG),
+            "nonprivileged": request_log_entries(&NONPRIVILEGED_REQUEST_LOG),
+        }),
+    )
+}
+
+/// Returns the rate for the asset pair given by the `base`, `base_class`, `quote` and
+/// `quote_class` query parameters in the shape of an [ExchangeRate]. The optional `timestamp`
+/// query parameter selects the minute of the rate. Like [super::get_cached_exchange_rate], only
+/// the cached rates and the forex rate store are used.
+pub fn get_rate_json(query: Option<&str>) -> HttpResponse {
+    get_rate_json_internal(&CanisterEnvironment::new(), query)
+}
+
+fn get_rate_json_internal(env: &impl Environment, query: Option<&str>) -> HttpResponse {
+    let request = match parse_rate_request(query.unwrap_or_default()) {
+        Ok(request) => request,
+        Err(err) => return with_cache_control(error_response(400, err), "no-store"),
+    };
+
+    match super::get_cached_exchange_rate_internal(env, &request) {
+        Ok(rate) => match serde_json::to_value(&rate) {
+            Ok(mut rate_json) => {
+                rate_json["rate"] = u64_json(rate.rate);
+                rate_json["metadata"]["standard_deviation"] =
+                    u64_json(rate.metadata.standard_deviation);
+                // The rate for a given minute does not change once it is cached, whereas the
+                // latest rate moves on every minute.
+                let max_age_secs = if request.timestamp.is_some() {
+                    HISTORICAL_RATE_MAX_AGE_SECS
+                } else {
+                    LATEST_RATE_MAX_AGE_SECS
+                };
+                with_cache_control(
+                    json_response(200, rate_json),
+                    &format!("public, max-age={}", max_age_secs),
+                )
+            }
+            Err(err) => error_response(500, format!("Failed to encode rate: {}", err)),
+        },
+        Err(error) => {
+            let status_code = match error {
+                ExchangeRateError::Other(ref other)
+                    if other.code == errors::RATE_NOT_CACHED_ERROR_CODE =>
+                {
+                    404
+                }
+                ExchangeRateError::CryptoBaseAssetNotFound
+                | ExchangeRateError::CryptoQuoteAssetNotFound
+                | ExchangeRateError::ForexInvalidTimestamp
+                | ExchangeRateError::ForexBaseAssetNotFound
+                | ExchangeRateError::ForexQuoteAssetNotFound
+                | ExchangeRateError::ForexAssetsNotFound => 404,
+                _ => 400,
+            };
+            with_cache_control(
+                error_response(status_code, format!("{:?}", error)),
+                "no-store",
+            )
+        }
+    }
+}
+
+fn parse_rate_request(query: &str) -> Result<GetExchangeRateRequest, String> {
+    let get_asset = |symbol_parameter: &str, class_parameter: &str| {
+        let symbol = get_query_parameter(query, symbol_parameter)
+            .ok_or_else(|| format!("Missing query parameter: {}.", symbol_parameter))?;
+        let class = match get_query_parameter(query, class_parameter).as_deref() {
+            Some("crypto") => AssetClass::Cryptocurrency,
+            Some("fiat") => AssetClass::FiatCurrency,
+            Some(class) => {
+                return Err(format!(
+                    "Invalid {}: {}. Expected crypto or fiat.",
+                    class_parameter, class
+                ))
+            }
+            None => return Err(format!("Missing query parameter: {}.", class_parameter)),
+        };
+        Ok(Asset { symbol, class })
+    };
+
+    let base_asset = get_asset("base", "base_class")?;
+    let quote_asset = get_asset("quote", "quote_class")?;
+    let timestamp = get_query_parameter(query, "timestamp")
+        .map(|timestamp| {
+            timestamp
+                .parse::<u64>()
+                .map_err(|_| format!("Invalid timestamp: {}.", timestamp))
+        })
+        .transpose()?;
+
+    Ok(GetExchangeRateRequest {
+        base_asset,
+        quote_asset,
+        timestamp,
+    })
+}
+
+/// Returns the percent-decoded value of the given parameter in the query string, if any.
+fn get_query_parameter(query: &str, name: &str) -> Option<String> {
+    form_urlencoded::parse(query.as_bytes())
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.into_owned())
+}
+
+fn request_log_entries(log: &'static LocalKey<RefCell<RequestLog>>) -> Vec<Value> {
+    log.with(|cell| {
+        cell.borrow()
+            .entries()
+            .take(MAX_REQUEST_LOG_PAGE_SIZE)
+            .map(|entry| {
+                json!({
+                    "timestamp": entry.timestamp,
+                    "base_asset": asset_json(&entry.request.base_asset),
+                    "quote_asset": asset_json(&entry.request.quote_asset),
+                    "requested_timestamp": entry.request.timestamp,
+                    "result": result_json(&entry.result),
+                })
+            })
+            .collect()
+    })
+}
+
+fn asset_json(asset: &Asset) -> Value {
+    json!({ "symbol": asset.symbol, "class": format!("{:?}", asset.class) })
+}
+
+fn result_json(result: &GetExchangeRateResult) -> Value {
+    match result {
+        Ok(rate) => json!({
+            "Ok": {
+                "rate": u64_json(rate.rate),
+                "timestamp": rate.timestamp,
+                "decimals": rate.metadata.decimals,
+                "base_asset_num_received_rates": rate.metadata.base_asset_num_received_rates,
+                "quote_asset_num_received_rates": rate.metadata.quote_asset_num_received_rates,
+                "standard_deviation": u64_json(rate.metadata.standard_deviation),
+                "forex_timestamp": rate.metadata.forex_timestamp,
+            }
+        }),
+        Err(error) => json!({ "Err": format!("{:?}", error) }),
+    }
+}
+
+/// Encodes a rate or a standard deviation as a decimal string, as it may exceed 2^53, beyond
+/// which JSON numbers lose precision in JavaScript.
+fn u64_json(value: u64) -> Value {
+    Value::String(value.to_string())
+}
+
+/// Parses a date in the format `YYYY-MM-DD` into the timestamp of the beginning of the day.
+fn parse_date(date: &str) -> Result<u64, String> {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .and_then(|datetime| u64::try_from(datetime.and_utc().timestamp()).ok())
+        .ok_or_else(|| format!("Invalid date: {}. Expected format: YYYY-MM-DD.", date))
+}
+
+fn error_response(status_code: u16, error: String) -> HttpResponse {
+    json_response(status_code, json!({ "error": error }))
+}
+
+fn with_cache_control(mut response: HttpResponse, cache_control: &str) -> HttpResponse {
+    response
+        .headers
+        .push(("Cache-Control".to_string(), cache_control.to_string()));
+    response
+}
+
+fn json_response(status_code: u16, value: Value) -> HttpResponse {
+    let body = value.to_string().into_bytes();
+    HttpResponse {
+        status_code,
+        headers: vec![
+            ("Content-Type".to_string(), "application/json".to_string()),
+            ("Content-Length".to_string(), body.len().to_string()),
+        ],
+        body: ByteBuf::from(body),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use maplit::btreemap;
+
+    use std::collections::{BTreeMap, BTreeSet};
+
+    use candid::Principal;
+
+    use super::*;
+    use crate::{
+        api::test::icp_asset, environment::test::TestEnvironment, exchanges::ListedPairs,
+        request_log, usdt_asset, with_cache_mut, with_forex_rate_store_mut, with_listing_store_mut,
+        QueriedExchangeRate, RateAggregation, RATE_UNIT,
+    };
+
+    fn body_json(response: &HttpResponse) -> Value {
+        serde_json::from_slice(response.body.as_slice()).expect("body should be valid JSON")
+    }
+
+    fn assert_json_headers(response: &HttpResponse) {
+        assert!(response
+            .headers
+            .contains(&("Content-Type".to_string(), "application/json".to_string())));
+        assert!(response.headers.contains(&(
+            "Content-Length".to_string(),
+            response.body.len().to_string()
+        )));
+    }
+
+    /// The function verifies that the forex rates of the requested day are returned.
+    #[test]
+    fn get_forex_json_returns_rates_for_date() {
+        let eur = QueriedExchangeRate::new(
+            Asset {
+                symbol: "EUR".to_string(),
+                class: ic_xrc_types::AssetClass::FiatCurrency,
+            },
+            Asset {
+                symbol: "USD".to_string(),
+                class: ic_xrc_types::AssetClass::FiatCurrency,
+            },
+            1_704_153_600,
+            &[1_100_000_000, 1_100_000_000],
+            2,
+            2,
+            None,
+            RateAggregation::Median,
+        );
+        with_forex_rate_store_mut(|store| {
+            store.put(1_704_153_600, btreemap! { "EUR".to_string() => eur })
+        });
+
+        let response = get_forex_json(Some("date=2024-01-02"));
+        assert_eq!(response.status_code, 200);
+        assert_json_headers(&response);
+        assert_eq!(
+            body_json(&response),
+            json!({
+                "timestamp": 1_704_153_600,
+                "rates": {
+                    "EUR": { "rate": "1100000000", "decimals": 9, "num_sources": 2 }
+                }
+            })
+        );
+        assert_eq!(
+            body_json(&get_forex_json(None)),
+            body_json(&get_forex_json(Some("date=2024-01-02")))
+        );
+
+        assert_eq!(get_forex_json(Some("date=2024-01-03")).status_code, 404);
+    }
+
+    /// The function verifies that a malformed date is rejected.
+    #[test]
+    fn get_forex_json_rejects_invalid_date() {
+        let response = get_forex_json(Some("date=02.01.2024"));
+        assert_eq!(response.status_code, 400);
+        assert_json_headers(&response);
+        assert_eq!(
+            body_json(&response),
+            json!({ "error": "Invalid date: 02.01.2024. Expected format: YYYY-MM-DD." })
+        );
+    }
+
+    /// The function verifies that the keys of the cached rates are returned.
+    #[test]
+    fn get_cache_json_returns_cache_keys() {
+        with_cache_mut(|cache| {
+            cache.insert(&QueriedExchangeRate::new(
+                icp_asset(),
+                usdt_asset(),
+                1_704_153_600,
+                &[4 * RATE_UNIT],
+                1,
+                1,
+                None,
+                RateAggregation::Median,
+            ))
+        });
+
+        let response = get_cache_json();
+        assert_eq!(response.status_code, 200);
+        assert_json_headers(&response);
+        assert_eq!(
+            body_json(&response),
+            json!([{ "symbol": "ICP", "timestamp": 1_704_153_600 }])
+        );
+    }
+
+    /// The function verifies that a cached rate is returned in the shape of an [ExchangeRate]
+    /// together with a cache-control header.
+    #[test]
+    fn get_rate_json_returns_cached_rate() {
+        with_cache_mut(|cache| {
+            cache.insert(&QueriedExchangeRate::new(
+                icp_asset(),
+                usdt_asset(),
+                1_704_153_600,
+                &[4 * RATE_UNIT, 4 * RATE_UNIT],
+                2,
+                2,
+                None,
+                RateAggregation::Median,
+            ))
+        });
+        let env = TestEnvironment::builder()
+            .with_time_secs(1_704_153_660)
+            .build();
+
+        let response = get_rate_json_internal(
+            &env,
+            Some("base=ICP&base_class=crypto&quote=USDT&quote_class=crypto&timestamp=1704153600"),
+        );
+        assert_eq!(response.status_code, 200);
+        assert!(response.headers.contains(&(
+            "Cache-Control".to_string(),
+            "public, max-age=3600".to_string()
+        )));
+        let rate = body_json(&response);
+        assert_eq!(
+            rate["base_asset"],
+            json!({ "symbol": "ICP", "class": "Cryptocurrency" })
+        );
+        assert_eq!(rate["timestamp"], json!(1_704_153_600));
+        assert_eq!(rate["rate"], json!("4000000000"));
+        assert_eq!(rate["metadata"]["standard_deviation"], json!("0"));
+        assert_eq!(rate["metadata"]["base_asset_num_received_rates"], json!(2));
+
+        // Without a timestamp, the rate of the previous minute is used.
+        let response = get_rate_json_internal(
+            &env,
+            Some("base=ICP&base_class=crypto&quote=USDT&quote_class=crypto"),
+        );
+        assert_eq!(response.status_code, 200);
+        assert!(response.headers.contains(&(
+            "Cache-Control".to_string(),
+            "public, max-age=60".to_string()
+        )));
+    }
+
+    /// The function verifies that rates beyond 2^53 are encoded as strings without losing
+    /// precision and that the query parameters are percent-decoded.
+    #[test]
+    fn get_rate_json_encodes_large_rates_as_strings_and_decodes_parameters() {
+        let large_rate = (1 << 53) + 1;
+        with_cache_mut(|cache| {
+            cache.insert(&QueriedExchangeRate::new(
+                icp_asset(),
+                usdt_asset(),
+                1_704_153_600,
+                &[large_rate, large_rate],
+                2,
+                2,
+                None,
+                RateAggregation::Median,
+            ))
+        });
+        let env = TestEnvironment::builder()
+            .with_time_secs(1_704_153_660)
+            .build();
+
+        let response = get_rate_json_internal(
+            &env,
+            Some(
+                "base=%49CP&base_class=crypt%6F&quote=USDT&quote_class=crypto&timestamp=1704153600",
+            ),
+        );
+        assert_eq!(response.status_code, 200);
+        let rate = body_json(&response);
+        assert_eq!(rate["rate"], json!("9007199254740993"));
+        assert_eq!(rate["base_asset"]["symbol"], json!("ICP"));
+    }
+
+    /// The function verifies that malformed requests and rates that are not cached are rejected.
+    #[test]
+    fn get_rate_json_rejects_invalid_and_uncached_requests() {
+        let env = TestEnvironment::builder()
+            .with_time_secs(1_704_153_660)
+            .build();
+
+        let response = get_rate_json_internal(&env, Some("base=ICP&base_class=crypto"));
+        assert_eq!(response.status_code, 400);
+        assert_eq!(
+            body_json(&response),
+            json!({ "error": "Missing query parameter: quote." })
+        );
+
+        let response = get_rate_json_internal(
+            &env,
+            Some("base=ICP&base_class=token&quote=USD&quote_class=fiat"),
+        );
+        assert_eq!(response.status_code, 400);
+
+        let response = get_rate_json_internal(
+            &env,
+            Some("base=ICP&base_class=crypto&quote=USDT&quote_class=crypto&timestamp=1704153600"),
+        );
+        assert_eq!(response.status_code, 404);
+        assert!(response
+            .headers
+            .contains(&("Cache-Control".to_string(), "no-store".to_string())));
+    }
+
+    /// The function verifies that the status is returned as JSON.
+    #[test]
+    fn get_status_json_returns_status() {
+        let response = get_status_json();
+        assert_eq!(response.status_code, 200);
+        assert_json_headers(&response);
+        let status = body_json(&response);
+        assert_eq!(status["cache_size"], json!(0));
+        assert!(status["exchanges"]
+            .as_array()
+            .expect("exchanges should be an array")
+            .iter()
+            .any(|exchange| exchange["name"] == "Coinbase"));
+    }
+
+    /// The function verifies that the last accepted listing of every exchange is returned.
+    #[test]
+    fn get_listings_json_returns_listings() {
+        assert_eq!(body_json(&get_listings_json()), json!({}));
+
+        with_listing_store_mut(|store| {
+            store.accept(
+                "Coinbase",
+                ListedPairs {
+                    bases: BTreeSet::from(["BTC".to_string(), "ICP".to_string()]),
+                    total_markets: 300,
+                    pairs: BTreeMap::new(),
+                },
+                1_704_153_600,
+            );
+        });
+
+        let response = get_listings_json();
+        assert_eq!(response.status_code, 200);
+        assert_json_headers(&response);
+        assert_eq!(
+            body_json(&response),
+            json!({
+                "Coinbase": {
+                    "bases": ["BTC", "ICP"],
+                    "total_markets": 300,
+                    "last_success_secs": 1_704_153_600,
+                }
+            })
+        );
+    }
+
+    /// The function verifies that the entries of both request logs are returned without the
+    /// callers, starting with the most recent entry.
+    #[test]
+    fn get_requests_json_returns_request_logs() {
+        let request = GetExchangeRateRequest {
+            base_asset: icp_asset(),
+            quote_asset: usdt_asset(),
+            timestamp: Some(1_704_153_600),
+        };
+        let caller = Principal::from_text("q4eej-kyaaa-aaaaa-aaaha-cai").unwrap();
+        request_log::log(
+            &NONPRIVILEGED_REQUEST_LOG,
+            &caller,
+            10,
+            &request,
+            &Err(ExchangeRateError::RateLimited),
+        );
+        request_log::log(
+            &NONPRIVILEGED_REQUEST_LOG,
+            &caller,
+            20,
+            &request,
+            &Err(ExchangeRateError::Pending),
+        );
+
+        let response = get_requests_json();
+        assert_eq!(response.status_code, 200);
+        assert_json_headers(&response);
+        let logs = body_json(&response);
+        assert_eq!(logs["privileged"], json!([]));
+        assert_eq!(
+            logs["nonprivileged"],
+            json!([
+                {
+                    "timestamp": 20,
+                    "base_asset": { "symbol": "ICP", "class": "Cryptocurrency" },
+                    "quote_asset": { "symbol": "USDT", "class": "Cryptocurrency" },
+                    "requested_timestamp": 1_704_153_600,
+                    "result": { "Err": "Pending" },
+                },
+                {
+                    "timestamp": 10,
+                    "base_asset": { "symbol": "ICP", "class": "Cryptocurrency" },
+                    "quote_asset": { "symbol": "USDT", "class": "Cryptocurrency" },
+                    "requested_timestamp": 1_704_153_600,
+                    "result": { "Err": "RateLimited" },
+                },
+            ])
+        );
+    }
+}
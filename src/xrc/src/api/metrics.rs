@@ -190,6 +190,21 @@ fn encode_metrics(w: &mut MetricsEncoder<Vec<u8>>) -> std::io::Result<()> {
         MetricName::ExchangeListingRejectedTotal,
         "Total per-exchange listing refreshes not applied, labeled by reason: 'fetch' (no listing could be fetched and parsed - an HTTP outcall error, transform trap, or candid encode/decode failure) or 'guard' (a 200 that failed the structural acceptance guard - API change or parser break). A rising 'guard' rate points at a parser/API issue; a rising 'fetch' rate at connectivity or a malformed/oversized response.",
     )?;
+    encode_labeled_gauge_family(
+        w,
+        MetricName::ExchangeReputationScoreBps,
+        "Rolling average deviation (basis points) of each exchange's rates for a cryptocurrency from the median of all rates received in the same call, labeled by exchange and symbol; a persistently high value points at a biased venue for that asset.",
+    )?;
+    encode_labeled_gauge_family(
+        w,
+        MetricName::StablecoinDeviationBps,
+        "Deviation (basis points) of each stablecoin's USDT rate from the stablecoin chosen as the USD proxy in the most recent depeg check; a single high value points at a depegging stablecoin.",
+    )?;
+    encode_labeled_counter_family(
+        w,
+        MetricName::StablecoinDepegTotal,
+        "Total depeg checks in which no majority of the stablecoins agreed with the chosen stablecoin, labeled by outcome: 'flagged' (the USDT/USD rate was still used) or 'rejected' (the request failed).",
+    )?;
 
     Ok(())
 }
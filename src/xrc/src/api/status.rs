@@ -0,0 +1,318 @@
+use ic_xrc_types::{
+    ExchangeCallStatus, ExchangeStatus, ForexStatus, InflightRequest, OutcomeCount, Status,
+};
+use strum::IntoEnumIterator;
+
+use crate::{
+    forex::FOREX_SOURCES, inflight::get_inflight_keys, make_metric_key,
+    rate_limiting::get_request_counter, with_cache, with_labeled_counters, with_labeled_gauges,
+    with_listing_store, Exchange, ExchangeCallKind, LabelKey, LabelPairs, MetricName, EXCHANGES,
+};
+
+/// The build features of the canister together with whether they are enabled.
+const FEATURES: [(&str, bool); 4] = [
+    ("application-subnet", cfg!(feature = "application-subnet")),
+    ("ipv4-support", cfg!(feature = "ipv4-support")),
+    (
+        "disable-forex-weekend-check",
+        cfg!(feature = "disable-forex-weekend-check"),
+    ),
+    (
+        "disable-forex-timezone-offset",
+        cfg!(feature = "disable-forex-timezone-offset"),
+    ),
+];
+
+/// Returns the state and the health of the canister. The same data is exposed as Prometheus
+/// text by [super::get_metrics], which canisters cannot easily parse.
+pub fn get_status() -> Status {
+    Status {
+        exchanges: EXCHANGES.iter().map(get_exchange_status).collect(),
+        forex_sources: FOREX_SOURCES
+            .iter()
+            .map(|forex| {
+                let name = forex.to_string();
+                let labels = [(LabelKey::Forex, name.as_str())];
+                ForexStatus {
+                    available: forex.is_available(),
+                    enabled: forex.is_enabled(),
+                    last_success_seconds: get_gauge_seconds(
+                        MetricName::ForexLastSuccessSeconds,
+                        &labels,
+                    ),
+                    outcomes: get_outcome_counts(MetricName::ForexFetchTotal, &labels),
+                    name,
+                }
+            })
+            .collect(),
+        periodic_forex_run_last_seconds: get_gauge_seconds(
+            MetricName::PeriodicForexRunLastSeconds,
+            &[],
+        ),
+        cache_size: with_cache(|cache| cache.len()) as u64,
+        request_counter: get_request_counter() as u64,
+        inflight_requests: get_inflight_keys()
+            .into_iter()
+            .map(|(symbol, timestamp)| InflightRequest { symbol, timestamp })
+            .collect(),
+        features: FEATURES
+            .iter()
+            .filter(|(_, enabled)| *enabled)
+            .map(|(feature, _)| feature.to_string())
+            .collect(),
+    }
+}
+
+fn get_exchange_status(exchange: &Exchange) -> ExchangeStatus {
+    let name = exchange.name();
+    let calls = ExchangeCallKind::iter()
+        .map(|kind| {
+            let kind: &'static str = kind.into();
+            let labels = [(LabelKey::Exchange, name), (LabelKey::Kind, kind)];
+            ExchangeCallStatus {
+                kind: kind.to_string(),
+                last_success_seconds: get_gauge_seconds(
+                    MetricName::ExchangeLastSuccessSeconds,
+                    &labels,
+                ),
+                outcomes: get_outcome_counts(MetricName::ExchangeFetchTotal, &labels),
+            }
+        })
+        .collect();
+    let listing = with_listing_store(|store| {
+        store
+            .get(name)
+            .map(|listing| (listing.last_success_secs, listing.total_markets))
+    });
+
+    ExchangeStatus {
+        name: name.to_string(),
+        available: exchange.is_available(),
+        enabled: exchange.is_enabled(),
+        calls,
+        listing_last_success_seconds: listing.map(|(last_success_secs, _)| last_success_secs),
+        listing_total_markets: listing.map(|(_, total_markets)| total_markets),
+    }
+}
+
+/// Returns true if the series has all of the given labels.
+fn has_labels(series_labels: &LabelPairs, labels: &[(LabelKey, &str)]) -> bool {
+    labels.iter().all(|(key, value)| {
+        series_labels
+            .iter()
+            .any(|(series_key, series_value)| series_key == key && series_value == value)
+    })
+}
+
+/// Returns the counts of the series of the counter with the given labels per outcome.
+fn get_outcome_counts(name: MetricName, labels: &[(LabelKey, &str)]) -> Vec<OutcomeCount> {
+    with_labeled_counters(|counters| {
+        counters
+            .iter()
+            .filter(|((series_name, series_labels), _)| {
+                *series_name == name && has_labels(series_labels, labels)
+            })
+            .filter_map(|((_, series_labels), count)| {
+                series_labels
+                    .iter()
+                    .find(|(key, _)| *key == LabelKey::Outcome)
+                    .map(|(_, outcome)| OutcomeCount {
+                        outcome: outcome.clone(),
+                        count: *count,
+                    })
+            })
+            .collect()
+    })
+}
+
+/// Returns the value of the gauge with exactly the given labels in seconds.
+fn get_gauge_seconds(name: MetricName, labels: &[(LabelKey, &str)]) -> Option<u64> {
+    let key = make_metric_key(name, labels);
+    with_labeled_gauges(|gauges| gauges.get(&key).map(|value| *value as u64))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ic_xrc_types::{Asset, AssetClass, Config};
+    use std::collections::{BTreeMap, BTreeSet};
+
+    use crate::{
+        config, exchanges::ListedPairs, increment_labeled_counter,
+        inflight::test::set_inflight_tracking, rate_limiting::test::set_request_counter,
+        reset_labeled_metrics_for_test, set_labeled_gauge, usdt_asset, with_cache_mut,
+        with_listing_store_mut, Outcome, QueriedExchangeRate, RateAggregation,
+    };
+
+    /// The function verifies that the status reports the outcome counters and gauges of the
+    /// sources as well as the inflight requests.
+    #[test]
+    fn get_status_reports_source_health() {
+        reset_labeled_metrics_for_test();
+        let crypto: &'static str = ExchangeCallKind::Crypto.into();
+        increment_labeled_counter(
+            MetricName::ExchangeFetchTotal,
+            &[
+                (LabelKey::Exchange, "Coinbase"),
+                (LabelKey::Kind, crypto),
+                (LabelKey::Outcome, Outcome::Success.into()),
+            ],
+        );
+        increment_labeled_counter(
+            MetricName::ExchangeFetchTotal,
+            &[
+                (LabelKey::Exchange, "Coinbase"),
+                (LabelKey::Kind, crypto),
+                (LabelKey::Outcome, Outcome::HttpError.into()),
+            ],
+        );
+        set_labeled_gauge(
+            MetricName::ExchangeLastSuccessSeconds,
+            &[(LabelKey::Exchange, "Coinbase"), (LabelKey::Kind, crypto)],
+            1_700_000_000.0,
+        );
+        set_labeled_gauge(
+            MetricName::ForexLastSuccessSeconds,
+            &[(LabelKey::Forex, "EuropeanCentralBank")],
+            1_700_000_060.0,
+        );
+        set_labeled_gauge(
+            MetricName::PeriodicForexRunLastSeconds,
+            &[],
+            1_700_000_120.0,
+        );
+        set_inflight_tracking(vec!["ICP".to_string()], 60);
+
+        let status = get_status();
+
+        let coinbase = status
+            .exchanges
+            .iter()
+            .find(|exchange| exchange.name == "Coinbase")
+            .expect("Coinbase should be reported");
+        assert!(coinbase.enabled);
+        assert_eq!(
+            coinbase.calls[0],
+            ExchangeCallStatus {
+                kind: "crypto".to_string(),
+                last_success_seconds: Some(1_700_000_000),
+                outcomes: vec![
+                    OutcomeCount {
+                        outcome: "http_error".to_string(),
+                        count: 1,
+                    },
+                    OutcomeCount {
+                        outcome: "success".to_string(),
+                        count: 1,
+                    },
+                ],
+            }
+        );
+        assert_eq!(coinbase.calls[1].last_success_seconds, None);
+        assert!(coinbase.calls[1].outcomes.is_empty());
+        assert_eq!(coinbase.listing_last_success_seconds, None);
+
+        let ecb = status
+            .forex_sources
+            .iter()
+            .find(|forex| forex.name == "EuropeanCentralBank")
+            .expect("EuropeanCentralBank should be reported");
+        assert_eq!(ecb.last_success_seconds, Some(1_700_000_060));
+
+        assert_eq!(status.periodic_forex_run_last_seconds, Some(1_700_000_120));
+        assert_eq!(
+            status.inflight_requests,
+            vec![InflightRequest {
+                symbol: "ICP".to_string(),
+                timestamp: 60,
+            }]
+        );
+        assert_eq!(status.request_counter, 0);
+        assert_eq!(status.cache_size, 0);
+    }
+
+    /// The function verifies that the status reports the size of the cache, the request
+    /// counter and the accepted listings of the exchanges.
+    #[test]
+    fn get_status_reports_cache_request_counter_and_listings() {
+        with_cache_mut(|cache| {
+            for (symbol, timestamp) in [("BTC", 0), ("BTC", 60), ("ICP", 60)] {
+                cache.insert(&QueriedExchangeRate::new(
+                    Asset {
+                        symbol: symbol.to_string(),
+                        class: AssetClass::Cryptocurrency,
+                    },
+                    usdt_asset(),
+                    timestamp,
+                    &[1_000_000_000],
+                    1,
+                    1,
+                    None,
+                    RateAggregation::Median,
+                ));
+            }
+        });
+        set_request_counter(12);
+        with_listing_store_mut(|store| {
+            store.accept(
+                "Coinbase",
+                ListedPairs {
+                    bases: BTreeSet::from(["BTC".to_string()]),
+                    total_markets: 300,
+                    pairs: BTreeMap::new(),
+                },
+                1_700_000_000,
+            );
+        });
+
+        let status = get_status();
+
+        assert_eq!(status.cache_size, 3);
+        assert_eq!(status.request_counter, 12);
+        assert!(status.inflight_requests.is_empty());
+        for exchange in &status.exchanges {
+            if exchange.name == "Coinbase" {
+                assert_eq!(exchange.listing_last_success_seconds, Some(1_700_000_000));
+                assert_eq!(exchange.listing_total_markets, Some(300));
+            } else {
+                assert_eq!(exchange.listing_last_success_seconds, None);
+                assert_eq!(exchange.listing_total_markets, None);
+            }
+        }
+        assert_eq!(
+            status.features.contains(&"ipv4-support".to_string()),
+            cfg!(feature = "ipv4-support")
+        );
+    }
+
+    /// The function verifies that every exchange and forex source is reported, and that the
+    /// sources disabled through the configuration are reported as disabled.
+    #[test]
+    fn get_status_reports_disabled_sources() {
+        config::set_config(Config {
+            disabled_exchanges: Some(vec!["Coinbase".to_string()]),
+            disabled_forex_sources: Some(vec!["EuropeanCentralBank".to_string()]),
+            ..config::default_config()
+        });
+
+        let status = get_status();
+
+        assert_eq!(status.exchanges.len(), EXCHANGES.len());
+        assert_eq!(status.forex_sources.len(), FOREX_SOURCES.len());
+        for exchange in &status.exchanges {
+            assert_eq!(exchange.enabled, exchange.name != "Coinbase");
+            assert_eq!(exchange.calls.len(), ExchangeCallKind::iter().count());
+        }
+        for forex in &status.forex_sources {
+            assert_eq!(forex.enabled, forex.name != "EuropeanCentralBank");
+            assert_eq!(forex.last_success_seconds, None);
+            assert!(forex.outcomes.is_empty());
+        }
+        let gate_io = status
+            .exchanges
+            .iter()
+            .find(|exchange| exchange.name == "GateIo")
+            .expect("GateIo should be reported");
+        assert_eq!(gate_io.available, cfg!(feature = "ipv4-support"));
+    }
+}
@@ -4,25 +4,42 @@ use std::{
 };
 
 use async_trait::async_trait;
+use candid::Principal;
 use futures::FutureExt;
-use ic_xrc_types::{Asset, AssetClass, ExchangeRateError, GetExchangeRateRequest};
+use ic_xrc_types::{
+    Asset, AssetClass, CallerTier, Config, ExchangeRate, ExchangeRateCostEstimate,
+    ExchangeRateError, ExchangeRateOptions, ExchangeRateSources, GetCertifiedExchangeRateRequest,
+    GetExchangeRateRequest, GetExchangeRateSeriesRequest, GetExchangeRateV2Request,
+    GetExchangeRateV2Result, GetTwapExchangeRateRequest, PrivilegedPrincipal, SourceRate,
+    StablecoinDepegPolicy, UpdateConfigError,
+};
 use maplit::btreemap;
 
 use crate::{
-    environment::test::TestEnvironment,
+    config::{self, NNS_GOVERNANCE_CANISTER_ID},
+    environment::{test::TestEnvironment, Environment},
     exchanges::{Coinbase, ListedPairs},
     forex::COMPUTED_XDR_SYMBOL,
     inflight::test::set_inflight_tracking,
-    rate_limiting::test::{set_request_counter, REQUEST_COUNTER_TRIGGER_RATE_LIMIT},
-    usdt_asset, with_cache_mut, with_forex_rate_store_mut, with_listing_store_mut,
-    CallExchangeError, Exchange,
-    QueriedExchangeRate, EXCHANGES, PRIVILEGED_CANISTER_IDS, RATE_UNIT, USDC, USDS,
-    XRC_BASE_CYCLES_COST, XRC_IMMEDIATE_REFUND_CYCLES, XRC_MINIMUM_FEE_COST,
-    XRC_OUTBOUND_HTTP_CALL_CYCLES_COST, XRC_REQUEST_CYCLES_COST,
+    rate_limiting::{
+        test::{set_request_counter, REQUEST_COUNTER_TRIGGER_RATE_LIMIT},
+        MAX_NUM_RATES_PER_REQUEST, REQUEST_COUNTER_LIMIT,
+    },
+    usdt_asset, utils, with_cache_mut, with_certified_rates, with_forex_rate_collector_mut,
+    with_forex_rate_store_mut, with_listing_store_mut, CallExchangeError, Exchange,
+    QueriedExchangeRate, QueriedSourceRates, RateAggregation, DAI, EXCHANGES, FDUSD,
+    PRIVILEGED_CANISTER_IDS, PYUSD, RATE_UNIT, USDC, USDS, XRC_BASE_CYCLES_COST,
+    XRC_IMMEDIATE_REFUND_CYCLES, XRC_MINIMUM_FEE_COST, XRC_OUTBOUND_HTTP_CALL_CYCLES_COST,
+    XRC_REQUEST_CYCLES_COST,
 };
 
 use super::{
-    get_exchange_rate_internal, usd_asset, CallExchanges, QueriedExchangeRateWithFailedExchanges,
+    estimate_exchange_rate_cost_internal, get_cached_exchange_rate_internal,
+    get_certified_exchange_rate_internal, get_config, get_cryptocurrency_usd_rates_internal,
+    get_exchange_rate_internal, get_exchange_rate_series_internal, get_exchange_rate_v2_internal,
+    get_exchange_rates_internal, get_twap_exchange_rate_internal, list_supported_assets_internal,
+    update_config_internal, usd_asset, CallExchanges, QueriedExchangeRateWithFailedExchanges,
+    RateSeries, UsdtRateRoute, MAX_NUM_REQUESTS_PER_BATCH,
 };
 
 /// The function returns the Euro asset.
@@ -77,6 +94,7 @@ fn test_cxdr_rate() -> QueriedExchangeRate {
         4,
         4,
         Some(0),
+        RateAggregation::Median,
     )
 }
 
@@ -94,6 +112,13 @@ struct TestCallExchangesImpl {
     #[allow(clippy::type_complexity)]
     /// The received [CallExchanges::get_cryptocurrency_usdt_rate] calls from the test.
     get_stablecoin_rates_calls: RwLock<Vec<(Vec<Exchange>, Vec<String>, u64)>>,
+    /// Contains the responses when [CallExchanges::get_cryptocurrency_usdt_rate_series] is called.
+    get_cryptocurrency_usdt_rate_series_responses:
+        BTreeMap<String, Result<RateSeries, CallExchangeError>>,
+    /// The received [CallExchanges::get_cryptocurrency_usdt_rate_series] calls from the test.
+    get_cryptocurrency_usdt_rate_series_calls: RwLock<Vec<(Asset, u64, u64)>>,
+    /// Contains the responses when [CallExchanges::get_stablecoin_rate_series] is called.
+    get_stablecoin_rate_series_responses: BTreeMap<String, Result<RateSeries, CallExchangeError>>,
 }
 
 impl TestCallExchangesImpl {
@@ -137,6 +162,24 @@ impl TestCallExchangesImplBuilder {
         self
     }
 
+    /// Sets the responses for when [CallExchanges::get_cryptocurrency_usdt_rate_series] is called.
+    fn with_get_cryptocurrency_usdt_rate_series_responses(
+        mut self,
+        responses: BTreeMap<String, Result<RateSeries, CallExchangeError>>,
+    ) -> Self {
+        self.r#impl.get_cryptocurrency_usdt_rate_series_responses = responses;
+        self
+    }
+
+    /// Sets the responses for when [CallExchanges::get_stablecoin_rate_series] is called.
+    fn with_get_stablecoin_rate_series_responses(
+        mut self,
+        responses: BTreeMap<String, Result<RateSeries, CallExchangeError>>,
+    ) -> Self {
+        self.r#impl.get_stablecoin_rate_series_responses = responses;
+        self
+    }
+
     /// Returns the built implmentation.
     fn build(self) -> TestCallExchangesImpl {
         self.r#impl
@@ -149,6 +192,7 @@ impl CallExchanges for TestCallExchangesImpl {
         &self,
         exchanges: &[&Exchange],
         asset: &Asset,
+        _route: &UsdtRateRoute,
         timestamp: u64,
     ) -> Result<QueriedExchangeRateWithFailedExchanges, CallExchangeError> {
         let exchanges_vec = exchanges
@@ -182,17 +226,46 @@ impl CallExchanges for TestCallExchangesImpl {
             timestamp,
         ));
 
-        let mut results = vec![];
-        for asset in assets {
-            let entry = self
-                .get_stablecoin_rates_responses
-                .get(*asset)
-                .expect("Failed to retrieve stablecoin rate")
-                .clone();
-            results.push(entry);
-        }
+        assets
+            .iter()
+            .map(|asset| {
+                self.get_stablecoin_rates_responses
+                    .get(*asset)
+                    .cloned()
+                    .unwrap_or(Err(CallExchangeError::NoRatesFound))
+            })
+            .collect()
+    }
 
-        results
+    async fn get_cryptocurrency_usdt_rate_series(
+        &self,
+        _exchanges: &[&Exchange],
+        asset: &Asset,
+        _route: &UsdtRateRoute,
+        start: u64,
+        end: u64,
+    ) -> Result<RateSeries, CallExchangeError> {
+        self.get_cryptocurrency_usdt_rate_series_calls
+            .write()
+            .unwrap()
+            .push((asset.clone(), start, end));
+        self.get_cryptocurrency_usdt_rate_series_responses
+            .get(&asset.symbol)
+            .cloned()
+            .unwrap_or(Err(CallExchangeError::NoRatesFound))
+    }
+
+    async fn get_stablecoin_rate_series(
+        &self,
+        _exchanges: &[&Exchange],
+        symbol: &str,
+        _start: u64,
+        _end: u64,
+    ) -> Result<RateSeries, CallExchangeError> {
+        self.get_stablecoin_rate_series_responses
+            .get(symbol)
+            .cloned()
+            .unwrap_or(Err(CallExchangeError::NoRatesFound))
     }
 }
 
@@ -206,6 +279,7 @@ fn btc_queried_exchange_rate_mock() -> QueriedExchangeRate {
         EXCHANGES.len(),
         3,
         None,
+        RateAggregation::Median,
     )
 }
 
@@ -229,6 +303,7 @@ fn icp_queried_exchange_rate_mock() -> QueriedExchangeRate {
         EXCHANGES.len(),
         3,
         None,
+        RateAggregation::Median,
     )
 }
 
@@ -252,6 +327,7 @@ fn icp_queried_exchange_rate_with_one_rate_mock() -> QueriedExchangeRate {
         EXCHANGES.len(),
         1,
         None,
+        RateAggregation::Median,
     )
 }
 
@@ -274,8 +350,10 @@ fn failed_validation_does_not_poison_cache_with_zero_rate() {
         EXCHANGES.len(),
         1,
         None,
+        RateAggregation::Median,
     );
     assert!(empty_post_filter_rate.rates.is_empty());
+    cache_usd_conversion_rates(timestamp);
 
     let call_exchanges_impl = TestCallExchangesImpl::builder()
         .with_get_cryptocurrency_usdt_rate_responses(btreemap! {
@@ -323,6 +401,7 @@ fn empty_post_filter_rate_is_not_cached_and_forces_refetch() {
     set_request_counter(0);
 
     let timestamp: u64 = 12_345_660;
+    cache_usd_conversion_rates(timestamp);
     let empty_post_filter_rate = QueriedExchangeRate::new(
         icp_asset(),
         usdt_asset(),
@@ -331,6 +410,7 @@ fn empty_post_filter_rate_is_not_cached_and_forces_refetch() {
         EXCHANGES.len(),
         1,
         None,
+        RateAggregation::Median,
     );
 
     let call_exchanges_impl = TestCallExchangesImpl::builder()
@@ -460,9 +540,8 @@ fn cache_only_crypto_pair_validates_the_composed_rate() {
 fn cache_only_crypto_fiat_pair_validates_the_composed_rate() {
     with_cache_mut(|cache| {
         cache.insert(&inconsistent_crypto_usdt_rate_mock(icp_asset()));
-        cache.insert(&stablecoin_mock(USDS, &[RATE_UNIT]));
-        cache.insert(&stablecoin_mock(USDC, &[RATE_UNIT]));
     });
+    cache_usd_conversion_rates(0);
     set_inflight_tracking(vec!["ICP".to_string()], 60);
     let call_exchanges_impl = TestCallExchangesImpl::builder().build();
     let env = TestEnvironment::builder()
@@ -499,9 +578,23 @@ fn stablecoin_mock(symbol: &str, rates: &[u64]) -> QueriedExchangeRate {
         EXCHANGES.len(),
         rates.len(),
         None,
+        RateAggregation::Median,
     )
 }
 
+/// Caches the rates of the stablecoin basket at the given timestamp, with which the rates that
+/// exchanges quote in USD are converted into USDT, so that no stablecoin rates need to be
+/// retrieved.
+fn cache_usd_conversion_rates(timestamp: u64) {
+    with_cache_mut(|cache| {
+        for symbol in super::STABLECOIN_BASES {
+            let mut rate = stablecoin_mock(symbol, &[RATE_UNIT]);
+            rate.timestamp = timestamp;
+            cache.insert(&rate);
+        }
+    });
+}
+
 fn stablecoin_mock_with_failed_exchanges(
     symbol: &str,
     rates: &[u64],
@@ -608,6 +701,33 @@ fn get_exchange_rate_skips_exchanges_that_fail_for_stablecoin_rate() {
     );
 }
 
+/// This function tests that the USD rates of the cryptocurrencies in the baskets are derived from
+/// their USDT rates and that cryptocurrencies without rates are omitted.
+#[test]
+fn get_cryptocurrency_usd_rates_converts_usdt_rates() {
+    let call_exchanges_impl = TestCallExchangesImpl::builder()
+        .with_get_cryptocurrency_usdt_rate_responses(btreemap! {
+            "BTC".to_string() => Ok(btc_queried_exchange_rate_with_failed_exchanges_mock(vec![])),
+        })
+        .with_get_stablecoin_rates_responses(btreemap! {
+            USDS.to_string() => Ok(stablecoin_mock_with_failed_exchanges(USDS, &[RATE_UNIT], vec![])),
+            USDC.to_string() => Ok(stablecoin_mock_with_failed_exchanges(USDC, &[RATE_UNIT], vec![]))
+        })
+        .build();
+
+    let rates = get_cryptocurrency_usd_rates_internal(
+        &call_exchanges_impl,
+        &["BTC".to_string(), "ICP".to_string()],
+        1678752000,
+        1678752000,
+    )
+    .now_or_never()
+    .expect("future should complete");
+
+    assert_eq!(rates.keys().collect::<Vec<_>>(), vec!["BTC"]);
+    assert_eq!(utils::median(&rates["BTC"]), 16_000 * RATE_UNIT);
+}
+
 /// This function tests that [get_exchange_rate] will return an [ExchangeRateError::NotEnoughCycles]
 /// when not enough cycles are sent by the caller.
 #[test]
@@ -780,6 +900,7 @@ fn get_exchange_rate_will_charge_the_base_cost_plus_outbound_cycles_worth_of_cyc
     with_cache_mut(|cache| {
         cache.insert(&btc_queried_exchange_rate_mock());
     });
+    cache_usd_conversion_rates(0);
 
     let request = GetExchangeRateRequest {
         base_asset: btc_asset(),
@@ -876,64 +997,273 @@ fn get_exchange_rate_for_crypto_usd_pair() {
     );
 }
 
-/// This function tests to ensure a rate is returned when asking for a
-/// USD/crypto pair.
-#[test]
-fn get_exchange_rate_for_usd_crypto_pair() {
-    let call_exchanges_impl = TestCallExchangesImpl::builder()
-        .with_get_cryptocurrency_usdt_rate_responses(btreemap! {
-            "ICP".to_string() => Ok(icp_queried_exchange_rate_with_failed_exchanges_mock(vec![]))
-        })
-        .with_get_stablecoin_rates_responses(btreemap! {
-            USDS.to_string() => Ok(stablecoin_mock_with_failed_exchanges(USDS, &[RATE_UNIT], vec![])),
-            USDC.to_string() => Ok(stablecoin_mock_with_failed_exchanges(USDC, &[RATE_UNIT], vec![]))
-        })
-        .build();
-    let env = TestEnvironment::builder()
-        .with_cycles_available(XRC_REQUEST_CYCLES_COST)
-        .with_accepted_cycles(XRC_REQUEST_CYCLES_COST - XRC_IMMEDIATE_REFUND_CYCLES)
-        .build();
+mod get_exchange_rates {
+    use super::*;
 
-    let request = GetExchangeRateRequest {
-        base_asset: usd_asset(),
-        quote_asset: icp_asset(),
-        timestamp: Some(0),
-    };
+    fn call_exchanges_impl() -> TestCallExchangesImpl {
+        TestCallExchangesImpl::builder()
+            .with_get_cryptocurrency_usdt_rate_responses(btreemap! {
+                "BTC".to_string() => Ok(btc_queried_exchange_rate_with_failed_exchanges_mock(vec![])),
+                "ICP".to_string() => Ok(icp_queried_exchange_rate_with_failed_exchanges_mock(vec![]))
+            })
+            .with_get_stablecoin_rates_responses(btreemap! {
+                USDS.to_string() => Ok(stablecoin_mock_with_failed_exchanges(USDS, &[RATE_UNIT], vec![])),
+                USDC.to_string() => Ok(stablecoin_mock_with_failed_exchanges(USDC, &[RATE_UNIT], vec![])),
+            })
+            .build()
+    }
 
-    let result = get_exchange_rate_internal(&env, &call_exchanges_impl, &request)
-        .now_or_never()
-        .expect("future should complete");
-    assert!(
-        matches!(result, Ok(ref rate) if rate.rate == 250_000_000),
-        "Received the following result: {:#?}",
-        result
-    );
-    assert_eq!(
-        call_exchanges_impl
+    fn request(base_asset: Asset, quote_asset: Asset) -> GetExchangeRateRequest {
+        GetExchangeRateRequest {
+            base_asset,
+            quote_asset,
+            timestamp: Some(0),
+        }
+    }
+
+    /// This function tests that every distinct rate needed by a batch is only retrieved once
+    /// and that the batch is charged for the union of the rates needed.
+    #[test]
+    fn get_exchange_rates_retrieves_each_distinct_rate_once() {
+        let call_exchanges_impl = call_exchanges_impl();
+        // 4 valid requests need the ICP and BTC rates and the rates of the five stablecoins.
+        // ICP/USD needs the ICP rate and the stablecoin rates, of which only 2 are charged as
+        // for a single request, and BTC/USD needs the BTC rate.
+        let fee = 4 * XRC_BASE_CYCLES_COST + 3 * XRC_OUTBOUND_HTTP_CALL_CYCLES_COST;
+        let env = TestEnvironment::builder()
+            .with_cycles_available(2 * XRC_REQUEST_CYCLES_COST)
+            .with_accepted_cycles(fee)
+            .build();
+        let requests = vec![
+            request(icp_asset(), usd_asset()),
+            request(icp_asset(), usdt_asset()),
+            request(btc_asset(), usd_asset()),
+            request(btc_asset(), icp_asset()),
+        ];
+
+        let results = get_exchange_rates_internal(&env, &call_exchanges_impl, &requests)
+            .now_or_never()
+            .expect("future should complete")
+            .expect("batch should succeed");
+
+        let rates = results
+            .iter()
+            .map(|result| result.as_ref().map(|rate| rate.rate).ok())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rates,
+            vec![
+                Some(4 * RATE_UNIT),
+                Some(4 * RATE_UNIT),
+                Some(16_000 * RATE_UNIT),
+                Some(4_000 * RATE_UNIT),
+            ]
+        );
+        let cryptocurrency_calls = call_exchanges_impl
             .get_cryptocurrency_usdt_rate_calls
             .read()
             .unwrap()
-            .len(),
-        1
-    );
-    assert_eq!(
-        call_exchanges_impl
+            .iter()
+            .map(|(_, asset, _)| asset.symbol.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(cryptocurrency_calls, vec!["BTC", "ICP"]);
+        let stablecoin_calls = call_exchanges_impl
             .get_stablecoin_rates_calls
             .read()
+            .unwrap();
+        assert_eq!(stablecoin_calls.len(), 1);
+        assert_eq!(stablecoin_calls[0].1, vec![DAI, FDUSD, PYUSD, USDC, USDS]);
+    }
+
+    /// This function tests that a batch is rejected as a whole if the attached cycles do not
+    /// cover the fee for the batch.
+    #[test]
+    fn get_exchange_rates_fails_when_not_enough_cycles_for_the_batch() {
+        let call_exchanges_impl = call_exchanges_impl();
+        let env = TestEnvironment::builder()
+            .with_cycles_available(XRC_REQUEST_CYCLES_COST)
+            .build();
+        // The ICP, BTC, PEPE, and stablecoin rates are needed, which costs more than 1B cycles.
+        let requests = vec![
+            request(icp_asset(), usd_asset()),
+            request(btc_asset(), usd_asset()),
+            request(pepe_asset(), usd_asset()),
+        ];
+
+        let result = get_exchange_rates_internal(&env, &call_exchanges_impl, &requests)
+            .now_or_never()
+            .expect("future should complete");
+
+        assert!(
+            matches!(result, Err(ExchangeRateError::NotEnoughCycles)),
+            "Received the following result: {:#?}",
+            result
+        );
+        assert!(call_exchanges_impl
+            .get_cryptocurrency_usdt_rate_calls
+            .read()
             .unwrap()
-            .len(),
-        1
-    );
+            .is_empty());
+    }
+
+    /// This function tests that a batch with too many requests is rejected.
+    #[test]
+    fn get_exchange_rates_fails_when_there_are_too_many_requests() {
+        let env = TestEnvironment::builder()
+            .with_cycles_available(XRC_REQUEST_CYCLES_COST)
+            .build();
+        let requests = vec![request(icp_asset(), usdt_asset()); MAX_NUM_REQUESTS_PER_BATCH + 1];
+
+        let result = get_exchange_rates_internal(&env, &call_exchanges_impl(), &requests)
+            .now_or_never()
+            .expect("future should complete");
+
+        assert!(
+            matches!(result, Err(ExchangeRateError::Other(ref error)) if error.code == crate::errors::TOO_MANY_REQUESTS_IN_BATCH_ERROR_CODE),
+            "Received the following result: {:#?}",
+            result
+        );
+    }
+
+    /// This function tests that a request of a batch that does not fit into the remaining
+    /// request counter budget is retrieved after the preceding requests, and that only a
+    /// request that does not fit into the budget on its own is rate limited.
+    #[test]
+    fn get_exchange_rates_retrieves_the_rates_in_chunks_that_fit_the_budget() {
+        let available_exchanges_count = EXCHANGES.iter().filter(|e| e.is_available()).count();
+        // Only a single additional rate may be retrieved at a time.
+        set_request_counter(REQUEST_COUNTER_LIMIT - available_exchanges_count);
+        cache_usd_conversion_rates(0);
+        let call_exchanges_impl = call_exchanges_impl();
+        let fee = 2 * XRC_BASE_CYCLES_COST
+            + XRC_MINIMUM_FEE_COST
+            + 2 * XRC_OUTBOUND_HTTP_CALL_CYCLES_COST;
+        let env = TestEnvironment::builder()
+            .with_cycles_available(XRC_REQUEST_CYCLES_COST)
+            .with_accepted_cycles(fee)
+            .build();
+        let requests = vec![
+            request(icp_asset(), usdt_asset()),
+            request(btc_asset(), usdt_asset()),
+            request(
+                pepe_asset(),
+                Asset {
+                    symbol: "ETH".to_string(),
+                    class: AssetClass::Cryptocurrency,
+                },
+            ),
+        ];
+
+        let results = get_exchange_rates_internal(&env, &call_exchanges_impl, &requests)
+            .now_or_never()
+            .expect("future should complete")
+            .expect("batch should succeed");
+
+        assert!(
+            matches!(results[0], Ok(ref rate) if rate.rate == 4 * RATE_UNIT),
+            "Received the following results: {:#?}",
+            results
+        );
+        assert!(
+            matches!(results[1], Ok(ref rate) if rate.rate == 16_000 * RATE_UNIT),
+            "Received the following results: {:#?}",
+            results
+        );
+        assert!(
+            matches!(results[2], Err(ExchangeRateError::RateLimited)),
+            "Received the following results: {:#?}",
+            results
+        );
+        assert_eq!(
+            call_exchanges_impl
+                .get_cryptocurrency_usdt_rate_calls
+                .read()
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+
+    /// This function tests that a batch whose rates exceed the request counter budget is not
+    /// rate limited.
+    #[test]
+    fn get_exchange_rates_retrieves_more_rates_than_fit_the_budget() {
+        let available_exchanges_count = EXCHANGES.iter().filter(|e| e.is_available()).count();
+        let num_requests = REQUEST_COUNTER_LIMIT / available_exchanges_count + 1;
+        let symbols = (0..num_requests)
+            .map(|index| format!("TOKEN{}", index))
+            .collect::<Vec<_>>();
+        let call_exchanges_impl = TestCallExchangesImpl::builder()
+            .with_get_cryptocurrency_usdt_rate_responses(
+                symbols
+                    .iter()
+                    .map(|symbol| {
+                        (
+                            symbol.clone(),
+                            Ok(icp_queried_exchange_rate_with_failed_exchanges_mock(vec![])),
+                        )
+                    })
+                    .collect(),
+            )
+            .build();
+        cache_usd_conversion_rates(0);
+        let fee =
+            num_requests as u128 * (XRC_BASE_CYCLES_COST + XRC_OUTBOUND_HTTP_CALL_CYCLES_COST);
+        let env = TestEnvironment::builder()
+            .with_cycles_available(fee)
+            .with_accepted_cycles(fee)
+            .build();
+        let requests = symbols
+            .iter()
+            .map(|symbol| {
+                request(
+                    Asset {
+                        symbol: symbol.clone(),
+                        class: AssetClass::Cryptocurrency,
+                    },
+                    usdt_asset(),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let results = get_exchange_rates_internal(&env, &call_exchanges_impl, &requests)
+            .now_or_never()
+            .expect("future should complete")
+            .expect("batch should succeed");
+
+        assert!(
+            results.iter().all(Result::is_ok),
+            "Received the following results: {:#?}",
+            results
+        );
+        assert_eq!(
+            call_exchanges_impl
+                .get_cryptocurrency_usdt_rate_calls
+                .read()
+                .unwrap()
+                .len(),
+            num_requests
+        );
+    }
 }
 
-/// This function tests to ensure a rate is returned when asking for a
-/// crypto/non-USD pair.
-#[test]
-fn get_exchange_rate_for_crypto_non_usd_pair() {
-    with_forex_rate_store_mut(|store| {
-        store.put(
-            0,
-            btreemap! {
+mod get_cached_exchange_rate {
+    use super::*;
+
+    fn setup_cache() {
+        with_cache_mut(|cache| {
+            cache.insert(&icp_queried_exchange_rate_mock());
+            cache.insert(&btc_queried_exchange_rate_mock());
+            cache.insert(&stablecoin_mock(USDS, &[RATE_UNIT]));
+            cache.insert(&stablecoin_mock(USDC, &[RATE_UNIT]));
+        });
+    }
+
+    fn setup_forex_store() {
+        with_forex_rate_store_mut(|store| {
+            store.put(
+                0,
+                btreemap! {
                     "EUR".to_string() =>
                         QueriedExchangeRate::new(
                             eur_asset(),
@@ -943,58 +1273,1416 @@ fn get_exchange_rate_for_crypto_non_usd_pair() {
                             4,
                             4,
                             Some(0),
+                            RateAggregation::Median,
                         ),
-                    // It is necessary to have a CXDR rate with at least 4 sources for the rate store to return a result
                     COMPUTED_XDR_SYMBOL.to_string() => test_cxdr_rate(),
-            },
+                },
+            );
+        });
+    }
+
+    fn assert_rate(base_asset: Asset, quote_asset: Asset, expected_rate: u64) {
+        let env = TestEnvironment::builder().build();
+        let request = GetExchangeRateRequest {
+            base_asset,
+            quote_asset,
+            timestamp: Some(0),
+        };
+        let result = get_cached_exchange_rate_internal(&env, &request);
+        assert!(
+            matches!(result, Ok(ref rate) if rate.rate == expected_rate),
+            "Received the following result: {:#?}",
+            result
         );
-    });
+    }
 
-    let call_exchanges_impl = TestCallExchangesImpl::builder()
-        .with_get_cryptocurrency_usdt_rate_responses(btreemap! {
-            "ICP".to_string() => Ok(icp_queried_exchange_rate_with_failed_exchanges_mock(vec![]))
-        })
-        .with_get_stablecoin_rates_responses(btreemap! {
-           USDS.to_string() => Ok(stablecoin_mock_with_failed_exchanges(USDS, &[RATE_UNIT], vec![])),
-            USDC.to_string() => Ok(stablecoin_mock_with_failed_exchanges(USDC, &[RATE_UNIT], vec![]))
-        })
-        .build();
-    let env = TestEnvironment::builder()
-        .with_cycles_available(XRC_REQUEST_CYCLES_COST)
-        .with_accepted_cycles(XRC_REQUEST_CYCLES_COST - XRC_IMMEDIATE_REFUND_CYCLES)
-        .build();
+    /// This function tests that the rates of all asset class combinations are composed from
+    /// the cache and the forex rate store.
+    #[test]
+    fn get_cached_exchange_rate_composes_rates_from_the_cache_and_forex_store() {
+        setup_cache();
+        setup_forex_store();
+
+        assert_rate(btc_asset(), icp_asset(), 4_000 * RATE_UNIT);
+        assert_rate(icp_asset(), usd_asset(), 4 * RATE_UNIT);
+        assert_rate(icp_asset(), eur_asset(), 5 * RATE_UNIT);
+        assert_rate(eur_asset(), icp_asset(), 200_000_000);
+        assert_rate(eur_asset(), usd_asset(), 800_000_000);
+    }
 
-    let request = GetExchangeRateRequest {
-        base_asset: icp_asset(),
-        quote_asset: eur_asset(),
-        timestamp: Some(0),
-    };
-    let result = get_exchange_rate_internal(&env, &call_exchanges_impl, &request)
-        .now_or_never()
-        .expect("future should complete");
-    assert!(
-        matches!(result, Ok(ref rate) if rate.rate == 5 * RATE_UNIT),
-        "Received the following result: {:#?}",
-        result
-    );
-    assert_eq!(
-        call_exchanges_impl
-            .get_cryptocurrency_usdt_rate_calls
-            .read()
-            .unwrap()
-            .len(),
-        1
-    );
-    assert_eq!(
-        call_exchanges_impl
-            .get_stablecoin_rates_calls
-            .read()
-            .unwrap()
-            .len(),
-        1
+    /// This function tests that a "not cached" error is returned when a needed rate is missing
+    /// from the cache.
+    #[test]
+    fn get_cached_exchange_rate_returns_not_cached_error() {
+        with_cache_mut(|cache| {
+            cache.insert(&icp_queried_exchange_rate_mock());
+        });
+        let env = TestEnvironment::builder().build();
+
+        let request = GetExchangeRateRequest {
+            base_asset: btc_asset(),
+            quote_asset: icp_asset(),
+            timestamp: Some(0),
+        };
+        let result = get_cached_exchange_rate_internal(&env, &request);
+        assert!(
+            matches!(result, Err(ExchangeRateError::Other(ref error)) if error.code == crate::errors::RATE_NOT_CACHED_ERROR_CODE),
+            "Received the following result: {:#?}",
+            result
+        );
+
+        // The stablecoin rates are needed as well for a crypto/fiat pair.
+        let request = GetExchangeRateRequest {
+            base_asset: icp_asset(),
+            quote_asset: usd_asset(),
+            timestamp: Some(0),
+        };
+        let result = get_cached_exchange_rate_internal(&env, &request);
+        assert!(
+            matches!(result, Err(ExchangeRateError::Other(ref error)) if error.code == crate::errors::RATE_NOT_CACHED_ERROR_CODE),
+            "Received the following result: {:#?}",
+            result
+        );
+    }
+
+    /// This function tests that a crypto/fiat rate is composed as long as at least
+    /// [stablecoin::MIN_NUM_STABLECOIN_RATES] stablecoin rates are cached, as on the update path.
+    #[test]
+    fn get_cached_exchange_rate_requires_only_the_minimum_number_of_stablecoin_rates() {
+        with_cache_mut(|cache| {
+            cache.insert(&icp_queried_exchange_rate_mock());
+            cache.insert(&stablecoin_mock(DAI, &[RATE_UNIT]));
+        });
+        let env = TestEnvironment::builder().build();
+        let request = GetExchangeRateRequest {
+            base_asset: icp_asset(),
+            quote_asset: usd_asset(),
+            timestamp: Some(0),
+        };
+
+        let result = get_cached_exchange_rate_internal(&env, &request);
+        assert!(
+            matches!(result, Err(ExchangeRateError::Other(ref error)) if error.code == crate::errors::RATE_NOT_CACHED_ERROR_CODE),
+            "Received the following result: {:#?}",
+            result
+        );
+
+        // Two of the five stablecoin rates suffice.
+        with_cache_mut(|cache| {
+            cache.insert(&stablecoin_mock(PYUSD, &[RATE_UNIT]));
+        });
+        let result = get_cached_exchange_rate_internal(&env, &request);
+        assert!(
+            matches!(result, Ok(ref rate) if rate.rate == 4 * RATE_UNIT),
+            "Received the following result: {:#?}",
+            result
+        );
+    }
+
+    /// This function tests that the rates from the previous minute are used if no timestamp
+    /// is provided and the current minute is not cached yet.
+    #[test]
+    fn get_cached_exchange_rate_falls_back_to_the_previous_minute() {
+        setup_cache();
+        let env = TestEnvironment::builder().with_time_secs(90).build();
+        let request = GetExchangeRateRequest {
+            base_asset: icp_asset(),
+            quote_asset: usdt_asset(),
+            timestamp: None,
+        };
+
+        let result = get_cached_exchange_rate_internal(&env, &request);
+
+        assert!(
+            matches!(result, Ok(ref rate) if rate.rate == 4 * RATE_UNIT && rate.timestamp == 0),
+            "Received the following result: {:#?}",
+            result
+        );
+    }
+}
+
+mod estimate_exchange_rate_cost {
+    use super::*;
+
+    fn call_exchanges_impl() -> TestCallExchangesImpl {
+        TestCallExchangesImpl::builder()
+            .with_get_cryptocurrency_usdt_rate_responses(btreemap! {
+                "ICP".to_string() => Ok(icp_queried_exchange_rate_with_failed_exchanges_mock(vec![])),
+                "BTC".to_string() => Ok(btc_queried_exchange_rate_with_failed_exchanges_mock(vec![])),
+            })
+            .build()
+    }
+
+    /// Estimates the cost of the request and verifies that `get_exchange_rate` charges the
+    /// estimated fee.
+    fn estimate_and_get_exchange_rate(
+        request: &GetExchangeRateRequest,
+    ) -> ExchangeRateCostEstimate {
+        let env = TestEnvironment::builder()
+            .with_cycles_available(XRC_REQUEST_CYCLES_COST)
+            .build();
+        let estimate = estimate_exchange_rate_cost_internal(&env, request);
+
+        let env = TestEnvironment::builder()
+            .with_cycles_available(XRC_REQUEST_CYCLES_COST)
+            .with_accepted_cycles(estimate.fee)
+            .build();
+        get_exchange_rate_internal(&env, &call_exchanges_impl(), request)
+            .now_or_never()
+            .expect("future should complete")
+            .ok();
+        estimate
+    }
+
+    /// This function tests that the estimate depends on the number of rates that are not cached
+    /// yet and that it matches the fee that is charged.
+    #[test]
+    fn estimate_exchange_rate_cost_depends_on_cached_rates() {
+        let request = GetExchangeRateRequest {
+            base_asset: btc_asset(),
+            quote_asset: icp_asset(),
+            timestamp: Some(0),
+        };
+
+        let estimate = estimate_and_get_exchange_rate(&request);
+        assert_eq!(
+            estimate,
+            ExchangeRateCostEstimate {
+                cycles_required: XRC_REQUEST_CYCLES_COST,
+                fee: XRC_BASE_CYCLES_COST + 2 * XRC_OUTBOUND_HTTP_CALL_CYCLES_COST,
+                refund: XRC_REQUEST_CYCLES_COST
+                    - XRC_BASE_CYCLES_COST
+                    - 2 * XRC_OUTBOUND_HTTP_CALL_CYCLES_COST,
+                // The BTC and ICP rates, and the rates of the stablecoin basket to convert the
+                // rates that exchanges quote in USD.
+                num_outbound_rates_needed: 7,
+            }
+        );
+
+        // Both rates are cached now.
+        let estimate = estimate_and_get_exchange_rate(&request);
+        assert_eq!(
+            estimate,
+            ExchangeRateCostEstimate {
+                cycles_required: XRC_REQUEST_CYCLES_COST,
+                fee: XRC_BASE_CYCLES_COST,
+                refund: XRC_REQUEST_CYCLES_COST - XRC_BASE_CYCLES_COST,
+                num_outbound_rates_needed: 0,
+            }
+        );
+    }
+
+    /// This function tests that the minimum fee is estimated for an invalid request.
+    #[test]
+    fn estimate_exchange_rate_cost_returns_minimum_fee_for_invalid_request() {
+        let request = GetExchangeRateRequest {
+            base_asset: Asset {
+                symbol: "<ICP>".to_string(),
+                class: AssetClass::Cryptocurrency,
+            },
+            quote_asset: usdt_asset(),
+            timestamp: Some(0),
+        };
+
+        let estimate = estimate_and_get_exchange_rate(&request);
+
+        assert_eq!(estimate.fee, XRC_MINIMUM_FEE_COST);
+        assert_eq!(
+            estimate.refund,
+            XRC_REQUEST_CYCLES_COST - XRC_MINIMUM_FEE_COST
+        );
+    }
+
+    /// This function tests that callers in the discounted tier are charged half of the fee.
+    #[test]
+    fn estimate_exchange_rate_cost_applies_discounted_tier() {
+        let caller = TestEnvironment::builder().build().caller();
+        config::set_config(Config {
+            privileged_principals: Some(vec![PrivilegedPrincipal {
+                principal: caller,
+                tier: CallerTier::Discounted,
+            }]),
+            ..config::default_config()
+        });
+        let request = GetExchangeRateRequest {
+            base_asset: btc_asset(),
+            quote_asset: icp_asset(),
+            timestamp: Some(0),
+        };
+
+        let estimate = estimate_and_get_exchange_rate(&request);
+
+        let fee = (XRC_BASE_CYCLES_COST + 2 * XRC_OUTBOUND_HTTP_CALL_CYCLES_COST) / 2;
+        assert_eq!(
+            estimate,
+            ExchangeRateCostEstimate {
+                cycles_required: XRC_REQUEST_CYCLES_COST,
+                fee,
+                refund: XRC_REQUEST_CYCLES_COST - fee,
+                num_outbound_rates_needed: 7,
+            }
+        );
+    }
+
+    /// This function tests that nothing is charged to privileged callers.
+    #[test]
+    fn estimate_exchange_rate_cost_is_zero_for_privileged_callers() {
+        let env = TestEnvironment::builder()
+            .with_caller(PRIVILEGED_CANISTER_IDS[0])
+            .build();
+        let request = GetExchangeRateRequest {
+            base_asset: icp_asset(),
+            quote_asset: usd_asset(),
+            timestamp: Some(0),
+        };
+
+        let estimate = estimate_exchange_rate_cost_internal(&env, &request);
+
+        assert_eq!(
+            estimate,
+            ExchangeRateCostEstimate {
+                cycles_required: 0,
+                fee: 0,
+                refund: 0,
+                num_outbound_rates_needed: 6,
+            }
+        );
+    }
+}
+
+mod update_config {
+    use super::*;
+
+    /// This function tests that only NNS Governance can update the configuration and that
+    /// invalid configurations are rejected.
+    #[test]
+    fn update_config_is_restricted_to_nns_governance() {
+        let default_config = get_config();
+        let config = Config {
+            max_cache_size: 10,
+            ..default_config.clone()
+        };
+
+        let env = TestEnvironment::builder()
+            .with_caller(PRIVILEGED_CANISTER_IDS[0])
+            .build();
+        assert_eq!(
+            update_config_internal(&env, config.clone()),
+            Err(UpdateConfigError::NotAuthorized)
+        );
+        assert_eq!(get_config(), default_config);
+
+        let env = TestEnvironment::builder()
+            .with_caller(NNS_GOVERNANCE_CANISTER_ID)
+            .build();
+        let invalid_config = Config {
+            rate_deviation_divisor: 0,
+            ..config.clone()
+        };
+        assert!(matches!(
+            update_config_internal(&env, invalid_config),
+            Err(UpdateConfigError::InvalidConfig(_))
+        ));
+        assert_eq!(get_config(), default_config);
+
+        assert_eq!(update_config_internal(&env, config.clone()), Ok(()));
+        assert_eq!(get_config(), config);
+    }
+
+    /// This function tests that a principal added to the free tier is not charged and that a
+    /// default privileged canister that is no longer listed must attach cycles.
+    #[test]
+    fn updated_privileged_principals_are_applied() {
+        let free_principal = Principal::from_text("ryjl3-tyaaa-aaaaa-aaaba-cai")
+            .expect("should be a valid textual principal ID");
+        let env = TestEnvironment::builder()
+            .with_caller(NNS_GOVERNANCE_CANISTER_ID)
+            .build();
+        let config = Config {
+            privileged_principals: Some(vec![PrivilegedPrincipal {
+                principal: free_principal,
+                tier: CallerTier::Free,
+            }]),
+            ..get_config()
+        };
+        assert_eq!(update_config_internal(&env, config), Ok(()));
+
+        let call_exchanges_impl = TestCallExchangesImpl::builder()
+            .with_get_cryptocurrency_usdt_rate_responses(btreemap! {
+                "ICP".to_string() => Ok(icp_queried_exchange_rate_with_failed_exchanges_mock(vec![]))
+            })
+            .build();
+        let request = GetExchangeRateRequest {
+            base_asset: icp_asset(),
+            quote_asset: usdt_asset(),
+            timestamp: Some(0),
+        };
+
+        let env = TestEnvironment::builder()
+            .with_caller(free_principal)
+            .build();
+        let result = get_exchange_rate_internal(&env, &call_exchanges_impl, &request)
+            .now_or_never()
+            .expect("future should complete");
+        assert!(matches!(result, Ok(ref rate) if rate.rate == 4 * RATE_UNIT));
+
+        let env = TestEnvironment::builder()
+            .with_caller(PRIVILEGED_CANISTER_IDS[0])
+            .build();
+        let result = get_exchange_rate_internal(&env, &call_exchanges_impl, &request)
+            .now_or_never()
+            .expect("future should complete");
+        assert!(matches!(result, Err(ExchangeRateError::NotEnoughCycles)));
+    }
+
+    /// This function tests that an updated request counter limit is used for rate limiting.
+    #[test]
+    fn updated_request_counter_limit_is_applied() {
+        let env = TestEnvironment::builder()
+            .with_caller(NNS_GOVERNANCE_CANISTER_ID)
+            .build();
+        let request_counter_limit = EXCHANGES.len() * MAX_NUM_RATES_PER_REQUEST;
+        let config = Config {
+            request_counter_limit: request_counter_limit as u64,
+            ..get_config()
+        };
+        assert_eq!(update_config_internal(&env, config), Ok(()));
+        // The counter would leave room for the request under the default limit.
+        set_request_counter(request_counter_limit - 1);
+
+        let call_exchanges_impl = TestCallExchangesImpl::builder()
+            .with_get_cryptocurrency_usdt_rate_responses(btreemap! {
+                "BTC".to_string() => Ok(btc_queried_exchange_rate_with_failed_exchanges_mock(vec![])),
+                "ICP".to_string() => Ok(icp_queried_exchange_rate_with_failed_exchanges_mock(vec![]))
+            })
+            .build();
+        let env = TestEnvironment::builder()
+            .with_cycles_available(XRC_REQUEST_CYCLES_COST)
+            .with_accepted_cycles(XRC_MINIMUM_FEE_COST)
+            .build();
+        let request = GetExchangeRateRequest {
+            base_asset: btc_asset(),
+            quote_asset: icp_asset(),
+            timestamp: Some(0),
+        };
+
+        let result = get_exchange_rate_internal(&env, &call_exchanges_impl, &request)
+            .now_or_never()
+            .expect("future should complete");
+        assert!(matches!(result, Err(ExchangeRateError::RateLimited)));
+    }
+}
+
+mod get_request_log {
+    use ic_xrc_types::{GetRequestLogError, GetRequestLogRequest, RequestLogFilter};
+
+    use super::*;
+    use crate::api::get_request_log_internal;
+
+    /// This function tests that only NNS Governance and the controllers of the canister may
+    /// read the request logs.
+    #[test]
+    fn get_request_log_requires_nns_governance_or_a_controller() {
+        let request = GetRequestLogRequest {
+            offset: 0,
+            limit: 10,
+            filter: RequestLogFilter::default(),
+        };
+        let controller = Principal::from_text("r7inp-6aaaa-aaaaa-aaabq-cai")
+            .expect("should be a valid textual principal ID");
+
+        let env = TestEnvironment::builder()
+            .with_caller(PRIVILEGED_CANISTER_IDS[0])
+            .with_controller(controller)
+            .build();
+        assert!(matches!(
+            get_request_log_internal(&env, &request),
+            Err(GetRequestLogError::NotAuthorized)
+        ));
+
+        let env = TestEnvironment::builder()
+            .with_caller(NNS_GOVERNANCE_CANISTER_ID)
+            .build();
+        assert!(matches!(get_request_log_internal(&env, &request), Ok(page) if page.total == 0));
+
+        let env = TestEnvironment::builder()
+            .with_caller(controller)
+            .with_controller(controller)
+            .build();
+        assert!(matches!(get_request_log_internal(&env, &request), Ok(page) if page.total == 0));
+    }
+}
+
+mod get_certified_exchange_rate {
+    use super::*;
+
+    fn setup_forex_store() {
+        with_forex_rate_store_mut(|store| {
+            store.put(
+                0,
+                btreemap! {
+                    "EUR".to_string() =>
+                        QueriedExchangeRate::new(
+                            eur_asset(),
+                            usd_asset(),
+                            0,
+                            &[800_000_000, 800_000_000, 800_000_000, 800_000_000],
+                            4,
+                            4,
+                            Some(0),
+                            RateAggregation::Median,
+                        ),
+                    COMPUTED_XDR_SYMBOL.to_string() => test_cxdr_rate(),
+                },
+            );
+        });
+    }
+
+    fn call_exchanges_impl() -> TestCallExchangesImpl {
+        TestCallExchangesImpl::builder()
+            .with_get_cryptocurrency_usdt_rate_responses(btreemap! {
+                "ICP".to_string() => Ok(icp_queried_exchange_rate_with_failed_exchanges_mock(vec![])),
+                "BTC".to_string() => Ok(btc_queried_exchange_rate_with_failed_exchanges_mock(vec![])),
+            })
+            .with_get_stablecoin_rates_responses(btreemap! {
+                USDS.to_string() => Ok(stablecoin_mock_with_failed_exchanges(USDS, &[RATE_UNIT], vec![])),
+                USDC.to_string() => Ok(stablecoin_mock_with_failed_exchanges(USDC, &[RATE_UNIT], vec![])),
+            })
+            .build()
+    }
+
+    fn get_exchange_rate(base_asset: Asset, quote_asset: Asset) -> ExchangeRate {
+        let env = TestEnvironment::builder()
+            .with_cycles_available(XRC_REQUEST_CYCLES_COST)
+            .with_accepted_cycles(XRC_REQUEST_CYCLES_COST - XRC_IMMEDIATE_REFUND_CYCLES)
+            .build();
+        let request = GetExchangeRateRequest {
+            base_asset,
+            quote_asset,
+            timestamp: Some(0),
+        };
+        get_exchange_rate_internal(&env, &call_exchanges_impl(), &request)
+            .now_or_never()
+            .expect("future should complete")
+            .expect("rate should be retrieved")
+    }
+
+    /// This function tests that a successful update call for a privileged asset pair sets the
+    /// certified data and that the rate can then be retrieved with its certificate.
+    #[test]
+    fn get_certified_exchange_rate_returns_rate_of_privileged_pair() {
+        setup_forex_store();
+        let env = TestEnvironment::builder()
+            .with_cycles_available(XRC_REQUEST_CYCLES_COST)
+            .with_accepted_cycles(XRC_REQUEST_CYCLES_COST - XRC_IMMEDIATE_REFUND_CYCLES)
+            .build();
+        let request = GetExchangeRateRequest {
+            base_asset: icp_asset(),
+            quote_asset: eur_asset(),
+            timestamp: Some(0),
+        };
+        let rate = get_exchange_rate_internal(&env, &call_exchanges_impl(), &request)
+            .now_or_never()
+            .expect("future should complete")
+            .expect("rate should be retrieved");
+        assert!(!env.certified_data().is_empty());
+        assert_eq!(
+            env.certified_data(),
+            with_certified_rates(|rates| rates.root_hash()).to_vec()
+        );
+
+        let env = TestEnvironment::builder()
+            .with_data_certificate(vec![1, 2, 3])
+            .build();
+        let request = GetCertifiedExchangeRateRequest {
+            base_asset: icp_asset(),
+            quote_asset: eur_asset(),
+        };
+        let certified_rate = get_certified_exchange_rate_internal(&env, &request)
+            .expect("certified rate should be returned");
+        assert_eq!(certified_rate.rate, rate);
+        assert_eq!(certified_rate.certificate, vec![1, 2, 3]);
+        assert!(!certified_rate.witness.is_empty());
+    }
+
+    /// This function tests that the rates of unprivileged asset pairs are not certified.
+    #[test]
+    fn get_certified_exchange_rate_returns_error_for_uncertified_pair() {
+        get_exchange_rate(btc_asset(), icp_asset());
+        let env = TestEnvironment::builder()
+            .with_data_certificate(vec![1, 2, 3])
+            .build();
+        let request = GetCertifiedExchangeRateRequest {
+            base_asset: btc_asset(),
+            quote_asset: icp_asset(),
+        };
+
+        let result = get_certified_exchange_rate_internal(&env, &request);
+
+        assert!(
+            matches!(result, Err(ExchangeRateError::Other(ref error)) if error.code == crate::errors::RATE_NOT_CERTIFIED_ERROR_CODE),
+            "Received the following result: {:#?}",
+            result
+        );
+    }
+
+    /// This function tests that an error is returned if no certificate is available, i.e.,
+    /// if the endpoint is not called as a query.
+    #[test]
+    fn get_certified_exchange_rate_requires_certificate() {
+        setup_forex_store();
+        get_exchange_rate(icp_asset(), eur_asset());
+        let env = TestEnvironment::builder().build();
+        let request = GetCertifiedExchangeRateRequest {
+            base_asset: icp_asset(),
+            quote_asset: eur_asset(),
+        };
+
+        let result = get_certified_exchange_rate_internal(&env, &request);
+
+        assert!(
+            matches!(result, Err(ExchangeRateError::Other(ref error)) if error.code == crate::errors::RATE_NOT_CERTIFIED_ERROR_CODE),
+            "Received the following result: {:#?}",
+            result
+        );
+    }
+}
+
+mod get_exchange_rate_series {
+    use super::*;
+
+    /// Returns a series of USDT rates for the given asset with a single rate per timestamp.
+    fn series_mock(asset: Asset, rates: &[(u64, u64)]) -> RateSeries {
+        rates
+            .iter()
+            .map(|(timestamp, rate)| {
+                let rate = QueriedExchangeRate::new(
+                    asset.clone(),
+                    usdt_asset(),
+                    *timestamp,
+                    &[*rate, *rate, *rate],
+                    EXCHANGES.len(),
+                    3,
+                    None,
+                    RateAggregation::Median,
+                );
+                (*timestamp, rate)
+            })
+            .collect()
+    }
+
+    fn stablecoin_asset(symbol: &str) -> Asset {
+        Asset {
+            symbol: symbol.to_string(),
+            class: AssetClass::Cryptocurrency,
+        }
+    }
+
+    fn request(
+        base_asset: Asset,
+        quote_asset: Asset,
+        start: u64,
+        end: u64,
+        step: u64,
+    ) -> GetExchangeRateSeriesRequest {
+        GetExchangeRateSeriesRequest {
+            base_asset,
+            quote_asset,
+            start,
+            end,
+            step,
+        }
+    }
+
+    fn setup_forex_store() {
+        with_forex_rate_store_mut(|store| {
+            for (timestamp, rate) in [(0, 800_000_000), (86_400, RATE_UNIT)] {
+                store.put(
+                    timestamp,
+                    btreemap! {
+                        "EUR".to_string() =>
+                            QueriedExchangeRate::new(
+                                eur_asset(),
+                                usd_asset(),
+                                timestamp,
+                                &[rate, rate, rate, rate],
+                                4,
+                                4,
+                                Some(timestamp),
+                                RateAggregation::Median,
+                            ),
+                        COMPUTED_XDR_SYMBOL.to_string() => test_cxdr_rate(),
+                    },
+                );
+            }
+        });
+    }
+
+    /// This function tests that a series for a cryptocurrency pair is retrieved with a single
+    /// window per asset and that minutes missing a rate for either asset are omitted.
+    #[test]
+    fn get_exchange_rate_series_for_crypto_pair() {
+        let call_exchanges_impl = TestCallExchangesImpl::builder()
+            .with_get_cryptocurrency_usdt_rate_series_responses(btreemap! {
+                "BTC".to_string() => Ok(series_mock(btc_asset(), &[
+                    (60, 16_000 * RATE_UNIT),
+                    (120, 16_000 * RATE_UNIT),
+                    (180, 18_000 * RATE_UNIT),
+                ])),
+                "ICP".to_string() => Ok(series_mock(icp_asset(), &[
+                    (60, 4 * RATE_UNIT),
+                    (180, 8 * RATE_UNIT),
+                ])),
+            })
+            .build();
+        let env = TestEnvironment::builder()
+            .with_time_secs(200)
+            .with_cycles_available(XRC_REQUEST_CYCLES_COST)
+            .with_accepted_cycles(XRC_BASE_CYCLES_COST + 2 * XRC_OUTBOUND_HTTP_CALL_CYCLES_COST)
+            .build();
+
+        let result = get_exchange_rate_series_internal(
+            &env,
+            &call_exchanges_impl,
+            &request(btc_asset(), icp_asset(), 70, 190, 60),
+        )
+        .now_or_never()
+        .expect("future should complete")
+        .expect("series should be returned");
+
+        let rates = result
+            .iter()
+            .map(|rate| (rate.timestamp, rate.rate))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rates,
+            vec![(60, 4_000 * RATE_UNIT), (180, 2_250 * RATE_UNIT)]
+        );
+        assert_eq!(
+            *call_exchanges_impl
+                .get_cryptocurrency_usdt_rate_series_calls
+                .read()
+                .unwrap(),
+            vec![(btc_asset(), 60, 180), (icp_asset(), 60, 180)]
+        );
+    }
+
+    /// This function tests that a series for a crypto/fiat pair combines the cryptocurrency
+    /// and stablecoin rates of every minute with the forex rate of the same day.
+    #[test]
+    fn get_exchange_rate_series_for_crypto_fiat_pair() {
+        setup_forex_store();
+        let call_exchanges_impl = TestCallExchangesImpl::builder()
+            .with_get_cryptocurrency_usdt_rate_series_responses(btreemap! {
+                "ICP".to_string() => Ok(series_mock(icp_asset(), &[
+                    (86_340, 4 * RATE_UNIT),
+                    (86_400, 9 * RATE_UNIT),
+                ])),
+            })
+            .with_get_stablecoin_rate_series_responses(btreemap! {
+                USDS.to_string() => Ok(series_mock(stablecoin_asset(USDS), &[
+                    (86_340, RATE_UNIT),
+                    (86_400, RATE_UNIT),
+                ])),
+                USDC.to_string() => Ok(series_mock(stablecoin_asset(USDC), &[
+                    (86_340, RATE_UNIT),
+                    (86_400, RATE_UNIT),
+                ])),
+            })
+            .build();
+        let env = TestEnvironment::builder()
+            .with_time_secs(2 * 86_400)
+            .with_cycles_available(XRC_REQUEST_CYCLES_COST)
+            .with_accepted_cycles(XRC_BASE_CYCLES_COST + 2 * XRC_OUTBOUND_HTTP_CALL_CYCLES_COST)
+            .build();
+
+        let result = get_exchange_rate_series_internal(
+            &env,
+            &call_exchanges_impl,
+            &request(icp_asset(), eur_asset(), 86_340, 86_400, 60),
+        )
+        .now_or_never()
+        .expect("future should complete")
+        .expect("series should be returned");
+
+        let rates = result
+            .iter()
+            .map(|rate| (rate.timestamp, rate.rate))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rates,
+            vec![(86_340, 5 * RATE_UNIT), (86_400, 9 * RATE_UNIT)]
+        );
+    }
+
+    /// This function tests that a series for a fiat pair is read from the forex rate store at
+    /// day granularity without any outbound calls.
+    #[test]
+    fn get_exchange_rate_series_for_fiat_pair() {
+        setup_forex_store();
+        let call_exchanges_impl = TestCallExchangesImpl::builder().build();
+        let env = TestEnvironment::builder()
+            .with_time_secs(2 * 86_400)
+            .with_cycles_available(XRC_REQUEST_CYCLES_COST)
+            .with_accepted_cycles(XRC_BASE_CYCLES_COST)
+            .build();
+
+        let result = get_exchange_rate_series_internal(
+            &env,
+            &call_exchanges_impl,
+            &request(eur_asset(), usd_asset(), 0, 86_400, 86_400),
+        )
+        .now_or_never()
+        .expect("future should complete")
+        .expect("series should be returned");
+
+        let rates = result.iter().map(|rate| rate.rate).collect::<Vec<_>>();
+        assert_eq!(rates, vec![800_000_000, RATE_UNIT]);
+        assert!(call_exchanges_impl
+            .get_cryptocurrency_usdt_rate_series_calls
+            .read()
+            .unwrap()
+            .is_empty());
+    }
+
+    /// This function tests that invalid series are rejected and charged the minimum fee.
+    #[test]
+    fn get_exchange_rate_series_rejects_invalid_series() {
+        let call_exchanges_impl = TestCallExchangesImpl::builder().build();
+        let env = TestEnvironment::builder()
+            .with_time_secs(100_000)
+            .with_cycles_available(XRC_REQUEST_CYCLES_COST)
+            .with_accepted_cycles(XRC_MINIMUM_FEE_COST)
+            .build();
+
+        let invalid_requests = vec![
+            // The step is zero.
+            request(btc_asset(), icp_asset(), 0, 60, 0),
+            // The start is after the end.
+            request(btc_asset(), icp_asset(), 120, 60, 60),
+            // The step is not a multiple of a minute.
+            request(btc_asset(), icp_asset(), 0, 60, 30),
+            // The step is not a multiple of a day for a fiat pair.
+            request(eur_asset(), usd_asset(), 0, 86_400, 3_600),
+            // The window spans more than an hour.
+            request(btc_asset(), icp_asset(), 0, 3_660, 60),
+        ];
+        for series_request in invalid_requests {
+            let result =
+                get_exchange_rate_series_internal(&env, &call_exchanges_impl, &series_request)
+                    .now_or_never()
+                    .expect("future should complete");
+            assert!(
+                matches!(result, Err(ExchangeRateError::Other(ref error)) if error.code == crate::errors::INVALID_SERIES_ERROR_CODE),
+                "Received the following result: {:#?}",
+                result
+            );
+        }
+
+        let result = get_exchange_rate_series_internal(
+            &env,
+            &call_exchanges_impl,
+            &request(btc_asset(), icp_asset(), 0, 100_060, 60),
+        )
+        .now_or_never()
+        .expect("future should complete");
+        assert!(
+            matches!(result, Err(ExchangeRateError::Other(ref error)) if error.code == crate::errors::TIMESTAMP_IS_IN_FUTURE_ERROR_CODE),
+            "Received the following result: {:#?}",
+            result
+        );
+        assert!(call_exchanges_impl
+            .get_cryptocurrency_usdt_rate_series_calls
+            .read()
+            .unwrap()
+            .is_empty());
+    }
+
+    /// This function tests that the windows of a series are counted on the route chosen for a
+    /// single rate: a cryptocurrency that too few exchanges list against USDT is priced through
+    /// an intermediate asset, which takes two windows.
+    #[test]
+    fn get_num_series_windows_needed_counts_the_windows_on_the_route() {
+        let now_secs = 6_000;
+        setup_listings_with_only_btc(now_secs);
+        with_listing_store_mut(|store| {
+            for exchange in super::super::get_available_exchanges().into_iter().take(3) {
+                store.accept(
+                    exchange.name(),
+                    ListedPairs {
+                        bases: BTreeSet::from(["BTC".to_string()]),
+                        total_markets: 300,
+                        pairs: BTreeMap::from([(
+                            "PEPE".to_string(),
+                            BTreeSet::from(["BTC".to_string()]),
+                        )]),
+                    },
+                    now_secs,
+                );
+            }
+        });
+        let num_windows_needed = |base_asset: Asset, quote_asset: Asset| {
+            super::super::get_num_series_windows_needed(
+                &GetExchangeRateRequest {
+                    base_asset,
+                    quote_asset,
+                    timestamp: Some(now_secs),
+                },
+                now_secs,
+            )
+        };
+
+        assert_eq!(num_windows_needed(btc_asset(), usdt_asset()), 1);
+        assert_eq!(num_windows_needed(pepe_asset(), usdt_asset()), 2);
+        assert_eq!(num_windows_needed(pepe_asset(), btc_asset()), 3);
+        assert_eq!(
+            num_windows_needed(pepe_asset(), gbp_asset()),
+            2 + super::super::STABLECOIN_BASES.len()
+        );
+    }
+
+    /// This function tests that a TWAP averages the median rates of the minutes in the window
+    /// ending at the current minute, skipping minutes without a rate, and reports the window
+    /// in the metadata.
+    #[test]
+    fn get_twap_exchange_rate_averages_the_minute_rates() {
+        let call_exchanges_impl = TestCallExchangesImpl::builder()
+            .with_get_cryptocurrency_usdt_rate_series_responses(btreemap! {
+                "BTC".to_string() => Ok(series_mock(btc_asset(), &[
+                    (60, 16_000 * RATE_UNIT),
+                    (120, 16_000 * RATE_UNIT),
+                    (180, 16_000 * RATE_UNIT),
+                    (240, 16_000 * RATE_UNIT),
+                    (300, 16_000 * RATE_UNIT),
+                ])),
+                "ICP".to_string() => Ok(series_mock(icp_asset(), &[
+                    (60, 4 * RATE_UNIT),
+                    (120, 4 * RATE_UNIT),
+                    (180, 4 * RATE_UNIT),
+                    (300, 8 * RATE_UNIT),
+                ])),
+            })
+            .build();
+        let env = TestEnvironment::builder()
+            .with_time_secs(330)
+            .with_cycles_available(XRC_REQUEST_CYCLES_COST)
+            .with_accepted_cycles(XRC_BASE_CYCLES_COST + 2 * XRC_OUTBOUND_HTTP_CALL_CYCLES_COST)
+            .build();
+
+        let rate = get_twap_exchange_rate_internal(
+            &env,
+            &call_exchanges_impl,
+            &GetTwapExchangeRateRequest {
+                base_asset: btc_asset(),
+                quote_asset: icp_asset(),
+                timestamp: None,
+                window_minutes: 5,
+            },
+        )
+        .now_or_never()
+        .expect("future should complete")
+        .expect("TWAP should be returned");
+
+        // The minute rates are 4,000, 4,000, 4,000, and 2,000.
+        assert_eq!(rate.rate, 3_500 * RATE_UNIT);
+        assert_eq!(rate.timestamp, 300);
+        assert_eq!(rate.metadata.twap_window_minutes, Some(5));
+        assert_eq!(
+            *call_exchanges_impl
+                .get_cryptocurrency_usdt_rate_series_calls
+                .read()
+                .unwrap(),
+            vec![(btc_asset(), 60, 300), (icp_asset(), 60, 300)]
+        );
+    }
+
+    /// This function tests that TWAP requests with an invalid window or without a
+    /// cryptocurrency are rejected and charged the minimum fee.
+    #[test]
+    fn get_twap_exchange_rate_rejects_invalid_requests() {
+        let call_exchanges_impl = TestCallExchangesImpl::builder().build();
+        let env = TestEnvironment::builder()
+            .with_time_secs(100_000)
+            .with_cycles_available(XRC_REQUEST_CYCLES_COST)
+            .with_accepted_cycles(XRC_MINIMUM_FEE_COST)
+            .build();
+
+        let invalid_requests = [
+            (btc_asset(), icp_asset(), 0),
+            (btc_asset(), icp_asset(), 61),
+            (eur_asset(), usd_asset(), 5),
+        ];
+        for (base_asset, quote_asset, window_minutes) in invalid_requests {
+            let result = get_twap_exchange_rate_internal(
+                &env,
+                &call_exchanges_impl,
+                &GetTwapExchangeRateRequest {
+                    base_asset,
+                    quote_asset,
+                    timestamp: None,
+                    window_minutes,
+                },
+            )
+            .now_or_never()
+            .expect("future should complete");
+            assert!(
+                matches!(result, Err(ExchangeRateError::Other(ref error)) if error.code == crate::errors::INVALID_TWAP_ERROR_CODE),
+                "Received the following result: {:#?}",
+                result
+            );
+        }
+        assert!(call_exchanges_impl
+            .get_cryptocurrency_usdt_rate_series_calls
+            .read()
+            .unwrap()
+            .is_empty());
+    }
+}
+
+/// This function tests that the supported assets are the bases of the exchange listings and
+/// the fiat currencies of the latest day in the forex rate store.
+#[test]
+fn list_supported_assets_returns_listed_bases_and_latest_forex_rates() {
+    let coinbase = Exchange::Coinbase(Coinbase);
+    with_listing_store_mut(|store| {
+        store.accept(
+            coinbase.name(),
+            ListedPairs {
+                bases: BTreeSet::from(["BTC".to_string(), "ICP".to_string()]),
+                total_markets: 300,
+                pairs: BTreeMap::new(),
+            },
+            0,
+        );
+    });
+    with_forex_rate_store_mut(|store| {
+        store.put(
+            0,
+            btreemap! {
+                "GBP".to_string() => QueriedExchangeRate::new(
+                    gbp_asset(),
+                    usd_asset(),
+                    0,
+                    &[1_200_000_000, 1_200_000_000],
+                    2,
+                    2,
+                    Some(0),
+                    RateAggregation::Median,
+                ),
+            },
+        );
+        store.put(
+            86_400,
+            btreemap! {
+                "EUR".to_string() => QueriedExchangeRate::new(
+                    eur_asset(),
+                    usd_asset(),
+                    86_400,
+                    &[800_000_000, 800_000_000, 800_000_000],
+                    3,
+                    3,
+                    Some(86_400),
+                    RateAggregation::Median,
+                ),
+            },
+        );
+    });
+    let env = TestEnvironment::builder().with_time_secs(86_400).build();
+
+    let supported_assets = list_supported_assets_internal(&env);
+
+    let cryptocurrencies = supported_assets
+        .cryptocurrencies
+        .iter()
+        .map(|cryptocurrency| cryptocurrency.symbol.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(cryptocurrencies, vec!["BTC", "ICP", "USDT"]);
+    assert_eq!(
+        supported_assets.cryptocurrencies[0].exchanges,
+        vec![coinbase.name().to_string()]
+    );
+    assert_eq!(supported_assets.forex_timestamp, Some(86_400));
+    assert_eq!(supported_assets.fiat_currencies.len(), 1);
+    assert_eq!(supported_assets.fiat_currencies[0].symbol, "EUR");
+    assert_eq!(supported_assets.fiat_currencies[0].num_sources, 3);
+}
+
+/// This function tests to ensure a rate is returned when asking for a
+/// USD/crypto pair.
+#[test]
+fn get_exchange_rate_for_usd_crypto_pair() {
+    let call_exchanges_impl = TestCallExchangesImpl::builder()
+        .with_get_cryptocurrency_usdt_rate_responses(btreemap! {
+            "ICP".to_string() => Ok(icp_queried_exchange_rate_with_failed_exchanges_mock(vec![]))
+        })
+        .with_get_stablecoin_rates_responses(btreemap! {
+            USDS.to_string() => Ok(stablecoin_mock_with_failed_exchanges(USDS, &[RATE_UNIT], vec![])),
+            USDC.to_string() => Ok(stablecoin_mock_with_failed_exchanges(USDC, &[RATE_UNIT], vec![]))
+        })
+        .build();
+    let env = TestEnvironment::builder()
+        .with_cycles_available(XRC_REQUEST_CYCLES_COST)
+        .with_accepted_cycles(XRC_REQUEST_CYCLES_COST - XRC_IMMEDIATE_REFUND_CYCLES)
+        .build();
+
+    let request = GetExchangeRateRequest {
+        base_asset: usd_asset(),
+        quote_asset: icp_asset(),
+        timestamp: Some(0),
+    };
+
+    let result = get_exchange_rate_internal(&env, &call_exchanges_impl, &request)
+        .now_or_never()
+        .expect("future should complete");
+    assert!(
+        matches!(result, Ok(ref rate) if rate.rate == 250_000_000),
+        "Received the following result: {:#?}",
+        result
+    );
+    assert_eq!(
+        call_exchanges_impl
+            .get_cryptocurrency_usdt_rate_calls
+            .read()
+            .unwrap()
+            .len(),
+        1
+    );
+    assert_eq!(
+        call_exchanges_impl
+            .get_stablecoin_rates_calls
+            .read()
+            .unwrap()
+            .len(),
+        1
+    );
+}
+
+/// This function tests to ensure a rate is returned when asking for a
+/// crypto/non-USD pair.
+#[test]
+fn get_exchange_rate_for_crypto_non_usd_pair() {
+    with_forex_rate_store_mut(|store| {
+        store.put(
+            0,
+            btreemap! {
+                    "EUR".to_string() =>
+                        QueriedExchangeRate::new(
+                            eur_asset(),
+                            usd_asset(),
+                            0,
+                            &[800_000_000, 800_000_000, 800_000_000, 800_000_000],
+                            4,
+                            4,
+                            Some(0),
+                            RateAggregation::Median,
+                        ),
+                    // It is necessary to have a CXDR rate with at least 4 sources for the rate store to return a result
+                    COMPUTED_XDR_SYMBOL.to_string() => test_cxdr_rate(),
+            },
+        );
+    });
+
+    let call_exchanges_impl = TestCallExchangesImpl::builder()
+        .with_get_cryptocurrency_usdt_rate_responses(btreemap! {
+            "ICP".to_string() => Ok(icp_queried_exchange_rate_with_failed_exchanges_mock(vec![]))
+        })
+        .with_get_stablecoin_rates_responses(btreemap! {
+           USDS.to_string() => Ok(stablecoin_mock_with_failed_exchanges(USDS, &[RATE_UNIT], vec![])),
+            USDC.to_string() => Ok(stablecoin_mock_with_failed_exchanges(USDC, &[RATE_UNIT], vec![]))
+        })
+        .build();
+    let env = TestEnvironment::builder()
+        .with_cycles_available(XRC_REQUEST_CYCLES_COST)
+        .with_accepted_cycles(XRC_REQUEST_CYCLES_COST - XRC_IMMEDIATE_REFUND_CYCLES)
+        .build();
+
+    let request = GetExchangeRateRequest {
+        base_asset: icp_asset(),
+        quote_asset: eur_asset(),
+        timestamp: Some(0),
+    };
+    let result = get_exchange_rate_internal(&env, &call_exchanges_impl, &request)
+        .now_or_never()
+        .expect("future should complete");
+    assert!(
+        matches!(result, Ok(ref rate) if rate.rate == 5 * RATE_UNIT),
+        "Received the following result: {:#?}",
+        result
+    );
+    assert_eq!(
+        call_exchanges_impl
+            .get_cryptocurrency_usdt_rate_calls
+            .read()
+            .unwrap()
+            .len(),
+        1
+    );
+    assert_eq!(
+        call_exchanges_impl
+            .get_stablecoin_rates_calls
+            .read()
+            .unwrap()
+            .len(),
+        1
+    );
+}
+
+/// This function tests that `get_exchange_rate_v2` returns the individual source rates behind
+/// the rate: the exchange rates of the cryptocurrency, the chosen stablecoin, and the forex
+/// sources.
+#[test]
+fn get_exchange_rate_v2_returns_source_rates() {
+    with_forex_rate_store_mut(|store| {
+        store.put(
+            0,
+            btreemap! {
+                "EUR".to_string() => QueriedExchangeRate::new(
+                    eur_asset(),
+                    usd_asset(),
+                    0,
+                    &[800_000_000, 800_000_000, 800_000_000, 800_000_000],
+                    4,
+                    4,
+                    Some(0),
+                    RateAggregation::Median,
+                ),
+                COMPUTED_XDR_SYMBOL.to_string() => test_cxdr_rate(),
+            },
+        );
+    });
+    with_forex_rate_collector_mut(|collector| {
+        for source in ["MonetaryAuthorityOfSingapore", "EuropeanCentralBank"] {
+            collector.update(
+                source.to_string(),
+                0,
+                btreemap! { "EUR".to_string() => 800_000_000 },
+            );
+        }
+    });
+
+    let mut icp_rate = icp_queried_exchange_rate_mock();
+    icp_rate.source_rates = Some(QueriedSourceRates {
+        base_asset: vec![
+            ("Coinbase".to_string(), 4 * RATE_UNIT),
+            ("KuCoin".to_string(), 4 * RATE_UNIT),
+            ("Okx".to_string(), 4 * RATE_UNIT),
+        ],
+        ..Default::default()
+    });
+    let call_exchanges_impl = TestCallExchangesImpl::builder()
+        .with_get_cryptocurrency_usdt_rate_responses(btreemap! {
+            "ICP".to_string() => Ok(QueriedExchangeRateWithFailedExchanges {
+                queried_exchange_rate: icp_rate,
+                failed_exchanges: vec![],
+            })
+        })
+        .with_get_stablecoin_rates_responses(btreemap! {
+            USDS.to_string() => Ok(stablecoin_mock_with_failed_exchanges(USDS, &[RATE_UNIT], vec![])),
+            USDC.to_string() => Ok(stablecoin_mock_with_failed_exchanges(USDC, &[RATE_UNIT], vec![]))
+        })
+        .build();
+    let env = TestEnvironment::builder()
+        .with_cycles_available(XRC_REQUEST_CYCLES_COST)
+        .with_accepted_cycles(XRC_REQUEST_CYCLES_COST - XRC_IMMEDIATE_REFUND_CYCLES)
+        .build();
+
+    let request = GetExchangeRateV2Request {
+        base_asset: icp_asset(),
+        quote_asset: eur_asset(),
+        timestamp: Some(0),
+        options: None,
+    };
+    let rate = get_exchange_rate_v2_internal(&env, &call_exchanges_impl, &request)
+        .now_or_never()
+        .expect("future should complete")
+        .expect("the rate should be determined");
+
+    assert_eq!(rate.rate, 5 * RATE_UNIT);
+    assert_eq!(
+        rate.sources,
+        ExchangeRateSources {
+            base_asset_rates: ["Coinbase", "KuCoin", "Okx"]
+                .into_iter()
+                .map(|source| SourceRate {
+                    source: source.to_string(),
+                    rate: 4 * RATE_UNIT,
+                })
+                .collect(),
+            quote_asset_rates: vec![],
+            stablecoin: Some(SourceRate {
+                source: USDC.to_string(),
+                rate: RATE_UNIT,
+            }),
+            forex_sources: vec![
+                "EuropeanCentralBank".to_string(),
+                "MonetaryAuthorityOfSingapore".to_string(),
+            ],
+        }
     );
 }
 
+mod get_exchange_rate_v2_options {
+    use super::*;
+
+    fn call_exchanges_impl() -> TestCallExchangesImpl {
+        TestCallExchangesImpl::builder()
+            .with_get_cryptocurrency_usdt_rate_responses(btreemap! {
+                "ICP".to_string() => Ok(icp_queried_exchange_rate_with_failed_exchanges_mock(vec![]))
+            })
+            .build()
+    }
+
+    /// Requests the ICP/USDT rate, expecting the given number of outbound calls to be charged.
+    fn get_icp_usdt_rate(
+        call_exchanges_impl: &TestCallExchangesImpl,
+        options: ExchangeRateOptions,
+        num_outbound_calls: u128,
+    ) -> GetExchangeRateV2Result {
+        cache_usd_conversion_rates(0);
+        let env = TestEnvironment::builder()
+            .with_cycles_available(XRC_REQUEST_CYCLES_COST)
+            .with_accepted_cycles(
+                XRC_BASE_CYCLES_COST + num_outbound_calls * XRC_OUTBOUND_HTTP_CALL_CYCLES_COST,
+            )
+            .build();
+        let request = GetExchangeRateV2Request {
+            base_asset: icp_asset(),
+            quote_asset: usdt_asset(),
+            timestamp: Some(0),
+            options: Some(options),
+        };
+        get_exchange_rate_v2_internal(&env, call_exchanges_impl, &request)
+            .now_or_never()
+            .expect("future should complete")
+    }
+
+    /// This function tests that a cached rate with too few source rates is ignored and the
+    /// rate is retrieved from the exchanges instead.
+    #[test]
+    fn cached_rate_with_too_few_rates_is_ignored() {
+        with_cache_mut(|cache| {
+            cache.insert(&icp_queried_exchange_rate_with_one_rate_mock());
+        });
+        let call_exchanges_impl = call_exchanges_impl();
+
+        let result = get_icp_usdt_rate(
+            &call_exchanges_impl,
+            ExchangeRateOptions {
+                min_num_received_rates: Some(3),
+                ..Default::default()
+            },
+            1,
+        );
+
+        assert!(
+            matches!(result, Ok(ref rate) if rate.rate == 4 * RATE_UNIT),
+            "Received the following result: {:#?}",
+            result
+        );
+        assert_eq!(
+            call_exchanges_impl
+                .get_cryptocurrency_usdt_rate_calls
+                .read()
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    /// This function tests that the cached rate is used by default, but not if the caller
+    /// does not accept cached data.
+    #[test]
+    fn cached_rate_is_ignored_if_cached_data_is_not_accepted() {
+        with_cache_mut(|cache| {
+            cache.insert(&icp_queried_exchange_rate_with_one_rate_mock());
+        });
+        let call_exchanges_impl = call_exchanges_impl();
+
+        let result = get_icp_usdt_rate(&call_exchanges_impl, ExchangeRateOptions::default(), 0);
+        assert!(
+            matches!(result, Ok(ref rate) if rate.rate == 8 * RATE_UNIT),
+            "Received the following result: {:#?}",
+            result
+        );
+
+        let result = get_icp_usdt_rate(
+            &call_exchanges_impl,
+            ExchangeRateOptions {
+                accept_cached_data: Some(false),
+                ..Default::default()
+            },
+            1,
+        );
+        assert!(
+            matches!(result, Ok(ref rate) if rate.rate == 4 * RATE_UNIT),
+            "Received the following result: {:#?}",
+            result
+        );
+    }
+
+    /// This function tests that an error is returned if the retrieved rate does not meet
+    /// the caller's requirements.
+    #[test]
+    fn rate_not_meeting_requirements_returns_error() {
+        let call_exchanges_impl = call_exchanges_impl();
+
+        let result = get_icp_usdt_rate(
+            &call_exchanges_impl,
+            ExchangeRateOptions {
+                min_num_received_rates: Some(4),
+                ..Default::default()
+            },
+            1,
+        );
+        assert!(
+            matches!(result, Err(ExchangeRateError::Other(ref error)) if error.code == crate::errors::RATE_REQUIREMENTS_NOT_MET_ERROR_CODE),
+            "Received the following result: {:#?}",
+            result
+        );
+
+        let icp_rate = QueriedExchangeRate::new(
+            icp_asset(),
+            usdt_asset(),
+            0,
+            &[4 * RATE_UNIT, 4 * RATE_UNIT + 1_000, 4 * RATE_UNIT + 2_000],
+            EXCHANGES.len(),
+            3,
+            None,
+            RateAggregation::Median,
+        );
+        with_cache_mut(|cache| {
+            cache.insert(&icp_rate);
+        });
+        let result = get_icp_usdt_rate(
+            &call_exchanges_impl,
+            ExchangeRateOptions {
+                max_standard_deviation: Some(999),
+                ..Default::default()
+            },
+            0,
+        );
+        assert!(
+            matches!(result, Err(ExchangeRateError::Other(ref error)) if error.code == crate::errors::RATE_REQUIREMENTS_NOT_MET_ERROR_CODE),
+            "Received the following result: {:#?}",
+            result
+        );
+
+        let result = get_icp_usdt_rate(
+            &call_exchanges_impl,
+            ExchangeRateOptions {
+                max_standard_deviation: Some(1_000),
+                ..Default::default()
+            },
+            0,
+        );
+        assert!(
+            matches!(result, Ok(ref rate) if rate.metadata.standard_deviation == 1_000),
+            "Received the following result: {:#?}",
+            result
+        );
+    }
+}
+
 /// This function tests to ensure a rate is returned when asking for a
 /// non-USD/crypto pair.
 #[test]
@@ -1012,6 +2700,7 @@ fn get_exchange_rate_for_non_usd_crypto_pair() {
                             4,
                             4,
                             Some(0),
+                            RateAggregation::Median,
                         ),
                     // It is necessary to have a CXDR rate with at least 4 sources for the rate store to return a result
                     COMPUTED_XDR_SYMBOL.to_string() => test_cxdr_rate(),
@@ -1081,6 +2770,7 @@ fn get_exchange_rate_for_non_usd_crypto_pair_crypto_asset_not_found() {
                             4,
                             4,
                             Some(0),
+                            RateAggregation::Median,
                         ),
                     // It is necessary to have a CXDR rate with at least 4 sources for the rate store to return a result
                     COMPUTED_XDR_SYMBOL.to_string() => test_cxdr_rate(),
@@ -1161,6 +2851,7 @@ fn get_exchange_rate_for_fiat_eur_usd_pair() {
                             4,
                             4,
                             Some(0),
+                            RateAggregation::Median,
                         ),
                     // It is necessary to have a CXDR rate with at least 4 sources for the rate store to return a result
                     COMPUTED_XDR_SYMBOL.to_string() => test_cxdr_rate(),
@@ -1205,6 +2896,7 @@ fn get_exchange_rate_for_fiat_with_unknown_symbol() {
                             4,
                             4,
                             Some(0),
+                            RateAggregation::Median,
                         ),
                     // It is necessary to have a CXDR rate with at least 4 sources for the rate store to return a result
                     COMPUTED_XDR_SYMBOL.to_string() => test_cxdr_rate(),
@@ -1252,6 +2944,7 @@ fn get_exchange_rate_for_fiat_with_unknown_timestamp() {
                             4,
                             4,
                             Some(0),
+                            RateAggregation::Median,
                         ),
                     // It is necessary to have a CXDR rate with at least 4 sources for the rate store to return a result
                     COMPUTED_XDR_SYMBOL.to_string() => test_cxdr_rate(),
@@ -1361,6 +3054,7 @@ fn get_exchange_rate_will_retrieve_rates_if_inflight_tracking_contains_any_symbo
 /// calls.
 #[test]
 fn get_exchange_rate_can_retrieve_icp_usdt() {
+    cache_usd_conversion_rates(0);
     let call_exchanges_impl = TestCallExchangesImpl::builder()
         .with_get_cryptocurrency_usdt_rate_responses(btreemap! {
             "ICP".to_string() => Ok(icp_queried_exchange_rate_with_failed_exchanges_mock(vec![]))
@@ -1394,6 +3088,7 @@ fn get_exchange_rate_can_retrieve_icp_usdt() {
 /// calls.
 #[test]
 fn get_exchange_rate_can_retrieve_usdt_icp() {
+    cache_usd_conversion_rates(0);
     let call_exchanges_impl = TestCallExchangesImpl::builder()
         .with_get_cryptocurrency_usdt_rate_responses(btreemap! {
             "ICP".to_string() => Ok(icp_queried_exchange_rate_with_failed_exchanges_mock(vec![]))
@@ -1441,6 +3136,7 @@ mod privileged_asset_rate_limiting {
                             4,
                             4,
                             Some(0),
+                            RateAggregation::Median,
                         ),
                     COMPUTED_XDR_SYMBOL.to_string() => test_cxdr_rate(),
                 },
@@ -1520,56 +3216,207 @@ mod privileged_asset_rate_limiting {
 
     /// Privileged pair (BTC-GBP) with timestamp in the past: rate limiter applies (no bypass).
     #[test]
-    fn privileged_pair_timestamp_past_hits_rate_limiter() {
+    fn privileged_pair_timestamp_past_hits_rate_limiter() {
+        setup_forex_store_gbp_at_0();
+        let current_timestamp: u64 = 150; // so that requested 0 is not "recent"
+        let env = TestEnvironment::builder()
+            .with_time_secs(current_timestamp)
+            .with_cycles_available(XRC_REQUEST_CYCLES_COST)
+            .with_accepted_cycles(XRC_MINIMUM_FEE_COST)
+            .build();
+        let request = GetExchangeRateRequest {
+            base_asset: btc_asset(),
+            quote_asset: gbp_asset(),
+            timestamp: Some(0),
+        };
+        set_request_counter(REQUEST_COUNTER_TRIGGER_RATE_LIMIT);
+
+        let result = get_exchange_rate_internal(&env, &call_exchanges_impl(), &request)
+            .now_or_never()
+            .expect("future should complete");
+
+        assert!(
+            matches!(result, Err(ExchangeRateError::RateLimited)),
+            "Privileged pair with timestamp in the past should be rate limited, got: {:#?}",
+            result
+        );
+    }
+
+    /// This function tests that [get_exchange_rate] returns [ExchangeRateError::RateLimited]
+    /// for a non-privileged crypto-fiat pair (PEPE-GBP) when the rate limit is hit.
+    /// Uses a request timestamp in the past (0) and current time 150 so the timestamp is not "recent".
+    #[test]
+    fn unprivileged_pair_hits_rate_limiter() {
+        setup_forex_store_gbp_at_0();
+        let env = TestEnvironment::builder()
+            .with_cycles_available(XRC_REQUEST_CYCLES_COST)
+            .with_accepted_cycles(XRC_MINIMUM_FEE_COST)
+            .build();
+        let request = GetExchangeRateRequest {
+            base_asset: pepe_asset(),
+            quote_asset: gbp_asset(),
+            timestamp: None,
+        };
+        set_request_counter(REQUEST_COUNTER_TRIGGER_RATE_LIMIT);
+
+        let result = get_exchange_rate_internal(&env, &call_exchanges_impl(), &request)
+            .now_or_never()
+            .expect("future should complete");
+
+        assert!(
+            matches!(result, Err(ExchangeRateError::RateLimited)),
+            "Expected RateLimited for PEPE-GBP when rate limit is hit, got: {:#?}",
+            result
+        );
+    }
+
+    /// This function tests that a crypto-fiat pair request that needs the rates of the
+    /// cryptocurrency and of all stablecoins is not rate limited if no other requests are
+    /// in flight.
+    #[test]
+    fn crypto_fiat_pair_without_cached_rates_is_not_rate_limited() {
+        setup_forex_store_gbp_at_0();
+        let num_rates_needed = 1 + super::super::STABLECOIN_BASES.len();
+        let request_counter_limit =
+            crate::with_config(|config| config.request_counter_limit as usize);
+        assert!(
+            super::super::get_available_exchanges().len() * num_rates_needed
+                <= request_counter_limit
+        );
+        let env = TestEnvironment::builder()
+            .with_cycles_available(XRC_REQUEST_CYCLES_COST)
+            .with_accepted_cycles(XRC_BASE_CYCLES_COST + 2 * XRC_OUTBOUND_HTTP_CALL_CYCLES_COST)
+            .build();
+        let request = GetExchangeRateRequest {
+            base_asset: icp_asset(),
+            quote_asset: gbp_asset(),
+            timestamp: Some(0),
+        };
+        set_request_counter(0);
+
+        let result = get_exchange_rate_internal(&env, &call_exchanges_impl(), &request)
+            .now_or_never()
+            .expect("future should complete");
+
+        assert!(
+            matches!(result, Ok(ref rate) if rate.base_asset.symbol == "ICP"),
+            "Expected a rate for ICP-GBP, got: {:#?}",
+            result
+        );
+    }
+
+    /// This function tests that a crypto-fiat pair request that needs the rates of the
+    /// cryptocurrency and of all stablecoins is not rate limited while another request that
+    /// needs the maximum number of rates is in flight.
+    #[test]
+    fn crypto_fiat_pair_without_cached_rates_is_not_rate_limited_alongside_another_request() {
         setup_forex_store_gbp_at_0();
-        let current_timestamp: u64 = 150; // so that requested 0 is not "recent"
         let env = TestEnvironment::builder()
-            .with_time_secs(current_timestamp)
             .with_cycles_available(XRC_REQUEST_CYCLES_COST)
-            .with_accepted_cycles(XRC_MINIMUM_FEE_COST)
+            .with_accepted_cycles(XRC_BASE_CYCLES_COST + 2 * XRC_OUTBOUND_HTTP_CALL_CYCLES_COST)
             .build();
         let request = GetExchangeRateRequest {
-            base_asset: btc_asset(),
+            base_asset: icp_asset(),
             quote_asset: gbp_asset(),
             timestamp: Some(0),
         };
-        set_request_counter(REQUEST_COUNTER_TRIGGER_RATE_LIMIT);
+        set_request_counter(
+            super::super::get_available_exchanges().len() * MAX_NUM_RATES_PER_REQUEST,
+        );
 
-        let result = get_exchange_rate_internal(&env, &call_exchanges_impl(), &request)
+        let call_exchanges_impl = call_exchanges_impl();
+        let result = get_exchange_rate_internal(&env, &call_exchanges_impl, &request)
             .now_or_never()
             .expect("future should complete");
 
         assert!(
-            matches!(result, Err(ExchangeRateError::RateLimited)),
-            "Privileged pair with timestamp in the past should be rate limited, got: {:#?}",
+            matches!(result, Ok(ref rate) if rate.base_asset.symbol == "ICP"),
+            "Expected a rate for ICP-GBP, got: {:#?}",
             result
         );
+        assert_eq!(
+            call_exchanges_impl
+                .get_stablecoin_rates_calls
+                .read()
+                .unwrap()[0]
+                .1
+                .len(),
+            super::super::STABLECOIN_BASES.len()
+        );
     }
 
-    /// This function tests that [get_exchange_rate] returns [ExchangeRateError::RateLimited]
-    /// for a non-privileged crypto-fiat pair (PEPE-GBP) when the rate limit is hit.
-    /// Uses a request timestamp in the past (0) and current time 150 so the timestamp is not "recent".
+    /// This function tests that a non-privileged crypto-fiat pair request for a cryptocurrency
+    /// that is routed through an intermediate asset is admitted if the request counter leaves
+    /// room for the rates on the route and the stablecoin rates, even though the cryptocurrency
+    /// would be queried directly if the route failed.
     #[test]
-    fn unprivileged_pair_hits_rate_limiter() {
+    fn routed_crypto_fiat_pair_is_not_rate_limited_for_the_fallback() {
         setup_forex_store_gbp_at_0();
+        let now_secs = 100;
+        setup_listings_with_only_btc(now_secs);
+        // Three exchanges list PEPE against ETH and one exchange lists it against USDT.
+        with_listing_store_mut(|store| {
+            for (index, exchange) in super::super::get_available_exchanges()
+                .into_iter()
+                .take(4)
+                .enumerate()
+            {
+                let (bases, pairs) = if index < 3 {
+                    (
+                        BTreeSet::from(["BTC".to_string()]),
+                        BTreeMap::from([("PEPE".to_string(), BTreeSet::from(["ETH".to_string()]))]),
+                    )
+                } else {
+                    (
+                        BTreeSet::from(["BTC".to_string(), "PEPE".to_string()]),
+                        BTreeMap::new(),
+                    )
+                };
+                store.accept(
+                    exchange.name(),
+                    ListedPairs {
+                        bases,
+                        total_markets: 300,
+                        pairs,
+                    },
+                    now_secs,
+                );
+            }
+        });
+        let call_exchanges_impl = TestCallExchangesImpl::builder()
+            .with_get_cryptocurrency_usdt_rate_responses(btreemap! {
+                "PEPE".to_string() => Ok(icp_queried_exchange_rate_with_failed_exchanges_mock(vec![]))
+            })
+            .with_get_stablecoin_rates_responses(btreemap! {
+                USDS.to_string() => Ok(stablecoin_mock_with_failed_exchanges(USDS, &[RATE_UNIT], vec![])),
+                USDC.to_string() => Ok(stablecoin_mock_with_failed_exchanges(USDC, &[RATE_UNIT], vec![])),
+            })
+            .build();
         let env = TestEnvironment::builder()
             .with_cycles_available(XRC_REQUEST_CYCLES_COST)
-            .with_accepted_cycles(XRC_MINIMUM_FEE_COST)
+            .with_accepted_cycles(XRC_BASE_CYCLES_COST + 2 * XRC_OUTBOUND_HTTP_CALL_CYCLES_COST)
+            .with_time_secs(now_secs)
             .build();
         let request = GetExchangeRateRequest {
             base_asset: pepe_asset(),
             quote_asset: gbp_asset(),
-            timestamp: None,
+            timestamp: Some(0),
         };
-        set_request_counter(REQUEST_COUNTER_TRIGGER_RATE_LIMIT);
+        // The counter leaves room for the PEPE/ETH and ETH/USDT rates and the rates of all
+        // stablecoins, but not for the PEPE/USDT rate on top of them.
+        let num_rates_needed = 2 + super::super::STABLECOIN_BASES.len();
+        set_request_counter(
+            REQUEST_COUNTER_LIMIT
+                - super::super::get_available_exchanges().len() * num_rates_needed,
+        );
 
-        let result = get_exchange_rate_internal(&env, &call_exchanges_impl(), &request)
+        let result = get_exchange_rate_internal(&env, &call_exchanges_impl, &request)
             .now_or_never()
             .expect("future should complete");
 
         assert!(
-            matches!(result, Err(ExchangeRateError::RateLimited)),
-            "Expected RateLimited for PEPE-GBP when rate limit is hit, got: {:#?}",
+            result.is_ok(),
+            "Expected a rate for PEPE-GBP, got: {:#?}",
             result
         );
     }
@@ -1694,9 +3541,8 @@ mod uses_previous_minute_when_timestamp_is_null_if_request_would_be_pending {
     fn crypto_fiat_pair_has_asset_and_stablecoins_in_cache() {
         with_cache_mut(|cache| {
             cache.insert(&icp_queried_exchange_rate_mock());
-            cache.insert(&stablecoin_mock(USDS, &[RATE_UNIT]));
-            cache.insert(&stablecoin_mock(USDC, &[RATE_UNIT]));
         });
+        cache_usd_conversion_rates(0);
         set_inflight_tracking(vec!["BTC".to_string(), "ICP".to_string()], 60);
         let call_exchanges_impl = TestCallExchangesImpl::builder().build();
         let env = TestEnvironment::builder()
@@ -2249,131 +4095,695 @@ mod request_contains_invalid_symbols {
     }
 }
 
-mod stablecoin_symbol_metrics {
-    use super::super::record_stablecoin_symbol_rates_received;
-    use crate::{
-        make_metric_key, reset_labeled_metrics_for_test, with_labeled_counters, LabelKey,
-        MetricName,
+mod stablecoin_symbol_metrics {
+    use super::super::record_stablecoin_symbol_rates_received;
+    use crate::{
+        make_metric_key, reset_labeled_metrics_for_test, with_labeled_counters, LabelKey,
+        MetricName,
+    };
+
+    fn reset() {
+        reset_labeled_metrics_for_test();
+    }
+
+    fn usds_key() -> crate::MetricKey {
+        make_metric_key(
+            MetricName::StablecoinSymbolRatesReceived,
+            &[(LabelKey::Symbol, "USDS")],
+        )
+    }
+
+    #[test]
+    fn counts_usable_rates_across_calls() {
+        reset();
+        record_stablecoin_symbol_rates_received("USDS", &[1, 2, 3, 4, 5, 6]);
+        record_stablecoin_symbol_rates_received("USDS", &[10, 20]);
+
+        with_labeled_counters(|m| {
+            assert_eq!(m.get(&usds_key()).copied(), Some(8));
+        });
+    }
+
+    #[test]
+    fn excludes_zero_rates_from_the_count() {
+        // The fix-point of this test: `Ok(0)` results reach
+        // `get_stablecoin_rate` in the non-invert path (e.g. USDS/USDT
+        // pairs where USDS is the base) and would otherwise inflate the
+        // counter even though `QueriedExchangeRate::new` drops them
+        // downstream. The filter must live inside the recorder so the
+        // call site can't accidentally count raw `rates.len()`.
+        reset();
+        record_stablecoin_symbol_rates_received("USDS", &[0, 5_000, 0, 1_000, 0]);
+
+        with_labeled_counters(|m| {
+            assert_eq!(
+                m.get(&usds_key()).copied(),
+                Some(2),
+                "only the two non-zero rates should count"
+            );
+        });
+    }
+
+    #[test]
+    fn materialises_series_on_empty_input() {
+        // The DAI-rebrand scenario: a symbol that produces no usable
+        // rates at all. The series must exist after the first call so
+        // a `rate(...) == 0` alert has something to evaluate against
+        // from t=0, even before any non-zero rate has ever been seen.
+        reset();
+        record_stablecoin_symbol_rates_received("USDS", &[]);
+
+        with_labeled_counters(|m| {
+            assert_eq!(m.get(&usds_key()).copied(), Some(0));
+        });
+    }
+
+    #[test]
+    fn materialises_series_when_all_inputs_are_zero() {
+        // Same materialisation guarantee, but with the filter actually
+        // exercised — every exchange responded with `Ok(0)` so the
+        // input slice is non-empty but the usable count is still zero.
+        reset();
+        record_stablecoin_symbol_rates_received("USDS", &[0, 0, 0]);
+
+        with_labeled_counters(|m| {
+            assert_eq!(m.get(&usds_key()).copied(), Some(0));
+        });
+    }
+
+    #[test]
+    fn separates_symbols() {
+        reset();
+        record_stablecoin_symbol_rates_received("USDS", &[1, 2, 3]);
+        record_stablecoin_symbol_rates_received("USDC", &[1, 2, 3, 4, 5]);
+
+        with_labeled_counters(|m| {
+            assert_eq!(m.len(), 2);
+            let usdc = make_metric_key(
+                MetricName::StablecoinSymbolRatesReceived,
+                &[(LabelKey::Symbol, "USDC")],
+            );
+            assert_eq!(m.get(&usds_key()).copied(), Some(3));
+            assert_eq!(m.get(&usdc).copied(), Some(5));
+        });
+    }
+}
+
+/// The series path queries only exchanges whose discovered listing contains the
+/// requested base and whose candle endpoint takes a time range: an exchange with
+/// a fresh listing that omits the base is dropped, while exchanges with no listing
+/// fail open and are kept. This guards the exchange selection shared by
+/// `get_cryptocurrency_usdt_rate` and `get_cryptocurrency_usdt_rate_series`.
+#[test]
+fn window_exchanges_quoting_base_in_usd_filters_by_listing() {
+    let now_secs = 1_000;
+    let exchanges: Vec<&Exchange> = EXCHANGES
+        .iter()
+        .filter(|exchange| exchange.supports_windows())
+        .collect();
+    let gated = exchanges[0];
+
+    // Clean slate, then give one exchange a fresh listing that includes BTC but
+    // not ICP. Every other exchange is left without a listing (fail-open).
+    with_listing_store_mut(|store| {
+        *store = Default::default();
+        store.accept(
+            gated.name(),
+            ListedPairs {
+                bases: BTreeSet::from(["BTC".to_string()]),
+                total_markets: 300,
+                pairs: BTreeMap::new(),
+            },
+            now_secs,
+        );
+    });
+
+    // ICP is absent from the gated exchange's listing, so it is dropped; the
+    // listing-less exchanges fail open and are kept.
+    let icp = super::window_exchanges_quoting_base_in_usd(&exchanges, "ICP", now_secs);
+    assert!(!icp.iter().any(|(e, _)| e.name() == gated.name()));
+    assert_eq!(icp.len(), exchanges.len() - 1);
+
+    // BTC is listed on the gated exchange, so the full set is queried.
+    let btc = super::window_exchanges_quoting_base_in_usd(&exchanges, "BTC", now_secs);
+    assert_eq!(btc.len(), exchanges.len());
+
+    // Exchanges that cannot serve a window are never queried.
+    let all_exchanges: Vec<&Exchange> = EXCHANGES.iter().collect();
+    let btc = super::window_exchanges_quoting_base_in_usd(&all_exchanges, "BTC", now_secs);
+    assert_eq!(btc.len(), exchanges.len());
+}
+
+/// Every exchange is queried with the preferred USD-like quote asset that its listing contains
+/// the base against, falling back to its preferred quote asset without a listing.
+#[test]
+fn exchanges_quoting_base_in_usd_selects_listed_quote() {
+    let now_secs = 1_000;
+    let exchanges: Vec<&Exchange> = EXCHANGES.iter().collect();
+    let coinbase = Exchange::Coinbase(Coinbase);
+    with_listing_store_mut(|store| {
+        store.accept(
+            coinbase.name(),
+            ListedPairs {
+                bases: BTreeSet::from(["BTC".to_string()]),
+                total_markets: 300,
+                pairs: BTreeMap::from([(
+                    "ICP".to_string(),
+                    BTreeSet::from(["USDC".to_string(), "USDT".to_string()]),
+                )]),
+            },
+            now_secs,
+        );
+    });
+
+    let quoted = super::exchanges_quoting_base_in_usd(&exchanges, "ICP", now_secs)
+        .into_iter()
+        .map(|(exchange, quote)| (exchange.name(), quote))
+        .collect::<BTreeMap<_, _>>();
+    // The ICP/USDT pair is missing from the USDT bases and USDC is not a quote of Coinbase.
+    assert_eq!(quoted.get(coinbase.name()), None);
+    assert_eq!(quoted.len(), exchanges.len() - 1);
+    assert!(quoted
+        .values()
+        .all(|quote| *quote == "USDT" || *quote == "USD"));
+    assert_eq!(quoted.get("CryptoCom"), Some(&"USD"));
+
+    let quoted = super::exchanges_quoting_base_in_usd(&exchanges, "BTC", now_secs);
+    assert!(quoted.contains(&(&coinbase, "USDT")));
+}
+
+/// The rates quoted in USDC or USD are converted into USDT with the cached stablecoin rates, and
+/// no conversion rate is returned if the stablecoin rates cannot be retrieved.
+#[test]
+fn get_usd_quote_conversion_rates_uses_stablecoin_rates() {
+    let timestamp = 1_704_153_600;
+    let stablecoin_rate = |symbol: &str, rate: u64| {
+        QueriedExchangeRate::new(
+            Asset {
+                symbol: symbol.to_string(),
+                class: AssetClass::Cryptocurrency,
+            },
+            usdt_asset(),
+            timestamp,
+            &[rate, rate],
+            2,
+            2,
+            None,
+            RateAggregation::Median,
+        )
+    };
+    with_cache_mut(|cache| {
+        cache.insert(&stablecoin_rate(USDC, 1_020_000_000));
+        cache.insert(&stablecoin_rate(USDS, 1_000_000_000));
+    });
+    let quotes = BTreeSet::from(["USDC", "USD"]);
+
+    let conversion_rates = super::get_usd_quote_conversion_rates(&[], &quotes, timestamp)
+        .now_or_never()
+        .expect("future should complete");
+    // Of the two stablecoins, the first one is considered the most consistent one.
+    assert_eq!(
+        conversion_rates,
+        BTreeMap::from([("USD", 1_020_000_000), ("USDC", 1_020_000_000)])
+    );
+    assert_eq!(
+        super::convert_usd_quoted_rate(2 * RATE_UNIT, conversion_rates["USDC"]),
+        2_040_000_000
+    );
+
+    let conversion_rates = super::get_usd_quote_conversion_rates(&[], &quotes, timestamp + 60)
+        .now_or_never()
+        .expect("future should complete");
+    assert!(conversion_rates.is_empty());
+}
+
+/// This function tests that the rates of all stablecoins in the basket that are not cached are
+/// retrieved to convert rates quoted in USD, and that only the USDC rate is retrieved to convert
+/// rates quoted in USDC.
+#[test]
+fn get_missed_conversion_stablecoins_returns_all_missed_basket_stablecoins() {
+    let timestamp = 1_704_153_600;
+    let stablecoin_rate = |symbol: &str| {
+        QueriedExchangeRate::new(
+            Asset {
+                symbol: symbol.to_string(),
+                class: AssetClass::Cryptocurrency,
+            },
+            usdt_asset(),
+            timestamp,
+            &[RATE_UNIT, RATE_UNIT],
+            2,
+            2,
+            None,
+            RateAggregation::Median,
+        )
+    };
+    let usd = BTreeSet::from(["USD"]);
+    let usdc = BTreeSet::from(["USDC"]);
+    let usd_and_usdc = BTreeSet::from(["USD", "USDC"]);
+
+    assert_eq!(
+        super::get_missed_conversion_stablecoins(&usd, timestamp),
+        BTreeSet::from([USDC, USDS, DAI, FDUSD, PYUSD])
+    );
+    assert_eq!(
+        super::get_missed_conversion_stablecoins(&usdc, timestamp),
+        BTreeSet::from([USDC])
+    );
+
+    // Cached stablecoins are not retrieved again, but the remaining ones still are, even
+    // though enough rates are cached to derive the USD conversion rate.
+    with_cache_mut(|cache| {
+        cache.insert(&stablecoin_rate(DAI));
+        cache.insert(&stablecoin_rate(PYUSD));
+    });
+    assert_eq!(
+        super::get_missed_conversion_stablecoins(&usd, timestamp),
+        BTreeSet::from([USDC, USDS, FDUSD])
+    );
+    assert_eq!(
+        super::get_missed_conversion_stablecoins(&usd_and_usdc, timestamp),
+        BTreeSet::from([USDC, USDS, FDUSD])
+    );
+
+    with_cache_mut(|cache| cache.insert(&stablecoin_rate(USDC)));
+    assert!(super::get_missed_conversion_stablecoins(&usdc, timestamp).is_empty());
+    assert_eq!(
+        super::get_missed_conversion_stablecoins(&usd, timestamp),
+        BTreeSet::from([USDS, FDUSD])
+    );
+
+    // The USD conversion rate is derived from the cached stablecoins of the basket.
+    let conversion_rates = super::get_usd_quote_conversion_rates(&[], &usd_and_usdc, timestamp)
+        .now_or_never()
+        .expect("future should complete");
+    assert_eq!(
+        conversion_rates,
+        BTreeMap::from([("USD", RATE_UNIT), ("USDC", RATE_UNIT)])
+    );
+}
+
+/// This function tests that a request for a pair quoted in USDT retrieves the rates of all
+/// stablecoins in the basket to convert the rates that exchanges quote in USD, so that a
+/// subsequent request for a crypto/fiat pair checks the depeg over the whole basket instead of
+/// the two stablecoins that would suffice for the conversion.
+#[test]
+fn usd_quote_conversion_caches_the_whole_stablecoin_basket() {
+    config::set_config(Config {
+        stablecoin_depeg_policy: Some(StablecoinDepegPolicy::Reject {
+            max_deviation_bps: 200,
+        }),
+        ..config::default_config()
+    });
+    with_forex_rate_store_mut(|store| {
+        store.put(
+            0,
+            btreemap! {
+                "EUR".to_string() =>
+                    QueriedExchangeRate::new(
+                        eur_asset(),
+                        usd_asset(),
+                        0,
+                        &[800_000_000, 800_000_000, 800_000_000, 800_000_000],
+                        4,
+                        4,
+                        Some(0),
+                        RateAggregation::Median,
+                    ),
+                COMPUTED_XDR_SYMBOL.to_string() => test_cxdr_rate(),
+            },
+        );
+    });
+    // USDC depegged, while the other stablecoins of the basket agree.
+    let stablecoin_responses = super::STABLECOIN_BASES
+        .iter()
+        .map(|symbol| {
+            let rate = if *symbol == USDC {
+                970_000_000
+            } else {
+                RATE_UNIT
+            };
+            (
+                symbol.to_string(),
+                Ok(stablecoin_mock_with_failed_exchanges(
+                    symbol,
+                    &[rate],
+                    vec![],
+                )),
+            )
+        })
+        .collect();
+    let call_exchanges_impl = TestCallExchangesImpl::builder()
+        .with_get_cryptocurrency_usdt_rate_responses(btreemap! {
+            "ICP".to_string() => Ok(icp_queried_exchange_rate_with_failed_exchanges_mock(vec![]))
+        })
+        .with_get_stablecoin_rates_responses(stablecoin_responses)
+        .build();
+
+    let env = TestEnvironment::builder()
+        .with_cycles_available(XRC_REQUEST_CYCLES_COST)
+        .with_accepted_cycles(XRC_BASE_CYCLES_COST + 2 * XRC_OUTBOUND_HTTP_CALL_CYCLES_COST)
+        .build();
+    let request = GetExchangeRateRequest {
+        base_asset: icp_asset(),
+        quote_asset: usdt_asset(),
+        timestamp: Some(0),
+    };
+    let result = get_exchange_rate_internal(&env, &call_exchanges_impl, &request)
+        .now_or_never()
+        .expect("future should complete");
+    assert!(
+        result.is_ok(),
+        "Received the following result: {:#?}",
+        result
+    );
+    let retrieved_stablecoins = call_exchanges_impl
+        .get_stablecoin_rates_calls
+        .read()
+        .unwrap()
+        .iter()
+        .flat_map(|(_, symbols, _)| symbols.clone())
+        .collect::<BTreeSet<_>>();
+    assert_eq!(
+        retrieved_stablecoins,
+        super::STABLECOIN_BASES
+            .iter()
+            .map(|symbol| symbol.to_string())
+            .collect()
+    );
+
+    // All rates are cached now, and the depegged USDC rate is outvoted.
+    let env = TestEnvironment::builder()
+        .with_cycles_available(XRC_REQUEST_CYCLES_COST)
+        .with_accepted_cycles(XRC_BASE_CYCLES_COST)
+        .build();
+    let request = GetExchangeRateRequest {
+        base_asset: icp_asset(),
+        quote_asset: eur_asset(),
+        timestamp: Some(0),
     };
+    let result = get_exchange_rate_internal(&env, &call_exchanges_impl, &request)
+        .now_or_never()
+        .expect("future should complete");
+    assert!(
+        matches!(result, Ok(ref rate) if rate.rate == 5 * RATE_UNIT),
+        "Received the following result: {:#?}",
+        result
+    );
+    assert_eq!(
+        call_exchanges_impl
+            .get_stablecoin_rates_calls
+            .read()
+            .unwrap()
+            .len(),
+        1
+    );
+}
 
-    fn reset() {
-        reset_labeled_metrics_for_test();
-    }
+/// Gives every exchange a fresh listing that only contains BTC.
+fn setup_listings_with_only_btc(now_secs: u64) {
+    with_listing_store_mut(|store| {
+        for exchange in EXCHANGES.iter() {
+            store.accept(
+                exchange.name(),
+                ListedPairs {
+                    bases: BTreeSet::from(["BTC".to_string()]),
+                    total_markets: 300,
+                    pairs: BTreeMap::new(),
+                },
+                now_secs,
+            );
+        }
+    });
+}
 
-    fn usds_key() -> crate::MetricKey {
-        make_metric_key(
-            MetricName::StablecoinSymbolRatesReceived,
-            &[(LabelKey::Symbol, "USDS")],
-        )
-    }
+/// This function tests that a cryptocurrency that too few exchanges list against USDT is routed
+/// through the intermediate asset that the most exchanges list it against, and that it is then
+/// no longer rejected as not listed.
+#[test]
+fn select_intermediate_asset_prefers_most_listed_intermediate() {
+    let now_secs = 1_000;
+    let exchanges: Vec<&Exchange> = EXCHANGES.iter().collect();
+    setup_listings_with_only_btc(now_secs);
+    assert!(super::select_intermediate_asset(&exchanges, "PEPE", now_secs).is_none());
+    assert!(super::check_asset_is_listed(&exchanges, "PEPE", now_secs).is_err());
 
-    #[test]
-    fn counts_usable_rates_across_calls() {
-        reset();
-        record_stablecoin_symbol_rates_received("USDS", &[1, 2, 3, 4, 5, 6]);
-        record_stablecoin_symbol_rates_received("USDS", &[10, 20]);
+    // Two exchanges list PEPE against BTC and three list it against ETH.
+    with_listing_store_mut(|store| {
+        for (index, exchange) in exchanges.iter().take(3).enumerate() {
+            let quotes = if index < 2 {
+                vec!["BTC", "ETH"]
+            } else {
+                vec!["ETH"]
+            };
+            store.accept(
+                exchange.name(),
+                ListedPairs {
+                    bases: BTreeSet::from(["BTC".to_string()]),
+                    total_markets: 300,
+                    pairs: BTreeMap::from([(
+                        "PEPE".to_string(),
+                        quotes.into_iter().map(str::to_string).collect(),
+                    )]),
+                },
+                now_secs,
+            );
+        }
+    });
 
-        with_labeled_counters(|m| {
-            assert_eq!(m.get(&usds_key()).copied(), Some(8));
-        });
-    }
+    let (intermediate, listing_exchanges) =
+        super::select_intermediate_asset(&exchanges, "PEPE", now_secs)
+            .expect("PEPE should be routed");
+    assert_eq!(intermediate, "ETH");
+    assert_eq!(
+        listing_exchanges
+            .iter()
+            .map(|(exchange, quote)| (exchange.name(), *quote))
+            .collect::<Vec<_>>(),
+        exchanges
+            .iter()
+            .take(3)
+            .map(|exchange| (exchange.name(), "ETH"))
+            .collect::<Vec<_>>()
+    );
+    assert!(super::check_asset_is_listed(&exchanges, "PEPE", now_secs).is_ok());
 
-    #[test]
-    fn excludes_zero_rates_from_the_count() {
-        // The fix-point of this test: `Ok(0)` results reach
-        // `get_stablecoin_rate` in the non-invert path (e.g. USDS/USDT
-        // pairs where USDS is the base) and would otherwise inflate the
-        // counter even though `QueriedExchangeRate::new` drops them
-        // downstream. The filter must live inside the recorder so the
-        // call site can't accidentally count raw `rates.len()`.
-        reset();
-        record_stablecoin_symbol_rates_received("USDS", &[0, 5_000, 0, 1_000, 0]);
+    // An asset with sufficiently many direct sources is not routed.
+    assert!(super::select_intermediate_asset(&exchanges, "BTC", now_secs).is_none());
+}
 
-        with_labeled_counters(|m| {
-            assert_eq!(
-                m.get(&usds_key()).copied(),
-                Some(2),
-                "only the two non-zero rates should count"
+/// This function tests that a request for a cryptocurrency that is routed through an
+/// intermediate asset is charged and rate limited for the intermediate rate as well.
+#[test]
+fn get_exchange_rate_counts_the_rates_of_a_routed_cryptocurrency() {
+    let now_secs = 100;
+    setup_listings_with_only_btc(now_secs);
+    // Three exchanges list PEPE against ETH only, so PEPE has no direct sources to fall back on.
+    with_listing_store_mut(|store| {
+        for exchange in EXCHANGES.iter().take(3) {
+            store.accept(
+                exchange.name(),
+                ListedPairs {
+                    bases: BTreeSet::from(["BTC".to_string()]),
+                    total_markets: 300,
+                    pairs: BTreeMap::from([(
+                        "PEPE".to_string(),
+                        BTreeSet::from(["ETH".to_string()]),
+                    )]),
+                },
+                now_secs,
             );
-        });
-    }
+        }
+    });
+    with_cache_mut(|cache| {
+        cache.insert(&btc_queried_exchange_rate_mock());
+    });
+    let call_exchanges_impl = TestCallExchangesImpl::builder()
+        .with_get_cryptocurrency_usdt_rate_responses(btreemap! {
+            "PEPE".to_string() => Ok(icp_queried_exchange_rate_with_failed_exchanges_mock(vec![]))
+        })
+        .build();
+    let request = GetExchangeRateRequest {
+        base_asset: pepe_asset(),
+        quote_asset: btc_asset(),
+        timestamp: Some(0),
+    };
 
-    #[test]
-    fn materialises_series_on_empty_input() {
-        // The DAI-rebrand scenario: a symbol that produces no usable
-        // rates at all. The series must exist after the first call so
-        // a `rate(...) == 0` alert has something to evaluate against
-        // from t=0, even before any non-zero rate has ever been seen.
-        reset();
-        record_stablecoin_symbol_rates_received("USDS", &[]);
+    // The PEPE/ETH and the ETH/USDT rates are charged.
+    let env = TestEnvironment::builder()
+        .with_cycles_available(XRC_REQUEST_CYCLES_COST)
+        .with_accepted_cycles(XRC_BASE_CYCLES_COST + 2 * XRC_OUTBOUND_HTTP_CALL_CYCLES_COST)
+        .with_time_secs(now_secs)
+        .build();
+    let result = get_exchange_rate_internal(&env, &call_exchanges_impl, &request)
+        .now_or_never()
+        .expect("future should complete");
+    assert!(result.is_ok(), "{:?}", result);
 
-        with_labeled_counters(|m| {
-            assert_eq!(m.get(&usds_key()).copied(), Some(0));
-        });
-    }
+    // The request is rate limited if the counter leaves room for a single rate only.
+    with_cache_mut(|cache| {
+        cache.insert(&btc_queried_exchange_rate_mock());
+    });
+    let request_counter_limit = crate::with_config(|config| config.request_counter_limit as usize);
+    set_request_counter(request_counter_limit - super::get_available_exchanges().len());
+    let env = TestEnvironment::builder()
+        .with_cycles_available(XRC_REQUEST_CYCLES_COST)
+        .with_accepted_cycles(XRC_MINIMUM_FEE_COST)
+        .with_time_secs(now_secs)
+        .build();
+    let result = get_exchange_rate_internal(&env, &call_exchanges_impl, &request)
+        .now_or_never()
+        .expect("future should complete");
+    assert!(
+        matches!(result, Err(ExchangeRateError::RateLimited)),
+        "{:?}",
+        result
+    );
+}
 
-    #[test]
-    fn materialises_series_when_all_inputs_are_zero() {
-        // Same materialisation guarantee, but with the filter actually
-        // exercised — every exchange responded with `Ok(0)` so the
-        // input slice is non-empty but the usable count is still zero.
-        reset();
-        record_stablecoin_symbol_rates_received("USDS", &[0, 0, 0]);
+/// This function tests that the rates are only weighted by volume if the configuration enables
+/// the volume-weighted median and every exchange reported a volume.
+#[test]
+fn get_rate_weights_uses_volumes_only_if_enabled_and_reported() {
+    let neutral_weights = vec![super::NEUTRAL_RATE_WEIGHT; 3];
+    assert_eq!(super::get_rate_weights(&[10, 1, 1]), neutral_weights);
 
-        with_labeled_counters(|m| {
-            assert_eq!(m.get(&usds_key()).copied(), Some(0));
-        });
-    }
+    config::set_config(Config {
+        crypto_rate_aggregation: Some(ic_xrc_types::RateAggregationMethod::VolumeWeightedMedian),
+        ..config::default_config()
+    });
+    assert_eq!(super::get_rate_weights(&[10, 1, 1]), vec![10, 1, 1]);
+    // An exchange without a volume must not be ignored, so the rates are not weighted.
+    assert_eq!(super::get_rate_weights(&[10, 1, 0]), neutral_weights);
+}
 
-    #[test]
-    fn separates_symbols() {
-        reset();
-        record_stablecoin_symbol_rates_received("USDS", &[1, 2, 3]);
-        record_stablecoin_symbol_rates_received("USDC", &[1, 2, 3, 4, 5]);
+/// This function tests that a cryptocurrency that no exchange lists is rejected with a dedicated
+/// error before any outbound calls are made, and that only the minimum fee is charged.
+#[test]
+fn get_exchange_rate_returns_asset_not_listed_error_without_outcalls() {
+    setup_listings_with_only_btc(0);
+    let call_exchanges_impl = TestCallExchangesImpl::builder()
+        .with_get_cryptocurrency_usdt_rate_responses(btreemap! {
+            "BTC".to_string() => Ok(btc_queried_exchange_rate_with_failed_exchanges_mock(vec![])),
+        })
+        .build();
+    let env = TestEnvironment::builder()
+        .with_cycles_available(XRC_REQUEST_CYCLES_COST)
+        .with_accepted_cycles(XRC_MINIMUM_FEE_COST)
+        .build();
 
-        with_labeled_counters(|m| {
-            assert_eq!(m.len(), 2);
-            let usdc = make_metric_key(
-                MetricName::StablecoinSymbolRatesReceived,
-                &[(LabelKey::Symbol, "USDC")],
-            );
-            assert_eq!(m.get(&usds_key()).copied(), Some(3));
-            assert_eq!(m.get(&usdc).copied(), Some(5));
-        });
+    for (base_asset, quote_asset) in [
+        (pepe_asset(), usdt_asset()),
+        (btc_asset(), pepe_asset()),
+        (pepe_asset(), usd_asset()),
+        (usd_asset(), pepe_asset()),
+    ] {
+        let request = GetExchangeRateRequest {
+            base_asset,
+            quote_asset,
+            timestamp: Some(0),
+        };
+        let result = get_exchange_rate_internal(&env, &call_exchanges_impl, &request)
+            .now_or_never()
+            .expect("future should complete");
+        assert!(
+            matches!(result, Err(ExchangeRateError::Other(ref error)) if error.code == crate::errors::ASSET_NOT_LISTED_ERROR_CODE),
+            "Received the following result: {:#?}",
+            result
+        );
     }
+
+    assert!(call_exchanges_impl
+        .get_cryptocurrency_usdt_rate_calls
+        .read()
+        .unwrap()
+        .is_empty());
 }
 
-/// The crypto path queries only exchanges whose discovered listing contains the
-/// requested base: an exchange with a fresh listing that omits the base is
-/// dropped, while exchanges with no listing fail open and are kept. This guards
-/// the listing-based gating wired into `get_cryptocurrency_usdt_rate`.
+/// This function tests that the listing check precedes the rate limit and inflight checks, i.e.,
+/// that a request for an unlisted asset returns the "asset not listed" error even if the canister
+/// is at capacity or the asset is already being retrieved.
 #[test]
-fn exchanges_listing_base_against_usdt_filters_by_listing() {
-    let now_secs = 1_000;
-    let exchanges: Vec<&Exchange> = EXCHANGES.iter().collect();
-    let gated = exchanges[0];
+fn get_exchange_rate_checks_listings_before_rate_limit_and_inflight() {
+    setup_listings_with_only_btc(0);
+    let call_exchanges_impl = TestCallExchangesImpl::builder().build();
+    let env = TestEnvironment::builder()
+        .with_cycles_available(XRC_REQUEST_CYCLES_COST)
+        .with_accepted_cycles(XRC_MINIMUM_FEE_COST)
+        .build();
+    let request = GetExchangeRateRequest {
+        base_asset: pepe_asset(),
+        quote_asset: usdt_asset(),
+        timestamp: Some(0),
+    };
 
-    // Clean slate, then give one exchange a fresh listing that includes BTC but
-    // not ICP. Every other exchange is left without a listing (fail-open).
-    with_listing_store_mut(|store| {
-        *store = Default::default();
-        store.accept(
-            gated.name(),
-            ListedPairs {
-                bases: BTreeSet::from(["BTC".to_string()]),
-                total_markets: 300,
-            },
-            now_secs,
-        );
-    });
+    set_request_counter(REQUEST_COUNTER_TRIGGER_RATE_LIMIT);
+    let result = get_exchange_rate_internal(&env, &call_exchanges_impl, &request)
+        .now_or_never()
+        .expect("future should complete");
+    assert!(
+        matches!(result, Err(ExchangeRateError::Other(ref error)) if error.code == crate::errors::ASSET_NOT_LISTED_ERROR_CODE),
+        "Received the following result: {:#?}",
+        result
+    );
 
-    // ICP is absent from the gated exchange's listing, so it is dropped; the
-    // listing-less exchanges fail open and are kept.
-    let icp = super::exchanges_listing_base_against_usdt(&exchanges, "ICP", now_secs);
-    assert!(!icp.iter().any(|e| e.name() == gated.name()));
-    assert_eq!(icp.len(), exchanges.len() - 1);
+    set_request_counter(0);
+    set_inflight_tracking(vec!["PEPE".to_string()], 0);
+    let result = get_exchange_rate_internal(&env, &call_exchanges_impl, &request)
+        .now_or_never()
+        .expect("future should complete");
+    assert!(
+        matches!(result, Err(ExchangeRateError::Other(ref error)) if error.code == crate::errors::ASSET_NOT_LISTED_ERROR_CODE),
+        "Received the following result: {:#?}",
+        result
+    );
+}
 
-    // BTC is listed on the gated exchange, so the full set is queried.
-    let btc = super::exchanges_listing_base_against_usdt(&exchanges, "BTC", now_secs);
-    assert_eq!(btc.len(), exchanges.len());
+/// This function tests that a request of a batch for a cryptocurrency that no exchange lists
+/// is rejected without retrieving any rates for it.
+#[test]
+fn get_exchange_rates_returns_asset_not_listed_error_without_outcalls() {
+    setup_listings_with_only_btc(0);
+    let call_exchanges_impl = TestCallExchangesImpl::builder()
+        .with_get_cryptocurrency_usdt_rate_responses(btreemap! {
+            "BTC".to_string() => Ok(btc_queried_exchange_rate_with_failed_exchanges_mock(vec![])),
+        })
+        .build();
+    let fee = XRC_BASE_CYCLES_COST + XRC_MINIMUM_FEE_COST + XRC_OUTBOUND_HTTP_CALL_CYCLES_COST;
+    let env = TestEnvironment::builder()
+        .with_cycles_available(XRC_REQUEST_CYCLES_COST)
+        .with_accepted_cycles(fee)
+        .build();
+    let requests = [pepe_asset(), btc_asset()]
+        .into_iter()
+        .map(|base_asset| GetExchangeRateRequest {
+            base_asset,
+            quote_asset: usdt_asset(),
+            timestamp: Some(0),
+        })
+        .collect::<Vec<_>>();
+
+    let results = get_exchange_rates_internal(&env, &call_exchanges_impl, &requests)
+        .now_or_never()
+        .expect("future should complete")
+        .expect("batch should succeed");
+
+    assert!(
+        matches!(results[0], Err(ExchangeRateError::Other(ref error)) if error.code == crate::errors::ASSET_NOT_LISTED_ERROR_CODE),
+        "Received the following results: {:#?}",
+        results
+    );
+    assert!(
+        matches!(results[1], Ok(ref rate) if rate.rate == 16_000 * RATE_UNIT),
+        "Received the following results: {:#?}",
+        results
+    );
+    let cryptocurrency_calls = call_exchanges_impl
+        .get_cryptocurrency_usdt_rate_calls
+        .read()
+        .unwrap()
+        .iter()
+        .map(|(_, asset, _)| asset.symbol.clone())
+        .collect::<Vec<_>>();
+    assert_eq!(cryptocurrency_calls, vec!["BTC"]);
 }
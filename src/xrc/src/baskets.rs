@@ -0,0 +1,323 @@
+//! The registry of the weighted basket currencies, e.g., CXDR. The USD rate of a basket is
+//! computed for every collection day from the collected forex rates and, for cryptocurrency
+//! components, from the exchange rates. The baskets are part of the configuration, so NNS
+//! Governance can add a basket or reweight an existing one through `update_config`.
+
+use ic_xrc_types::{Asset, AssetClass, Basket, BasketComponent};
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{forex::COMPUTED_XDR_SYMBOL, with_config, USD};
+
+/// A set of IMF SDR weights, i.e., the amounts of the currencies in one SDR multiplied by
+/// 1_000_000, together with the first day on which the set is in effect.
+pub(crate) struct SdrWeights {
+    pub effective_from: u64,
+    pub weights_per_million: [(&'static str, u64); 5],
+}
+
+/// The IMF SDR weights, ordered by their effective dates. An entry must never be changed or
+/// removed once it is in effect, so that the CXDR rates of past days stay reproducible, and a
+/// new set of weights may only be added with an effective date in the future. The schedule
+/// only provides the default baskets: a running canister persists its baskets with the
+/// configuration, so new weights are added to it through `update_config`.
+pub(crate) const SDR_WEIGHT_SCHEDULE: &[SdrWeights] = &[
+    // The weights with which the CXDR rates have been computed for every day so far.
+    SdrWeights {
+        effective_from: 0,
+        weights_per_million: [
+            (USD, 582_520),
+            ("EUR", 386_710),
+            ("CNY", 1_017_400),
+            ("JPY", 11_900_000),
+            ("GBP", 85_946),
+        ],
+    },
+];
+
+/// Returns a CXDR basket definition for every set of weights in the [SDR_WEIGHT_SCHEDULE].
+pub(crate) fn default_baskets() -> Vec<Basket> {
+    SDR_WEIGHT_SCHEDULE
+        .iter()
+        .map(|sdr_weights| Basket {
+            symbol: COMPUTED_XDR_SYMBOL.to_string(),
+            components: sdr_weights
+                .weights_per_million
+                .iter()
+                .map(|(symbol, weight_per_million)| BasketComponent {
+                    asset: Asset {
+                        symbol: symbol.to_string(),
+                        class: AssetClass::FiatCurrency,
+                    },
+                    weight_per_million: *weight_per_million,
+                })
+                .collect(),
+            effective_from: sdr_weights.effective_from,
+        })
+        .collect()
+}
+
+/// Returns the configured baskets, or the default baskets if none are configured.
+fn get_baskets() -> Vec<Basket> {
+    with_config(|config| config.baskets.clone()).unwrap_or_else(default_baskets)
+}
+
+/// Returns the baskets in effect on the day of the given timestamp, i.e., for each symbol the
+/// definition with the latest effective date that is not after the timestamp.
+pub(crate) fn get_effective_baskets(timestamp: u64) -> Vec<Basket> {
+    let mut effective_baskets: BTreeMap<String, Basket> = BTreeMap::new();
+    for basket in get_baskets()
+        .into_iter()
+        .filter(|basket| basket.effective_from <= timestamp)
+    {
+        let is_newer = effective_baskets
+            .get(&basket.symbol)
+            .is_none_or(|current| current.effective_from < basket.effective_from);
+        if is_newer {
+            effective_baskets.insert(basket.symbol.clone(), basket);
+        }
+    }
+    effective_baskets.into_values().collect()
+}
+
+/// Returns the symbols of the cryptocurrencies contained in the baskets in effect on the day
+/// of the given timestamp.
+pub(crate) fn get_crypto_component_symbols(timestamp: u64) -> BTreeSet<String> {
+    get_effective_baskets(timestamp)
+        .into_iter()
+        .flat_map(|basket| basket.components)
+        .filter(|component| component.asset.class == AssetClass::Cryptocurrency)
+        .map(|component| component.asset.symbol)
+        .collect()
+}
+
+/// Checks that every basket can be computed and that CXDR, which the forex rate store relies
+/// on to decide whether a day has sufficiently many sources, is defined for every day.
+pub(crate) fn validate(baskets: &[Basket]) -> Result<(), String> {
+    if !baskets
+        .iter()
+        .any(|basket| basket.symbol == COMPUTED_XDR_SYMBOL && basket.effective_from == 0)
+    {
+        return Err(format!(
+            "{} must have a definition effective from 0",
+            COMPUTED_XDR_SYMBOL
+        ));
+    }
+    for (index, basket) in baskets.iter().enumerate() {
+        if !is_uppercase_symbol(&basket.symbol) || basket.symbol == USD {
+            return Err(format!("{} is not a valid basket symbol", basket.symbol));
+        }
+        if baskets.iter().take(index).any(|other| {
+            other.symbol == basket.symbol && other.effective_from == basket.effective_from
+        }) {
+            return Err(format!(
+                "{} has more than one definition effective from {}",
+                basket.symbol, basket.effective_from
+            ));
+        }
+        if basket.components.is_empty() {
+            return Err(format!("{} has no components", basket.symbol));
+        }
+        for (index, component) in basket.components.iter().enumerate() {
+            let symbol = &component.asset.symbol;
+            if !is_uppercase_symbol(symbol) {
+                return Err(format!("{} is not an uppercase symbol", symbol));
+            }
+            if baskets.iter().any(|other| other.symbol == *symbol) {
+                return Err(format!(
+                    "{} cannot contain the basket {}",
+                    basket.symbol, symbol
+                ));
+            }
+            if component.weight_per_million == 0 {
+                return Err(format!(
+                    "the weight of {} in {} must be positive",
+                    symbol, basket.symbol
+                ));
+            }
+            if basket
+                .components
+                .iter()
+                .take(index)
+                .any(|other| other.asset.symbol == *symbol)
+            {
+                return Err(format!(
+                    "{} contains {} more than once",
+                    basket.symbol, symbol
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Checks that replacing the configured baskets with the given baskets neither changes nor
+/// removes a definition that is already in effect and does not add a definition that would
+/// take effect in the past, so that the basket rates of past days stay reproducible.
+pub(crate) fn validate_update(new_baskets: &[Basket], now_secs: u64) -> Result<(), String> {
+    let in_effect = |baskets: Vec<Basket>| {
+        baskets
+            .into_iter()
+            .filter(|basket| basket.effective_from <= now_secs)
+            .collect::<Vec<_>>()
+    };
+    let current_in_effect = in_effect(get_baskets());
+    let new_in_effect = in_effect(new_baskets.to_vec());
+    if let Some(basket) = current_in_effect
+        .iter()
+        .find(|basket| !new_in_effect.contains(basket))
+    {
+        return Err(format!(
+            "the definition of {} effective from {} is in effect and cannot be changed",
+            basket.symbol, basket.effective_from
+        ));
+    }
+    if let Some(basket) = new_in_effect
+        .iter()
+        .find(|basket| !current_in_effect.contains(basket))
+    {
+        return Err(format!(
+            "the definition of {} effective from {} must take effect in the future",
+            basket.symbol, basket.effective_from
+        ));
+    }
+    Ok(())
+}
+
+fn is_uppercase_symbol(symbol: &str) -> bool {
+    !symbol.is_empty() && *symbol == symbol.to_uppercase()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config;
+    use ic_xrc_types::Config;
+
+    fn basket(symbol: &str, components: &[(&str, AssetClass, u64)], effective_from: u64) -> Basket {
+        Basket {
+            symbol: symbol.to_string(),
+            components: components
+                .iter()
+                .map(|(symbol, class, weight_per_million)| BasketComponent {
+                    asset: Asset {
+                        symbol: symbol.to_string(),
+                        class: class.clone(),
+                    },
+                    weight_per_million: *weight_per_million,
+                })
+                .collect(),
+            effective_from,
+        }
+    }
+
+    /// The function verifies that, for each symbol, the latest definition that is already in
+    /// effect is chosen.
+    #[test]
+    fn get_effective_baskets_chooses_latest_definition() {
+        let index = |effective_from, weight_per_million| {
+            basket(
+                "INDEX",
+                &[
+                    ("EUR", AssetClass::FiatCurrency, weight_per_million),
+                    ("BTC", AssetClass::Cryptocurrency, 10),
+                ],
+                effective_from,
+            )
+        };
+        let mut baskets = default_baskets();
+        baskets.extend([index(2_000, 500_000), index(1_000, 400_000)]);
+        config::set_config(Config {
+            baskets: Some(baskets),
+            ..config::default_config()
+        });
+
+        let cxdr = default_baskets();
+        assert_eq!(get_effective_baskets(999), cxdr);
+        assert_eq!(
+            get_effective_baskets(1_999),
+            [cxdr.clone(), vec![index(1_000, 400_000)]].concat()
+        );
+        assert_eq!(
+            get_effective_baskets(2_000),
+            [cxdr, vec![index(2_000, 500_000)]].concat()
+        );
+        assert!(get_crypto_component_symbols(999).is_empty());
+        assert_eq!(
+            get_crypto_component_symbols(1_000),
+            BTreeSet::from(["BTC".to_string()])
+        );
+    }
+
+    /// The function verifies that baskets that cannot be computed are rejected.
+    #[test]
+    fn validate_rejects_invalid_baskets() {
+        assert_eq!(validate(&default_baskets()), Ok(()));
+
+        let with_default = |basket: Basket| [default_baskets(), vec![basket]].concat();
+        let fiat = AssetClass::FiatCurrency;
+        let invalid_baskets = [
+            vec![],
+            vec![basket(COMPUTED_XDR_SYMBOL, &[("EUR", fiat.clone(), 1)], 1)],
+            with_default(basket("index", &[("EUR", fiat.clone(), 1)], 0)),
+            with_default(basket(USD, &[("EUR", fiat.clone(), 1)], 0)),
+            with_default(basket(COMPUTED_XDR_SYMBOL, &[("EUR", fiat.clone(), 1)], 0)),
+            with_default(basket("INDEX", &[], 0)),
+            with_default(basket("INDEX", &[("eur", fiat.clone(), 1)], 0)),
+            with_default(basket(
+                "INDEX",
+                &[(COMPUTED_XDR_SYMBOL, fiat.clone(), 1)],
+                0,
+            )),
+            with_default(basket("INDEX", &[("EUR", fiat.clone(), 0)], 0)),
+            with_default(basket(
+                "INDEX",
+                &[("EUR", fiat.clone(), 1), ("EUR", fiat.clone(), 2)],
+                0,
+            )),
+        ];
+        for baskets in invalid_baskets {
+            assert!(
+                validate(&baskets).is_err(),
+                "{:?} should be invalid",
+                baskets
+            );
+        }
+
+        let valid_baskets = with_default(basket(
+            "INDEX",
+            &[("EUR", fiat, 1), ("BTC", AssetClass::Cryptocurrency, 2)],
+            0,
+        ));
+        assert_eq!(validate(&valid_baskets), Ok(()));
+    }
+
+    /// The function verifies that only definitions that are not yet in effect can be added,
+    /// changed or removed.
+    #[test]
+    fn validate_update_keeps_definitions_in_effect() {
+        let now_secs = 1_700_000_000;
+        let index = |effective_from| {
+            basket(
+                "INDEX",
+                &[("EUR", AssetClass::FiatCurrency, 1)],
+                effective_from,
+            )
+        };
+        let future_baskets = [default_baskets(), vec![index(now_secs + 1)]].concat();
+        assert_eq!(validate_update(&future_baskets, now_secs), Ok(()));
+        config::set_config(Config {
+            baskets: Some(future_baskets),
+            ..config::default_config()
+        });
+        assert_eq!(validate_update(&default_baskets(), now_secs), Ok(()));
+
+        // The definition is in effect once the effective date has passed.
+        assert!(validate_update(&default_baskets(), now_secs + 1).is_err());
+        assert!(validate_update(&[], now_secs).is_err());
+        let mut reweighted_baskets = default_baskets();
+        reweighted_baskets[0].components[0].weight_per_million += 1;
+        assert!(validate_update(&reweighted_baskets, now_secs).is_err());
+        let past_baskets = [default_baskets(), vec![index(now_secs)]].concat();
+        assert!(validate_update(&past_baskets, now_secs).is_err());
+    }
+}
@@ -2,7 +2,7 @@ extern crate lru;
 use lru::LruCache;
 use std::num::NonZeroUsize;
 
-use crate::{usdt_asset, QueriedExchangeRate, RATE_UNIT, USDT};
+use crate::{usdt_asset, QueriedExchangeRate, RateAggregation, RATE_UNIT, USDT};
 use ic_xrc_types::AssetClass::Cryptocurrency;
 
 /// The [ExchangeRateCache] caches exchange rates.
@@ -50,6 +50,7 @@ impl ExchangeRateCache {
                 0,
                 0,
                 None,
+                RateAggregation::Median,
             ))
         } else {
             self.lru_cache
@@ -58,6 +59,18 @@ impl ExchangeRateCache {
         }
     }
 
+    /// The function changes the maximum size of the cache, evicting the least recently
+    /// used rates if the cache holds more rates than the new maximum size.
+    pub(crate) fn resize(&mut self, capacity: usize) {
+        self.lru_cache.resize(NonZeroUsize::new(capacity).unwrap());
+    }
+
+    /// The function returns the base asset symbols and timestamps of the cached exchange rates,
+    /// starting with the most recently used rate.
+    pub(crate) fn keys(&self) -> Vec<(String, u64)> {
+        self.lru_cache.iter().map(|(key, _)| key.clone()).collect()
+    }
+
     /// The function returns the number of cached exchange rates.
     pub(crate) fn len(&self) -> usize {
         self.lru_cache.len()
@@ -70,15 +83,23 @@ mod test {
     use crate::api::test::icp_asset;
     use crate::api::usd_asset;
     use crate::cache::ExchangeRateCache;
-    use crate::{usdt_asset, QueriedExchangeRate, RATE_UNIT};
+    use crate::{usdt_asset, QueriedExchangeRate, RateAggregation, RATE_UNIT};
 
     /// The function verifies that the exchange rate for a cryptocurrency base asset that is not
     /// USDT is cached correctly.
     #[test]
     fn cache_stores_cryptocurrency_rate() {
         let mut cache = ExchangeRateCache::new(10);
-        let inserted_rate =
-            QueriedExchangeRate::new(icp_asset(), usdt_asset(), 0, &[100 * RATE_UNIT], 1, 1, None);
+        let inserted_rate = QueriedExchangeRate::new(
+            icp_asset(),
+            usdt_asset(),
+            0,
+            &[100 * RATE_UNIT],
+            1,
+            1,
+            None,
+            RateAggregation::Median,
+        );
         cache.insert(&inserted_rate);
         let cached_rate = cache.get("icp", 0);
         assert_eq!(cache.len(), 1);
@@ -99,6 +120,7 @@ mod test {
             0,
             0,
             None,
+            RateAggregation::Median,
         );
         assert!(matches!(cached_rate, Some(rate) if rate == expected_rate));
     }
@@ -115,6 +137,7 @@ mod test {
             0,
             0,
             None,
+            RateAggregation::Median,
         ));
         assert_eq!(cache.len(), 0);
     }
@@ -131,6 +154,7 @@ mod test {
             0,
             0,
             None,
+            RateAggregation::Median,
         ));
         assert_eq!(cache.len(), 0);
     }
@@ -0,0 +1,172 @@
+use candid::{decode_one, encode_one};
+use ic_certification::{labeled, labeled_hash, AsHashTree, Hash, HashTree, RbTree};
+use ic_xrc_types::{Asset, ExchangeRate};
+use serde::Serialize;
+
+use crate::{environment::Environment, utils, with_certified_rates, with_certified_rates_mut};
+
+/// The label of the subtree that contains the certified rates.
+const RATES_LABEL: &[u8] = b"rates";
+
+/// The latest rates of the privileged asset pairs, stored in a hash tree whose root hash is
+/// set as the canister's certified data. The rates are not persisted across upgrades.
+#[derive(Default)]
+pub(crate) struct CertifiedRates {
+    /// Maps the key of an asset pair to its candid-encoded [ExchangeRate].
+    tree: RbTree<Vec<u8>, Vec<u8>>,
+}
+
+impl CertifiedRates {
+    /// Stores the given rate unless a more recent rate of the asset pair is already stored.
+    /// Returns true if the rate was stored.
+    pub(crate) fn insert(&mut self, rate: &ExchangeRate) -> bool {
+        let key = pair_key(&rate.base_asset, &rate.quote_asset);
+        let stored_rate = self
+            .tree
+            .get(&key)
+            .and_then(|value| decode_one::<ExchangeRate>(value).ok());
+        if let Some(stored_rate) = stored_rate {
+            if stored_rate.timestamp > rate.timestamp {
+                return false;
+            }
+        }
+
+        let value = match encode_one(rate) {
+            Ok(value) => value,
+            Err(_) => return false,
+        };
+        self.tree.insert(key, value);
+        true
+    }
+
+    /// Returns the stored rate of the asset pair together with a witness proving that the
+    /// rate is part of the hash tree.
+    pub(crate) fn get(
+        &self,
+        base_asset: &Asset,
+        quote_asset: &Asset,
+    ) -> Option<(ExchangeRate, HashTree)> {
+        let key = pair_key(base_asset, quote_asset);
+        let value = self.tree.get(&key)?;
+        let rate = decode_one(value).ok()?;
+        Some((rate, labeled(RATES_LABEL, self.tree.witness(&key))))
+    }
+
+    /// Returns the root hash of the hash tree, which is used as the canister's certified data.
+    pub(crate) fn root_hash(&self) -> Hash {
+        labeled_hash(RATES_LABEL, &self.tree.root_hash())
+    }
+}
+
+/// Returns the key of an asset pair in the hash tree, e.g., `ICP/CXDR`.
+fn pair_key(base_asset: &Asset, quote_asset: &Asset) -> Vec<u8> {
+    format!("{}/{}", base_asset.symbol, quote_asset.symbol).into_bytes()
+}
+
+/// Encodes the witness as a self-describing CBOR value, as defined by the IC interface
+/// specification for hash trees.
+pub(crate) fn encode_witness(witness: &HashTree) -> Vec<u8> {
+    let mut serializer = serde_cbor::Serializer::new(vec![]);
+    serializer
+        .self_describe()
+        .expect("Writing the CBOR tag must succeed.");
+    witness
+        .serialize(&mut serializer)
+        .expect("Encoding the witness must succeed.");
+    serializer.into_inner()
+}
+
+/// Certifies the given rate if its asset pair is privileged and updates the canister's
+/// certified data accordingly. Must only be called from update calls.
+pub(crate) fn certify_rate(env: &impl Environment, rate: &ExchangeRate) {
+    if !utils::is_privileged_asset_pair(&rate.base_asset, &rate.quote_asset) {
+        return;
+    }
+
+    if with_certified_rates_mut(|rates| rates.insert(rate)) {
+        set_certified_data(env);
+    }
+}
+
+/// Sets the root hash of the certified rates as the canister's certified data.
+pub(crate) fn set_certified_data(env: &impl Environment) {
+    let root_hash = with_certified_rates(|rates| rates.root_hash());
+    env.set_certified_data(&root_hash);
+}
+
+#[cfg(test)]
+mod test {
+    use ic_certification::LookupResult;
+    use ic_xrc_types::{AssetClass, ExchangeRateMetadata};
+
+    use super::*;
+
+    fn icp_xdr_rate(timestamp: u64, rate: u64) -> ExchangeRate {
+        ExchangeRate {
+            base_asset: Asset {
+                symbol: "ICP".to_string(),
+                class: AssetClass::Cryptocurrency,
+            },
+            quote_asset: Asset {
+                symbol: "CXDR".to_string(),
+                class: AssetClass::FiatCurrency,
+            },
+            timestamp,
+            rate,
+            metadata: ExchangeRateMetadata {
+                decimals: 9,
+                base_asset_num_queried_sources: 4,
+                base_asset_num_received_rates: 4,
+                quote_asset_num_queried_sources: 2,
+                quote_asset_num_received_rates: 2,
+                standard_deviation: 0,
+                forex_timestamp: Some(0),
+                twap_window_minutes: None,
+                base_asset_num_discarded_rates: None,
+                quote_asset_num_discarded_rates: None,
+                base_asset_route: None,
+                quote_asset_route: None,
+            },
+        }
+    }
+
+    /// This function tests that the witness of a stored rate proves the candid-encoded rate
+    /// under the root hash of the certified rates.
+    #[test]
+    fn certified_rates_witness_proves_rate() {
+        let mut rates = CertifiedRates::default();
+        let rate = icp_xdr_rate(60, 3_000_000_000);
+        assert!(rates.insert(&rate));
+
+        let (stored_rate, witness) = rates
+            .get(&rate.base_asset, &rate.quote_asset)
+            .expect("rate should be stored");
+        assert_eq!(stored_rate, rate);
+        assert_eq!(witness.digest(), rates.root_hash());
+
+        let encoded_rate = encode_one(&rate).expect("rate should be encodable");
+        assert_eq!(
+            witness.lookup_path([RATES_LABEL, b"ICP/CXDR".as_slice()]),
+            LookupResult::Found(encoded_rate.as_slice())
+        );
+    }
+
+    /// This function tests that an older rate does not replace a more recent one.
+    #[test]
+    fn certified_rates_keeps_most_recent_rate() {
+        let mut rates = CertifiedRates::default();
+        let recent_rate = icp_xdr_rate(120, 3_000_000_000);
+        assert!(rates.insert(&recent_rate));
+        let root_hash = rates.root_hash();
+
+        assert!(!rates.insert(&icp_xdr_rate(60, 2_000_000_000)));
+        assert_eq!(rates.root_hash(), root_hash);
+
+        assert!(rates.insert(&icp_xdr_rate(180, 4_000_000_000)));
+        assert_ne!(rates.root_hash(), root_hash);
+        let (stored_rate, _) = rates
+            .get(&recent_rate.base_asset, &recent_rate.quote_asset)
+            .expect("rate should be stored");
+        assert_eq!(stored_rate.rate, 4_000_000_000);
+    }
+}
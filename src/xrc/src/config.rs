@@ -0,0 +1,559 @@
+//! Runtime configuration of the canister's tuning values. The values default to
+//! the compile-time constants of the respective modules, can be changed by NNS
+//! Governance through `update_config` and are persisted across upgrades.
+
+use candid::Principal;
+use ic_xrc_types::{
+    CallerTier, Config, ExchangeReputationPolicy, OutlierFilter, PrivilegedPrincipal,
+    RateAggregationMethod, StablecoinDepegPolicy, UpdateConfigError,
+};
+
+use crate::{
+    baskets,
+    forex::{MAX_DAYS_TO_GO_BACK, MIN_SOURCES_TO_REPORT},
+    listings::{MAX_LISTING_STALENESS_SECS, MIN_TOTAL_MARKETS},
+    rate_limiting::{MAX_NUM_RATES_PER_REQUEST, REQUEST_COUNTER_LIMIT},
+    with_cache_mut, with_config, with_config_mut, EXCHANGES, FOREX_SOURCES, MAX_CACHE_SIZE,
+    MAX_RELATIVE_DIFFERENCE_DIVISOR, PRIVILEGED_CANISTER_IDS, PRIVILEGED_CRYPTO_ASSETS,
+    RATE_DEVIATION_DIVISOR,
+};
+
+/// The only principal allowed to update the configuration.
+/// NNS Governance: rrkah-fqaaa-aaaaa-aaaaq-cai
+pub(crate) const NNS_GOVERNANCE_CANISTER_ID: Principal =
+    Principal::from_slice(&[0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x01, 0x01]);
+
+/// By default, the reputation scores are only tracked and exported as metrics. NNS Governance
+/// opts into discarding or down-weighting rates once the scores have been observed.
+const DEFAULT_EXCHANGE_REPUTATION_POLICY: ExchangeReputationPolicy =
+    ExchangeReputationPolicy::Ignore;
+
+/// By default, a rate is rejected if no majority of the stablecoins is within 2% of the chosen
+/// stablecoin, which is well above the usual spread of pegged stablecoins and well below the
+/// deviation seen when a stablecoin depegs. The request then fails with a dedicated error
+/// instead of returning a rate derived from a depegged stablecoin.
+const DEFAULT_STABLECOIN_DEPEG_POLICY: StablecoinDepegPolicy = StablecoinDepegPolicy::Reject {
+    max_deviation_bps: 200,
+};
+
+/// Returns the configuration made up of the compile-time defaults.
+pub(crate) fn default_config() -> Config {
+    Config {
+        rate_deviation_divisor: RATE_DEVIATION_DIVISOR,
+        max_relative_difference_divisor: MAX_RELATIVE_DIFFERENCE_DIVISOR,
+        request_counter_limit: REQUEST_COUNTER_LIMIT as u64,
+        min_forex_sources_to_report: MIN_SOURCES_TO_REPORT as u64,
+        max_forex_days_to_go_back: MAX_DAYS_TO_GO_BACK,
+        min_total_markets: MIN_TOTAL_MARKETS,
+        max_listing_staleness_secs: MAX_LISTING_STALENESS_SECS,
+        max_cache_size: MAX_CACHE_SIZE as u64,
+        disabled_exchanges: None,
+        disabled_forex_sources: None,
+        privileged_principals: Some(default_privileged_principals()),
+        privileged_crypto_assets: Some(default_privileged_crypto_assets()),
+        crypto_outlier_filter: Some(OutlierFilter::RelativeDifference),
+        fiat_outlier_filter: Some(OutlierFilter::RelativeDifference),
+        crypto_rate_aggregation: Some(RateAggregationMethod::Median),
+        exchange_reputation_policy: Some(DEFAULT_EXCHANGE_REPUTATION_POLICY),
+        stablecoin_depeg_policy: Some(DEFAULT_STABLECOIN_DEPEG_POLICY),
+        baskets: Some(baskets::default_baskets()),
+    }
+}
+
+/// Returns the privileged canisters, which are free of charge by default.
+fn default_privileged_principals() -> Vec<PrivilegedPrincipal> {
+    PRIVILEGED_CANISTER_IDS
+        .iter()
+        .map(|principal| PrivilegedPrincipal {
+            principal: *principal,
+            tier: CallerTier::Free,
+        })
+        .collect()
+}
+
+/// Returns the cryptocurrencies that are privileged by default.
+fn default_privileged_crypto_assets() -> Vec<String> {
+    PRIVILEGED_CRYPTO_ASSETS
+        .iter()
+        .map(|symbol| symbol.to_string())
+        .collect()
+}
+
+/// Checks that the configuration does not contain values that would break the canister.
+fn validate(config: &Config) -> Result<(), String> {
+    if config.rate_deviation_divisor == 0 {
+        return Err("rate_deviation_divisor must be positive".to_string());
+    }
+    if config.max_relative_difference_divisor == 0 {
+        return Err("max_relative_difference_divisor must be positive".to_string());
+    }
+    // Every request that needs the maximum number of rates must be admitted while no other
+    // request is in flight.
+    let min_request_counter_limit = EXCHANGES.len().saturating_mul(MAX_NUM_RATES_PER_REQUEST);
+    if config.request_counter_limit < min_request_counter_limit as u64 {
+        return Err(format!(
+            "request_counter_limit must be at least {}",
+            min_request_counter_limit
+        ));
+    }
+    if config.min_forex_sources_to_report == 0 {
+        return Err("min_forex_sources_to_report must be positive".to_string());
+    }
+    if config.min_total_markets == 0 {
+        return Err("min_total_markets must be positive".to_string());
+    }
+    if config.max_listing_staleness_secs == 0 {
+        return Err("max_listing_staleness_secs must be positive".to_string());
+    }
+    if config.max_cache_size == 0 || usize::try_from(config.max_cache_size).is_err() {
+        return Err("max_cache_size must be positive and fit into usize".to_string());
+    }
+    for name in config.disabled_exchanges.iter().flatten() {
+        if !EXCHANGES.iter().any(|exchange| exchange.name() == name) {
+            return Err(format!("{} is not a known exchange", name));
+        }
+    }
+    for name in config.disabled_forex_sources.iter().flatten() {
+        if !FOREX_SOURCES.iter().any(|forex| forex.to_string() == *name) {
+            return Err(format!("{} is not a known forex source", name));
+        }
+    }
+    for (index, privileged_principal) in config.privileged_principals.iter().flatten().enumerate() {
+        if privileged_principal.principal == Principal::anonymous() {
+            return Err("the anonymous principal cannot be privileged".to_string());
+        }
+        if config
+            .privileged_principals
+            .iter()
+            .flatten()
+            .take(index)
+            .any(|other| other.principal == privileged_principal.principal)
+        {
+            return Err(format!(
+                "{} is listed more than once",
+                privileged_principal.principal
+            ));
+        }
+    }
+    for symbol in config.privileged_crypto_assets.iter().flatten() {
+        if symbol.is_empty() || *symbol != symbol.to_uppercase() {
+            return Err(format!("{} is not an uppercase symbol", symbol));
+        }
+    }
+    for filter in config
+        .crypto_outlier_filter
+        .iter()
+        .chain(config.fiat_outlier_filter.iter())
+    {
+        if let OutlierFilter::MedianAbsoluteDeviation { threshold_percent }
+        | OutlierFilter::InterquartileRange { threshold_percent } = filter
+        {
+            if *threshold_percent == 0 {
+                return Err("the threshold of an outlier filter must be positive".to_string());
+            }
+        }
+    }
+    if let Some(
+        ExchangeReputationPolicy::Exclude { max_score_bps }
+        | ExchangeReputationPolicy::DownWeight { max_score_bps, .. },
+    ) = config.exchange_reputation_policy
+    {
+        if max_score_bps == 0 {
+            return Err("the max_score_bps of the reputation policy must be positive".to_string());
+        }
+    }
+    if let Some(ExchangeReputationPolicy::DownWeight { weight_percent, .. }) =
+        config.exchange_reputation_policy
+    {
+        if weight_percent > 100 {
+            return Err(
+                "the weight_percent of the reputation policy must not exceed 100".to_string(),
+            );
+        }
+    }
+    if let Some(
+        StablecoinDepegPolicy::Flag { max_deviation_bps }
+        | StablecoinDepegPolicy::Reject { max_deviation_bps },
+    ) = config.stablecoin_depeg_policy
+    {
+        if max_deviation_bps == 0 || max_deviation_bps > 10_000 {
+            return Err(
+                "the max_deviation_bps of the depeg policy must be between 1 and 10000".to_string(),
+            );
+        }
+    }
+    if let Some(ref baskets) = config.baskets {
+        baskets::validate(baskets)?;
+    }
+    Ok(())
+}
+
+/// Returns a copy of the current configuration.
+pub(crate) fn get_config() -> Config {
+    with_config(|config| config.clone())
+}
+
+/// Returns false if the exchange with the given name was disabled by NNS Governance.
+pub(crate) fn is_exchange_enabled(name: &str) -> bool {
+    with_config(|config| {
+        !config
+            .disabled_exchanges
+            .iter()
+            .flatten()
+            .any(|n| n == name)
+    })
+}
+
+/// Returns false if the forex source with the given name was disabled by NNS Governance.
+pub(crate) fn is_forex_enabled(name: &str) -> bool {
+    with_config(|config| {
+        !config
+            .disabled_forex_sources
+            .iter()
+            .flatten()
+            .any(|n| n == name)
+    })
+}
+
+/// Returns the tier of the given caller. Callers that are not listed among the privileged
+/// principals are charged the standard fee.
+pub(crate) fn caller_tier(caller: &Principal) -> CallerTier {
+    with_config(|config| match config.privileged_principals {
+        Some(ref privileged_principals) => privileged_principals
+            .iter()
+            .find(|privileged_principal| privileged_principal.principal == *caller)
+            .map_or(CallerTier::Standard, |privileged_principal| {
+                privileged_principal.tier
+            }),
+        None if PRIVILEGED_CANISTER_IDS.contains(caller) => CallerTier::Free,
+        None => CallerTier::Standard,
+    })
+}
+
+/// Returns the configured privileged principals, or the default ones if none are configured.
+pub(crate) fn privileged_principals() -> Vec<PrivilegedPrincipal> {
+    with_config(|config| config.privileged_principals.clone())
+        .unwrap_or_else(default_privileged_principals)
+}
+
+/// Returns true if the given cryptocurrency symbol is privileged.
+pub(crate) fn is_privileged_crypto_asset(symbol: &str) -> bool {
+    with_config(|config| match config.privileged_crypto_assets {
+        Some(ref symbols) => symbols.iter().any(|privileged| privileged == symbol),
+        None => PRIVILEGED_CRYPTO_ASSETS.contains(&symbol),
+    })
+}
+
+/// Replaces the current configuration and resizes the cache accordingly.
+/// The configuration must have been validated.
+pub(crate) fn set_config(config: Config) {
+    let max_cache_size = config.max_cache_size as usize;
+    with_config_mut(|current| *current = config);
+    with_cache_mut(|cache| cache.resize(max_cache_size));
+}
+
+/// Replaces the current configuration if the caller is NNS Governance and the
+/// configuration is valid. The basket definitions that are in effect at `now_secs`
+/// must not change.
+pub(crate) fn update_config(
+    caller: Principal,
+    config: Config,
+    now_secs: u64,
+) -> Result<(), UpdateConfigError> {
+    if caller != NNS_GOVERNANCE_CANISTER_ID {
+        return Err(UpdateConfigError::NotAuthorized);
+    }
+    validate(&config).map_err(UpdateConfigError::InvalidConfig)?;
+    let config = with_persisted_baskets(config);
+    if let Some(ref new_baskets) = config.baskets {
+        baskets::validate_update(new_baskets, now_secs)
+            .map_err(UpdateConfigError::InvalidConfig)?;
+    }
+    set_config(config);
+    Ok(())
+}
+
+/// Returns the configuration with its baskets set, so that they are persisted with it. A
+/// configuration without baskets gets the default baskets, which are kept from then on, so
+/// that changing the defaults in a later version cannot change the basket rates of past days.
+pub(crate) fn with_persisted_baskets(mut config: Config) -> Config {
+    config.baskets.get_or_insert_with(baskets::default_baskets);
+    config
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// This function tests that the default configuration passes validation.
+    #[test]
+    fn default_config_is_valid() {
+        assert_eq!(validate(&default_config()), Ok(()));
+    }
+
+    /// This function tests that zero divisors and a zero cache size are rejected.
+    #[test]
+    fn validate_rejects_zero_values() {
+        let config = Config {
+            rate_deviation_divisor: 0,
+            ..default_config()
+        };
+        assert!(validate(&config).is_err());
+
+        let config = Config {
+            max_relative_difference_divisor: 0,
+            ..default_config()
+        };
+        assert!(validate(&config).is_err());
+
+        let config = Config {
+            max_cache_size: 0,
+            ..default_config()
+        };
+        assert!(validate(&config).is_err());
+
+        let config = Config {
+            fiat_outlier_filter: Some(OutlierFilter::InterquartileRange {
+                threshold_percent: 0,
+            }),
+            ..default_config()
+        };
+        assert!(validate(&config).is_err());
+
+        let config = Config {
+            exchange_reputation_policy: Some(ExchangeReputationPolicy::DownWeight {
+                max_score_bps: 100,
+                weight_percent: 101,
+            }),
+            ..default_config()
+        };
+        assert!(validate(&config).is_err());
+
+        let config = Config {
+            stablecoin_depeg_policy: Some(StablecoinDepegPolicy::Flag {
+                max_deviation_bps: 0,
+            }),
+            ..default_config()
+        };
+        assert!(validate(&config).is_err());
+
+        let config = Config {
+            stablecoin_depeg_policy: Some(StablecoinDepegPolicy::Reject {
+                max_deviation_bps: 10_001,
+            }),
+            ..default_config()
+        };
+        assert!(validate(&config).is_err());
+
+        let min_request_counter_limit = (EXCHANGES.len() * MAX_NUM_RATES_PER_REQUEST) as u64;
+        let config = Config {
+            request_counter_limit: 0,
+            ..default_config()
+        };
+        assert!(validate(&config).is_err());
+
+        let config = Config {
+            request_counter_limit: min_request_counter_limit - 1,
+            ..default_config()
+        };
+        assert!(validate(&config).is_err());
+
+        let config = Config {
+            request_counter_limit: min_request_counter_limit,
+            ..default_config()
+        };
+        assert_eq!(validate(&config), Ok(()));
+
+        let config = Config {
+            min_forex_sources_to_report: 0,
+            ..default_config()
+        };
+        assert!(validate(&config).is_err());
+
+        let config = Config {
+            min_total_markets: 0,
+            ..default_config()
+        };
+        assert!(validate(&config).is_err());
+
+        let config = Config {
+            max_listing_staleness_secs: 0,
+            ..default_config()
+        };
+        assert!(validate(&config).is_err());
+
+        let config = Config {
+            exchange_reputation_policy: Some(ExchangeReputationPolicy::Exclude {
+                max_score_bps: 0,
+            }),
+            ..default_config()
+        };
+        assert!(validate(&config).is_err());
+    }
+
+    /// This function tests that only known exchanges and forex sources can be disabled.
+    #[test]
+    fn validate_rejects_unknown_sources() {
+        let config = Config {
+            disabled_exchanges: Some(vec!["Coinbase".to_string(), "Unknown".to_string()]),
+            ..default_config()
+        };
+        assert!(validate(&config).is_err());
+
+        let config = Config {
+            disabled_forex_sources: Some(vec!["Unknown".to_string()]),
+            ..default_config()
+        };
+        assert!(validate(&config).is_err());
+
+        let config = Config {
+            disabled_exchanges: Some(vec!["Coinbase".to_string()]),
+            disabled_forex_sources: Some(vec!["EuropeanCentralBank".to_string()]),
+            ..default_config()
+        };
+        assert_eq!(validate(&config), Ok(()));
+    }
+
+    /// This function tests that invalid privileged principals and assets are rejected.
+    #[test]
+    fn validate_rejects_invalid_privileges() {
+        let privileged_principal = PrivilegedPrincipal {
+            principal: PRIVILEGED_CANISTER_IDS[0],
+            tier: CallerTier::Discounted,
+        };
+        let config = Config {
+            privileged_principals: Some(vec![
+                privileged_principal.clone(),
+                privileged_principal.clone(),
+            ]),
+            ..default_config()
+        };
+        assert!(validate(&config).is_err());
+
+        let config = Config {
+            privileged_principals: Some(vec![PrivilegedPrincipal {
+                principal: Principal::anonymous(),
+                tier: CallerTier::Free,
+            }]),
+            ..default_config()
+        };
+        assert!(validate(&config).is_err());
+
+        let config = Config {
+            privileged_crypto_assets: Some(vec!["sol".to_string()]),
+            ..default_config()
+        };
+        assert!(validate(&config).is_err());
+    }
+
+    /// This function tests that the tiers and privileged assets are taken from the configuration
+    /// and fall back to the defaults if they are absent.
+    #[test]
+    fn caller_tier_and_privileged_assets_follow_the_config() {
+        let sns_principal = Principal::from_text("q4eej-kyaaa-aaaaa-aaaha-cai")
+            .expect("should be a valid textual principal ID");
+        assert_eq!(caller_tier(&PRIVILEGED_CANISTER_IDS[0]), CallerTier::Free);
+        assert_eq!(caller_tier(&sns_principal), CallerTier::Standard);
+        assert!(is_privileged_crypto_asset("ICP"));
+        assert!(!is_privileged_crypto_asset("SOL"));
+
+        set_config(Config {
+            privileged_principals: Some(vec![
+                PrivilegedPrincipal {
+                    principal: PRIVILEGED_CANISTER_IDS[0],
+                    tier: CallerTier::Standard,
+                },
+                PrivilegedPrincipal {
+                    principal: sns_principal,
+                    tier: CallerTier::Discounted,
+                },
+            ]),
+            privileged_crypto_assets: Some(vec!["SOL".to_string()]),
+            ..default_config()
+        });
+        assert_eq!(
+            caller_tier(&PRIVILEGED_CANISTER_IDS[0]),
+            CallerTier::Standard
+        );
+        assert_eq!(caller_tier(&sns_principal), CallerTier::Discounted);
+        assert_eq!(
+            caller_tier(&PRIVILEGED_CANISTER_IDS[1]),
+            CallerTier::Standard
+        );
+        assert!(!is_privileged_crypto_asset("ICP"));
+        assert!(is_privileged_crypto_asset("SOL"));
+
+        set_config(Config {
+            privileged_principals: None,
+            privileged_crypto_assets: None,
+            ..default_config()
+        });
+        assert_eq!(caller_tier(&PRIVILEGED_CANISTER_IDS[1]), CallerTier::Free);
+        assert!(is_privileged_crypto_asset("ICP"));
+    }
+
+    /// This function tests that disabled exchanges and forex sources are reported as such.
+    #[test]
+    fn disabled_sources_are_not_enabled() {
+        assert!(is_exchange_enabled("Coinbase"));
+        assert!(is_forex_enabled("EuropeanCentralBank"));
+
+        set_config(Config {
+            disabled_exchanges: Some(vec!["Coinbase".to_string()]),
+            disabled_forex_sources: Some(vec!["EuropeanCentralBank".to_string()]),
+            ..default_config()
+        });
+
+        assert!(!is_exchange_enabled("Coinbase"));
+        assert!(is_exchange_enabled("KuCoin"));
+        assert!(!is_forex_enabled("EuropeanCentralBank"));
+        assert!(is_forex_enabled("CentralBankOfMyanmar"));
+    }
+
+    /// This function tests that only NNS Governance may update the configuration.
+    #[test]
+    fn update_config_requires_nns_governance() {
+        let config = Config {
+            request_counter_limit: 100,
+            ..default_config()
+        };
+
+        assert_eq!(
+            update_config(Principal::anonymous(), config.clone(), 0),
+            Err(UpdateConfigError::NotAuthorized)
+        );
+        assert_eq!(get_config(), default_config());
+
+        assert_eq!(
+            update_config(NNS_GOVERNANCE_CANISTER_ID, config.clone(), 0),
+            Ok(())
+        );
+        assert_eq!(get_config(), config);
+    }
+
+    /// This function tests that the baskets are persisted with the configuration if they are
+    /// absent and that an absent definition cannot replace one that is in effect.
+    #[test]
+    fn update_config_persists_the_baskets() {
+        let config = Config {
+            baskets: None,
+            ..default_config()
+        };
+        assert_eq!(
+            update_config(NNS_GOVERNANCE_CANISTER_ID, config.clone(), 1_000),
+            Ok(())
+        );
+        assert_eq!(get_config().baskets, Some(baskets::default_baskets()));
+
+        let mut reweighted_cxdr = baskets::default_baskets()[0].clone();
+        reweighted_cxdr.components[0].weight_per_million += 1;
+        reweighted_cxdr.effective_from = 2_000;
+        let reweighted_baskets = [baskets::default_baskets(), vec![reweighted_cxdr]].concat();
+        set_config(Config {
+            baskets: Some(reweighted_baskets.clone()),
+            ..default_config()
+        });
+        assert!(update_config(NNS_GOVERNANCE_CANISTER_ID, config, 2_000).is_err());
+        assert_eq!(get_config().baskets, Some(reweighted_baskets));
+    }
+}
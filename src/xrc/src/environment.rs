@@ -1,6 +1,9 @@
 use candid::Principal;
-use ic_cdk::api::{msg_caller, msg_cycles_accept, msg_cycles_available};
-use ic_xrc_types::ExchangeRateError;
+use ic_cdk::api::{
+    certified_data_set, data_certificate, is_controller, msg_caller, msg_cycles_accept,
+    msg_cycles_available,
+};
+use ic_xrc_types::{CallerTier, ExchangeRateError};
 
 use crate::{
     utils, XRC_BASE_CYCLES_COST, XRC_MINIMUM_FEE_COST, XRC_OUTBOUND_HTTP_CALL_CYCLES_COST,
@@ -40,6 +43,22 @@ pub(crate) trait Environment {
         msg_cycles_accept(max_amount)
     }
 
+    /// Sets the canister's certified data.
+    fn set_certified_data(&self, data: &[u8]) {
+        certified_data_set(data)
+    }
+
+    /// Gets the certificate of the canister's certified data. It is only available in
+    /// non-replicated query calls.
+    fn data_certificate(&self) -> Option<Vec<u8>> {
+        data_certificate()
+    }
+
+    /// Checks if the given principal is a controller of the canister.
+    fn is_controller(&self, principal: &Principal) -> bool {
+        is_controller(principal)
+    }
+
     /// Checks if the call has enough cycles attached.
     fn has_enough_cycles(&self) -> bool {
         self.cycles_available() >= XRC_REQUEST_CYCLES_COST
@@ -47,12 +66,18 @@ pub(crate) trait Environment {
 
     /// Checks if enough cycles have been sent as defined by [XRC_REQUEST_CYCLES_COST].
     /// If there are enough cycles, accept the cycles up to the [XRC_REQUEST_CYCLES_COST].
+    /// A batch fee may exceed [XRC_REQUEST_CYCLES_COST], in which case the full fee must
+    /// be attached.
     fn charge_cycles(&self, option: ChargeOption) -> Result<(), ChargeCyclesError> {
         if !self.has_enough_cycles() {
             return Err(ChargeCyclesError::NotEnoughCycles);
         }
 
-        let fee = calculate_fee(option);
+        let fee = calculate_fee_for_caller(option, &self.caller());
+        if self.cycles_available() < fee {
+            return Err(ChargeCyclesError::NotEnoughCycles);
+        }
+
         let accepted = self.accept_cycles(fee);
         if accepted != fee {
             // We should panic here as this will cause a refund of the cycles to occur.
@@ -71,6 +96,37 @@ pub(crate) enum ChargeOption {
     /// multiplied by the defined provided usize. If usize is greater than or equal to 3, 2 is used.
     /// This only occurs if the stablecoin rates are needed.
     OutboundRatesNeeded(usize),
+    /// Used for `get_exchange_rates`. The base fee cost is charged for every request that passed
+    /// validation and the minimum fee cost for every request that failed it. Every distinct rate
+    /// that needs to be retrieved for the whole batch is attributed to the first request that
+    /// needs it, and the outbound cycles cost is charged for the rates of every request with
+    /// the same cap as for [ChargeOption::OutboundRatesNeeded], so that a batch never costs
+    /// more than sending its requests one by one.
+    Batch {
+        /// The number of requests that passed validation.
+        num_valid_requests: usize,
+        /// The number of requests that failed validation.
+        num_invalid_requests: usize,
+        /// The number of distinct rates attributed to each request that passed validation.
+        outbound_rates_needed: Vec<usize>,
+    },
+    /// Used for `get_exchange_rate_series` and `get_twap_exchange_rate`. The base fee cost plus
+    /// the outbound cycles cost for the windows of rates that need to be retrieved, with the
+    /// same cap as for [ChargeOption::OutboundRatesNeeded].
+    Series(usize),
+}
+
+/// Callers in the [CallerTier::Discounted] tier are charged the fee divided by this divisor.
+const DISCOUNTED_FEE_DIVISOR: u128 = 2;
+
+/// This function calculates the fee that the given caller is charged, taking the caller's tier
+/// into account.
+pub(crate) fn calculate_fee_for_caller(option: ChargeOption, caller: &Principal) -> u128 {
+    match utils::caller_tier(caller) {
+        CallerTier::Free => 0,
+        CallerTier::Discounted => calculate_fee(option) / DISCOUNTED_FEE_DIVISOR,
+        CallerTier::Standard => calculate_fee(option),
+    }
 }
 
 /// This function calculates the fee based on the number of outbound requests needed in order
@@ -78,27 +134,45 @@ pub(crate) enum ChargeOption {
 fn calculate_fee(option: ChargeOption) -> u128 {
     match option {
         ChargeOption::MinimumFee => XRC_MINIMUM_FEE_COST,
-        ChargeOption::OutboundRatesNeeded(outbound_rates_needed) => {
-            match outbound_rates_needed {
-                // No requests are needed.
-                0 => XRC_BASE_CYCLES_COST,
-                // Only 1 request is needed.
-                1 => XRC_BASE_CYCLES_COST
-                    .checked_add(XRC_OUTBOUND_HTTP_CALL_CYCLES_COST)
-                    .expect("Cannot add the needed cycles to base cost as it causes an overflow"),
-                // 2 or more (stablecoin) requests are needed.
-                _ => {
-                    let outbound_cost = XRC_OUTBOUND_HTTP_CALL_CYCLES_COST
-                        .checked_mul(2)
-                        .expect("Cannot calculate outbound costs as it would cause an overflow");
-                    XRC_BASE_CYCLES_COST.checked_add(outbound_cost).expect(
-                        "Cannot add the needed cycles to base cost as it causes an overflow",
-                    )
-                }
-            }
+        ChargeOption::OutboundRatesNeeded(outbound_rates_needed) => XRC_BASE_CYCLES_COST
+            .checked_add(calculate_outbound_cost(outbound_rates_needed))
+            .expect("Cannot add the needed cycles to base cost as it causes an overflow"),
+        ChargeOption::Batch {
+            num_valid_requests,
+            num_invalid_requests,
+            outbound_rates_needed,
+        } => {
+            let base_cost = XRC_BASE_CYCLES_COST
+                .checked_mul(num_valid_requests as u128)
+                .expect("Cannot calculate base costs as it would cause an overflow");
+            let minimum_fee_cost = XRC_MINIMUM_FEE_COST
+                .checked_mul(num_invalid_requests as u128)
+                .expect("Cannot calculate minimum fee costs as it would cause an overflow");
+            let outbound_cost = outbound_rates_needed
+                .into_iter()
+                .map(calculate_outbound_cost)
+                .try_fold(0u128, |cost, request_cost| cost.checked_add(request_cost))
+                .expect("Cannot calculate outbound costs as it would cause an overflow");
+            base_cost
+                .checked_add(minimum_fee_cost)
+                .and_then(|cost| cost.checked_add(outbound_cost))
+                .expect("Cannot add the needed cycles to base cost as it causes an overflow")
         }
+        ChargeOption::Series(num_windows_needed) => XRC_BASE_CYCLES_COST
+            .checked_add(calculate_outbound_cost(num_windows_needed))
+            .expect("Cannot add the needed cycles to base cost as it causes an overflow"),
     }
 }
+
+/// This function calculates the outbound cycles cost of a single request with the given number
+/// of needed outbound rates or windows. If 2 or more are needed, only 2 are charged. For a single
+/// rate, this only occurs if the stablecoin rates are needed.
+fn calculate_outbound_cost(outbound_rates_needed: usize) -> u128 {
+    XRC_OUTBOUND_HTTP_CALL_CYCLES_COST
+        .checked_mul(outbound_rates_needed.min(2) as u128)
+        .expect("Cannot calculate outbound costs as it would cause an overflow")
+}
+
 /// An environment that interacts with the canister API.
 pub(crate) struct CanisterEnvironment;
 
@@ -113,6 +187,8 @@ impl Environment for CanisterEnvironment {}
 
 #[cfg(test)]
 pub(crate) mod test {
+    use std::cell::RefCell;
+
     use super::*;
 
     /// An environment that simulates pieces of the canister API in order to exercise
@@ -122,6 +198,9 @@ pub(crate) mod test {
         cycles_available: u128,
         cycles_accepted: u128,
         time_secs: u64,
+        controllers: Vec<Principal>,
+        certified_data: RefCell<Vec<u8>>,
+        data_certificate: Option<Vec<u8>>,
     }
 
     impl Default for TestEnvironment {
@@ -132,6 +211,9 @@ pub(crate) mod test {
                 cycles_available: Default::default(),
                 cycles_accepted: Default::default(),
                 time_secs: Default::default(),
+                controllers: Default::default(),
+                certified_data: Default::default(),
+                data_certificate: Default::default(),
             }
         }
     }
@@ -141,6 +223,11 @@ pub(crate) mod test {
         pub(crate) fn builder() -> TestEnvironmentBuilder {
             TestEnvironmentBuilder::new()
         }
+
+        /// Returns the certified data that was last set.
+        pub(crate) fn certified_data(&self) -> Vec<u8> {
+            self.certified_data.borrow().clone()
+        }
     }
 
     /// A builder for creating new [TestEnvironment]s.
@@ -180,6 +267,18 @@ pub(crate) mod test {
             self
         }
 
+        /// Adds a principal to the [TestEnviroment]'s `controllers` field.
+        pub(crate) fn with_controller(mut self, controller: Principal) -> Self {
+            self.env.controllers.push(controller);
+            self
+        }
+
+        /// Sets the [TestEnviroment]'s `data_certificate` field.
+        pub(crate) fn with_data_certificate(mut self, data_certificate: Vec<u8>) -> Self {
+            self.env.data_certificate = Some(data_certificate);
+            self
+        }
+
         /// Returns the built TestEnvironment.
         pub(crate) fn build(self) -> TestEnvironment {
             self.env
@@ -199,6 +298,18 @@ pub(crate) mod test {
             self.cycles_available
         }
 
+        fn is_controller(&self, principal: &Principal) -> bool {
+            self.controllers.contains(principal)
+        }
+
+        fn set_certified_data(&self, data: &[u8]) {
+            *self.certified_data.borrow_mut() = data.to_vec();
+        }
+
+        fn data_certificate(&self) -> Option<Vec<u8>> {
+            self.data_certificate.clone()
+        }
+
         fn accept_cycles(&self, cycles_accepted: u128) -> u128 {
             // Exit early if `self.cycles_accepted` is 0
             // Used so we can mimic being unable to accept cycles.
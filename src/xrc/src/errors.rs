@@ -4,6 +4,14 @@ pub(crate) const TIMESTAMP_IS_IN_FUTURE_ERROR_CODE: u32 = 1;
 pub(crate) const BASE_ASSET_INVALID_SYMBOL_ERROR_CODE: u32 = 2;
 pub(crate) const QUOTE_ASSET_INVALID_SYMBOL_ERROR_CODE: u32 = 3;
 pub(crate) const INVALID_RATE_ERROR_CODE: u32 = 4;
+pub(crate) const TOO_MANY_REQUESTS_IN_BATCH_ERROR_CODE: u32 = 5;
+pub(crate) const RATE_NOT_CACHED_ERROR_CODE: u32 = 6;
+pub(crate) const INVALID_SERIES_ERROR_CODE: u32 = 7;
+pub(crate) const ASSET_NOT_LISTED_ERROR_CODE: u32 = 8;
+pub(crate) const INVALID_TWAP_ERROR_CODE: u32 = 9;
+pub(crate) const RATE_NOT_CERTIFIED_ERROR_CODE: u32 = 10;
+pub(crate) const RATE_REQUIREMENTS_NOT_MET_ERROR_CODE: u32 = 11;
+pub(crate) const STABLECOIN_DEPEG_ERROR_CODE: u32 = 12;
 
 pub(crate) const BASE_ASSET_INVALID_SYMBOL_ERROR_MESSAGE: &str = "Base asset symbol is invalid";
 pub(crate) const QUOTE_ASSET_INVALID_SYMBOL_ERROR_MESSAGE: &str = "Quote asset symbol is invalid";
@@ -35,3 +43,73 @@ pub(crate) fn quote_asset_symbol_invalid_error() -> ExchangeRateError {
         description: QUOTE_ASSET_INVALID_SYMBOL_ERROR_MESSAGE.to_string(),
     })
 }
+
+pub(crate) fn too_many_requests_in_batch_error(
+    num_requests: usize,
+    max_num_requests: usize,
+) -> ExchangeRateError {
+    ExchangeRateError::Other(OtherError {
+        code: TOO_MANY_REQUESTS_IN_BATCH_ERROR_CODE,
+        description: format!(
+            "The batch contains {} requests, but at most {} are allowed",
+            num_requests, max_num_requests
+        ),
+    })
+}
+
+pub(crate) fn rate_not_cached_error(symbol: &str, timestamp: u64) -> ExchangeRateError {
+    ExchangeRateError::Other(OtherError {
+        code: RATE_NOT_CACHED_ERROR_CODE,
+        description: format!("The {} rate at {} is not cached", symbol, timestamp),
+    })
+}
+
+pub(crate) fn invalid_series_error(reason: &str) -> ExchangeRateError {
+    ExchangeRateError::Other(OtherError {
+        code: INVALID_SERIES_ERROR_CODE,
+        description: format!("The requested series is invalid: {}", reason),
+    })
+}
+
+pub(crate) fn asset_not_listed_error(symbol: &str) -> ExchangeRateError {
+    ExchangeRateError::Other(OtherError {
+        code: ASSET_NOT_LISTED_ERROR_CODE,
+        description: format!("{} is not listed on any exchange", symbol),
+    })
+}
+
+pub(crate) fn invalid_twap_error(reason: &str) -> ExchangeRateError {
+    ExchangeRateError::Other(OtherError {
+        code: INVALID_TWAP_ERROR_CODE,
+        description: format!("The requested TWAP is invalid: {}", reason),
+    })
+}
+
+pub(crate) fn rate_not_certified_error(base_symbol: &str, quote_symbol: &str) -> ExchangeRateError {
+    ExchangeRateError::Other(OtherError {
+        code: RATE_NOT_CERTIFIED_ERROR_CODE,
+        description: format!("The {}/{} rate is not certified", base_symbol, quote_symbol),
+    })
+}
+
+pub(crate) fn certificate_not_available_error() -> ExchangeRateError {
+    ExchangeRateError::Other(OtherError {
+        code: RATE_NOT_CERTIFIED_ERROR_CODE,
+        description: "No certificate is available; the endpoint must be called as a query"
+            .to_string(),
+    })
+}
+
+pub(crate) fn rate_requirements_not_met_error(reason: &str) -> ExchangeRateError {
+    ExchangeRateError::Other(OtherError {
+        code: RATE_REQUIREMENTS_NOT_MET_ERROR_CODE,
+        description: format!("The rate does not meet the requirements: {}", reason),
+    })
+}
+
+pub(crate) fn stablecoin_depeg_error(reason: &str) -> ExchangeRateError {
+    ExchangeRateError::Other(OtherError {
+        code: STABLECOIN_DEPEG_ERROR_CODE,
+        description: format!("The stablecoin rates disagree: {}", reason),
+    })
+}
@@ -1,14 +1,12 @@
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 
-use candid::{decode_args, encode_args, Deserialize, Error as CandidError};
+use candid::{decode_args, encode_args, CandidType, Deserialize, Error as CandidError};
 
-use ic_xrc_types::Asset;
 use serde::de::DeserializeOwned;
 
-use crate::api::usd_asset;
-use crate::{usdt_asset, utils, ONE_KIB};
+use crate::{config, utils, ONE_KIB};
 use crate::{ExtractError, RATE_UNIT};
-use crate::{USDC, USDS, USDT};
+use crate::{DAI, FDUSD, PYUSD, USD, USDC, USDS, USDT};
 
 /// This macro generates the necessary boilerplate when adding an exchange to this module.
 macro_rules! exchanges {
@@ -65,13 +63,34 @@ macro_rules! exchanges {
                 }
             }
 
-            /// This method extracts the rate encoded in the given input.
-            pub fn extract_rate(&self, bytes: &[u8]) -> Result<u64, ExtractError> {
+            /// This method extracts the rate and the traded volume encoded in the given input.
+            pub fn extract_rate(&self, bytes: &[u8]) -> Result<ExtractedRate, ExtractError> {
                 match self {
                     $(Exchange::$name(exchange) => exchange.extract_rate(bytes)),*,
                 }
             }
 
+            /// This method checks if the exchange can be queried for a window of candles.
+            pub fn supports_windows(&self) -> bool {
+                match self {
+                    $(Exchange::$name(exchange) => exchange.supports_windows()),*,
+                }
+            }
+
+            /// This method returns the formatted URL for a window of candles for the exchange.
+            pub fn get_window_url(&self, base_asset: &str, quote_asset: &str, start: u64, end: u64) -> String {
+                match self {
+                    $(Exchange::$name(exchange) => exchange.get_window_url(base_asset, quote_asset, start, end)),*,
+                }
+            }
+
+            /// This method extracts the timestamped rates of all candles encoded in the given input.
+            pub fn extract_candles(&self, bytes: &[u8]) -> Result<Vec<(u64, u64)>, ExtractError> {
+                match self {
+                    $(Exchange::$name(exchange) => exchange.extract_candles(bytes)),*,
+                }
+            }
+
             /// This method returns the URL of the exchange's public spot-listing
             /// endpoint (used to discover tradable pairs).
             pub fn listing_url(&self) -> &str {
@@ -81,8 +100,9 @@ macro_rules! exchanges {
             }
 
             /// This method parses a listing-endpoint response into the set of base
-            /// assets the exchange currently lists against USDT, plus the total
-            /// number of spot markets parsed (see [ListedPairs]).
+            /// assets the exchange currently lists against USDT, the tradable
+            /// base/quote pairs, and the total number of spot markets parsed (see
+            /// [ListedPairs]).
             pub fn extract_listed_usdt_bases(&self, bytes: &[u8]) -> Result<ListedPairs, ExtractError> {
                 match self {
                     $(Exchange::$name(exchange) => exchange.extract_listed_usdt_bases(bytes)),*,
@@ -96,10 +116,11 @@ macro_rules! exchanges {
                 }
             }
 
-            /// This method lists the USD assets supported by the exchange.
-            pub fn supported_usd_asset_type(&self) -> Asset {
+            /// This method lists the USD-like quote assets of the exchange's markets, in order
+            /// of preference.
+            pub fn supported_usd_quote_assets(&self) -> &'static [&'static str] {
                 match self {
-                    $(Exchange::$name(exchange) => exchange.supported_usd_asset()),*,
+                    $(Exchange::$name(exchange) => exchange.supported_usd_quote_assets()),*,
                 }
             }
 
@@ -125,32 +146,42 @@ macro_rules! exchanges {
             /// signals that the response parsed but carried no datapoint (an
             /// empty candle window), which the caller treats as "no data"
             /// rather than an error.
-            pub fn encode_response(rate: Option<u64>) -> Result<Vec<u8>, CandidError> {
+            pub fn encode_response(rate: Option<ExtractedRate>) -> Result<Vec<u8>, CandidError> {
                 encode_args((rate,))
             }
 
             /// Decodes the response from the exchange transform method. `None`
             /// means the upstream returned no datapoint (see [encode_response]).
-            pub fn decode_response(bytes: &[u8]) -> Result<Option<u64>, CandidError> {
-                decode_args::<(Option<u64>,)>(bytes).map(|decoded| decoded.0)
+            pub fn decode_response(bytes: &[u8]) -> Result<Option<ExtractedRate>, CandidError> {
+                decode_args::<(Option<ExtractedRate>,)>(bytes).map(|decoded| decoded.0)
+            }
+
+            /// Encodes the candles in the exchange window transform method.
+            pub fn encode_window_response(candles: &[(u64, u64)]) -> Result<Vec<u8>, CandidError> {
+                encode_args((candles,))
+            }
+
+            /// Decodes the candles from the exchange window transform method.
+            pub fn decode_window_response(bytes: &[u8]) -> Result<Vec<(u64, u64)>, CandidError> {
+                decode_args::<(Vec<(u64, u64)>,)>(bytes).map(|decoded| decoded.0)
             }
 
             /// Encodes a parsed listing as the listing transform's output — the
             /// small, canonical payload the replicas reach consensus on. The
-            /// bases are a `BTreeSet`, so candid emits them in a deterministic
-            /// (sorted) order.
+            /// bases and pairs are `BTree` collections, so candid emits them in a
+            /// deterministic (sorted) order.
             pub fn encode_listing_response(listed: &ListedPairs) -> Result<Vec<u8>, CandidError> {
-                encode_args((&listed.bases, listed.total_markets as u64))
+                encode_args((&listed.bases, listed.total_markets as u64, &listed.pairs))
             }
 
             /// Decodes the listing payload produced by [encode_listing_response].
             pub fn decode_listing_response(bytes: &[u8]) -> Result<ListedPairs, CandidError> {
-                decode_args::<(BTreeSet<String>, u64)>(bytes).map(|(bases, total_markets)| {
-                    ListedPairs {
+                decode_args::<(BTreeSet<String>, u64, BTreeMap<String, BTreeSet<String>>)>(bytes)
+                    .map(|(bases, total_markets, pairs)| ListedPairs {
                         bases,
                         total_markets: total_markets as usize,
-                    }
-                })
+                        pairs,
+                    })
             }
 
             /// This method returns the exchange's max response bytes.
@@ -160,6 +191,14 @@ macro_rules! exchanges {
                 }
             }
 
+            /// This method returns the exchange's max response bytes for a window
+            /// of the given number of candles.
+            pub fn window_max_response_bytes(&self, num_candles: u64) -> u64 {
+                match self {
+                    $(Exchange::$name(exchange) => exchange.window_max_response_bytes(num_candles)),*,
+                }
+            }
+
             /// This method returns the exchange's max response bytes for a
             /// listing outcall.
             pub fn listing_max_response_bytes(&self) -> u64 {
@@ -177,6 +216,12 @@ macro_rules! exchanges {
                 utils::is_ipv4_support_available() || self.supports_ipv6()
             }
 
+            /// This method returns whether the exchange is enabled, i.e., whether it was
+            /// not disabled by NNS Governance through the configuration.
+            pub fn is_enabled(&self) -> bool {
+                config::is_exchange_enabled(self.name())
+            }
+
             /// This method returns the number of cycles expected to be sent when
             /// calling an exchange. The value returned is at least the maximum
             /// required for each exchanges.
@@ -194,6 +239,16 @@ macro_rules! exchanges {
 
 exchanges! { Coinbase, KuCoin, Okx, GateIo, Mexc, Poloniex, CryptoCom, Bitget, Digifinex }
 
+/// The rate of a candle together with the volume traded during the candle, both scaled by
+/// `RATE_UNIT`. The volume is given in units of the base asset.
+#[derive(CandidType, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct ExtractedRate {
+    /// The extracted rate.
+    pub rate: u64,
+    /// The extracted volume.
+    pub volume: u64,
+}
+
 /// Used to determine how to parse the extracted value returned from
 /// [extract_rate]'s `extract_fn` argument.
 enum ExtractedValue {
@@ -201,22 +256,55 @@ enum ExtractedValue {
     Float(f64),
 }
 
+impl ExtractedValue {
+    /// Parses the value and scales it by `RATE_UNIT`. Values that do not fit into 64 bits
+    /// saturate.
+    fn scaled(self, bytes: &[u8]) -> Result<u64, ExtractError> {
+        let value = match self {
+            ExtractedValue::Str(value) => value
+                .parse::<f64>()
+                .map_err(|err| ExtractError::json_deserialize(bytes, err.to_string()))?,
+            ExtractedValue::Float(value) => value,
+        };
+        Ok((value * RATE_UNIT as f64) as u64)
+    }
+}
+
 /// This function provides a generic way to extract a rate out of the provided bytes.
+/// `extract_fn` returns the rate and the traded volume of the candle.
 fn extract_rate<R: DeserializeOwned>(
     bytes: &[u8],
-    extract_fn: impl FnOnce(R) -> Option<ExtractedValue>,
-) -> Result<u64, ExtractError> {
+    extract_fn: impl FnOnce(R) -> Option<(ExtractedValue, ExtractedValue)>,
+) -> Result<ExtractedRate, ExtractError> {
     let response = serde_json::from_slice::<R>(bytes)
         .map_err(|err| ExtractError::json_deserialize(bytes, err.to_string()))?;
-    let extracted_value = extract_fn(response).ok_or_else(|| ExtractError::extract(bytes))?;
-    let rate = match extracted_value {
-        ExtractedValue::Str(value) => value
-            .parse::<f64>()
-            .map_err(|err| ExtractError::json_deserialize(bytes, err.to_string()))?,
-        ExtractedValue::Float(value) => value,
-    };
-
-    Ok((rate * RATE_UNIT as f64) as u64)
+    let (rate, volume) = extract_fn(response).ok_or_else(|| ExtractError::extract(bytes))?;
+
+    Ok(ExtractedRate {
+        rate: rate.scaled(bytes)?,
+        volume: volume.scaled(bytes)?,
+    })
+}
+
+/// This function provides a generic way to extract the timestamped rates of a window of candles
+/// out of the provided bytes. `extract_fn` returns the timestamp in seconds and the value of
+/// every candle.
+fn extract_candles<R: DeserializeOwned>(
+    bytes: &[u8],
+    extract_fn: impl FnOnce(R) -> Vec<(u64, ExtractedValue)>,
+) -> Result<Vec<(u64, u64)>, ExtractError> {
+    let response = serde_json::from_slice::<R>(bytes)
+        .map_err(|err| ExtractError::json_deserialize(bytes, err.to_string()))?;
+    extract_fn(response)
+        .into_iter()
+        .map(|(timestamp, extracted_value)| Ok((timestamp, extracted_value.scaled(bytes)?)))
+        .collect()
+}
+
+/// Parses a timestamp in seconds from a string, defaulting to 0 (which is later discarded as it
+/// lies outside of any requested window) if it cannot be parsed.
+fn parse_timestamp_secs(value: &str) -> u64 {
+    value.parse::<u64>().unwrap_or_default()
 }
 
 /// A single spot market parsed from an exchange's listing endpoint, normalized
@@ -244,6 +332,10 @@ impl ListedMarket {
 /// The result of parsing an exchange's listing endpoint:
 /// * `bases` — the base assets currently tradable against USDT, uppercased and
 ///   deduplicated. This is the set the crypto path is gated on.
+/// * `pairs` — the full graph of tradable markets: every base asset mapped to
+///   the quote assets it is tradable against, all uppercased. The router uses it
+///   to price an asset through an intermediate asset, e.g., BTC, if too few
+///   exchanges list it against USDT.
 /// * `total_markets` — the total number of spot markets parsed across all
 ///   quotes. This is a structural-health signal for the refresh acceptance
 ///   guard: it stays roughly stable across refreshes (even when a venue
@@ -257,12 +349,14 @@ impl ListedMarket {
 pub struct ListedPairs {
     pub bases: BTreeSet<String>,
     pub total_markets: usize,
+    pub pairs: BTreeMap<String, BTreeSet<String>>,
 }
 
 /// A generic way to extract the listed USDT bases out of the provided bytes,
 /// mirroring [extract_rate]: `markets_fn` projects the deserialized response
 /// down to the exchange's spot markets, then this folds over them in a single
-/// pass — keeping the USDT-quoted tradable bases and counting every market.
+/// pass — keeping the USDT-quoted tradable bases and the graph of all tradable
+/// pairs, and counting every market.
 ///
 /// Taking an [IntoIterator] (rather than a materialized `Vec`) lets each
 /// exchange stream its markets straight through without an intermediate
@@ -280,15 +374,25 @@ where
         .map_err(|err| ExtractError::json_deserialize(bytes, err.to_string()))?;
     let mut total_markets = 0;
     let mut bases = BTreeSet::new();
+    let mut pairs: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
     for market in markets_fn(response) {
         total_markets += 1;
-        if market.tradable && market.quote.eq_ignore_ascii_case(USDT) {
+        // Markets with an unknown quote (see the MEXC impl) cannot be routed through.
+        if !market.tradable || market.quote.is_empty() {
+            continue;
+        }
+        if market.quote.eq_ignore_ascii_case(USDT) {
             bases.insert(market.base.to_uppercase());
         }
+        pairs
+            .entry(market.base.to_uppercase())
+            .or_default()
+            .insert(market.quote.to_uppercase());
     }
     Ok(ListedPairs {
         bases,
         total_markets,
+        pairs,
     })
 }
 
@@ -302,6 +406,10 @@ const START_TIME: &str = "START_TIME";
 /// `END_TIME`: This string must be replaced with the end time derived from the timestamp in the request.
 const END_TIME: &str = "END_TIME";
 
+/// An upper bound on the size of a single candle in a window response. The largest candles
+/// (Poloniex) are about 250 bytes.
+const WINDOW_MAX_RESPONSE_BYTES_PER_CANDLE: u64 = 512;
+
 /// Default cap on the raw listing response a refresh outcall will accept. The
 /// XRC's largest listing (OKX, ~1.3 MiB) fits with headroom under the IC's
 /// ~2 MiB HTTP-outcall limit, and the subnet is feeless so over-provisioning
@@ -348,8 +456,44 @@ trait IsExchange {
             .replace(END_TIME, &self.format_end_time(timestamp))
     }
 
-    /// The implementation to extract the rate from the response's body.
-    fn extract_rate(&self, bytes: &[u8]) -> Result<u64, ExtractError>;
+    /// The implementation to extract the rate and the traded volume from the response's body.
+    fn extract_rate(&self, bytes: &[u8]) -> Result<ExtractedRate, ExtractError>;
+
+    /// Indicates if the exchange can be queried for a window of candles. By default, this is
+    /// the case if the base URL takes both a start and an end time.
+    fn supports_windows(&self) -> bool {
+        self.get_base_url().contains(START_TIME) && self.get_base_url().contains(END_TIME)
+    }
+
+    /// Provides the ability to format the start time of a window. Default implementation is
+    /// to simply return the provided timestamp as a string.
+    fn format_window_start_time(&self, timestamp: u64) -> String {
+        timestamp.to_string()
+    }
+
+    /// Provides the ability to format the end time of a window. Default implementation is
+    /// to simply return the provided timestamp as a string.
+    fn format_window_end_time(&self, timestamp: u64) -> String {
+        timestamp.to_string()
+    }
+
+    /// Generates a URL to retrieve the candles of all minutes from `start` to `end` (inclusive).
+    /// Unlike [IsExchange::get_url], no lookback or offset is applied to the window.
+    fn get_window_url(&self, base_asset: &str, quote_asset: &str, start: u64, end: u64) -> String {
+        let start = (start / 60) * 60;
+        let end = (end / 60) * 60;
+        self.get_base_url()
+            .replace(BASE_ASSET, &self.format_asset(base_asset))
+            .replace(QUOTE_ASSET, &self.format_asset(quote_asset))
+            .replace(START_TIME, &self.format_window_start_time(start))
+            .replace(END_TIME, &self.format_window_end_time(end))
+    }
+
+    /// The implementation to extract the timestamped rates of a window of candles from the
+    /// response's body. Exchanges that do not support windows return no candles.
+    fn extract_candles(&self, _bytes: &[u8]) -> Result<Vec<(u64, u64)>, ExtractError> {
+        Ok(vec![])
+    }
 
     /// The URL of the exchange's public spot-listing endpoint. Unlike
     /// [IsExchange::get_base_url] this takes no placeholders — the listing is
@@ -357,8 +501,8 @@ trait IsExchange {
     fn listing_url(&self) -> &str;
 
     /// Parses the listing endpoint's response body into the set of base assets
-    /// tradable against USDT and the total spot-market count (see
-    /// [ListedPairs]). Implementations project their own schema to a list of
+    /// tradable against USDT, the tradable pairs and the total spot-market count
+    /// (see [ListedPairs]). Implementations project their own schema to a list of
     /// [ListedMarket] and delegate to [extract_listed_pairs].
     fn extract_listed_usdt_bases(&self, bytes: &[u8]) -> Result<ListedPairs, ExtractError>;
 
@@ -367,9 +511,11 @@ trait IsExchange {
         false
     }
 
-    /// Return the exchange's supported USD asset type.
-    fn supported_usd_asset(&self) -> Asset {
-        usdt_asset()
+    /// Returns the USD-like quote assets of the exchange's markets, in order of preference.
+    /// For every asset, the first of them that the exchange lists the asset against is queried,
+    /// see `ListingStore::select_usd_quote`.
+    fn supported_usd_quote_assets(&self) -> &'static [&'static str] {
+        &[USDT, USDC]
     }
 
     fn supported_stablecoin_pairs(&self) -> &[(&str, &str)] {
@@ -380,6 +526,12 @@ trait IsExchange {
         3 * ONE_KIB
     }
 
+    /// The max response size for a window of the given number of candles.
+    fn window_max_response_bytes(&self, num_candles: u64) -> u64 {
+        self.max_response_bytes()
+            .saturating_add(num_candles.saturating_mul(WINDOW_MAX_RESPONSE_BYTES_PER_CANDLE))
+    }
+
     /// The max response size for this exchange's listing outcall. Listings are
     /// far larger than rate responses, so this overrides [max_response_bytes].
     fn listing_max_response_bytes(&self) -> u64 {
@@ -445,9 +597,23 @@ impl IsExchange for Coinbase {
             .to_string()
     }
 
-    fn extract_rate(&self, bytes: &[u8]) -> Result<u64, ExtractError> {
+    fn extract_rate(&self, bytes: &[u8]) -> Result<ExtractedRate, ExtractError> {
         extract_rate(bytes, |response: CoinbaseResponse| {
-            response.first().map(|kline| ExtractedValue::Float(kline.3))
+            response.first().map(|kline| {
+                (
+                    ExtractedValue::Float(kline.3),
+                    ExtractedValue::Float(kline.5),
+                )
+            })
+        })
+    }
+
+    fn extract_candles(&self, bytes: &[u8]) -> Result<Vec<(u64, u64)>, ExtractError> {
+        extract_candles(bytes, |response: CoinbaseResponse| {
+            response
+                .into_iter()
+                .map(|kline| (kline.0, ExtractedValue::Float(kline.3)))
+                .collect()
         })
     }
 
@@ -468,8 +634,9 @@ impl IsExchange for Coinbase {
         true
     }
 
-    fn supported_usd_asset(&self) -> Asset {
-        usd_asset()
+    // Coinbase's deepest order books are quoted in USD.
+    fn supported_usd_quote_assets(&self) -> &'static [&'static str] {
+        &[USD, USDT]
     }
 
     fn supported_stablecoin_pairs(&self) -> &[(&str, &str)] {
@@ -528,12 +695,29 @@ impl IsExchange for KuCoin {
             .to_string()
     }
 
-    fn extract_rate(&self, bytes: &[u8]) -> Result<u64, ExtractError> {
+    fn extract_rate(&self, bytes: &[u8]) -> Result<ExtractedRate, ExtractError> {
         extract_rate(bytes, |response: KuCoinResponse| {
+            response.data.first().map(|kline| {
+                (
+                    ExtractedValue::Str(kline.1.clone()),
+                    ExtractedValue::Str(kline.5.clone()),
+                )
+            })
+        })
+    }
+
+    fn format_window_end_time(&self, timestamp: u64) -> String {
+        // KuCoin needs `endAt` past the candle's start second to include it.
+        timestamp.saturating_add(1).to_string()
+    }
+
+    fn extract_candles(&self, bytes: &[u8]) -> Result<Vec<(u64, u64)>, ExtractError> {
+        extract_candles(bytes, |response: KuCoinResponse| {
             response
                 .data
-                .first()
-                .map(|kline| ExtractedValue::Str(kline.1.clone()))
+                .into_iter()
+                .map(|kline| (parse_timestamp_secs(&kline.0), ExtractedValue::Str(kline.1)))
+                .collect()
         })
     }
 
@@ -621,12 +805,39 @@ impl IsExchange for Okx {
         timestamp.saturating_mul(1000).saturating_add(1).to_string()
     }
 
-    fn extract_rate(&self, bytes: &[u8]) -> Result<u64, ExtractError> {
+    fn extract_rate(&self, bytes: &[u8]) -> Result<ExtractedRate, ExtractError> {
         extract_rate(bytes, |response: OkxResponse| {
+            response.data.first().map(|kline| {
+                (
+                    ExtractedValue::Str(kline.1.clone()),
+                    ExtractedValue::Str(kline.5.clone()),
+                )
+            })
+        })
+    }
+
+    fn format_window_start_time(&self, timestamp: u64) -> String {
+        // Convert seconds to milliseconds and subtract 1 millisecond as "before" is exclusive.
+        timestamp.saturating_mul(1000).saturating_sub(1).to_string()
+    }
+
+    fn format_window_end_time(&self, timestamp: u64) -> String {
+        // Convert seconds to milliseconds and add 1 millisecond as "after" is exclusive.
+        timestamp.saturating_mul(1000).saturating_add(1).to_string()
+    }
+
+    fn extract_candles(&self, bytes: &[u8]) -> Result<Vec<(u64, u64)>, ExtractError> {
+        extract_candles(bytes, |response: OkxResponse| {
             response
                 .data
-                .first()
-                .map(|kline| ExtractedValue::Str(kline.1.clone()))
+                .into_iter()
+                .map(|kline| {
+                    (
+                        parse_timestamp_secs(&kline.0) / 1000,
+                        ExtractedValue::Str(kline.1),
+                    )
+                })
+                .collect()
         })
     }
 
@@ -649,6 +860,10 @@ impl IsExchange for Okx {
     fn supports_ipv6(&self) -> bool {
         true
     }
+
+    fn supported_stablecoin_pairs(&self) -> &[(&str, &str)] {
+        &[(USDS, USDT), (USDC, USDT), (DAI, USDT)]
+    }
 }
 
 /// Gate.io
@@ -676,11 +891,23 @@ impl IsExchange for GateIo {
         "https://api.gateio.ws/api/v4/spot/candlesticks?currency_pair=BASE_ASSET_QUOTE_ASSET&interval=1m&from=START_TIME&to=END_TIME"
     }
 
-    fn extract_rate(&self, bytes: &[u8]) -> Result<u64, ExtractError> {
+    fn extract_rate(&self, bytes: &[u8]) -> Result<ExtractedRate, ExtractError> {
         extract_rate(bytes, |response: GateIoResponse| {
+            response.first().map(|kline| {
+                (
+                    ExtractedValue::Str(kline.3.clone()),
+                    ExtractedValue::Str(kline.6.clone()),
+                )
+            })
+        })
+    }
+
+    fn extract_candles(&self, bytes: &[u8]) -> Result<Vec<(u64, u64)>, ExtractError> {
+        extract_candles(bytes, |response: GateIoResponse| {
             response
-                .first()
-                .map(|kline| ExtractedValue::Str(kline.3.clone()))
+                .into_iter()
+                .map(|kline| (parse_timestamp_secs(&kline.0), ExtractedValue::Str(kline.3)))
+                .collect()
         })
     }
 
@@ -695,6 +922,16 @@ impl IsExchange for GateIo {
                 .map(|pair| ListedMarket::new(pair.base, pair.quote, pair.trade_status == "tradable"))
         })
     }
+
+    fn supported_stablecoin_pairs(&self) -> &[(&str, &str)] {
+        &[
+            (USDS, USDT),
+            (USDC, USDT),
+            (DAI, USDT),
+            (FDUSD, USDT),
+            (PYUSD, USDT),
+        ]
+    }
 }
 
 /// MEXC
@@ -718,7 +955,8 @@ type MexcResponse = Vec<(u64, String, String, String, String, String, u64, Strin
 /// MEXC's `defaultSymbols` lists only tradable symbols as concatenated strings
 /// (e.g. `"BTCUSDT"`) with no separator, so the quote can only be recovered for
 /// the suffixes we care about. Non-USDT symbols are kept (so they count toward
-/// `total_markets`) but cannot be split, so their quote is left unknown. Note
+/// `total_markets`) but cannot be split, so their quote is left unknown and
+/// only the USDT markets of MEXC end up in the pair graph. Note
 /// that because this endpoint omits non-tradable symbols, `total_markets` here
 /// tracks the tradable universe rather than the full listing — a weaker
 /// structural-health signal than for other exchanges (see [ListedPairs]).
@@ -732,11 +970,14 @@ impl IsExchange for Mexc {
         "https://api.mexc.com/api/v3/klines?symbol=BASE_ASSETQUOTE_ASSET&interval=1m&startTime=START_TIME&limit=1"
     }
 
-    fn extract_rate(&self, bytes: &[u8]) -> Result<u64, ExtractError> {
+    fn extract_rate(&self, bytes: &[u8]) -> Result<ExtractedRate, ExtractError> {
         extract_rate(bytes, |response: MexcResponse| {
-            response
-                .first()
-                .map(|kline| ExtractedValue::Str(kline.1.clone()))
+            response.first().map(|kline| {
+                (
+                    ExtractedValue::Str(kline.1.clone()),
+                    ExtractedValue::Str(kline.5.clone()),
+                )
+            })
         })
     }
 
@@ -757,6 +998,16 @@ impl IsExchange for Mexc {
                 })
         })
     }
+
+    fn supported_stablecoin_pairs(&self) -> &[(&str, &str)] {
+        &[
+            (USDS, USDT),
+            (USDC, USDT),
+            (DAI, USDT),
+            (FDUSD, USDT),
+            (PYUSD, USDT),
+        ]
+    }
 }
 
 /// Poloniex
@@ -802,11 +1053,33 @@ impl IsExchange for Poloniex {
         timestamp.saturating_mul(1000).saturating_add(1).to_string()
     }
 
-    fn extract_rate(&self, bytes: &[u8]) -> Result<u64, ExtractError> {
+    fn extract_rate(&self, bytes: &[u8]) -> Result<ExtractedRate, ExtractError> {
         extract_rate(bytes, |response: PoloniexResponse| {
+            response.first().map(|kline| {
+                (
+                    ExtractedValue::Str(kline.2.clone()),
+                    ExtractedValue::Str(kline.5.clone()),
+                )
+            })
+        })
+    }
+
+    fn format_window_start_time(&self, timestamp: u64) -> String {
+        // Convert seconds to milliseconds.
+        timestamp.saturating_mul(1000).to_string()
+    }
+
+    fn format_window_end_time(&self, timestamp: u64) -> String {
+        // Convert seconds to milliseconds and add 1 millisecond.
+        timestamp.saturating_mul(1000).saturating_add(1).to_string()
+    }
+
+    fn extract_candles(&self, bytes: &[u8]) -> Result<Vec<(u64, u64)>, ExtractError> {
+        extract_candles(bytes, |response: PoloniexResponse| {
             response
-                .first()
-                .map(|kline| ExtractedValue::Str(kline.2.clone()))
+                .into_iter()
+                .map(|kline| (kline.12 / 1000, ExtractedValue::Str(kline.2)))
+                .collect()
         })
     }
 
@@ -850,6 +1123,7 @@ struct CryptoResponseResult {
 #[derive(Deserialize)]
 struct CryptoResponseResultData {
     o: String,
+    v: String,
 }
 
 #[derive(Deserialize)]
@@ -883,13 +1157,14 @@ impl IsExchange for CryptoCom {
         timestamp.saturating_mul(1000).to_string()
     }
 
-    fn extract_rate(&self, bytes: &[u8]) -> Result<u64, ExtractError> {
+    fn extract_rate(&self, bytes: &[u8]) -> Result<ExtractedRate, ExtractError> {
         extract_rate(bytes, |response: CryptoResponse| {
-            response
-                .result
-                .data
-                .first()
-                .map(|kline| ExtractedValue::Str(kline.o.clone()))
+            response.result.data.first().map(|kline| {
+                (
+                    ExtractedValue::Str(kline.o.clone()),
+                    ExtractedValue::Str(kline.v.clone()),
+                )
+            })
         })
     }
 
@@ -922,6 +1197,10 @@ impl IsExchange for CryptoCom {
     fn supported_stablecoin_pairs(&self) -> &[(&str, &str)] {
         &[]
     }
+
+    fn supported_usd_quote_assets(&self) -> &'static [&'static str] {
+        &[USD, USDT]
+    }
 }
 
 /// Bitget
@@ -967,12 +1246,14 @@ impl IsExchange for Bitget {
             .to_string()
     }
 
-    fn extract_rate(&self, bytes: &[u8]) -> Result<u64, ExtractError> {
+    fn extract_rate(&self, bytes: &[u8]) -> Result<ExtractedRate, ExtractError> {
         extract_rate(bytes, |response: BitgetResponse| {
-            response
-                .data
-                .first()
-                .map(|kline| ExtractedValue::Str(kline.1.clone()))
+            response.data.first().map(|kline| {
+                (
+                    ExtractedValue::Str(kline.1.clone()),
+                    ExtractedValue::Str(kline.5.clone()),
+                )
+            })
         })
     }
 
@@ -998,6 +1279,10 @@ impl IsExchange for Bitget {
     fn supports_ipv6(&self) -> bool {
         true
     }
+
+    fn supported_stablecoin_pairs(&self) -> &[(&str, &str)] {
+        &[(USDS, USDT), (USDC, USDT), (FDUSD, USDT)]
+    }
 }
 
 /// Digifinex
@@ -1022,12 +1307,24 @@ impl IsExchange for Digifinex {
         "https://openapi.digifinex.com/v3/kline?symbol=BASE_ASSET_QUOTE_ASSET&period=1&start_time=START_TIME&end_time=END_TIME"
     }
 
-    fn extract_rate(&self, bytes: &[u8]) -> Result<u64, ExtractError> {
+    fn extract_rate(&self, bytes: &[u8]) -> Result<ExtractedRate, ExtractError> {
         extract_rate(bytes, |response: DigifinexResponse| {
+            response.data.first().map(|kline| {
+                (
+                    ExtractedValue::Float(kline.5),
+                    ExtractedValue::Float(kline.1),
+                )
+            })
+        })
+    }
+
+    fn extract_candles(&self, bytes: &[u8]) -> Result<Vec<(u64, u64)>, ExtractError> {
+        extract_candles(bytes, |response: DigifinexResponse| {
             response
                 .data
-                .first()
-                .map(|kline| ExtractedValue::Float(kline.5))
+                .into_iter()
+                .map(|kline| (kline.0, ExtractedValue::Float(kline.5)))
+                .collect()
         })
     }
 
@@ -1053,6 +1350,10 @@ impl IsExchange for Digifinex {
     fn supports_ipv6(&self) -> bool {
         true
     }
+
+    fn supported_stablecoin_pairs(&self) -> &[(&str, &str)] {
+        &[(USDS, USDT), (USDC, USDT), (DAI, USDT)]
+    }
 }
 
 #[cfg(test)]
@@ -1155,27 +1456,27 @@ mod test {
         assert!(digifinex.supports_ipv6());
     }
 
-    /// The function tests if the USD asset type is correct.
+    /// The function tests if the USD-like quote assets are correct.
     #[test]
-    fn supported_usd_asset_type() {
+    fn supported_usd_quote_assets() {
         let coinbase = Coinbase;
-        assert_eq!(coinbase.supported_usd_asset(), usd_asset());
+        assert_eq!(coinbase.supported_usd_quote_assets(), &[USD, USDT]);
         let kucoin = KuCoin;
-        assert_eq!(kucoin.supported_usd_asset(), usdt_asset());
+        assert_eq!(kucoin.supported_usd_quote_assets(), &[USDT, USDC]);
         let okx = Okx;
-        assert_eq!(okx.supported_usd_asset(), usdt_asset());
+        assert_eq!(okx.supported_usd_quote_assets(), &[USDT, USDC]);
         let gate_io = GateIo;
-        assert_eq!(gate_io.supported_usd_asset(), usdt_asset());
+        assert_eq!(gate_io.supported_usd_quote_assets(), &[USDT, USDC]);
         let mexc = Mexc;
-        assert_eq!(mexc.supported_usd_asset(), usdt_asset());
+        assert_eq!(mexc.supported_usd_quote_assets(), &[USDT, USDC]);
         let poloniex = Poloniex;
-        assert_eq!(poloniex.supported_usd_asset(), usdt_asset());
+        assert_eq!(poloniex.supported_usd_quote_assets(), &[USDT, USDC]);
         let crypto = CryptoCom;
-        assert_eq!(crypto.supported_usd_asset(), usdt_asset());
+        assert_eq!(crypto.supported_usd_quote_assets(), &[USD, USDT]);
         let bitget = Bitget;
-        assert_eq!(bitget.supported_usd_asset(), usdt_asset());
+        assert_eq!(bitget.supported_usd_quote_assets(), &[USDT, USDC]);
         let digifinex = Digifinex;
-        assert_eq!(digifinex.supported_usd_asset(), usdt_asset());
+        assert_eq!(digifinex.supported_usd_quote_assets(), &[USDT, USDC]);
     }
 
     /// The function tests if the supported stablecoins are correct.
@@ -1188,17 +1489,29 @@ mod test {
         let okx = Okx;
         assert_eq!(
             okx.supported_stablecoin_pairs(),
-            &[(USDS, USDT), (USDC, USDT)]
+            &[(USDS, USDT), (USDC, USDT), (DAI, USDT)]
         );
         let gate_io = GateIo;
         assert_eq!(
             gate_io.supported_stablecoin_pairs(),
-            &[(USDS, USDT), (USDC, USDT)]
+            &[
+                (USDS, USDT),
+                (USDC, USDT),
+                (DAI, USDT),
+                (FDUSD, USDT),
+                (PYUSD, USDT)
+            ]
         );
         let mexc = Mexc;
         assert_eq!(
             mexc.supported_stablecoin_pairs(),
-            &[(USDS, USDT), (USDC, USDT)]
+            &[
+                (USDS, USDT),
+                (USDC, USDT),
+                (DAI, USDT),
+                (FDUSD, USDT),
+                (PYUSD, USDT)
+            ]
         );
         let poloniex = Poloniex;
         assert_eq!(poloniex.supported_stablecoin_pairs(), &[(USDT, USDC)]);
@@ -1207,12 +1520,12 @@ mod test {
         let bitget = Bitget;
         assert_eq!(
             bitget.supported_stablecoin_pairs(),
-            &[(USDS, USDT), (USDC, USDT)]
+            &[(USDS, USDT), (USDC, USDT), (FDUSD, USDT)]
         );
         let digifinex = Digifinex;
         assert_eq!(
             digifinex.supported_stablecoin_pairs(),
-            &[(USDS, USDT), (USDC, USDT)]
+            &[(USDS, USDT), (USDC, USDT), (DAI, USDT)]
         );
     }
 
@@ -1225,7 +1538,13 @@ mod test {
         // the first entry (49.18), not an older candle.
         let query_response = load_file("test-data/exchanges/coinbase.json");
         let extracted_rate = coinbase.extract_rate(&query_response);
-        assert!(matches!(extracted_rate, Ok(rate) if rate == 49_180_000_000));
+        assert!(matches!(
+            extracted_rate,
+            Ok(ExtractedRate {
+                rate: 49_180_000_000,
+                volume: 12_494_190_900
+            })
+        ));
     }
 
     /// The function tests if the KuCoin struct returns the correct exchange rate.
@@ -1234,7 +1553,13 @@ mod test {
         let kucoin = KuCoin;
         let query_response = load_file("test-data/exchanges/kucoin.json");
         let extracted_rate = kucoin.extract_rate(&query_response);
-        assert!(matches!(extracted_rate, Ok(rate) if rate == 345_426_000_000));
+        assert!(matches!(
+            extracted_rate,
+            Ok(ExtractedRate {
+                rate: 345_426_000_000,
+                volume: 280_479_105_570
+            })
+        ));
     }
 
     /// KuCoin returns candles newest-first, so when the closed-minute window
@@ -1247,7 +1572,13 @@ mod test {
             ["1620296760","340.000","339.000","341.000","338.000","100.0","34000.0"]
         ]}"#;
         let extracted_rate = kucoin.extract_rate(response);
-        assert!(matches!(extracted_rate, Ok(rate) if rate == 345_426_000_000));
+        assert!(matches!(
+            extracted_rate,
+            Ok(ExtractedRate {
+                rate: 345_426_000_000,
+                volume: 280_000_000_000
+            })
+        ));
     }
 
     /// The function tests if the OKX struct returns the correct exchange rate.
@@ -1256,7 +1587,13 @@ mod test {
         let okx = Okx;
         let query_response = load_file("test-data/exchanges/okx.json");
         let extracted_rate = okx.extract_rate(&query_response);
-        assert!(matches!(extracted_rate, Ok(rate) if rate == 41_960_000_000));
+        assert!(matches!(
+            extracted_rate,
+            Ok(ExtractedRate {
+                rate: 41_960_000_000,
+                volume: 461_846_542_000
+            })
+        ));
     }
 
     /// The function tests if the GateIo struct returns the correct exchange rate.
@@ -1265,7 +1602,13 @@ mod test {
         let gate_io = GateIo;
         let query_response = load_file("test-data/exchanges/gateio.json");
         let extracted_rate = gate_io.extract_rate(&query_response);
-        assert!(matches!(extracted_rate, Ok(rate) if rate == 42_640_000_000));
+        assert!(matches!(
+            extracted_rate,
+            Ok(ExtractedRate {
+                rate: 42_640_000_000,
+                volume: 0
+            })
+        ));
     }
 
     /// The function tests if the Mexc struct returns the correct exchange rate.
@@ -1274,7 +1617,13 @@ mod test {
         let mexc = Mexc;
         let query_response = load_file("test-data/exchanges/mexc.json");
         let extracted_rate = mexc.extract_rate(&query_response);
-        assert!(matches!(extracted_rate, Ok(rate) if rate == 46_101_000_000));
+        assert!(matches!(
+            extracted_rate,
+            Ok(ExtractedRate {
+                rate: 46_101_000_000,
+                volume: 45_720_000_000
+            })
+        ));
     }
 
     /// The function tests if the Poloniex struct returns the correct exchange rate.
@@ -1283,7 +1632,13 @@ mod test {
         let poloniex = Poloniex;
         let query_response = load_file("test-data/exchanges/poloniex.json");
         let extracted_rate = poloniex.extract_rate(&query_response);
-        assert!(matches!(extracted_rate, Ok(rate) if rate == 46_022_000_000));
+        assert!(matches!(
+            extracted_rate,
+            Ok(ExtractedRate {
+                rate: 46_022_000_000,
+                volume: 200_450_000
+            })
+        ));
     }
 
     /// The function tests if the Crypto struct returns the correct exchange rate.
@@ -1292,7 +1647,13 @@ mod test {
         let crypto = CryptoCom;
         let query_response = load_file("test-data/exchanges/crypto.json");
         let extracted_rate = crypto.extract_rate(&query_response);
-        assert!(matches!(extracted_rate, Ok(rate) if rate == 47_328_300_000));
+        assert!(matches!(
+            extracted_rate,
+            Ok(ExtractedRate {
+                rate: 47_328_300_000,
+                volume: 0
+            })
+        ));
     }
 
     /// The function tests if the Bitget struct returns the correct exchange rate.
@@ -1301,7 +1662,13 @@ mod test {
         let bitget = Bitget;
         let query_response = load_file("test-data/exchanges/bitget.json");
         let extracted_rate = bitget.extract_rate(&query_response);
-        assert!(matches!(extracted_rate, Ok(rate) if rate == 13_123_000_000));
+        assert!(matches!(
+            extracted_rate,
+            Ok(ExtractedRate {
+                rate: 13_123_000_000,
+                volume: 457_720_000_000
+            })
+        ));
     }
 
     /// The function tests if the Digifinex struct returns the correct exchange rate.
@@ -1310,7 +1677,123 @@ mod test {
         let digifinex = Digifinex;
         let query_response = load_file("test-data/exchanges/digifinex.json");
         let extracted_rate = digifinex.extract_rate(&query_response);
-        assert!(matches!(extracted_rate, Ok(rate) if rate == 11_357_000_000));
+        assert!(matches!(
+            extracted_rate,
+            Ok(ExtractedRate {
+                rate: 11_357_000_000,
+                volume: 3_360_000_000
+            })
+        ));
+    }
+
+    /// The function tests if the exchanges that take a start and an end time support
+    /// windows and return the correct window query string.
+    #[test]
+    fn window_query_string() {
+        // Note that the seconds are ignored, setting the window to 1661520360-1661523960.
+        let start = 1661520400;
+        let end = 1661524016;
+
+        let coinbase = Coinbase;
+        assert!(coinbase.supports_windows());
+        let query_string = coinbase.get_window_url("btc", "icp", start, end);
+        assert_eq!(query_string, "https://api.exchange.coinbase.com/products/BTC-ICP/candles?granularity=60&start=1661520360&end=1661523960");
+
+        let kucoin = KuCoin;
+        assert!(kucoin.supports_windows());
+        let query_string = kucoin.get_window_url("btc", "icp", start, end);
+        assert_eq!(query_string, "https://api.kucoin.com/api/v1/market/candles?symbol=BTC-ICP&type=1min&startAt=1661520360&endAt=1661523961");
+
+        let okx = Okx;
+        assert!(okx.supports_windows());
+        let query_string = okx.get_window_url("btc", "icp", start, end);
+        assert_eq!(query_string, "https://www.okx.com/api/v5/market/history-candles?instId=BTC-ICP&bar=1m&before=1661520359999&after=1661523960001");
+
+        let gate_io = GateIo;
+        assert!(gate_io.supports_windows());
+        let query_string = gate_io.get_window_url("btc", "icp", start, end);
+        assert_eq!(query_string, "https://api.gateio.ws/api/v4/spot/candlesticks?currency_pair=BTC_ICP&interval=1m&from=1661520360&to=1661523960");
+
+        let poloniex = Poloniex;
+        assert!(poloniex.supports_windows());
+        let query_string = poloniex.get_window_url("btc", "icp", start, end);
+        assert_eq!(query_string, "https://api.poloniex.com/markets/BTC_ICP/candles?interval=MINUTE_1&startTime=1661520360000&endTime=1661523960001");
+
+        let digifinex = Digifinex;
+        assert!(digifinex.supports_windows());
+        let query_string = digifinex.get_window_url("icp", "usdt", start, end);
+        assert_eq!(query_string, "https://openapi.digifinex.com/v3/kline?symbol=ICP_USDT&period=1&start_time=1661520360&end_time=1661523960");
+
+        assert!(!Mexc.supports_windows());
+        assert!(!CryptoCom.supports_windows());
+        assert!(!Bitget.supports_windows());
+    }
+
+    /// The function tests if the exchanges return the timestamped rates of all candles.
+    #[test]
+    fn extract_candles() {
+        let coinbase = Coinbase;
+        let query_response = load_file("test-data/exchanges/coinbase.json");
+        assert_eq!(
+            coinbase.extract_candles(&query_response).unwrap(),
+            vec![
+                (1647734400, 49_180_000_000),
+                (1647734340, 47_550_000_000),
+                (1647734280, 46_400_000_000)
+            ]
+        );
+
+        let kucoin = KuCoin;
+        let query_response = load_file("test-data/exchanges/kucoin.json");
+        assert_eq!(
+            kucoin.extract_candles(&query_response).unwrap(),
+            vec![(1620296820, 345_426_000_000)]
+        );
+
+        let okx = Okx;
+        let query_response = load_file("test-data/exchanges/okx.json");
+        assert_eq!(
+            okx.extract_candles(&query_response).unwrap(),
+            vec![(1637161920, 41_960_000_000)]
+        );
+
+        let gate_io = GateIo;
+        let query_response = load_file("test-data/exchanges/gateio.json");
+        assert_eq!(
+            gate_io.extract_candles(&query_response).unwrap(),
+            vec![(1620296820, 42_640_000_000)]
+        );
+
+        let poloniex = Poloniex;
+        let query_response = load_file("test-data/exchanges/poloniex.json");
+        assert_eq!(
+            poloniex.extract_candles(&query_response).unwrap(),
+            vec![(1677584340, 46_022_000_000)]
+        );
+
+        let digifinex = Digifinex;
+        let query_response = load_file("test-data/exchanges/digifinex.json");
+        assert_eq!(
+            digifinex.extract_candles(&query_response).unwrap(),
+            vec![(1706763600, 11_357_000_000)]
+        );
+
+        // Exchanges that do not support windows return no candles.
+        let mexc = Mexc;
+        let query_response = load_file("test-data/exchanges/mexc.json");
+        assert_eq!(mexc.extract_candles(&query_response).unwrap(), vec![]);
+    }
+
+    /// The function tests that the candles survive the window transform's encoding.
+    #[test]
+    fn encode_decode_window_response_round_trips() {
+        let candles = vec![(1647734400, 49_180_000_000), (1647734340, 47_550_000_000)];
+        let encoded =
+            Exchange::encode_window_response(&candles).expect("should be able to encode candles");
+        assert!(matches!(
+            Exchange::decode_window_response(&encoded),
+            Ok(decoded) if decoded == candles
+        ));
     }
 
     /// Asserts the common shape of every listing fixture: each holds four spot
@@ -1417,6 +1900,33 @@ mod test {
         assert_lists_btc_and_eth(&Digifinex, "test-data/exchanges/listings/digifinex.json");
     }
 
+    /// The function tests that the pair graph keeps every tradable market, including the
+    /// markets that are not quoted in USDT, and that it survives the listing transform's
+    /// encoding.
+    #[test]
+    fn extract_listed_pairs_keeps_tradable_pair_graph() {
+        let body = load_file("test-data/exchanges/listings/coinbase.json");
+        let listed = Coinbase
+            .extract_listed_usdt_bases(&body)
+            .expect("should parse the listing fixture");
+        let quotes = |quotes: &[&str]| quotes.iter().map(|s| s.to_string()).collect();
+        let expected: BTreeMap<String, BTreeSet<String>> = [
+            ("BTC".to_string(), quotes(&["USDT"])),
+            ("DOT".to_string(), quotes(&["BTC"])),
+            ("ETH".to_string(), quotes(&["USDT"])),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(listed.pairs, expected);
+
+        let encoded =
+            Exchange::encode_listing_response(&listed).expect("should be able to encode listing");
+        assert_eq!(
+            Exchange::decode_listing_response(&encoded).expect("should decode listing"),
+            listed
+        );
+    }
+
     /// The function tests the ability of an [Exchange] to encode the context to be sent
     /// to the exchange transform function.
     #[test]
@@ -1430,7 +1940,7 @@ mod test {
     }
 
     /// The function tests that [Exchange] encodes and decodes a response body
-    /// (the transform's `Option<u64>` payload) symmetrically, for both a present
+    /// (the transform's `Option<ExtractedRate>` payload) symmetrically, for both a present
     /// rate and the empty/no-data `None` case. The exact byte layout is not
     /// pinned: `encode_response`/`decode_response` are an ephemeral within-outcall
     /// round-trip in a single canister build (the bytes are never persisted or
@@ -1438,8 +1948,16 @@ mod test {
     /// matters.
     #[test]
     fn encode_decode_response_round_trips() {
-        let some = Exchange::encode_response(Some(100)).expect("should be able to encode value");
-        assert!(matches!(Exchange::decode_response(&some), Ok(Some(100))));
+        let extracted_rate = ExtractedRate {
+            rate: 100,
+            volume: 5,
+        };
+        let some = Exchange::encode_response(Some(extracted_rate))
+            .expect("should be able to encode value");
+        assert_eq!(
+            Exchange::decode_response(&some).ok(),
+            Some(Some(extracted_rate))
+        );
 
         let none = Exchange::encode_response(None).expect("should be able to encode no-data");
         assert!(matches!(Exchange::decode_response(&none), Ok(None)));
@@ -13,27 +13,23 @@ mod uzbekistan;
 use candid::{
     decode_args, decode_one, encode_args, encode_one, CandidType, Deserialize, Error as CandidError,
 };
-use ic_xrc_types::{Asset, AssetClass, ExchangeRateError};
-use std::cmp::min;
+use ic_xrc_types::{Asset, AssetClass, Basket, ExchangeRateError};
 use std::collections::{BTreeMap, HashMap};
 use std::collections::{HashSet, VecDeque};
 use std::mem::size_of_val;
 
 use crate::api::usd_asset;
+use crate::baskets;
+use crate::config;
 use crate::utils::integer_sqrt;
 use crate::{
-    median, standard_deviation, utils, AllocatedBytes, ExtractError, QueriedExchangeRate,
-    ONE_DAY_SECONDS, ONE_HOUR_SECONDS, ONE_KIB, RATE_UNIT, USD,
+    median, standard_deviation, utils, with_config, AllocatedBytes, ExtractError,
+    QueriedExchangeRate, RateAggregation, ONE_DAY_SECONDS, ONE_HOUR_SECONDS, ONE_KIB, RATE_UNIT,
+    USD,
 };
 
-/// The IMF SDR weights used to compute the XDR rate.
-pub(crate) const USD_XDR_WEIGHT_PER_MILLION: u128 = 582_520;
-pub(crate) const EUR_XDR_WEIGHT_PER_MILLION: u128 = 386_710;
-pub(crate) const CNY_XDR_WEIGHT_PER_MILLION: u128 = 1_017_400;
-pub(crate) const JPY_XDR_WEIGHT_PER_MILLION: u128 = 11_900_000;
-pub(crate) const GBP_XDR_WEIGHT_PER_MILLION: u128 = 85_946;
-
-/// The CMC uses a computed XDR (CXDR) rate based on the IMF SDR weights.
+/// The CMC uses a computed XDR (CXDR) rate based on the IMF SDR weights, see
+/// [crate::baskets::SDR_WEIGHT_SCHEDULE].
 pub(crate) const COMPUTED_XDR_SYMBOL: &str = "CXDR";
 
 /// Maximal number of days to keep around in the [ForexRatesCollector]
@@ -65,6 +61,8 @@ pub(crate) struct ForexRateStore {
 #[derive(Clone, Debug)]
 struct OneDayRatesCollector {
     rates: HashMap<String, Vec<u64>>,
+    /// The USD rates of the cryptocurrency components of the baskets.
+    crypto_rates: HashMap<String, Vec<u64>>,
     sources: HashSet<String>,
     timestamp: u64,
 }
@@ -76,8 +74,10 @@ pub struct ForexRatesCollector {
 }
 
 const TIMEZONE_AOE_SHIFT_HOURS: i16 = 12;
-const MAX_DAYS_TO_GO_BACK: u64 = 7;
-const MIN_SOURCES_TO_REPORT: usize = if cfg!(feature = "ipv4-support") { 4 } else { 2 };
+/// The default maximum number of days to go back when looking up forex rates.
+pub(crate) const MAX_DAYS_TO_GO_BACK: u64 = 7;
+/// The default minimum number of forex sources needed to report a rate.
+pub(crate) const MIN_SOURCES_TO_REPORT: usize = if cfg!(feature = "ipv4-support") { 4 } else { 2 };
 
 /// This macro generates the necessary boilerplate when adding a forex data source to this module.
 macro_rules! forex {
@@ -228,6 +228,12 @@ macro_rules! forex {
                 utils::is_ipv4_support_available() || self.supports_ipv6()
             }
 
+            /// This method returns whether the forex is enabled, i.e., whether it was
+            /// not disabled by NNS Governance through the configuration.
+            pub fn is_enabled(&self) -> bool {
+                config::is_forex_enabled(&self.to_string())
+            }
+
             /// This method returns the number of cycles expected to be sent when
             /// calling a forex. The value returned is at least the maximum
             /// required for each forex.
@@ -342,6 +348,35 @@ impl ForexRateStore {
         base_asset: &str,
         quote_asset: &str,
     ) -> Result<QueriedExchangeRate, GetForexRateError> {
+        self.get_with_max_staleness(
+            requested_timestamp,
+            current_timestamp,
+            base_asset,
+            quote_asset,
+            None,
+        )
+    }
+
+    /// Returns the exchange rate for the given two forex assets and a given timestamp like [ForexRateStore::get],
+    /// but only goes back up to `max_staleness_days` days (capped at the configured `max_forex_days_to_go_back`) if there are no rates for the requested day.
+    pub(crate) fn get_with_max_staleness(
+        &self,
+        requested_timestamp: u64,
+        current_timestamp: u64,
+        base_asset: &str,
+        quote_asset: &str,
+        max_staleness_days: Option<u64>,
+    ) -> Result<QueriedExchangeRate, GetForexRateError> {
+        let (configured_max_days_to_go_back, min_sources_to_report) = with_config(|config| {
+            (
+                config.max_forex_days_to_go_back,
+                config.min_forex_sources_to_report as usize,
+            )
+        });
+        let max_days_to_go_back = max_staleness_days
+            .map_or(configured_max_days_to_go_back, |days| {
+                days.min(configured_max_days_to_go_back)
+            });
         // Normalize timestamp to the beginning of the day.
         let mut requested_timestamp = (requested_timestamp / ONE_DAY_SECONDS) * ONE_DAY_SECONDS;
 
@@ -367,23 +402,24 @@ impl ForexRateStore {
                 0,
                 0,
                 Some(requested_timestamp),
+                RateAggregation::Median,
             ));
         }
 
         let mut go_back_days = 0;
 
-        // If we can't find forex rates for the requested timestamp, we may go back up to [MAX_DAYS_TO_GO_BACK] days as it might have been a weekend or a holiday.
-        while go_back_days <= MAX_DAYS_TO_GO_BACK {
+        // If we can't find forex rates for the requested timestamp, we may go back up to [Config::max_forex_days_to_go_back] days as it might have been a weekend or a holiday.
+        while go_back_days <= max_days_to_go_back {
             let query_timestamp =
                 requested_timestamp.saturating_sub(ONE_DAY_SECONDS * go_back_days);
             go_back_days += 1;
             if let Some(rates_for_timestamp) = self.rates.get(&query_timestamp) {
-                // We only return rates if we received [MIN_SOURCES_TO_REPORT] different rates for CXDR
+                // We only return rates if we received [Config::min_forex_sources_to_report] different rates for CXDR
                 // (which means we received enough rates for EUR, GBP, JPY, and CNY with respect to USD).
                 let mut enough_sources = false;
                 if let Some(cxdr_rate) = rates_for_timestamp.get(COMPUTED_XDR_SYMBOL) {
                     enough_sources =
-                        cxdr_rate.base_asset_num_received_rates >= MIN_SOURCES_TO_REPORT;
+                        cxdr_rate.base_asset_num_received_rates >= min_sources_to_report;
                 }
                 if !enough_sources {
                     continue;
@@ -438,10 +474,24 @@ impl ForexRateStore {
                 }
             }
         }
-        // If we got here, no rate is found for this timestamp within a range of [MAX_DAYS_TO_GO_BACK] days before it.
+        // If we got here, no rate is found for this timestamp within a range of [Config::max_forex_days_to_go_back] days before it.
         Err(GetForexRateError::InvalidTimestamp(requested_timestamp))
     }
 
+    /// Returns the most recent day with rates, i.e., its timestamp and its rates.
+    pub(crate) fn get_latest(&self) -> Option<(u64, &ForexMultiRateMap)> {
+        self.rates
+            .iter()
+            .max_by_key(|(timestamp, _)| **timestamp)
+            .map(|(timestamp, rates)| (*timestamp, rates))
+    }
+
+    /// Returns the rates stored for the day of the given timestamp, if any.
+    pub(crate) fn get_day(&self, timestamp: u64) -> Option<&ForexMultiRateMap> {
+        self.rates
+            .get(&((timestamp / ONE_DAY_SECONDS) * ONE_DAY_SECONDS))
+    }
+
     /// Inserts or updates rates for a given timestamp. If rates already exist for the given timestamp,
     /// only rates for which a new rate with a higher number of sources are replaced.
     pub(crate) fn put(&mut self, timestamp: u64, rates: ForexMultiRateMap) {
@@ -480,6 +530,7 @@ impl OneDayRatesCollector {
     fn new(timestamp: u64) -> Self {
         Self {
             rates: HashMap::new(),
+            crypto_rates: HashMap::new(),
             sources: HashSet::new(),
             timestamp,
         }
@@ -504,7 +555,10 @@ impl OneDayRatesCollector {
 
     /// Extracts all the up-to-date rates.
     pub(crate) fn get_rates_map(&self) -> ForexMultiRateMap {
-        let num_queried_sources = FOREX_SOURCES.iter().filter(|e| e.is_available()).count();
+        let num_queried_sources = FOREX_SOURCES
+            .iter()
+            .filter(|e| e.is_available() && e.is_enabled())
+            .count();
         let mut rates: ForexMultiRateMap = self
             .rates
             .iter()
@@ -526,86 +580,96 @@ impl OneDayRatesCollector {
                         num_queried_sources,
                         v.len(),
                         Some(self.timestamp),
+                        RateAggregation::Median,
                     ),
                 ))
             })
             .collect();
-        if let Some(rate) = self.get_computed_xdr_rate() {
-            rates.insert(COMPUTED_XDR_SYMBOL.to_string(), rate);
+        for basket in baskets::get_effective_baskets(self.timestamp) {
+            if let Some(rate) = self.get_basket_rate(&basket) {
+                rates.insert(basket.symbol, rate);
+            }
         }
         rates
     }
 
-    /// Computes and returns the XDR/USD rate based on the weights specified by the IMF.
-    fn get_computed_xdr_rate(&self) -> Option<QueriedExchangeRate> {
-        let eur_rates_option = self.rates.get("EUR");
-        let cny_rates_option = self.rates.get("CNY");
-        let jpy_rates_option = self.rates.get("JPY");
-        let gbp_rates_option = self.rates.get("GBP");
-        if let (Some(eur_rates), Some(cny_rates), Some(jpy_rates), Some(gbp_rates)) = (
-            eur_rates_option,
-            cny_rates_option,
-            jpy_rates_option,
-            gbp_rates_option,
-        ) {
-            let eur_rate = median(eur_rates) as u128;
-            let cny_rate = median(cny_rates) as u128;
-            let jpy_rate = median(jpy_rates) as u128;
-            let gbp_rate = median(gbp_rates) as u128;
-
-            // The factor `RATE_UNIT` is the scaled USD/USD rate, i.e., the rate 1.00 times `RATE_UNIT`.
-            let xdr_rate = (USD_XDR_WEIGHT_PER_MILLION
-                .saturating_mul(RATE_UNIT as u128)
-                .saturating_add(EUR_XDR_WEIGHT_PER_MILLION.saturating_mul(eur_rate))
-                .saturating_add(CNY_XDR_WEIGHT_PER_MILLION.saturating_mul(cny_rate))
-                .saturating_add(JPY_XDR_WEIGHT_PER_MILLION.saturating_mul(jpy_rate))
-                .saturating_add(GBP_XDR_WEIGHT_PER_MILLION.saturating_mul(gbp_rate)))
+    /// Computes and returns the basket/USD rate as the sum of the median USD rates of the
+    /// components multiplied by their weights, e.g., the CXDR/USD rate based on the weights
+    /// specified by the IMF. Returns `None` if there is no rate for any of the components.
+    fn get_basket_rate(&self, basket: &Basket) -> Option<QueriedExchangeRate> {
+        // The USD/USD rate is 1.00 times `RATE_UNIT` without any deviation.
+        let usd_rates = vec![RATE_UNIT];
+        let mut weighted_component_rates = vec![];
+        for component in &basket.components {
+            let rates = if component.asset.symbol == USD {
+                &usd_rates
+            } else {
+                match component.asset.class {
+                    AssetClass::FiatCurrency => self.rates.get(&component.asset.symbol)?,
+                    AssetClass::Cryptocurrency => self.crypto_rates.get(&component.asset.symbol)?,
+                }
+            };
+            weighted_component_rates.push((component.weight_per_million as u128, rates));
+        }
+
+        let basket_rate = weighted_component_rates
+            .iter()
+            .fold(0u128, |sum, (weight, rates)| {
+                sum.saturating_add(weight.saturating_mul(median(rates) as u128))
+            })
             .saturating_div(1_000_000u128) as u64;
 
-            let xdr_num_sources = min(
-                min(min(eur_rates.len(), cny_rates.len()), jpy_rates.len()),
-                gbp_rates.len(),
-            );
-
-            let weighted_eur_std_dev =
-                EUR_XDR_WEIGHT_PER_MILLION.saturating_mul(standard_deviation(eur_rates) as u128);
-            let weighted_cny_std_dev =
-                CNY_XDR_WEIGHT_PER_MILLION.saturating_mul(standard_deviation(cny_rates) as u128);
-            let weighted_jpy_std_dev =
-                JPY_XDR_WEIGHT_PER_MILLION.saturating_mul(standard_deviation(jpy_rates) as u128);
-            let weighted_gbp_std_dev =
-                GBP_XDR_WEIGHT_PER_MILLION.saturating_mul(standard_deviation(gbp_rates) as u128);
-
-            // Assuming independence, the variance is the sum of squared weighted standard deviations
-            // because Var(aX + bY) = a^2*Var(X) + b^2*Var(Y) for independent X and Y.
-            let variance = (weighted_eur_std_dev
-                .saturating_pow(2)
-                .saturating_add(weighted_cny_std_dev.saturating_pow(2))
-                .saturating_add(weighted_jpy_std_dev.saturating_pow(2))
-                .saturating_add(weighted_gbp_std_dev.saturating_pow(2)))
+        let basket_num_sources = basket
+            .components
+            .iter()
+            .zip(&weighted_component_rates)
+            .filter(|(component, _)| component.asset.symbol != USD)
+            .map(|(_, (_, rates))| rates.len())
+            .min()
+            .unwrap_or_default();
+
+        // Assuming independence, the variance is the sum of squared weighted standard deviations
+        // because Var(aX + bY) = a^2*Var(X) + b^2*Var(Y) for independent X and Y.
+        let variance = weighted_component_rates
+            .iter()
+            .fold(0u128, |sum, (weight, rates)| {
+                let weighted_std_dev = weight.saturating_mul(standard_deviation(rates) as u128);
+                sum.saturating_add(weighted_std_dev.saturating_pow(2))
+            })
             .saturating_div(1_000_000_000_000); // Removing the factor (10^6)^2 due to the weight scaling.
 
-            // The rates are set to [xdr_rate, xdr_rate, xdr_rate + difference], where
-            // difference = sqrt(3*variance). This set has the required properties:
-            // * The median of the set is xdr_rate.
-            // * The variance of the set is 'variance'.
-            let difference = integer_sqrt(3 * variance);
+        // The rates are set to [basket_rate, basket_rate, basket_rate + difference], where
+        // difference = sqrt(3*variance). This set has the required properties:
+        // * The median of the set is basket_rate.
+        // * The variance of the set is 'variance'.
+        let difference = integer_sqrt(3 * variance);
 
-            Some(QueriedExchangeRate::new(
-                Asset {
-                    symbol: COMPUTED_XDR_SYMBOL.to_string(),
-                    class: AssetClass::FiatCurrency,
-                },
-                usd_asset(),
-                self.timestamp,
-                &[xdr_rate, xdr_rate, xdr_rate.saturating_add(difference)],
-                FOREX_SOURCES.len(),
-                xdr_num_sources,
-                Some(self.timestamp),
-            ))
-        } else {
-            None
-        }
+        Some(QueriedExchangeRate::new(
+            Asset {
+                symbol: basket.symbol.clone(),
+                class: AssetClass::FiatCurrency,
+            },
+            usd_asset(),
+            self.timestamp,
+            &[
+                basket_rate,
+                basket_rate,
+                basket_rate.saturating_add(difference),
+            ],
+            FOREX_SOURCES.len(),
+            basket_num_sources,
+            Some(self.timestamp),
+            RateAggregation::Median,
+        ))
+    }
+
+    /// Returns the cryptocurrency components of the baskets in effect on this day for which
+    /// no rates have been collected yet.
+    fn get_missing_crypto_components(&self) -> Vec<String> {
+        baskets::get_crypto_component_symbols(self.timestamp)
+            .into_iter()
+            .filter(|symbol| !self.crypto_rates.contains_key(symbol))
+            .collect()
     }
 }
 
@@ -663,6 +727,36 @@ impl ForexRatesCollector {
             .map(|one_day_collector| one_day_collector.get_rates_map())
     }
 
+    /// Returns the cryptocurrency components of the baskets for which no rates have been
+    /// collected yet on the day of the given timestamp, if it exists in this collector.
+    pub(crate) fn get_missing_crypto_components(&self, timestamp: u64) -> Vec<String> {
+        self.days
+            .iter()
+            .find(|one_day_collector| one_day_collector.timestamp == timestamp)
+            .map(|one_day_collector| one_day_collector.get_missing_crypto_components())
+            .unwrap_or_default()
+    }
+
+    /// Adds the USD rates of cryptocurrency components of the baskets for the given timestamp.
+    /// The function returns true if the timestamp exists in this collector.
+    pub(crate) fn update_crypto_rates(
+        &mut self,
+        timestamp: u64,
+        rates: BTreeMap<String, Vec<u64>>,
+    ) -> bool {
+        match self
+            .days
+            .iter_mut()
+            .find(|one_day_collector| one_day_collector.timestamp == timestamp)
+        {
+            Some(one_day_collector) => {
+                one_day_collector.crypto_rates.extend(rates);
+                true
+            }
+            None => false,
+        }
+    }
+
     /// Return the list of sources used for a given timestamp.
     pub(crate) fn get_sources(&self, timestamp: u64) -> Option<Vec<String>> {
         self.days
@@ -815,6 +909,7 @@ mod test {
         // Create a collector, update three times, check median rates.
         let mut collector = OneDayRatesCollector {
             rates: HashMap::new(),
+            crypto_rates: HashMap::new(),
             timestamp: 1234,
             sources: HashSet::new(),
         };
@@ -994,6 +1089,7 @@ mod test {
                         MIN_SOURCES_TO_REPORT,
                         MIN_SOURCES_TO_REPORT,
                         Some(timestamp),
+                        RateAggregation::Median,
                     )
             },
         );
@@ -1009,6 +1105,19 @@ mod test {
         );
     }
 
+    /// Tests that the [ForexRateStore] returns the rates of the most recent day.
+    #[test]
+    fn rate_store_get_latest() {
+        let mut store = ForexRateStore::new();
+        assert!(store.get_latest().is_none());
+
+        add_enough_cxdr_rates_to_store(&mut store, 2 * ONE_DAY_SECONDS);
+        add_enough_cxdr_rates_to_store(&mut store, ONE_DAY_SECONDS);
+        let (timestamp, rates) = store.get_latest().expect("latest rates should exist");
+        assert_eq!(timestamp, 2 * ONE_DAY_SECONDS);
+        assert!(rates.contains_key(COMPUTED_XDR_SYMBOL));
+    }
+
     /// Tests that the [ForexRatesStore] struct correctly updates rates for the same timestamp.
     #[test]
     fn rate_store_update() {
@@ -1027,6 +1136,7 @@ mod test {
                         4,
                         4,
                         Some(1234),
+                        RateAggregation::Median,
                     ),
                 "SGD".to_string() =>
                     QueriedExchangeRate::new(
@@ -1040,6 +1150,7 @@ mod test {
                         5,
                         5,
                         Some(1234),
+                        RateAggregation::Median,
                     ),
                 "CHF".to_string() =>
                     QueriedExchangeRate::new(
@@ -1053,6 +1164,7 @@ mod test {
                         2,
                         2,
                         Some(1234),
+                        RateAggregation::Median,
                     ),
                 "CAD".to_string() =>
                     QueriedExchangeRate::new(
@@ -1066,6 +1178,7 @@ mod test {
                         2,
                         2,
                         Some(1234),
+                        RateAggregation::Median,
                     ),
             },
         );
@@ -1081,6 +1194,7 @@ mod test {
                         5,
                         5,
                         Some(1234),
+                        RateAggregation::Median,
                     ),
                 "GBP".to_string() =>
                     QueriedExchangeRate::new(
@@ -1094,6 +1208,7 @@ mod test {
                         6,
                         6,
                         Some(1234),
+                        RateAggregation::Median,
                     ),
                 "CHF".to_string() =>
                     QueriedExchangeRate::new(
@@ -1107,6 +1222,7 @@ mod test {
                         5,
                         5,
                         Some(1234),
+                        RateAggregation::Median,
                     ),
             },
         );
@@ -1163,6 +1279,7 @@ mod test {
                         4,
                         4,
                         Some(0),
+                        RateAggregation::Median,
                     ),
             },
         );
@@ -1180,6 +1297,7 @@ mod test {
                         5,
                         5,
                         Some(ONE_DAY_SECONDS),
+                        RateAggregation::Median,
                     ),
             },
         );
@@ -1197,6 +1315,7 @@ mod test {
                         5,
                         5,
                         Some(ONE_DAY_SECONDS * 2),
+                        RateAggregation::Median,
                     ),
             },
         );
@@ -1272,6 +1391,7 @@ mod test {
     fn collector_sdr_xdr() {
         let mut collector = OneDayRatesCollector {
             rates: HashMap::new(),
+            crypto_rates: HashMap::new(),
             timestamp: 1234,
             sources: HashSet::new(),
         };
@@ -1326,6 +1446,7 @@ mod test {
 
         let collector = OneDayRatesCollector {
             rates: map,
+            crypto_rates: HashMap::new(),
             timestamp: 0,
             sources: HashSet::new(),
         };
@@ -1366,12 +1487,62 @@ mod test {
                 quote_asset_num_received_rates: 2,
                 standard_deviation: 6688618,
                 forex_timestamp: Some(0),
+                twap_window_minutes: None,
+                base_asset_num_discarded_rates: Some(0),
+                quote_asset_num_discarded_rates: Some(0),
+                base_asset_route: None,
+                quote_asset_route: None,
             },
         };
 
         assert_eq!(cxdr_usd_rate, _expected_rate);
     }
 
+    /// This function tests that the CXDR rate of a day is computed with the IMF SDR weights
+    /// that are in effect on that day.
+    #[test]
+    fn compute_xdr_rate_uses_sdr_weights_of_the_day() {
+        let mut map: HashMap<String, Vec<u64>> = HashMap::new();
+        map.insert("EUR".to_string(), vec![RATE_UNIT]);
+        map.insert("CNY".to_string(), vec![RATE_UNIT / 10]);
+        map.insert("JPY".to_string(), vec![RATE_UNIT / 100]);
+        map.insert("GBP".to_string(), vec![RATE_UNIT]);
+        let get_cxdr_rate = |timestamp| {
+            let collector = OneDayRatesCollector {
+                rates: map.clone(),
+                crypto_rates: HashMap::new(),
+                timestamp,
+                sources: HashSet::new(),
+            };
+            collector.get_rates_map()[COMPUTED_XDR_SYMBOL].rates[0]
+        };
+
+        // 0.58252 + 0.38671 * 1 + 1.0174 * 0.1 + 11.9 * 0.01 + 0.085946 * 1 = 1.275916
+        assert_eq!(get_cxdr_rate(1_659_312_000), 1_275_916_000);
+
+        // New weights that are configured to take effect later do not change earlier days.
+        let effective_from = 1_900_000_000;
+        let mut reweighted_cxdr = crate::baskets::default_baskets()[0].clone();
+        reweighted_cxdr.effective_from = effective_from;
+        for (component, weight_per_million) in reweighted_cxdr
+            .components
+            .iter_mut()
+            .zip([578_130, 373_790, 1_099_300, 13_452_000, 80_870])
+        {
+            component.weight_per_million = weight_per_million;
+        }
+        crate::config::set_config(ic_xrc_types::Config {
+            baskets: Some(vec![
+                crate::baskets::default_baskets()[0].clone(),
+                reweighted_cxdr,
+            ]),
+            ..crate::config::default_config()
+        });
+        assert_eq!(get_cxdr_rate(effective_from - 86_400), 1_275_916_000);
+        // 0.57813 + 0.37379 * 1 + 1.0993 * 0.1 + 13.452 * 0.01 + 0.08087 * 1 = 1.27724
+        assert_eq!(get_cxdr_rate(effective_from), 1_277_240_000);
+    }
+
     /// This function tests that the computed set of artificial CXDR rates does not contain any zero rates.
     /// The fiat currency rates are taken from a real execution, which caused a CXDR rate to be
     /// zero because of a wrong JPY rate.
@@ -1398,6 +1569,7 @@ mod test {
 
         let collector = OneDayRatesCollector {
             rates: map,
+            crypto_rates: HashMap::new(),
             timestamp: 0,
             sources: HashSet::new(),
         };
@@ -1455,6 +1627,7 @@ mod test {
                     5,
                     5,
                     Some(1234),
+                    RateAggregation::Median,
                 )
             },
         );
@@ -1482,6 +1655,7 @@ mod test {
                     5,
                     5,
                     Some(timestamp),
+                    RateAggregation::Median,
                 )
             },
         );
@@ -1503,6 +1677,48 @@ mod test {
         ));
     }
 
+    /// This function tests that the "go back" mechanism respects the maximum staleness provided
+    /// by the caller.
+    #[test]
+    fn forex_go_back_days_respects_max_staleness() {
+        let mut store = ForexRateStore::new();
+
+        let timestamp = 1661990400; // Corresponds to 2022-09-01
+        let queried_timestamp = timestamp + ONE_DAY_SECONDS * 3;
+        // The current timestamp is far enough in the future that the requested day is over
+        // for all sources.
+        let current_timestamp = queried_timestamp + ONE_DAY_SECONDS * 2;
+
+        add_enough_cxdr_rates_to_store(&mut store, timestamp);
+        store.put(
+            timestamp,
+            btreemap! {
+                "EUR".to_string() => QueriedExchangeRate::new(
+                    eur_asset(),
+                    usd_asset(),
+                    timestamp,
+                    &[10_000],
+                    5,
+                    5,
+                    Some(timestamp),
+                    RateAggregation::Median,
+                )
+            },
+        );
+
+        assert_eq!(
+            store
+                .get_with_max_staleness(queried_timestamp, current_timestamp, "EUR", USD, Some(3))
+                .unwrap()
+                .forex_timestamp,
+            Some(timestamp)
+        );
+        assert!(matches!(
+            store.get_with_max_staleness(queried_timestamp, current_timestamp, "EUR", USD, Some(2)),
+            Err(GetForexRateError::InvalidTimestamp(_))
+        ));
+    }
+
     #[test]
     #[cfg(not(feature = "ipv4-support"))]
     fn is_available() {
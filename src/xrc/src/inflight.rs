@@ -1,8 +1,6 @@
 use std::{cell::RefCell, collections::HashSet};
 
-use ic_xrc_types::{Asset, ExchangeRateError};
-
-use crate::QueriedExchangeRate;
+use ic_xrc_types::Asset;
 
 /// A key contains the symbol and the timestamp.
 type Key = (String, u64);
@@ -39,43 +37,64 @@ pub(crate) fn is_inflight(asset: &Asset, timestamp: u64) -> bool {
     contains(&key)
 }
 
+/// Returns the symbol-timestamp pairs that are currently being requested, ordered by
+/// symbol and timestamp.
+pub(crate) fn get_inflight_keys() -> Vec<Key> {
+    let mut keys = INFLIGHT_CRYPTO_USDT_RATE_REQUESTS
+        .with(|cell| cell.borrow().iter().cloned().collect::<Vec<_>>());
+    keys.sort();
+    keys
+}
+
 /// Used to wrap around the HTTP outcalls so that the canister can avoid sending
 /// similar requests to crypto exchanges.
-pub(crate) async fn with_inflight_tracking<F>(
+pub(crate) async fn with_inflight_tracking<F, R>(
     symbols: Vec<String>,
     timestamp: u64,
     future: F,
-) -> Result<QueriedExchangeRate, ExchangeRateError>
+) -> R
 where
-    F: std::future::Future<Output = Result<QueriedExchangeRate, ExchangeRateError>>,
+    F: std::future::Future<Output = R>,
+{
+    let keys = symbols
+        .into_iter()
+        .map(|symbol| (symbol, timestamp))
+        .collect();
+    with_inflight_tracking_for_keys(keys, future).await
+}
+
+/// Same as [with_inflight_tracking], but the symbols may be paired with different timestamps.
+/// Used by batch requests which may need rates at several timestamps at once.
+pub(crate) async fn with_inflight_tracking_for_keys<F, R>(keys: Vec<Key>, future: F) -> R
+where
+    F: std::future::Future<Output = R>,
 {
     // Need to set the guard to maintain the lifetime until the future is complete.
-    let _guard = InflightCryptoUsdtRequestsGuard::new(symbols, timestamp);
+    let _guard = InflightCryptoUsdtRequestsGuard::new(keys);
     future.await
 }
 
 /// Guard to ensure that the tracking set adds and removes symbol-timestamp pairs
 /// correctly.
 struct InflightCryptoUsdtRequestsGuard {
-    symbols: Vec<String>,
-    timestamp: u64,
+    keys: Vec<Key>,
 }
 
 impl InflightCryptoUsdtRequestsGuard {
-    /// Adds all symbols paired to a given timestamp to the tracking set.
-    fn new(symbols: Vec<String>, timestamp: u64) -> Self {
-        for symbol in &symbols {
-            add((symbol.clone(), timestamp));
+    /// Adds all symbol-timestamp pairs to the tracking set.
+    fn new(keys: Vec<Key>) -> Self {
+        for key in &keys {
+            add(key.clone());
         }
-        Self { symbols, timestamp }
+        Self { keys }
     }
 }
 
 impl Drop for InflightCryptoUsdtRequestsGuard {
-    /// Removes all symbols paired to a given timestamp to the tracking set.
+    /// Removes all symbol-timestamp pairs from the tracking set.
     fn drop(&mut self) {
-        for symbol in &self.symbols {
-            remove(&(symbol.clone(), self.timestamp));
+        for key in &self.keys {
+            remove(key);
         }
     }
 }
@@ -84,7 +103,9 @@ impl Drop for InflightCryptoUsdtRequestsGuard {
 pub(crate) mod test {
 
     use crate::api::test::icp_asset;
+    use crate::QueriedExchangeRate;
     use futures::FutureExt;
+    use ic_xrc_types::ExchangeRateError;
 
     use super::*;
 
@@ -102,7 +123,7 @@ pub(crate) mod test {
             with_inflight_tracking(vec!["ICP".to_string(), "BTC".to_string()], 0, async move {
                 assert!(contains(&("ICP".to_string(), 0)));
                 assert!(contains(&("BTC".to_string(), 0)));
-                Ok(QueriedExchangeRate::default())
+                Ok::<_, ExchangeRateError>(QueriedExchangeRate::default())
             })
             .now_or_never()
             .expect("should succeed")
@@ -120,7 +141,7 @@ pub(crate) mod test {
             with_inflight_tracking(vec!["ICP".to_string(), "BTC".to_string()], 0, async move {
                 assert!(contains(&("ICP".to_string(), 0)));
                 assert!(contains(&("BTC".to_string(), 0)));
-                Err(ExchangeRateError::CryptoBaseAssetNotFound)
+                Err::<QueriedExchangeRate, _>(ExchangeRateError::CryptoBaseAssetNotFound)
             })
             .now_or_never()
             .expect("should succeed")
@@ -144,7 +165,7 @@ pub(crate) mod test {
                 check_containment().await;
                 panic!("panic");
                 #[allow(unreachable_code)]
-                Err(ExchangeRateError::CryptoBaseAssetNotFound)
+                Err::<QueriedExchangeRate, _>(ExchangeRateError::CryptoBaseAssetNotFound)
             })
             .catch_unwind()
             .now_or_never()
@@ -6,11 +6,15 @@
 //! Canisters can interact with the exchange rate canister through the [get_exchange_rate] endpoint.
 
 mod api;
+mod baskets;
 mod cache;
+mod certification;
+mod config;
 mod exchanges;
 mod forex;
 mod http;
 mod listings;
+mod outliers;
 mod stablecoin;
 
 mod environment;
@@ -18,7 +22,9 @@ mod errors;
 mod inflight;
 mod periodic;
 mod rate_limiting;
+mod reputation;
 mod request_log;
+mod stable_memory;
 /// This module provides types for responding to HTTP requests for metrics.
 pub mod types;
 mod utils;
@@ -27,13 +33,17 @@ use ::candid::{CandidType, Deserialize, Principal};
 // TODO(DEFI-2648): Migrate to non-deprecated.
 #[allow(deprecated)]
 use ic_cdk::api::management_canister::http_request::{HttpResponse, TransformArgs};
-use ic_xrc_types::{Asset, ExchangeRate, ExchangeRateError, ExchangeRateMetadata, OtherError};
+use ic_xrc_types::{
+    Asset, Config, ExchangeRate, ExchangeRateError, ExchangeRateMetadata, ExchangeRateOptions,
+    OtherError,
+};
 use request_log::RequestLog;
 use serde_bytes::ByteBuf;
 use strum::IntoEnumIterator;
 
 use crate::{
     cache::ExchangeRateCache,
+    environment::CanisterEnvironment,
     errors::{INVALID_RATE_ERROR_CODE, INVALID_RATE_ERROR_MESSAGE},
     forex::{ForexContextArgs, ForexRateMap, ForexRateStore, ForexRatesCollector},
     http::CanisterHttpRequest,
@@ -47,15 +57,29 @@ use std::{
     mem::{size_of, size_of_val},
 };
 
+pub use api::estimate_exchange_rate_cost;
+pub use api::get_cached_exchange_rate;
+pub use api::get_certified_exchange_rate;
+pub use api::get_config;
 pub use api::get_exchange_rate;
+pub use api::get_exchange_rate_series;
+pub use api::get_exchange_rate_v2;
+pub use api::get_exchange_rates;
+pub use api::get_request_log;
+pub use api::get_status;
+pub use api::get_twap_exchange_rate;
+pub use api::list_supported_assets;
+pub use api::update_config;
 pub use api::usdt_asset;
 pub use exchanges::{Exchange, EXCHANGES};
 pub use forex::{Forex, FOREX_SOURCES};
 
-use exchanges::ListedPairs;
+use certification::CertifiedRates;
+use exchanges::{ExtractedRate, ListedPairs};
 use listings::ListingStore;
+use reputation::ReputationStore;
 
-/// Rates may not deviate by more than one tenth of the smallest considered rate.
+/// By default, rates may not deviate by more than one tenth of the smallest considered rate.
 const RATE_DEVIATION_DIVISOR: u64 = 10;
 
 const LOG_PREFIX: &str = "[xrc]";
@@ -76,9 +100,10 @@ pub const XRC_BASE_CYCLES_COST: u128 = 20_000_000;
 /// The amount of cycles charged if a call fails (rate limited, failed to find forex rate in store, etc.).
 pub const XRC_MINIMUM_FEE_COST: u128 = 1_000_000;
 
-/// The maximum relative difference between accepted rates is 20%.
+/// By default, the maximum relative difference between accepted rates is 20%.
 pub const MAX_RELATIVE_DIFFERENCE_DIVISOR: u64 = 5;
 
+/// The canisters that are free of charge unless the configuration lists other privileged principals.
 const PRIVILEGED_CANISTER_IDS: [Principal; 3] = [
     // CMC: rkp4c-7iaaa-aaaaa-aaaca-cai
     Principal::from_slice(&[0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x01, 0x01]),
@@ -88,6 +113,7 @@ const PRIVILEGED_CANISTER_IDS: [Principal; 3] = [
     Principal::from_slice(&[0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x01, 0x01]),
 ];
 
+/// The privileged cryptocurrencies unless the configuration lists other ones.
 const PRIVILEGED_CRYPTO_ASSETS: [&str; 4] = [BTC, ETH, ICP, USDT];
 
 /// The currency symbol for the US dollar.
@@ -102,6 +128,15 @@ const USDS: &str = "USDS";
 /// The symbol for the USDC stablecoin.
 const USDC: &str = "USDC";
 
+/// The symbol for the DAI stablecoin.
+const DAI: &str = "DAI";
+
+/// The symbol for the FDUSD stablecoin.
+const FDUSD: &str = "FDUSD";
+
+/// The symbol for the PYUSD stablecoin.
+const PYUSD: &str = "PYUSD";
+
 /// The symbol for the Bitcoin cryptocurrency.
 const BTC: &str = "BTC";
 
@@ -111,7 +146,7 @@ const ETH: &str = "ETH";
 /// The symbol for the Internet Computer cryptocurrency.
 const ICP: &str = "ICP";
 
-/// The maximum size of the cache.
+/// The default maximum size of the cache.
 const MAX_CACHE_SIZE: usize = 1000;
 
 /// 9 decimal places are used for rates and standard deviations by default.
@@ -131,10 +166,10 @@ const ONE_HOUR_SECONDS: u64 = 60 * ONE_MINUTE_SECONDS;
 const ONE_DAY_SECONDS: u64 = 24 * ONE_HOUR_SECONDS;
 
 /// Maximum number of entries in the privileged request log.
-const MAX_PRIVILEGED_REQUEST_LOG_ENTRIES: usize = 100;
+const MAX_PRIVILEGED_REQUEST_LOG_ENTRIES: u64 = 5_000;
 
 /// Maximum number of entries in the non-privileged request log.
-const MAX_NONPRIVILEGED_REQUEST_LOG_ENTRIES: usize = 50;
+const MAX_NONPRIVILEGED_REQUEST_LOG_ENTRIES: u64 = 1_000;
 
 thread_local! {
     // The exchange rate cache.
@@ -148,10 +183,30 @@ thread_local! {
     /// across upgrades. See [`listings`].
     static LISTING_STORE: RefCell<ListingStore> = RefCell::new(ListingStore::default());
 
-    /// A simple structure to collect privileged canister requests and responses.
-    static PRIVILEGED_REQUEST_LOG: RefCell<RequestLog> = RefCell::new(RequestLog::new(MAX_PRIVILEGED_REQUEST_LOG_ENTRIES));
-    /// A simple structure to collect non-privileged canister requests and responses.
-    static NONPRIVILEGED_REQUEST_LOG: RefCell<RequestLog> = RefCell::new(RequestLog::new(MAX_NONPRIVILEGED_REQUEST_LOG_ENTRIES));
+    /// The latest rates of the privileged asset pairs, certified via the canister's
+    /// certified data. See [`certification`].
+    static CERTIFIED_RATES: RefCell<CertifiedRates> = RefCell::new(CertifiedRates::default());
+
+    /// Per-exchange reputation scores, persisted across upgrades. See [`reputation`].
+    static REPUTATION_STORE: RefCell<ReputationStore> = RefCell::new(ReputationStore::default());
+
+    /// The tuning values set by NNS Governance, persisted across upgrades. See [`config`].
+    static CONFIG: RefCell<Config> = RefCell::new(config::default_config());
+
+    /// A simple structure to collect privileged canister requests and responses, kept in stable memory.
+    static PRIVILEGED_REQUEST_LOG: RefCell<RequestLog> = RefCell::new(RequestLog::init(
+        MAX_PRIVILEGED_REQUEST_LOG_ENTRIES,
+        true,
+        stable_memory::get_memory(stable_memory::PRIVILEGED_REQUEST_LOG_ENTRIES_MEMORY_ID),
+        stable_memory::get_memory(stable_memory::PRIVILEGED_REQUEST_LOG_COUNT_MEMORY_ID),
+    ));
+    /// A simple structure to collect non-privileged canister requests and responses, kept in stable memory.
+    static NONPRIVILEGED_REQUEST_LOG: RefCell<RequestLog> = RefCell::new(RequestLog::init(
+        MAX_NONPRIVILEGED_REQUEST_LOG_ENTRIES,
+        false,
+        stable_memory::get_memory(stable_memory::NONPRIVILEGED_REQUEST_LOG_ENTRIES_MEMORY_ID),
+        stable_memory::get_memory(stable_memory::NONPRIVILEGED_REQUEST_LOG_COUNT_MEMORY_ID),
+    ));
 
     /// The counter used to determine if a request should be rate limited or not.
     static RATE_LIMITING_REQUEST_COUNTER: Cell<usize> = const { Cell::new(0) };
@@ -212,6 +267,12 @@ pub(crate) enum MetricName {
     ExchangeListingLastSuccessSeconds,
     #[strum(serialize = "xrc_exchange_listing_rejected_total")]
     ExchangeListingRejectedTotal,
+    #[strum(serialize = "xrc_exchange_reputation_score_bps")]
+    ExchangeReputationScoreBps,
+    #[strum(serialize = "xrc_stablecoin_deviation_bps")]
+    StablecoinDeviationBps,
+    #[strum(serialize = "xrc_stablecoin_depeg_total")]
+    StablecoinDepegTotal,
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, strum::IntoStaticStr)]
@@ -352,7 +413,9 @@ pub fn init_metrics() {
 
 fn init_at(now_secs: u64) {
     let now = now_secs as f64;
-    for forex in FOREX_SOURCES {
+    // Disabled sources are not called, so their gauges are not seeded to avoid
+    // alerting on them.
+    for forex in FOREX_SOURCES.iter().filter(|forex| forex.is_enabled()) {
         let name = forex.to_string();
         set_labeled_gauge(
             MetricName::ForexLastSuccessSeconds,
@@ -361,7 +424,7 @@ fn init_at(now_secs: u64) {
         );
     }
     set_labeled_gauge(MetricName::PeriodicForexRunLastSeconds, &[], now);
-    for exchange in EXCHANGES {
+    for exchange in EXCHANGES.iter().filter(|exchange| exchange.is_enabled()) {
         let name = exchange.name();
         for kind in ExchangeCallKind::iter() {
             // Don't seed a stablecoin gauge for an exchange that queries no
@@ -533,6 +596,36 @@ fn with_listing_store_mut<R>(f: impl FnOnce(&mut ListingStore) -> R) -> R {
     LISTING_STORE.with(|cell| f(&mut cell.borrow_mut()))
 }
 
+/// A helper method to read from the reputation store.
+fn with_reputation_store<R>(f: impl FnOnce(&ReputationStore) -> R) -> R {
+    REPUTATION_STORE.with(|cell| f(&cell.borrow()))
+}
+
+/// A helper method to mutate the reputation store.
+fn with_reputation_store_mut<R>(f: impl FnOnce(&mut ReputationStore) -> R) -> R {
+    REPUTATION_STORE.with(|cell| f(&mut cell.borrow_mut()))
+}
+
+/// A helper method to read from the certified rates.
+fn with_certified_rates<R>(f: impl FnOnce(&CertifiedRates) -> R) -> R {
+    CERTIFIED_RATES.with(|cell| f(&cell.borrow()))
+}
+
+/// A helper method to mutate the certified rates.
+fn with_certified_rates_mut<R>(f: impl FnOnce(&mut CertifiedRates) -> R) -> R {
+    CERTIFIED_RATES.with(|cell| f(&mut cell.borrow_mut()))
+}
+
+/// A helper method to read from the configuration.
+fn with_config<R>(f: impl FnOnce(&Config) -> R) -> R {
+    CONFIG.with(|cell| f(&cell.borrow()))
+}
+
+/// A helper method to mutate the configuration.
+fn with_config_mut<R>(f: impl FnOnce(&mut Config) -> R) -> R {
+    CONFIG.with(|cell| f(&mut cell.borrow_mut()))
+}
+
 /// A helper method to read from the forex rate collector.
 fn with_forex_rate_collector<R>(f: impl FnOnce(&ForexRatesCollector) -> R) -> R {
     FOREX_RATE_COLLECTOR.with(|cell| f(&cell.borrow()))
@@ -566,11 +659,97 @@ pub(crate) struct QueriedExchangeRate {
     pub quote_asset_num_received_rates: usize,
     /// The timestamp of the beginning of the day for which the forex rates were retrieved, if any.
     pub forex_timestamp: Option<u64>,
+    /// The individual source rates behind the rates, if they were recorded.
+    pub source_rates: Option<QueriedSourceRates>,
+    /// The weighted median rate, if the rates were aggregated using
+    /// [RateAggregation::WeightedMedian]. It is used instead of the plain median rate.
+    pub weighted_rate: Option<u64>,
+    /// The number of received rates for the base asset that were discarded, if known.
+    pub base_asset_num_discarded_rates: Option<usize>,
+    /// The number of received rates for the quote asset that were discarded, if known.
+    pub quote_asset_num_discarded_rates: Option<usize>,
+    /// The symbols of the assets through which the base asset was priced against USDT, if it is
+    /// a cryptocurrency whose rate was retrieved from the exchanges.
+    pub base_asset_route: Option<Vec<String>>,
+    /// The symbols of the assets through which the quote asset was priced against USDT, if it is
+    /// a cryptocurrency whose rate was retrieved from the exchanges.
+    pub quote_asset_route: Option<Vec<String>>,
+}
+
+/// The ways in which [QueriedExchangeRate::new] can aggregate the received rates.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum RateAggregation<'a> {
+    /// The rate is the plain median of the received rates.
+    Median,
+    /// The rate is the median of the received rates weighted by the given weights, which are
+    /// in the same order as the rates. The weights are usually the traded volumes, so that a
+    /// source with a low volume has less influence on the rate than a source with a high
+    /// volume.
+    WeightedMedian(&'a [u64]),
+}
+
+/// The individual source rates behind a [QueriedExchangeRate]. Cryptocurrency rates are quoted
+/// against USDT and scaled by `RATE_UNIT`.
+#[derive(CandidType, Deserialize, Clone, Debug, Default, PartialEq)]
+pub(crate) struct QueriedSourceRates {
+    /// The base asset rates together with the names of the exchanges that provided them.
+    pub base_asset: Vec<(String, u64)>,
+    /// The quote asset rates together with the names of the exchanges that provided them.
+    pub quote_asset: Vec<(String, u64)>,
+    /// The stablecoin that was used to approximate USD together with its rate, if any.
+    pub stablecoin: Option<(String, u64)>,
+}
+
+impl QueriedSourceRates {
+    /// The function combines the source rates of two multiplied [QueriedExchangeRate] structs.
+    /// The base asset rates are taken from the first struct and the quote asset rates from the
+    /// second struct.
+    fn multiply(
+        own_sources: Option<QueriedSourceRates>,
+        other_sources: Option<QueriedSourceRates>,
+    ) -> Option<QueriedSourceRates> {
+        match (own_sources, other_sources) {
+            (None, None) => None,
+            (own_sources, other_sources) => {
+                let own_sources = own_sources.unwrap_or_default();
+                let other_sources = other_sources.unwrap_or_default();
+                Some(QueriedSourceRates {
+                    base_asset: own_sources.base_asset,
+                    quote_asset: other_sources.quote_asset,
+                    stablecoin: own_sources.stablecoin.or(other_sources.stablecoin),
+                })
+            }
+        }
+    }
+
+    /// The function returns the source rates with base asset and quote asset swapped.
+    fn inverted(&self) -> Self {
+        Self {
+            base_asset: self.quote_asset.clone(),
+            quote_asset: self.base_asset.clone(),
+            stablecoin: self.stablecoin.clone(),
+        }
+    }
+}
+
+impl AllocatedBytes for QueriedSourceRates {
+    fn allocated_bytes(&self) -> usize {
+        size_of_val(self)
+            + self
+                .base_asset
+                .iter()
+                .chain(self.quote_asset.iter())
+                .chain(self.stablecoin.iter())
+                .fold(0, |acc, (source, rate)| {
+                    acc + size_of_val(source) + source.len() + size_of_val(rate)
+                })
+    }
 }
 
 impl PartialEq for QueriedExchangeRate {
     // All fields must be equal except for [decimals] where [None] is also considered
-    // equal to [Some(DECIMALS)].
+    // equal to [Some(DECIMALS)]. The [source_rates], the numbers of discarded rates and the
+    // routes are provenance information and are not compared.
     fn eq(&self, other: &Self) -> bool {
         self.base_asset == other.base_asset
             && self.quote_asset == other.quote_asset
@@ -584,6 +763,7 @@ impl PartialEq for QueriedExchangeRate {
             && self.quote_asset_num_queried_sources == other.quote_asset_num_queried_sources
             && self.quote_asset_num_received_rates == other.quote_asset_num_received_rates
             && self.forex_timestamp == other.forex_timestamp
+            && self.weighted_rate == other.weighted_rate
     }
 }
 
@@ -600,6 +780,12 @@ impl Default for QueriedExchangeRate {
             quote_asset_num_queried_sources: Default::default(),
             quote_asset_num_received_rates: Default::default(),
             forex_timestamp: None,
+            source_rates: None,
+            weighted_rate: None,
+            base_asset_num_discarded_rates: None,
+            quote_asset_num_discarded_rates: None,
+            base_asset_route: None,
+            quote_asset_route: None,
         }
     }
 }
@@ -624,6 +810,16 @@ impl std::ops::Mul for QueriedExchangeRate {
             (None, None) => None,
         };
 
+        // If only one of the rates is weighted, the median rate of the other one is
+        // used in its place.
+        let reference_rates = match (self.weighted_rate, other_rate.weighted_rate) {
+            (None, None) => None,
+            (own_rate, other_reference_rate) => Some((
+                own_rate.unwrap_or_else(|| median(&self.rates)),
+                other_reference_rate.unwrap_or_else(|| median(&other_rate.rates)),
+            )),
+        };
+
         let mut all_rates: Vec<u128> = vec![];
         let mut denominator: u128 = 10u128.pow(min(
             self.decimals.unwrap_or(DECIMALS),
@@ -697,6 +893,17 @@ impl std::ops::Mul for QueriedExchangeRate {
             rates.clear();
         }
 
+        // The weighted rate is scaled in the same way as the other rates.
+        let weighted_rate = reference_rates.filter(|_| !rates.is_empty()).and_then(
+            |(own_rate, other_reference_rate)| {
+                let rate = (own_rate as u128)
+                    .saturating_mul(other_reference_rate as u128)
+                    .checked_div(denominator)?
+                    .saturating_div(divisor);
+                (rate > 0 && rate <= max_value).then_some(rate as u64)
+            },
+        );
+
         Self {
             base_asset: self.base_asset,
             quote_asset: other_rate.quote_asset,
@@ -708,6 +915,12 @@ impl std::ops::Mul for QueriedExchangeRate {
             quote_asset_num_queried_sources: other_rate.quote_asset_num_queried_sources,
             quote_asset_num_received_rates: other_rate.quote_asset_num_received_rates,
             forex_timestamp,
+            source_rates: QueriedSourceRates::multiply(self.source_rates, other_rate.source_rates),
+            weighted_rate,
+            base_asset_num_discarded_rates: self.base_asset_num_discarded_rates,
+            quote_asset_num_discarded_rates: other_rate.quote_asset_num_discarded_rates,
+            base_asset_route: self.base_asset_route,
+            quote_asset_route: other_rate.quote_asset_route,
         }
     }
 }
@@ -734,6 +947,17 @@ impl AllocatedBytes for QueriedExchangeRate {
             + size_of_val(&self.quote_asset_num_received_rates)
             + size_of_val(&self.timestamp)
             + self.rates.allocated_bytes()
+            + self
+                .source_rates
+                .as_ref()
+                .map_or(0, |source_rates| source_rates.allocated_bytes())
+            + self.weighted_rate.map_or(0, |rate| size_of_val(&rate))
+            + self
+                .base_asset_route
+                .iter()
+                .chain(self.quote_asset_route.iter())
+                .flatten()
+                .fold(0, |acc, symbol| acc + size_of_val(symbol) + symbol.len())
     }
 }
 
@@ -749,15 +973,24 @@ impl From<QueriedExchangeRate> for ExchangeRate {
             base_asset: rate.base_asset,
             quote_asset: rate.quote_asset,
             timestamp: rate.timestamp,
-            rate: median(&rate.rates),
+            rate: rate.weighted_rate.unwrap_or_else(|| median(&rate.rates)),
             metadata: ExchangeRateMetadata {
                 decimals: rate.decimals.unwrap_or(DECIMALS),
                 base_asset_num_queried_sources: rate.base_asset_num_queried_sources,
                 base_asset_num_received_rates: rate.base_asset_num_received_rates,
                 quote_asset_num_queried_sources: rate.quote_asset_num_queried_sources,
                 quote_asset_num_received_rates: rate.quote_asset_num_received_rates,
+                // The standard deviation is deliberately unweighted: it describes how far the
+                // retained source rates are spread, and the weights are not carried through the
+                // multiplication and inversion of rates. A deviating low-volume source thus
+                // still widens it even though it barely moves the weighted rate.
                 standard_deviation: standard_deviation(&rate.rates),
                 forex_timestamp: rate.forex_timestamp,
+                twap_window_minutes: None,
+                base_asset_num_discarded_rates: rate.base_asset_num_discarded_rates,
+                quote_asset_num_discarded_rates: rate.quote_asset_num_discarded_rates,
+                base_asset_route: rate.base_asset_route,
+                quote_asset_route: rate.quote_asset_route,
             },
         }
     }
@@ -765,7 +998,8 @@ impl From<QueriedExchangeRate> for ExchangeRate {
 
 impl QueriedExchangeRate {
     /// The function creates a [QueriedExchangeRate] instance based on a lookup for the given
-    /// base-quote asset pair.
+    /// base-quote asset pair. The received rates are aggregated as specified by `aggregation`.
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn new(
         base_asset: Asset,
         quote_asset: Asset,
@@ -774,17 +1008,39 @@ impl QueriedExchangeRate {
         num_queried_sources: usize,
         num_received_rates: usize,
         forex_timestamp: Option<u64>,
+        aggregation: RateAggregation,
     ) -> QueriedExchangeRate {
-        let mut rates = rates.to_vec();
-        let median_rate = median(&rates);
+        let weights = match aggregation {
+            RateAggregation::Median => None,
+            RateAggregation::WeightedMedian(weights) => Some(weights),
+        };
+        // Every rate is paired with its weight, which is 0 if no weight is given.
+        let mut weighted_rates: Vec<(u64, u64)> = rates
+            .iter()
+            .enumerate()
+            .map(|(index, rate)| {
+                let weight = weights.and_then(|weights| weights.get(index)).copied();
+                (*rate, weight.unwrap_or_default())
+            })
+            .collect();
+        let max_relative_difference_divisor =
+            with_config(|config| config.max_relative_difference_divisor);
+        let (min_rate, max_rate) = outliers::get_inlier_range(
+            rates,
+            &outliers::get_outlier_filter(&base_asset.class),
+            max_relative_difference_divisor,
+        );
         // Filter out rates that are 0, which are invalid, or greater than RATE_UNIT * RATE_UNIT,
-        // which cannot be inverted, or deviate too much from the median rate.
-        rates.retain(|rate| {
-            *rate > 0
-                && *rate <= RATE_UNIT * RATE_UNIT
-                && (*rate).abs_diff(median_rate) <= median_rate / MAX_RELATIVE_DIFFERENCE_DIVISOR
+        // which cannot be inverted, or are outliers according to the configured filter.
+        weighted_rates.retain(|(rate, _)| {
+            *rate > 0 && *rate <= RATE_UNIT * RATE_UNIT && (min_rate..=max_rate).contains(rate)
         });
-        rates.sort();
+        let num_discarded_rates = rates.len() - weighted_rates.len();
+        weighted_rates.sort();
+        let weighted_rate = weights
+            .filter(|_| !weighted_rates.is_empty())
+            .map(|_| utils::weighted_median(&weighted_rates));
+        let rates = weighted_rates.into_iter().map(|(rate, _)| rate).collect();
 
         Self {
             base_asset,
@@ -797,6 +1053,12 @@ impl QueriedExchangeRate {
             quote_asset_num_queried_sources: num_queried_sources,
             quote_asset_num_received_rates: num_received_rates,
             forex_timestamp,
+            source_rates: None,
+            weighted_rate,
+            base_asset_num_discarded_rates: Some(num_discarded_rates),
+            quote_asset_num_discarded_rates: Some(num_discarded_rates),
+            base_asset_route: None,
+            quote_asset_route: None,
         }
     }
 
@@ -818,6 +1080,13 @@ impl QueriedExchangeRate {
             factor = factor.saturating_mul(10);
         }
 
+        let weighted_rate = self
+            .weighted_rate
+            .filter(|_| used_decimals <= 2 * DECIMALS)
+            .and_then(|rate| {
+                utils::checked_invert_rate((rate as u128).saturating_mul(factor), used_decimals)
+            });
+
         if used_decimals <= 2 * DECIMALS {
             all_rates = all_rates
                 .into_iter()
@@ -844,11 +1113,29 @@ impl QueriedExchangeRate {
             quote_asset_num_queried_sources: self.base_asset_num_queried_sources,
             quote_asset_num_received_rates: self.base_asset_num_received_rates,
             forex_timestamp: self.forex_timestamp,
+            source_rates: self
+                .source_rates
+                .as_ref()
+                .map(|source_rates| source_rates.inverted()),
+            weighted_rate,
+            base_asset_num_discarded_rates: self.quote_asset_num_discarded_rates,
+            quote_asset_num_discarded_rates: self.base_asset_num_discarded_rates,
+            base_asset_route: self.quote_asset_route.clone(),
+            quote_asset_route: self.base_asset_route.clone(),
         }
     }
 
     /// The function validates the rates in the [QueriedExchangeRate] struct.
     fn validate(self) -> Result<Self, ExchangeRateError> {
+        self.validate_with_options(&ExchangeRateOptions::default())
+    }
+
+    /// The function validates the rates in the [QueriedExchangeRate] struct and checks that
+    /// the caller's quality requirements in the given options are met.
+    fn validate_with_options(
+        self,
+        options: &ExchangeRateOptions,
+    ) -> Result<Self, ExchangeRateError> {
         // Verify that there are sufficiently many rates greater than zero but not greater than
         // `RATE_UNIT * RATE_UNIT`, which is close to the largest 64-bit integer for `RATE_UNIT = 10^9`.
         let median_rate = median(&self.rates);
@@ -860,15 +1147,50 @@ impl QueriedExchangeRate {
         }
 
         // Verify that the relative deviation among sufficiently many rates does
-        // not exceed 100/[RATE_DEVIATION_DIVISOR] percent.
+        // not exceed 100/[Config::rate_deviation_divisor] percent.
+        let rate_deviation_divisor = with_config(|config| config.rate_deviation_divisor);
         let num = self.rates.len();
         let diff = num / 2;
         if (diff..num).all(|end| {
             self.rates[end] - self.rates[end - diff]
-                > self.rates[end - diff].saturating_div(RATE_DEVIATION_DIVISOR)
+                > self.rates[end - diff].saturating_div(rate_deviation_divisor)
         }) {
             return Err(ExchangeRateError::InconsistentRatesReceived);
         }
+
+        // Verify that enough rates were received for every leg that required sources.
+        if let Some(min_num_received_rates) = options.min_num_received_rates {
+            let legs = [
+                (
+                    &self.base_asset,
+                    self.base_asset_num_queried_sources,
+                    self.base_asset_num_received_rates,
+                ),
+                (
+                    &self.quote_asset,
+                    self.quote_asset_num_queried_sources,
+                    self.quote_asset_num_received_rates,
+                ),
+            ];
+            for (asset, num_queried_sources, num_received_rates) in legs {
+                if num_queried_sources > 0 && (num_received_rates as u64) < min_num_received_rates {
+                    return Err(errors::rate_requirements_not_met_error(&format!(
+                        "received {} rates for {}, but at least {} are required",
+                        num_received_rates, asset.symbol, min_num_received_rates
+                    )));
+                }
+            }
+        }
+
+        if let Some(max_standard_deviation) = options.max_standard_deviation {
+            let standard_deviation = standard_deviation(&self.rates);
+            if standard_deviation > max_standard_deviation {
+                return Err(errors::rate_requirements_not_met_error(&format!(
+                    "the standard deviation is {}, but at most {} is allowed",
+                    standard_deviation, max_standard_deviation
+                )));
+            }
+        }
         Ok(self)
     }
 }
@@ -954,9 +1276,10 @@ async fn call_exchange(
     exchange: &Exchange,
     args: CallExchangeArgs,
     kind: ExchangeCallKind,
-) -> Result<u64, CallExchangeError> {
+) -> Result<ExtractedRate, CallExchangeError> {
     let result = call_exchange_raw(exchange, args).await;
-    record_exchange_outcome(exchange.name(), kind, &result, utils::time_secs());
+    let outcome_result = result.clone().map(|extracted_rate| extracted_rate.rate);
+    record_exchange_outcome(exchange.name(), kind, &outcome_result, utils::time_secs());
     result
 }
 
@@ -965,7 +1288,7 @@ async fn call_exchange(
 async fn call_exchange_raw(
     exchange: &Exchange,
     args: CallExchangeArgs,
-) -> Result<u64, CallExchangeError> {
+) -> Result<ExtractedRate, CallExchangeError> {
     let url = exchange.get_url(
         &args.base_asset.symbol,
         &args.quote_asset.symbol,
@@ -1005,6 +1328,65 @@ async fn call_exchange_raw(
     }
 }
 
+/// Like [call_exchange], but retrieves the candles of all minutes from `start` to `end`
+/// (inclusive) with a single outcall. An empty window is recorded as a `no_data` outcome.
+async fn call_exchange_window(
+    exchange: &Exchange,
+    base_symbol: &str,
+    quote_symbol: &str,
+    start: u64,
+    end: u64,
+    kind: ExchangeCallKind,
+) -> Result<Vec<(u64, u64)>, CallExchangeError> {
+    let result = call_exchange_window_raw(exchange, base_symbol, quote_symbol, start, end).await;
+    let outcome_result = match result {
+        Ok(ref candles) => match candles.first() {
+            Some((_, rate)) => Ok(*rate),
+            None => Err(CallExchangeError::NoData {
+                exchange: exchange.to_string(),
+            }),
+        },
+        Err(ref error) => Err(error.clone()),
+    };
+    record_exchange_outcome(exchange.name(), kind, &outcome_result, utils::time_secs());
+    result
+}
+
+// TODO(DEFI-2648): Migrate to non-deprecated.
+#[allow(deprecated)]
+async fn call_exchange_window_raw(
+    exchange: &Exchange,
+    base_symbol: &str,
+    quote_symbol: &str,
+    start: u64,
+    end: u64,
+) -> Result<Vec<(u64, u64)>, CallExchangeError> {
+    let url = exchange.get_window_url(base_symbol, quote_symbol, start, end);
+    let context = exchange
+        .encode_context()
+        .map_err(|error| CallExchangeError::Candid {
+            exchange: exchange.to_string(),
+            error: format!("Failure while encoding context: {}", error),
+        })?;
+    let num_candles = end.saturating_sub(start) / ONE_MINUTE_SECONDS + 1;
+    let response = CanisterHttpRequest::new()
+        .get(&url)
+        .transform_context("transform_exchange_window_http_response", context)
+        .max_response_bytes(exchange.window_max_response_bytes(num_candles))
+        .cycles(exchange.cycles())
+        .send()
+        .await
+        .map_err(|error| CallExchangeError::Http {
+            exchange: exchange.to_string(),
+            error,
+        })?;
+
+    Exchange::decode_window_response(&response.body).map_err(|error| CallExchangeError::Candid {
+        exchange: exchange.to_string(),
+        error: format!("Failure while decoding window response: {}", error),
+    })
+}
+
 /// Fetches an exchange's public spot listing and returns the set of base assets
 /// it currently lists against USDT (plus the total parsed market count). Mirrors
 /// [call_exchange_raw]: the heavy parse happens in `transform_listing_http_response`
@@ -1167,30 +1549,63 @@ async fn call_forex(forex: &Forex, args: ForexContextArgs) -> Result<ForexRateMa
 
 /// Serializes the state and stores it in stable memory.
 pub fn pre_upgrade() {
-    with_forex_rate_store(|forex_store| {
+    let bytes = with_forex_rate_store(|forex_store| {
         with_listing_store(|listing_store| {
-            ic_cdk::storage::stable_save((forex_store, listing_store))
+            with_config(|config| {
+                with_reputation_store(|reputation_store| {
+                    ::candid::encode_args((forex_store, listing_store, config, reputation_store))
+                })
+            })
         })
     })
-    .expect("Saving state must succeed.")
+    .expect("Saving state must succeed.");
+    stable_memory::save_upgrade_state(&bytes);
 }
 
+/// The state that is serialized in [pre_upgrade]. The request logs are not part of it, as
+/// they are kept in stable memory.
+type UpgradeState = (
+    ForexRateStore,
+    Option<ListingStore>,
+    Option<Config>,
+    Option<ReputationStore>,
+);
+
 /// Deserializes the state from stable memory and sets the canister state,
 /// then re-initializes ephemeral state via [`init_metrics`].
 pub fn post_upgrade() {
     // The listing store is decoded as a trailing `Option` so the first upgrade
     // from a version that saved only `(ForexRateStore,)` decodes it as `None`
     // (candid fills an absent trailing optional argument) instead of trapping.
-    let (forex_store, listing_store) =
-        ic_cdk::storage::stable_restore::<(ForexRateStore, Option<ListingStore>)>()
-            .expect("Failed to read from stable memory.");
+    // The configuration and the reputation store are decoded the same way and fall back to
+    // the defaults.
+    // The legacy layout must be read before the memory manager is initialized, as the
+    // memory manager overwrites the start of the stable memory.
+    let state = if stable_memory::is_legacy_layout() {
+        ic_cdk::storage::stable_restore::<UpgradeState>()
+    } else {
+        let bytes = stable_memory::load_upgrade_state().unwrap_or_default();
+        ::candid::decode_args::<UpgradeState>(&bytes).map_err(|err| err.to_string())
+    };
+    let (forex_store, listing_store, config, reputation_store) =
+        state.expect("Failed to read from stable memory.");
     FOREX_RATE_STORE.with(|cell| {
         *cell.borrow_mut() = forex_store;
     });
     LISTING_STORE.with(|cell| {
         *cell.borrow_mut() = listing_store.unwrap_or_default();
     });
+    config::set_config(config::with_persisted_baskets(
+        config.unwrap_or_else(config::default_config),
+    ));
+    REPUTATION_STORE.with(|cell| {
+        *cell.borrow_mut() = reputation_store.unwrap_or_default();
+    });
+    // The certified rates are not persisted, so the certified data is reset to match
+    // the empty set of certified rates.
+    certification::set_certified_data(&CanisterEnvironment::new());
     init_metrics();
+    reputation::set_score_metrics();
 }
 
 /// Called by the canister's heartbeat so periodic tasks can be executed.
@@ -1261,6 +1676,43 @@ pub fn transform_exchange_http_response(args: TransformArgs) -> HttpResponse {
     sanitized
 }
 
+/// Transform for the window outcall: extracts the timestamped rates of all candles in the
+/// response and replaces the body with them. Mirrors [transform_exchange_http_response].
+// TODO(DEFI-2648): Migrate to non-deprecated.
+#[allow(deprecated)]
+pub fn transform_exchange_window_http_response(args: TransformArgs) -> HttpResponse {
+    let mut sanitized = args.response;
+
+    let index = match Exchange::decode_context(&args.context) {
+        Ok(index) => index,
+        Err(err) => ic_cdk::trap(format!("Failed to decode context: {}", err)),
+    };
+
+    let exchange = match EXCHANGES.get(index) {
+        Some(exchange) => exchange,
+        None => {
+            ic_cdk::trap(format!(
+                "Provided index {} does not map to any supported exchange.",
+                index
+            ));
+        }
+    };
+
+    let candles = match exchange.extract_candles(&sanitized.body) {
+        Ok(candles) => candles,
+        Err(err) => ic_cdk::trap(format!("{}", err)),
+    };
+
+    sanitized.body = match Exchange::encode_window_response(&candles) {
+        Ok(body) => body,
+        Err(err) => ic_cdk::trap(format!("failed to encode candles: {}", err)),
+    };
+
+    // Strip out the headers as these will commonly cause an error to occur.
+    sanitized.headers = vec![];
+    sanitized
+}
+
 /// Transform for the listing outcall: parses the (large) listing body into the
 /// set of USDT-tradable bases and total market count, and replaces the body
 /// with that small canonical payload so the replicas reach consensus on it
@@ -1349,12 +1801,18 @@ pub fn transform_forex_http_response(args: TransformArgs) -> HttpResponse {
 }
 
 /// This adds the ability to handle HTTP requests to the canister.
-/// Used to expose metrics to prometheus.
+/// Used to expose metrics to prometheus and the state of the canister as JSON.
 pub fn http_request(req: types::HttpRequest) -> types::HttpResponse {
     let parts: Vec<&str> = req.url.split('?').collect();
     match parts[0] {
         "/dashboard" => api::get_dashboard(),
         "/metrics" => api::get_metrics(),
+        "/api/status" => api::get_status_json(),
+        "/api/forex" => api::get_forex_json(parts.get(1).copied()),
+        "/api/listings" => api::get_listings_json(),
+        "/api/cache" => api::get_cache_json(),
+        "/api/requests" => api::get_requests_json(),
+        "/rate" => api::get_rate_json(parts.get(1).copied()),
         _ => types::HttpResponse {
             status_code: 404,
             headers: vec![],
@@ -1466,7 +1924,7 @@ impl ExtractError {
 mod test {
 
     use crate::api::{test::btc_asset, usd_asset};
-    use ic_xrc_types::AssetClass;
+    use ic_xrc_types::{AssetClass, OutlierFilter};
 
     use super::*;
 
@@ -1482,6 +1940,38 @@ mod test {
         assert_eq!(response.len(), MAX_ERROR_RESPONSE_LEN);
     }
 
+    /// The JSON endpoints under `/api` are routed by path, ignoring the query string, and
+    /// unknown paths are answered with status 404.
+    #[test]
+    fn http_request_routes_json_endpoints() {
+        let get = |url: &str| {
+            http_request(types::HttpRequest {
+                method: "GET".to_string(),
+                url: url.to_string(),
+                headers: vec![],
+                body: ByteBuf::default(),
+            })
+        };
+        let content_type = ("Content-Type".to_string(), "application/json".to_string());
+
+        for url in [
+            "/api/status",
+            "/api/listings",
+            "/api/cache",
+            "/api/requests",
+            "/api/requests?ignored=1",
+        ] {
+            let response = get(url);
+            assert_eq!(response.status_code, 200, "{}", url);
+            assert!(response.headers.contains(&content_type), "{}", url);
+        }
+
+        // There are no forex rates yet.
+        assert_eq!(get("/api/forex").status_code, 404);
+        assert_eq!(get("/api/forex?date=2024-13-01").status_code, 400);
+        assert_eq!(get("/api/unknown").status_code, 404);
+    }
+
     /// The post_upgrade migration must tolerate stable memory written by a
     /// version that persisted only `(ForexRateStore,)`: decoding into
     /// `(ForexRateStore, Option<ListingStore>)` yields `None` for the listing
@@ -1504,6 +1994,88 @@ mod test {
         assert!(listing.is_some(), "persisted listing store must decode as Some");
     }
 
+    /// The post_upgrade migration must tolerate stable memory written by a
+    /// version that did not persist the configuration: the absent trailing
+    /// configuration decodes as `None`, and the current layout decodes it as `Some`.
+    #[test]
+    fn post_upgrade_tolerates_layout_without_config() {
+        use ::candid::{decode_args, encode_args};
+
+        let previous = encode_args((ForexRateStore::new(), ListingStore::default()))
+            .expect("encode previous layout");
+        let (_forex, listing, config): (ForexRateStore, Option<ListingStore>, Option<Config>) =
+            decode_args(&previous).expect("previous layout must still decode");
+        assert!(listing.is_some());
+        assert!(config.is_none(), "absent configuration must decode as None");
+
+        let current = encode_args((
+            ForexRateStore::new(),
+            ListingStore::default(),
+            config::default_config(),
+        ))
+        .expect("encode current layout");
+        let (_forex, _listing, config): (ForexRateStore, Option<ListingStore>, Option<Config>) =
+            decode_args(&current).expect("current layout must decode");
+        assert_eq!(config, Some(config::default_config()));
+    }
+
+    /// The post_upgrade migration must tolerate stable memory written by a
+    /// version that did not persist the reputation store: the absent trailing
+    /// store decodes as `None`, and the current layout decodes it as `Some`.
+    #[test]
+    fn post_upgrade_tolerates_layout_without_reputation_store() {
+        use ::candid::{decode_args, encode_args};
+
+        let previous = encode_args((ForexRateStore::new(), ListingStore::default()))
+            .expect("encode previous layout");
+        let (.., reputation_store): UpgradeState =
+            decode_args(&previous).expect("previous layout must still decode");
+        assert!(reputation_store.is_none());
+
+        REPUTATION_STORE.with(|cell| *cell.borrow_mut() = ReputationStore::default());
+        reputation::record_deviations(
+            "ICP",
+            &[
+                ("Coinbase".to_string(), RATE_UNIT),
+                ("KuCoin".to_string(), RATE_UNIT),
+                ("OKX".to_string(), 2 * RATE_UNIT),
+            ],
+            1,
+        );
+        let current = with_reputation_store(|reputation_store| {
+            encode_args((
+                ForexRateStore::new(),
+                ListingStore::default(),
+                config::default_config(),
+                reputation_store,
+            ))
+        })
+        .expect("encode current layout");
+        let (.., reputation_store): UpgradeState =
+            decode_args(&current).expect("current layout must decode");
+        let reputation_store = reputation_store.expect("persisted store must be restored");
+        assert_eq!(
+            reputation_store
+                .get("OKX", "ICP")
+                .map(|reputation| reputation.score_bps()),
+            Some(10_000)
+        );
+    }
+
+    /// The function verifies that `pre_upgrade` writes the state to the upgrades memory in
+    /// the [UpgradeState] layout, leaving out the request logs which are kept in stable memory.
+    #[test]
+    fn pre_upgrade_saves_state_without_request_logs() {
+        pre_upgrade();
+
+        let bytes = stable_memory::load_upgrade_state().expect("state must be saved");
+        let (_forex, listing, config, reputation_store): UpgradeState =
+            ::candid::decode_args(&bytes).expect("saved state must decode");
+        assert!(listing.is_some());
+        assert_eq!(config, Some(config::default_config()));
+        assert!(reputation_store.is_some());
+    }
+
     /// The function returns sample [QueriedExchangeRate] structs for testing.
     fn get_rates(
         first_asset: (String, String),
@@ -1524,6 +2096,7 @@ mod test {
                 3,
                 3,
                 None,
+                RateAggregation::Median,
             ),
             QueriedExchangeRate::new(
                 Asset {
@@ -1539,6 +2112,7 @@ mod test {
                 4,
                 4,
                 None,
+                RateAggregation::Median,
             ),
         )
     }
@@ -1559,6 +2133,7 @@ mod test {
             7,
             5,
             None,
+            RateAggregation::Median,
         );
         let btc_rate = QueriedExchangeRate::new(
             btc_asset(),
@@ -1574,6 +2149,7 @@ mod test {
             7,
             5,
             None,
+            RateAggregation::Median,
         );
         let btt_btc_queried_rate = (btt_rate / btc_rate)
             .validate()
@@ -1593,12 +2169,61 @@ mod test {
                 quote_asset_num_received_rates: 5,
                 standard_deviation: 0,
                 forex_timestamp: None,
+                twap_window_minutes: None,
+                base_asset_num_discarded_rates: Some(0),
+                quote_asset_num_discarded_rates: Some(0),
+                base_asset_route: None,
+                quote_asset_route: None,
             },
         };
 
         assert_eq!(btt_btc_exchange_rate, expected_exchange_rate);
     }
 
+    /// The function verifies that the outlier filter configured for the asset class of the base
+    /// asset is applied and that the number of discarded rates is reported.
+    #[test]
+    fn queried_exchange_rate_applies_configured_outlier_filter() {
+        let rates = [1_000, 1_001, 1_002, 1_003, 1_050].map(|rate| rate * RATE_UNIT);
+        let new_rate = || {
+            QueriedExchangeRate::new(
+                btc_asset(),
+                usdt_asset(),
+                0,
+                &rates,
+                7,
+                5,
+                None,
+                RateAggregation::Median,
+            )
+        };
+
+        let rate = new_rate();
+        assert_eq!(rate.rates.len(), 5);
+        assert_eq!(rate.base_asset_num_discarded_rates, Some(0));
+
+        config::set_config(Config {
+            crypto_outlier_filter: Some(OutlierFilter::MedianAbsoluteDeviation {
+                threshold_percent: 300,
+            }),
+            ..config::default_config()
+        });
+        let rate = new_rate();
+        assert_eq!(
+            rate.rates,
+            [1_000, 1_001, 1_002, 1_003].map(|rate| rate * RATE_UNIT)
+        );
+        let exchange_rate = ExchangeRate::from(rate.inverted());
+        assert_eq!(
+            exchange_rate.metadata.base_asset_num_discarded_rates,
+            Some(1)
+        );
+        assert_eq!(
+            exchange_rate.metadata.quote_asset_num_discarded_rates,
+            Some(1)
+        );
+    }
+
     /// The function verifies that that [QueriedExchangeRate] structs are multiplied correctly.
     #[test]
     fn queried_exchange_rate_multiplication() {
@@ -1627,6 +2252,12 @@ mod test {
             quote_asset_num_queried_sources: 4,
             quote_asset_num_received_rates: 4,
             forex_timestamp: None,
+            source_rates: None,
+            weighted_rate: None,
+            base_asset_num_discarded_rates: None,
+            quote_asset_num_discarded_rates: None,
+            base_asset_route: None,
+            quote_asset_route: None,
         };
 
         assert_eq!(a_c_rate, a_b_rate * b_c_rate);
@@ -1705,10 +2336,79 @@ mod test {
             quote_asset_num_queried_sources: 4,
             quote_asset_num_received_rates: 4,
             forex_timestamp: None,
+            source_rates: None,
+            weighted_rate: None,
+            base_asset_num_discarded_rates: None,
+            quote_asset_num_discarded_rates: None,
+            base_asset_route: None,
+            quote_asset_route: None,
         };
         assert_eq!(a_c_rate, a_b_rate / c_b_rate);
     }
 
+    /// The function verifies that the source rates of the base and quote assets are carried
+    /// over when [QueriedExchangeRate] structs are divided.
+    #[test]
+    fn queried_exchange_rate_division_keeps_source_rates() {
+        let (mut a_b_rate, mut c_b_rate) = get_rates(
+            ("A".to_string(), "B".to_string()),
+            ("C".to_string(), "B".to_string()),
+        );
+        a_b_rate.source_rates = Some(QueriedSourceRates {
+            base_asset: vec![("X".to_string(), 1)],
+            ..Default::default()
+        });
+        c_b_rate.source_rates = Some(QueriedSourceRates {
+            base_asset: vec![("Y".to_string(), 2)],
+            stablecoin: Some(("S".to_string(), 3)),
+            ..Default::default()
+        });
+
+        let a_c_rate = a_b_rate / c_b_rate;
+        assert_eq!(
+            a_c_rate.source_rates,
+            Some(QueriedSourceRates {
+                base_asset: vec![("X".to_string(), 1)],
+                quote_asset: vec![("Y".to_string(), 2)],
+                stablecoin: Some(("S".to_string(), 3)),
+            })
+        );
+        assert_eq!(
+            a_c_rate.inverted().source_rates,
+            Some(QueriedSourceRates {
+                base_asset: vec![("Y".to_string(), 2)],
+                quote_asset: vec![("X".to_string(), 1)],
+                stablecoin: Some(("S".to_string(), 3)),
+            })
+        );
+    }
+
+    /// The function verifies that the routes of the base and quote assets are carried over
+    /// when [QueriedExchangeRate] structs are divided and included in the metadata.
+    #[test]
+    fn queried_exchange_rate_division_keeps_routes() {
+        let (mut a_b_rate, mut c_b_rate) = get_rates(
+            ("A".to_string(), "B".to_string()),
+            ("C".to_string(), "B".to_string()),
+        );
+        let a_route = vec!["A".to_string(), "X".to_string(), "B".to_string()];
+        let c_route = vec!["C".to_string(), "B".to_string()];
+        a_b_rate.base_asset_route = Some(a_route.clone());
+        c_b_rate.base_asset_route = Some(c_route.clone());
+
+        let a_c_rate = a_b_rate / c_b_rate;
+        assert_eq!(a_c_rate.base_asset_route, Some(a_route.clone()));
+        assert_eq!(a_c_rate.quote_asset_route, Some(c_route.clone()));
+
+        let c_a_rate = a_c_rate.inverted();
+        assert_eq!(c_a_rate.base_asset_route, Some(c_route.clone()));
+        assert_eq!(c_a_rate.quote_asset_route, Some(a_route.clone()));
+
+        let metadata = ExchangeRate::from(a_c_rate).metadata;
+        assert_eq!(metadata.base_asset_route, Some(a_route));
+        assert_eq!(metadata.quote_asset_route, Some(c_route));
+    }
+
     /// The function verifies that the validity of a [QueriedExchangeRate] struct can be checked correctly.
     #[test]
     fn queried_exchange_rate_validity() {
@@ -1773,6 +2473,12 @@ mod test {
             quote_asset_num_queried_sources: 4,
             quote_asset_num_received_rates: 4,
             forex_timestamp: None,
+            source_rates: None,
+            weighted_rate: None,
+            base_asset_num_discarded_rates: None,
+            quote_asset_num_discarded_rates: None,
+            base_asset_route: None,
+            quote_asset_route: None,
         };
 
         assert_eq!(a_c_rate, a_b_rate / c_b_rate);
@@ -1799,6 +2505,7 @@ mod test {
             0,
             0,
             None,
+            RateAggregation::Median,
         );
         assert_eq!(queried_exchange_rate.rates, vec![7, 8, 9]);
 
@@ -1810,14 +2517,74 @@ mod test {
             RATE_UNIT * RATE_UNIT + 1,
             RATE_UNIT * RATE_UNIT + 1,
         ];
-        let exchange_rate =
-            QueriedExchangeRate::new(base_asset, quote_asset, 0, &rates, 6, 6, None);
+        let exchange_rate = QueriedExchangeRate::new(
+            base_asset,
+            quote_asset,
+            0,
+            &rates,
+            6,
+            6,
+            None,
+            RateAggregation::Median,
+        );
         assert!(matches!(
             exchange_rate.validate(),
             Err(ExchangeRateError::Other(_))
         ));
     }
 
+    /// The function verifies that rates from sources with a low volume carry less weight when
+    /// a [QueriedExchangeRate] struct is created with [RateAggregation::WeightedMedian],
+    /// and that the weighted rate is kept when the struct is inverted or multiplied.
+    #[test]
+    fn volume_weighted_median_gives_less_weight_to_low_volumes() {
+        let rates = [2_000_000_000, 2_020_000_000, 2_040_000_000];
+        let volumes = [10 * RATE_UNIT, RATE_UNIT, RATE_UNIT];
+        let rate = QueriedExchangeRate::new(
+            btc_asset(),
+            usdt_asset(),
+            0,
+            &rates,
+            3,
+            3,
+            None,
+            RateAggregation::WeightedMedian(&volumes),
+        );
+        assert_eq!(rate.rates, rates.to_vec());
+        assert_eq!(rate.weighted_rate, Some(2_000_000_000));
+        assert_eq!(ExchangeRate::from(rate.clone()).rate, 2_000_000_000);
+
+        let median_rate = QueriedExchangeRate::new(
+            btc_asset(),
+            usdt_asset(),
+            0,
+            &rates,
+            3,
+            3,
+            None,
+            RateAggregation::Median,
+        );
+        assert_eq!(median_rate.weighted_rate, None);
+        assert_eq!(ExchangeRate::from(median_rate).rate, 2_020_000_000);
+
+        assert_eq!(rate.inverted().weighted_rate, Some(500_000_000));
+
+        // The median rate is used for a struct without a weighted rate.
+        let usdt_usd_rate = QueriedExchangeRate::new(
+            usdt_asset(),
+            usd_asset(),
+            0,
+            &[1_500_000_000],
+            1,
+            1,
+            None,
+            RateAggregation::Median,
+        );
+        let btc_usd_rate = rate * usdt_usd_rate;
+        assert_eq!(btc_usd_rate.weighted_rate, Some(3_000_000_000));
+        assert_eq!(ExchangeRate::from(btc_usd_rate).rate, 3_000_000_000);
+    }
+
     /// The function verifies that multiplying and dividing [QueriedExchangeRate] structs
     /// with rates at the limits results in valid [QueriedExchangeRate] structs.
     #[test]
@@ -1839,6 +2606,7 @@ mod test {
             small_rates.len(),
             small_rates.len(),
             None,
+            RateAggregation::Median,
         );
 
         let small_rate_length = small_queried_exchange_rate.rates.len();
@@ -1860,6 +2628,7 @@ mod test {
             large_rates.len(),
             large_rates.len(),
             None,
+            RateAggregation::Median,
         );
 
         let large_rate_length = large_queried_exchange_rate.rates.len();
@@ -1916,6 +2685,7 @@ mod test {
             large_rates.len(),
             large_rates.len(),
             None,
+            RateAggregation::Median,
         );
 
         let greater_than_one_queried_exchange_rate = QueriedExchangeRate::new(
@@ -1926,6 +2696,7 @@ mod test {
             greater_than_one_rates.len(),
             greater_than_one_rates.len(),
             None,
+            RateAggregation::Median,
         );
 
         let multiplied_rate = large_queried_exchange_rate.clone() * large_queried_exchange_rate;
@@ -1980,6 +2751,7 @@ mod test {
             3,
             3,
             None,
+            RateAggregation::Median,
         );
 
         let btt_usd_exchange_rate = QueriedExchangeRate::new(
@@ -1990,6 +2762,7 @@ mod test {
             1,
             1,
             None,
+            RateAggregation::Median,
         );
 
         let btc_btt_exchange_rate = btc_usd_exchange_rate.clone() / btt_usd_exchange_rate.clone();
@@ -2013,7 +2786,16 @@ mod test {
     /// The function verifies that the [QueriedExchangeRate] struct can be inverted correctly despite
     /// having been provided an empty rates array.
     fn inverting_queried_exchange_rate_with_no_rates() {
-        let rate = QueriedExchangeRate::new(btc_asset(), usd_asset(), 0, &[], 0, 0, None);
+        let rate = QueriedExchangeRate::new(
+            btc_asset(),
+            usd_asset(),
+            0,
+            &[],
+            0,
+            0,
+            None,
+            RateAggregation::Median,
+        );
 
         assert!(matches!(
             rate.inverted().validate(),
@@ -2025,7 +2807,16 @@ mod test {
     /// The function verifies that the [QueriedExchangeRate] struct can be divided correctly despite
     /// having been provided an empty rates array.
     fn dividing_queried_exchange_rate_with_no_rates() {
-        let rate = QueriedExchangeRate::new(btc_asset(), usd_asset(), 0, &[], 0, 0, None);
+        let rate = QueriedExchangeRate::new(
+            btc_asset(),
+            usd_asset(),
+            0,
+            &[],
+            0,
+            0,
+            None,
+            RateAggregation::Median,
+        );
 
         assert!(matches!(
             (rate.clone() / rate.clone()).validate(),
@@ -2040,6 +2831,7 @@ mod test {
             3,
             3,
             None,
+            RateAggregation::Median,
         );
 
         assert!(matches!(
@@ -2264,6 +3056,41 @@ mod test {
             });
         }
 
+        #[test]
+        fn init_at_skips_disabled_sources() {
+            reset();
+            config::set_config(Config {
+                disabled_exchanges: Some(vec!["Coinbase".to_string()]),
+                disabled_forex_sources: Some(vec!["EuropeanCentralBank".to_string()]),
+                ..config::default_config()
+            });
+            let now = 1_700_000_000_u64;
+            init_at(now);
+
+            with_labeled_gauges(|m| {
+                let forex_key = make_metric_key(
+                    MetricName::ForexLastSuccessSeconds,
+                    &[(LabelKey::Forex, "EuropeanCentralBank")],
+                );
+                assert_eq!(m.get(&forex_key), None);
+                let forex_gauges = m
+                    .keys()
+                    .filter(|(name, _)| *name == MetricName::ForexLastSuccessSeconds)
+                    .count();
+                assert_eq!(forex_gauges, FOREX_SOURCES.len() - 1);
+
+                let listing_key = make_metric_key(
+                    MetricName::ExchangeListingLastSuccessSeconds,
+                    &[(LabelKey::Exchange, "Coinbase")],
+                );
+                assert_eq!(m.get(&listing_key), None);
+                assert!(!m.keys().any(|(name, labels)| {
+                    *name == MetricName::ExchangeLastSuccessSeconds
+                        && labels.contains(&(LabelKey::Exchange, "Coinbase".to_string()))
+                }));
+            });
+        }
+
         #[test]
         fn init_at_skips_stablecoin_gauge_for_exchange_without_stablecoin_pairs() {
             reset();
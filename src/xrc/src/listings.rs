@@ -16,10 +16,10 @@
 use candid::{CandidType, Deserialize};
 use std::collections::{BTreeMap, BTreeSet};
 
-use crate::exchanges::ListedPairs;
+use crate::{exchanges::ListedPairs, with_config, USDT};
 
-/// A refresh is rejected unless it parses to at least this many total markets.
-/// Guards against a structurally valid but near-empty/garbage response.
+/// By default, a refresh is rejected unless it parses to at least this many total
+/// markets (see `Config::min_total_markets`). Guards against a structurally valid but near-empty/garbage response.
 pub(crate) const MIN_TOTAL_MARKETS: u64 = 50;
 
 /// A refresh is rejected if its total market count falls below this fraction of
@@ -27,7 +27,8 @@ pub(crate) const MIN_TOTAL_MARKETS: u64 = 50;
 /// while still accepting legitimate shrinkage (delistings, USDT->USD migration).
 pub(crate) const MIN_RETAINED_FRACTION: f64 = 0.5;
 
-/// A discovered listing older than this is treated as untrustworthy by the
+/// By default, a discovered listing older than this (see
+/// `Config::max_listing_staleness_secs`) is treated as untrustworthy by the
 /// gating read, which then queries the exchange for everything (fail-open)
 /// rather than trusting a possibly-outdated set. With a daily refresh this
 /// tolerates a few missed runs before failing open.
@@ -50,6 +51,10 @@ pub(crate) struct ExchangeListing {
     pub total_markets: u64,
     /// Timestamp (seconds) of the last accepted refresh.
     pub last_success_secs: u64,
+    /// Every tradable base mapped to the quotes it is tradable against (see
+    /// [`ListedPairs`]). `None` for listings accepted before the pair graph was
+    /// kept; such an exchange is not used for routing until its next refresh.
+    pub pairs: Option<BTreeMap<String, BTreeSet<String>>>,
 }
 
 /// Maps each exchange (by [`crate::Exchange::name`]) to its last accepted
@@ -64,7 +69,8 @@ pub(crate) struct ListingStore {
 pub(crate) enum AcceptOutcome {
     /// Stored as the new last-known-good listing.
     Accepted,
-    /// Fewer than [`MIN_TOTAL_MARKETS`] markets parsed; previous listing kept.
+    /// Fewer than the configured minimum (default [`MIN_TOTAL_MARKETS`]) markets
+    /// parsed; previous listing kept.
     RejectedTooFewMarkets { total: u64 },
     /// Total markets dropped below [`MIN_RETAINED_FRACTION`] of the previous
     /// accepted total; previous listing kept.
@@ -84,7 +90,7 @@ impl ListingStore {
     ) -> AcceptOutcome {
         let total = fetched.total_markets as u64;
 
-        if total < MIN_TOTAL_MARKETS {
+        if total < with_config(|config| config.min_total_markets) {
             return AcceptOutcome::RejectedTooFewMarkets { total };
         }
 
@@ -110,6 +116,7 @@ impl ListingStore {
                 bases: fetched.bases,
                 total_markets: total,
                 last_success_secs: now_secs,
+                pairs: Some(fetched.pairs),
             },
         );
         AcceptOutcome::Accepted
@@ -120,11 +127,16 @@ impl ListingStore {
         self.by_exchange.get(exchange)
     }
 
+    /// Iterates over the last accepted listings, ordered by exchange name.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&String, &ExchangeListing)> {
+        self.by_exchange.iter()
+    }
+
     /// Whether the crypto path should query `exchange` for `base`/USDT.
     ///
     /// Fail-open: with no accepted listing for the exchange, or a listing older
-    /// than [`MAX_LISTING_STALENESS_SECS`], the exchange is queried (`true`)
-    /// rather than trusting a missing or stale set. Otherwise the exchange is
+    /// than the configured staleness (default [`MAX_LISTING_STALENESS_SECS`]),
+    /// the exchange is queried (`true`) rather than trusting a missing or stale set. Otherwise the exchange is
     /// queried only if its listing contains `base`. `base` is matched
     /// case-insensitively against the stored (uppercased) bases.
     pub(crate) fn should_query(&self, exchange: &str, base: &str, now_secs: u64) -> bool {
@@ -132,10 +144,102 @@ impl ListingStore {
             None => true,
             Some(listing) => {
                 let age = now_secs.saturating_sub(listing.last_success_secs);
-                age > MAX_LISTING_STALENESS_SECS || listing.bases.contains(&base.to_uppercase())
+                age > with_config(|config| config.max_listing_staleness_secs)
+                    || listing.bases.contains(&base.to_uppercase())
             }
         }
     }
+
+    /// Returns the quote asset to query `exchange` with for `base`: the first of
+    /// `usd_quotes` (the exchange's USD-like quote assets, in order of
+    /// preference) that its listing contains `base` against, if any.
+    ///
+    /// Fail-open like [`ListingStore::should_query`]: with no accepted listing or
+    /// a stale one, the preferred quote is returned. USDT is matched against the
+    /// listed bases and the other quotes against the pair graph, which a listing
+    /// accepted before the graph was kept lacks.
+    pub(crate) fn select_usd_quote(
+        &self,
+        exchange: &str,
+        base: &str,
+        usd_quotes: &[&'static str],
+        now_secs: u64,
+    ) -> Option<&'static str> {
+        let base = base.to_uppercase();
+        let listing = match self.by_exchange.get(exchange) {
+            Some(listing)
+                if now_secs.saturating_sub(listing.last_success_secs)
+                    <= with_config(|config| config.max_listing_staleness_secs) =>
+            {
+                listing
+            }
+            _ => return usd_quotes.first().copied(),
+        };
+        usd_quotes.iter().copied().find(|quote| {
+            if *quote == USDT {
+                self.should_query(exchange, &base, now_secs)
+            } else {
+                listing
+                    .pairs
+                    .as_ref()
+                    .and_then(|pairs| pairs.get(&base))
+                    .is_some_and(|quotes| quotes.contains(*quote))
+            }
+        })
+    }
+
+    /// Whether the fresh listing of `exchange` contains the tradable pair
+    /// `base`/`quote`, matched case-insensitively.
+    ///
+    /// Unlike [`ListingStore::should_query`], this does not fail open: the pair
+    /// graph is only used to route through an intermediate asset, and an exchange
+    /// without a fresh listing would merely be queried for a pair it may not list.
+    pub(crate) fn lists_pair(
+        &self,
+        exchange: &str,
+        base: &str,
+        quote: &str,
+        now_secs: u64,
+    ) -> bool {
+        self.by_exchange.get(exchange).is_some_and(|listing| {
+            let age = now_secs.saturating_sub(listing.last_success_secs);
+            age <= with_config(|config| config.max_listing_staleness_secs)
+                && listing
+                    .pairs
+                    .as_ref()
+                    .and_then(|pairs| pairs.get(&base.to_uppercase()))
+                    .is_some_and(|quotes| quotes.contains(&quote.to_uppercase()))
+        })
+    }
+
+    /// Returns every base listed by the fresh listings of the given `exchanges`,
+    /// each mapped to the exchanges listing it.
+    ///
+    /// Unlike [`ListingStore::should_query`], this does not fail open: an
+    /// exchange without a listing, or with a listing older than the configured
+    /// staleness (default [`MAX_LISTING_STALENESS_SECS`]), contributes no bases.
+    pub(crate) fn listed_bases(
+        &self,
+        exchanges: &[&str],
+        now_secs: u64,
+    ) -> BTreeMap<String, Vec<String>> {
+        let max_listing_staleness_secs = with_config(|config| config.max_listing_staleness_secs);
+        let mut listed_bases: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for (exchange, listing) in &self.by_exchange {
+            let age = now_secs.saturating_sub(listing.last_success_secs);
+            if !exchanges.contains(&exchange.as_str()) || age > max_listing_staleness_secs {
+                continue;
+            }
+
+            for base in &listing.bases {
+                listed_bases
+                    .entry(base.clone())
+                    .or_default()
+                    .push(exchange.clone());
+            }
+        }
+        listed_bases
+    }
 }
 
 #[cfg(test)]
@@ -152,6 +256,7 @@ mod test {
         ListedPairs {
             bases: base_set(bases),
             total_markets,
+            pairs: BTreeMap::new(),
         }
     }
 
@@ -324,4 +429,76 @@ mod test {
         // Just past the threshold: stale -> fail open.
         assert!(store.should_query("Okx", "DOGE", 1_000 + MAX_LISTING_STALENESS_SECS + 1));
     }
+
+    /// The pair graph is only consulted for fresh listings and, unlike the
+    /// gating read, does not fail open.
+    #[test]
+    fn lists_pair_requires_fresh_listing_with_pair() {
+        let mut store = ListingStore::default();
+        assert!(!store.lists_pair("Okx", "PEPE", "ETH", 1_000));
+
+        let mut listed = fetched(&["BTC"], 300);
+        listed.pairs = BTreeMap::from([("PEPE".to_string(), BTreeSet::from(["ETH".to_string()]))]);
+        store.accept("Okx", listed, 1_000);
+
+        assert!(store.lists_pair("Okx", "pepe", "eth", 1_000));
+        assert!(!store.lists_pair("Okx", "PEPE", "BTC", 1_000));
+        assert!(!store.lists_pair("Okx", "PEPE", "ETH", 1_000 + MAX_LISTING_STALENESS_SECS + 1));
+    }
+
+    /// The preferred USD-like quote that the listing contains the base against is
+    /// selected, failing open to the preferred quote without a fresh listing.
+    #[test]
+    fn select_usd_quote_prefers_listed_quote() {
+        let mut store = ListingStore::default();
+        let select = |store: &ListingStore, base, now_secs| {
+            store.select_usd_quote("Coinbase", base, &["USD", "USDT"], now_secs)
+        };
+        assert_eq!(select(&store, "ICP", 1_000), Some("USD"));
+
+        let mut listed = fetched(&["BTC", "ICP"], 300);
+        listed.pairs = BTreeMap::from([
+            ("BTC".to_string(), base_set(&["USD", "USDT"])),
+            ("ICP".to_string(), base_set(&["USDT"])),
+            ("PEPE".to_string(), base_set(&["EUR"])),
+        ]);
+        store.accept("Coinbase", listed, 1_000);
+
+        assert_eq!(select(&store, "btc", 1_000), Some("USD"));
+        assert_eq!(select(&store, "ICP", 1_000), Some("USDT"));
+        assert_eq!(select(&store, "PEPE", 1_000), None);
+        let stale_secs = 1_000 + MAX_LISTING_STALENESS_SECS + 1;
+        assert_eq!(select(&store, "PEPE", stale_secs), Some("USD"));
+
+        // Without a pair graph, only the USDT bases are known.
+        store.by_exchange.get_mut("Coinbase").unwrap().pairs = None;
+        assert_eq!(select(&store, "BTC", 1_000), Some("USDT"));
+        assert_eq!(select(&store, "ETH", 1_000), None);
+    }
+
+    /// Only fresh listings of the given exchanges contribute bases, and every
+    /// base is mapped to all exchanges listing it.
+    #[test]
+    fn listed_bases_merges_fresh_listings_of_given_exchanges() {
+        let mut store = ListingStore::default();
+        store.accept("Okx", fetched(&["BTC", "ICP"], 300), 1_000);
+        store.accept("KuCoin", fetched(&["BTC", "ETH"], 300), 1_000);
+        store.accept("Mexc", fetched(&["DOGE"], 300), 1_000);
+        store.accept("GateIo", fetched(&["PEPE"], 300), 0);
+
+        let listed =
+            store.listed_bases(&["Okx", "KuCoin", "GateIo"], MAX_LISTING_STALENESS_SECS + 1);
+
+        assert_eq!(
+            listed,
+            BTreeMap::from([
+                (
+                    "BTC".to_string(),
+                    vec!["KuCoin".to_string(), "Okx".to_string()]
+                ),
+                ("ETH".to_string(), vec!["KuCoin".to_string()]),
+                ("ICP".to_string(), vec!["Okx".to_string()]),
+            ])
+        );
+    }
 }
@@ -8,6 +8,82 @@ async fn get_exchange_rate(
     xrc::get_exchange_rate(request).await
 }
 
+#[ic_cdk::update]
+async fn get_exchange_rate_v2(
+    request: ic_xrc_types::GetExchangeRateV2Request,
+) -> ic_xrc_types::GetExchangeRateV2Result {
+    xrc::get_exchange_rate_v2(request).await
+}
+
+#[ic_cdk::update]
+async fn get_exchange_rates(
+    requests: ic_xrc_types::GetExchangeRatesRequest,
+) -> ic_xrc_types::GetExchangeRatesResult {
+    xrc::get_exchange_rates(requests).await
+}
+
+#[ic_cdk::update]
+async fn get_exchange_rate_series(
+    request: ic_xrc_types::GetExchangeRateSeriesRequest,
+) -> ic_xrc_types::GetExchangeRateSeriesResult {
+    xrc::get_exchange_rate_series(request).await
+}
+
+#[ic_cdk::update]
+async fn get_twap_exchange_rate(
+    request: ic_xrc_types::GetTwapExchangeRateRequest,
+) -> ic_xrc_types::GetExchangeRateResult {
+    xrc::get_twap_exchange_rate(request).await
+}
+
+#[ic_cdk::query]
+fn get_cached_exchange_rate(
+    request: ic_xrc_types::GetExchangeRateRequest,
+) -> ic_xrc_types::GetExchangeRateResult {
+    xrc::get_cached_exchange_rate(request)
+}
+
+#[ic_cdk::query]
+fn get_certified_exchange_rate(
+    request: ic_xrc_types::GetCertifiedExchangeRateRequest,
+) -> ic_xrc_types::GetCertifiedExchangeRateResult {
+    xrc::get_certified_exchange_rate(request)
+}
+
+#[ic_cdk::query]
+fn estimate_exchange_rate_cost(
+    request: ic_xrc_types::GetExchangeRateRequest,
+) -> ic_xrc_types::ExchangeRateCostEstimate {
+    xrc::estimate_exchange_rate_cost(request)
+}
+
+#[ic_cdk::query]
+fn list_supported_assets() -> ic_xrc_types::SupportedAssets {
+    xrc::list_supported_assets()
+}
+
+#[ic_cdk::query]
+fn get_config() -> ic_xrc_types::Config {
+    xrc::get_config()
+}
+
+#[ic_cdk::query]
+fn get_status() -> ic_xrc_types::Status {
+    xrc::get_status()
+}
+
+#[ic_cdk::query]
+fn get_request_log(
+    request: ic_xrc_types::GetRequestLogRequest,
+) -> ic_xrc_types::GetRequestLogResult {
+    xrc::get_request_log(request)
+}
+
+#[ic_cdk::update]
+fn update_config(config: ic_xrc_types::Config) -> ic_xrc_types::UpdateConfigResult {
+    xrc::update_config(config)
+}
+
 #[ic_cdk::query]
 // TODO(DEFI-2648): Migrate to non-deprecated.
 #[allow(deprecated)]
@@ -22,6 +98,13 @@ fn transform_forex_http_response(args: TransformArgs) -> HttpResponse {
     xrc::transform_forex_http_response(args)
 }
 
+#[ic_cdk::query]
+// TODO(DEFI-2648): Migrate to non-deprecated.
+#[allow(deprecated)]
+fn transform_exchange_window_http_response(args: TransformArgs) -> HttpResponse {
+    xrc::transform_exchange_window_http_response(args)
+}
+
 #[ic_cdk::query]
 // TODO(DEFI-2648): Migrate to non-deprecated.
 #[allow(deprecated)]
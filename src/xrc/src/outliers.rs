@@ -0,0 +1,170 @@
+//! Filters that determine which of the received rates of an asset are discarded as outliers.
+//! The filter is chosen per asset class through the configuration.
+
+use ic_xrc_types::{AssetClass, OutlierFilter};
+
+use crate::{utils::median, with_config};
+
+/// Returns the outlier filter configured for the given asset class.
+pub(crate) fn get_outlier_filter(class: &AssetClass) -> OutlierFilter {
+    with_config(|config| match class {
+        AssetClass::Cryptocurrency => config.crypto_outlier_filter,
+        AssetClass::FiatCurrency => config.fiat_outlier_filter,
+    })
+    .unwrap_or(OutlierFilter::RelativeDifference)
+}
+
+/// Returns the inclusive range of the rates that are retained by the given filter.
+///
+/// If the spread measure of a statistical filter is zero, e.g., because most rates are
+/// identical, the relative difference to the median is used instead, so that slightly
+/// deviating rates are not all discarded.
+pub(crate) fn get_inlier_range(
+    rates: &[u64],
+    filter: &OutlierFilter,
+    max_relative_difference_divisor: u64,
+) -> (u64, u64) {
+    let median_rate = median(rates);
+    let relative_difference_range = || {
+        let max_difference = median_rate / max_relative_difference_divisor;
+        (
+            median_rate.saturating_sub(max_difference),
+            median_rate.saturating_add(max_difference),
+        )
+    };
+
+    match filter {
+        OutlierFilter::RelativeDifference => relative_difference_range(),
+        OutlierFilter::MedianAbsoluteDeviation { threshold_percent } => {
+            let deviations = rates
+                .iter()
+                .map(|rate| rate.abs_diff(median_rate))
+                .collect::<Vec<_>>();
+            let median_absolute_deviation = median(&deviations);
+            if median_absolute_deviation == 0 {
+                return relative_difference_range();
+            }
+            let max_difference = scale_by_percent(median_absolute_deviation, *threshold_percent);
+            (
+                median_rate.saturating_sub(max_difference),
+                median_rate.saturating_add(max_difference),
+            )
+        }
+        OutlierFilter::InterquartileRange { threshold_percent } => {
+            let mut sorted_rates = rates.to_vec();
+            sorted_rates.sort_unstable();
+            // The quartiles are the medians of the lower and the upper half of the rates,
+            // excluding the middle rate if the number of rates is odd.
+            let half = sorted_rates.len() / 2;
+            let first_quartile = median(&sorted_rates[..half]);
+            let third_quartile = median(&sorted_rates[sorted_rates.len() - half..]);
+            let interquartile_range = third_quartile.saturating_sub(first_quartile);
+            if interquartile_range == 0 {
+                return relative_difference_range();
+            }
+            let max_distance = scale_by_percent(interquartile_range, *threshold_percent);
+            (
+                first_quartile.saturating_sub(max_distance),
+                third_quartile.saturating_add(max_distance),
+            )
+        }
+    }
+}
+
+/// Returns `percent` percent of the given value, saturating at the largest 64-bit integer.
+fn scale_by_percent(value: u64, percent: u64) -> u64 {
+    let scaled = value as u128 * percent as u128 / 100;
+    u64::try_from(scaled).unwrap_or(u64::MAX)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::RATE_UNIT;
+
+    fn retained(rates: &[u64], filter: OutlierFilter) -> Vec<u64> {
+        let (low, high) = get_inlier_range(rates, &filter, 5);
+        rates
+            .iter()
+            .copied()
+            .filter(|rate| (low..=high).contains(rate))
+            .collect()
+    }
+
+    /// The function verifies that the relative difference filter keeps the rates within
+    /// 1/`max_relative_difference_divisor` of the median.
+    #[test]
+    fn relative_difference_filter_discards_distant_rates() {
+        let rates = [80, 100, 100, 121, 130].map(|rate| rate * RATE_UNIT);
+        assert_eq!(
+            retained(&rates, OutlierFilter::RelativeDifference),
+            [80, 100, 100].map(|rate| rate * RATE_UNIT)
+        );
+    }
+
+    /// The function verifies that the MAD filter adapts to the spread of the rates: a tight
+    /// cluster rejects a rate that is only a few percent off, which the relative difference
+    /// filter would accept.
+    #[test]
+    fn median_absolute_deviation_filter_adapts_to_spread() {
+        let rates = [1_000, 1_001, 1_002, 1_003, 1_050].map(|rate| rate * RATE_UNIT);
+        let filter = OutlierFilter::MedianAbsoluteDeviation {
+            threshold_percent: 300,
+        };
+        assert_eq!(
+            retained(&rates, filter),
+            [1_000, 1_001, 1_002, 1_003].map(|rate| rate * RATE_UNIT)
+        );
+        assert_eq!(
+            retained(&rates, OutlierFilter::RelativeDifference),
+            rates.to_vec()
+        );
+
+        // Widely spread rates are all retained.
+        let rates = [60, 80, 100, 120, 150].map(|rate| rate * RATE_UNIT);
+        assert_eq!(retained(&rates, filter), rates.to_vec());
+    }
+
+    /// The function verifies that the IQR filter uses Tukey's fences.
+    #[test]
+    fn interquartile_range_filter_uses_fences() {
+        let rates = [90, 98, 99, 100, 101, 102, 140].map(|rate| rate * RATE_UNIT);
+        let filter = OutlierFilter::InterquartileRange {
+            threshold_percent: 150,
+        };
+        // Q1 = 98, Q3 = 102 and IQR = 4, so the range is [92, 108].
+        assert_eq!(
+            get_inlier_range(&rates, &filter, 5),
+            (92 * RATE_UNIT, 108 * RATE_UNIT)
+        );
+        assert_eq!(
+            retained(&rates, filter),
+            [98, 99, 100, 101, 102].map(|rate| rate * RATE_UNIT)
+        );
+    }
+
+    /// The function verifies that the relative difference is used if the spread is zero.
+    #[test]
+    fn statistical_filters_fall_back_if_spread_is_zero() {
+        let rates = [97, 100, 100, 100, 100, 100, 130].map(|rate| rate * RATE_UNIT);
+        let expected = [97, 100, 100, 100, 100, 100].map(|rate| rate * RATE_UNIT);
+        assert_eq!(
+            retained(
+                &rates,
+                OutlierFilter::MedianAbsoluteDeviation {
+                    threshold_percent: 300
+                }
+            ),
+            expected
+        );
+        assert_eq!(
+            retained(
+                &rates,
+                OutlierFilter::InterquartileRange {
+                    threshold_percent: 150
+                }
+            ),
+            expected
+        );
+    }
+}
@@ -8,6 +8,7 @@ use chrono::{Datelike, NaiveDateTime, Weekday};
 use futures::future::join_all;
 
 use crate::{
+    api::get_cryptocurrency_usd_rates,
     call_exchange_listing, call_forex,
     exchanges::ListedPairs,
     forex::{Forex, ForexContextArgs, ForexRateMap, FOREX_SOURCES},
@@ -72,6 +73,13 @@ trait ForexSources {
         Vec<(String, u64, ForexRateMap)>,
         Vec<(String, CallForexError)>,
     );
+
+    /// Returns the USD rates of the given cryptocurrencies, which are components of baskets.
+    async fn call_crypto_usd_rates(
+        &self,
+        symbols: &[String],
+        timestamp: u64,
+    ) -> BTreeMap<String, Vec<u64>>;
 }
 
 struct ForexSourcesImpl;
@@ -124,11 +132,20 @@ impl ForexSources for ForexSourcesImpl {
 
         (rates, errors)
     }
+
+    async fn call_crypto_usd_rates(
+        &self,
+        symbols: &[String],
+        timestamp: u64,
+    ) -> BTreeMap<String, Vec<u64>> {
+        get_cryptocurrency_usd_rates(symbols, timestamp).await
+    }
 }
 
 #[derive(Debug, PartialEq, Eq)]
 enum ForexStatusError {
     IpV4NotSupported,
+    Disabled,
     Weekend,
     AlreadyCollected,
 }
@@ -138,6 +155,10 @@ fn check_forex_status(forex: &Forex, timestamp: u64) -> Result<(), ForexStatusEr
         return Err(ForexStatusError::IpV4NotSupported);
     }
 
+    if !forex.is_enabled() {
+        return Err(ForexStatusError::Disabled);
+    }
+
     // Avoid querying on weekends
     if !cfg!(feature = "disable-forex-weekend-check") {
         // TODO(DEFI-2648): Migrate to non-deprecated.
@@ -238,6 +259,20 @@ async fn update_forex_store(
             timestamps_to_update.insert(timestamp);
         }
     }
+    // Collect the rates of the cryptocurrencies in the baskets for the updated days
+    for timestamp in &timestamps_to_update {
+        let symbols = with_forex_rate_collector(|collector| {
+            collector.get_missing_crypto_components(*timestamp)
+        });
+        if !symbols.is_empty() {
+            let rates = forex_sources
+                .call_crypto_usd_rates(&symbols, *timestamp)
+                .await;
+            with_forex_rate_collector_mut(|collector| {
+                collector.update_crypto_rates(*timestamp, rates)
+            });
+        }
+    }
     // Update the forex store with all days we collected new rates for
     for timestamp in timestamps_to_update {
         if let Some(mut forex_multi_rate_map) =
@@ -366,7 +401,7 @@ impl ListingSources for ListingSourcesImpl {
     fn exchange_names(&self) -> Vec<String> {
         EXCHANGES
             .iter()
-            .filter(|exchange| exchange.is_available())
+            .filter(|exchange| exchange.is_available() && exchange.is_enabled())
             .map(|exchange| exchange.name().to_string())
             .collect()
     }
@@ -377,10 +412,11 @@ impl ListingSources for ListingSourcesImpl {
     ) -> Vec<(String, Result<ListedPairs, CallExchangeError>)> {
         let mut names = vec![];
         let mut futures = vec![];
-        for exchange in EXCHANGES
-            .iter()
-            .filter(|exchange| exchange.is_available() && exchanges.iter().any(|e| e == exchange.name()))
-        {
+        for exchange in EXCHANGES.iter().filter(|exchange| {
+            exchange.is_available()
+                && exchange.is_enabled()
+                && exchanges.iter().any(|e| e == exchange.name())
+        }) {
             names.push(exchange.name().to_string());
             futures.push(call_exchange_listing(exchange));
         }
@@ -512,10 +548,12 @@ fn record_accepted_listing_metrics(exchange: &str) {
 mod test {
 
     use futures::FutureExt;
+    use ic_xrc_types::{Asset, AssetClass, Basket, BasketComponent, Config};
     use maplit::btreemap;
 
+    use crate::config;
     use crate::forex::COMPUTED_XDR_SYMBOL;
-    use crate::with_forex_rate_store;
+    use crate::{with_forex_rate_store, RATE_UNIT};
 
     use super::*;
 
@@ -523,11 +561,21 @@ mod test {
     struct MockForexSourcesImpl {
         maps: Vec<ForexRateMap>,
         errors: Vec<(String, CallForexError)>,
+        crypto_rates: BTreeMap<String, Vec<u64>>,
     }
 
     impl MockForexSourcesImpl {
         fn new(maps: Vec<ForexRateMap>, errors: Vec<(String, CallForexError)>) -> Self {
-            Self { maps, errors }
+            Self {
+                maps,
+                errors,
+                crypto_rates: BTreeMap::new(),
+            }
+        }
+
+        fn with_crypto_rates(mut self, crypto_rates: BTreeMap<String, Vec<u64>>) -> Self {
+            self.crypto_rates = crypto_rates;
+            self
         }
     }
 
@@ -552,6 +600,18 @@ mod test {
                     .collect(),
             )
         }
+
+        async fn call_crypto_usd_rates(
+            &self,
+            symbols: &[String],
+            _timestamp: u64,
+        ) -> BTreeMap<String, Vec<u64>> {
+            self.crypto_rates
+                .iter()
+                .filter(|(symbol, _)| symbols.contains(symbol))
+                .map(|(symbol, rates)| (symbol.clone(), rates.clone()))
+                .collect()
+        }
     }
 
     /// This function demonstrates that the forex rate store can be successfully updated by [update_forex_store].
@@ -582,6 +642,61 @@ mod test {
         );
     }
 
+    /// This function demonstrates that [update_forex_store] computes the baskets with
+    /// cryptocurrency components from the USD rates of the cryptocurrencies.
+    #[test]
+    fn forex_store_contains_baskets_with_crypto_components() {
+        let mut baskets = config::default_config().baskets.unwrap_or_default();
+        baskets.push(Basket {
+            symbol: "INDEX".to_string(),
+            components: vec![
+                BasketComponent {
+                    asset: Asset {
+                        symbol: "EUR".to_string(),
+                        class: AssetClass::FiatCurrency,
+                    },
+                    weight_per_million: 500_000,
+                },
+                BasketComponent {
+                    asset: Asset {
+                        symbol: "BTC".to_string(),
+                        class: AssetClass::Cryptocurrency,
+                    },
+                    weight_per_million: 10,
+                },
+            ],
+            effective_from: 0,
+        });
+        config::set_config(Config {
+            baskets: Some(baskets),
+            ..config::default_config()
+        });
+        let timestamp = 1666371931;
+        let start_of_day = start_of_day_timestamp(timestamp);
+        let map = btreemap! {
+            "EUR".to_string() => 1_100_000_000,
+            COMPUTED_XDR_SYMBOL.to_string() => 1_300_000_000,
+        };
+        let mock_forex_sources = MockForexSourcesImpl::new(
+            vec![map.clone(), map.clone(), map.clone(), map],
+            vec![],
+        )
+        .with_crypto_rates(btreemap! {
+            "BTC".to_string() => vec![59_900 * RATE_UNIT, 60_000 * RATE_UNIT, 60_100 * RATE_UNIT],
+        });
+        update_forex_store(timestamp, &mock_forex_sources)
+            .now_or_never()
+            .expect("should have executed");
+
+        // INDEX/USD = 0.5 * 1.1 + 0.00001 * 60_000 = 1.15
+        let result = with_forex_rate_store(|store| {
+            store.get(start_of_day, timestamp + ONE_DAY_SECONDS, "INDEX", "usd")
+        })
+        .expect("the basket rate should be stored");
+        assert_eq!(result.rates[0], 1_150_000_000);
+        assert_eq!(result.base_asset_num_received_rates, 3);
+    }
+
     /// This function demonstrates that the forex rate store can be successfully updated by [update_forex_store]
     /// on a six hour interval controlled by the [NEXT_RUN_SCHEDULED_AT_TIMESTAMP] state variable.
     #[test]
@@ -674,6 +789,19 @@ mod test {
         ));
     }
 
+    #[test]
+    fn check_forex_status_disabled() {
+        let forex = FOREX_SOURCES.first().expect("Myanmar expected"); // Myanmar
+        config::set_config(Config {
+            disabled_forex_sources: Some(vec![forex.to_string()]),
+            ..config::default_config()
+        });
+        assert!(matches!(
+            check_forex_status(forex, 1680220800),
+            Err(ForexStatusError::Disabled)
+        ));
+    }
+
     #[test]
     fn check_forex_status_weekend() {
         let forex = FOREX_SOURCES.first().expect("Myanmar expected"); // Myanmar
@@ -909,6 +1037,7 @@ mod test {
             ListedPairs {
                 bases: bases.iter().map(|s| s.to_string()).collect::<BTreeSet<_>>(),
                 total_markets,
+                pairs: BTreeMap::new(),
             }
         }
 
@@ -1,19 +1,22 @@
-use ic_xrc_types::{ExchangeRateError, GetExchangeRateRequest};
+use ic_xrc_types::GetExchangeRateRequest;
 
-use crate::{utils, QueriedExchangeRate, EXCHANGES, RATE_LIMITING_REQUEST_COUNTER};
+use crate::{api::STABLECOIN_BASES, utils, with_config, EXCHANGES, RATE_LIMITING_REQUEST_COUNTER};
 
-/// A limit for how many HTTP requests the exchange rate canister may issue at any given time.
-/// The request counter is not allowed to go over this limit.
-const REQUEST_COUNTER_LIMIT: usize = 56;
+/// The maximum number of rates that a single request may need to retrieve when it is admitted:
+/// the rates of both cryptocurrencies of a pair and of their intermediate assets, and the rates
+/// of all stablecoins.
+pub(crate) const MAX_NUM_RATES_PER_REQUEST: usize = 4 + STABLECOIN_BASES.len();
+
+/// The default limit for how many HTTP requests the exchange rate canister may issue at any given time.
+/// The request counter is not allowed to go over the configured limit. The default leaves room
+/// for two requests that need [MAX_NUM_RATES_PER_REQUEST] rates from every exchange.
+pub(crate) const REQUEST_COUNTER_LIMIT: usize = 2 * EXCHANGES.len() * MAX_NUM_RATES_PER_REQUEST;
 
 /// This function is used to wrap HTTP outcalls so that the requests can be rate limited.
 /// If the caller is the CMC, it will ignore the rate limiting.
-pub(crate) async fn with_request_counter<F>(
-    num_rates_needed: usize,
-    future: F,
-) -> Result<QueriedExchangeRate, ExchangeRateError>
+pub(crate) async fn with_request_counter<F, R>(num_rates_needed: usize, future: F) -> R
 where
-    F: std::future::Future<Output = Result<QueriedExchangeRate, ExchangeRateError>>,
+    F: std::future::Future<Output = R>,
 {
     // Need to set the guard to maintain the lifetime until the future is complete.
     let _guard = RateLimitingRequestCounterGuard::new(num_rates_needed);
@@ -25,7 +28,8 @@ pub(crate) fn is_rate_limited(num_rates_needed: usize, request: &GetExchangeRate
     let request_counter = get_request_counter();
     let available_exchanges_count = available_exchanges_count();
     let http_requests_needed = available_exchanges_count.saturating_mul(num_rates_needed);
-    http_requests_needed.saturating_add(request_counter) > REQUEST_COUNTER_LIMIT
+    let request_counter_limit = with_config(|config| config.request_counter_limit as usize);
+    http_requests_needed.saturating_add(request_counter) > request_counter_limit
         && (!utils::is_privileged_asset_pair(&request.base_asset, &request.quote_asset)
             || request.timestamp.is_some())
 }
@@ -36,7 +40,10 @@ pub(crate) fn get_request_counter() -> usize {
 }
 
 fn available_exchanges_count() -> usize {
-    EXCHANGES.iter().filter(|e| e.is_available()).count()
+    EXCHANGES
+        .iter()
+        .filter(|e| e.is_available() && e.is_enabled())
+        .count()
 }
 
 /// Guard to ensure the rate limiting request counter is incremented and decremented properly.
@@ -72,11 +79,13 @@ impl Drop for RateLimitingRequestCounterGuard {
 #[cfg(test)]
 pub(crate) mod test {
     use futures::FutureExt;
-    use ic_xrc_types::{Asset, AssetClass};
+    use ic_xrc_types::{Asset, AssetClass, Config, ExchangeRateError};
+
+    use crate::{config, QueriedExchangeRate};
 
     use super::*;
 
-    pub(crate) const REQUEST_COUNTER_TRIGGER_RATE_LIMIT: usize = 52;
+    pub(crate) const REQUEST_COUNTER_TRIGGER_RATE_LIMIT: usize = REQUEST_COUNTER_LIMIT - 4;
 
     pub(crate) fn set_request_counter(requests: usize) {
         RATE_LIMITING_REQUEST_COUNTER.with(|c| c.set(requests));
@@ -92,7 +101,7 @@ pub(crate) mod test {
                 get_request_counter(),
                 num_rates_needed * available_exchanges_count()
             );
-            Ok(QueriedExchangeRate::default())
+            Ok::<_, ExchangeRateError>(QueriedExchangeRate::default())
         })
         .now_or_never()
         .expect("should succeed")
@@ -111,7 +120,7 @@ pub(crate) mod test {
                 get_request_counter(),
                 num_rates_needed * available_exchanges_count()
             );
-            Err(ExchangeRateError::StablecoinRateNotFound)
+            Err::<QueriedExchangeRate, _>(ExchangeRateError::StablecoinRateNotFound)
         })
         .now_or_never()
         .expect("should succeed")
@@ -136,6 +145,21 @@ pub(crate) mod test {
         assert!(is_rate_limited(2, &default_exchange_rate_request()));
     }
 
+    /// The function verifies that disabled exchanges are not counted as available.
+    #[test]
+    fn available_exchanges_count_excludes_disabled_exchanges() {
+        let count = available_exchanges_count();
+        let disabled_exchange = EXCHANGES
+            .iter()
+            .find(|exchange| exchange.is_available())
+            .expect("an exchange should be available");
+        config::set_config(Config {
+            disabled_exchanges: Some(vec![disabled_exchange.name().to_string()]),
+            ..config::default_config()
+        });
+        assert_eq!(available_exchanges_count(), count - 1);
+    }
+
     fn default_exchange_rate_request() -> GetExchangeRateRequest {
         GetExchangeRateRequest {
             base_asset: Asset {
@@ -0,0 +1,344 @@
+//! Per-exchange reputation: a rolling score of how far the rates of each exchange
+//! deviate from the median of all rates received in the same
+//! `get_cryptocurrency_usdt_rate` call. The scores are kept per exchange and asset, as an
+//! exchange may be reliable for liquid assets and off for illiquid ones. The scores are
+//! persisted across upgrades.
+//!
+//! While the outlier filter rejects a bad rate call-by-call, the score also
+//! surfaces an exchange that is persistently off by a small amount. Depending on
+//! the configured [ExchangeReputationPolicy], the rates of an exchange whose score
+//! for the requested asset crosses the threshold are discarded or their weights are
+//! scaled down.
+
+use candid::{CandidType, Deserialize};
+use ic_xrc_types::ExchangeReputationPolicy;
+use std::collections::BTreeMap;
+
+use crate::{
+    set_labeled_gauge, utils::median, with_config, with_reputation_store,
+    with_reputation_store_mut, LabelKey, MetricName,
+};
+
+/// The number of samples over which the deviations are averaged. Once an exchange has
+/// this many samples, every new deviation has a weight of 1/`SCORE_WINDOW` in the score.
+const SCORE_WINDOW: u64 = 20;
+
+/// The policy only affects an exchange once its score is based on at least this many samples.
+const MIN_SAMPLES_FOR_POLICY: u64 = 10;
+
+/// Deviations are only recorded if at least this many rates were received, as the median of
+/// fewer rates is not a meaningful consensus.
+const MIN_RATES_FOR_SCORING: usize = 3;
+
+/// A single deviation is capped at 100% so that one wildly wrong rate cannot dominate the score.
+const MAX_DEVIATION_PPM: u64 = 1_000_000;
+
+/// The reputation of a single exchange for a single asset.
+///
+/// This is persisted to stable memory via candid across upgrades, so any field
+/// added later has to be `Option<T>`.
+#[derive(CandidType, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct ExchangeReputation {
+    /// The rolling average deviation of the rates from the median rate in parts per million.
+    pub score_ppm: u64,
+    /// The number of recorded deviations.
+    pub num_samples: u64,
+    /// Timestamp (seconds) of the last recorded deviation.
+    pub last_update_secs: u64,
+}
+
+impl ExchangeReputation {
+    /// Returns the score in basis points.
+    pub(crate) fn score_bps(&self) -> u64 {
+        self.score_ppm / 100
+    }
+
+    fn record(&mut self, deviation_ppm: u64, now_secs: u64) {
+        // The score is the plain average until the window is filled and an exponential
+        // moving average afterwards.
+        let window = self.num_samples.saturating_add(1).min(SCORE_WINDOW);
+        self.score_ppm = ((self.score_ppm as u128 * (window - 1) as u128 + deviation_ppm as u128)
+            / window as u128) as u64;
+        self.num_samples = self.num_samples.saturating_add(1);
+        self.last_update_secs = now_secs;
+    }
+}
+
+/// Maps each exchange (by [`crate::Exchange::name`]) and asset symbol to the reputation of
+/// the exchange for the asset. Persisted in stable memory across upgrades.
+#[derive(CandidType, Deserialize, Clone, Debug, Default)]
+pub(crate) struct ReputationStore {
+    by_exchange_and_asset: BTreeMap<(String, String), ExchangeReputation>,
+}
+
+impl ReputationStore {
+    /// Returns the reputation of `exchange` for the asset with the symbol `asset`, if any
+    /// deviation was recorded for them.
+    pub(crate) fn get(&self, exchange: &str, asset: &str) -> Option<&ExchangeReputation> {
+        self.by_exchange_and_asset
+            .get(&(exchange.to_string(), asset.to_string()))
+    }
+
+    /// Iterates over the reputations, ordered by exchange name and asset symbol.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&(String, String), &ExchangeReputation)> {
+        self.by_exchange_and_asset.iter()
+    }
+
+    /// Records the deviation of every rate of the asset `asset` in `source_rates` from their
+    /// median.
+    fn record(&mut self, asset: &str, source_rates: &[(String, u64)], now_secs: u64) {
+        if source_rates.len() < MIN_RATES_FOR_SCORING {
+            return;
+        }
+        let rates: Vec<u64> = source_rates.iter().map(|(_, rate)| *rate).collect();
+        let median_rate = median(&rates);
+        if median_rate == 0 {
+            return;
+        }
+        for (exchange, rate) in source_rates {
+            let deviation_ppm = (rate.abs_diff(median_rate) as u128 * 1_000_000
+                / median_rate as u128)
+                .min(MAX_DEVIATION_PPM as u128) as u64;
+            self.by_exchange_and_asset
+                .entry((exchange.clone(), asset.to_string()))
+                .or_default()
+                .record(deviation_ppm, now_secs);
+        }
+    }
+
+    /// Returns true if the score of `exchange` for `asset` exceeds `max_score_bps` and is based
+    /// on sufficiently many samples.
+    fn exceeds(&self, exchange: &str, asset: &str, max_score_bps: u64) -> bool {
+        self.get(exchange, asset).is_some_and(|reputation| {
+            reputation.num_samples >= MIN_SAMPLES_FOR_POLICY
+                && reputation.score_bps() > max_score_bps
+        })
+    }
+}
+
+/// Records the deviations of the rates of the asset `asset` received from the exchanges in a
+/// single call and updates the reputation score metric of the exchanges.
+pub(crate) fn record_deviations(asset: &str, source_rates: &[(String, u64)], now_secs: u64) {
+    with_reputation_store_mut(|store| store.record(asset, source_rates, now_secs));
+    set_score_metrics();
+}
+
+/// Sets the reputation score metric of every exchange and asset with a recorded deviation.
+pub(crate) fn set_score_metrics() {
+    with_reputation_store(|store| {
+        for ((exchange, asset), reputation) in store.iter() {
+            set_labeled_gauge(
+                MetricName::ExchangeReputationScoreBps,
+                &[(LabelKey::Exchange, exchange), (LabelKey::Symbol, asset)],
+                reputation.score_ppm as f64 / 100.0,
+            );
+        }
+    });
+}
+
+/// Applies the configured [ExchangeReputationPolicy] to the rates of the asset `asset` received
+/// from the exchanges. The source rates, rates and the weights with which the rates are
+/// aggregated must be in the same order. Returns the names of the exchanges whose rates were
+/// discarded.
+///
+/// Rates are never discarded if that would leave no rates at all.
+pub(crate) fn apply_policy(
+    asset: &str,
+    source_rates: &mut Vec<(String, u64)>,
+    rates: &mut Vec<u64>,
+    weights: &mut Vec<u64>,
+) -> Vec<String> {
+    let policy = with_config(|config| config.exchange_reputation_policy)
+        .unwrap_or(ExchangeReputationPolicy::Ignore);
+    with_reputation_store(|store| match policy {
+        ExchangeReputationPolicy::Ignore => vec![],
+        ExchangeReputationPolicy::Exclude { max_score_bps } => {
+            let excluded: Vec<bool> = source_rates
+                .iter()
+                .map(|(exchange, _)| store.exceeds(exchange, asset, max_score_bps))
+                .collect();
+            if excluded.iter().all(|excluded| *excluded) {
+                return vec![];
+            }
+            let excluded_exchanges = source_rates
+                .iter()
+                .zip(&excluded)
+                .filter(|(_, excluded)| **excluded)
+                .map(|((exchange, _), _)| exchange.clone())
+                .collect();
+            retain_unexcluded(source_rates, &excluded);
+            retain_unexcluded(rates, &excluded);
+            retain_unexcluded(weights, &excluded);
+            excluded_exchanges
+        }
+        ExchangeReputationPolicy::DownWeight {
+            max_score_bps,
+            weight_percent,
+        } => {
+            for ((exchange, _), weight) in source_rates.iter().zip(weights.iter_mut()) {
+                if store.exceeds(exchange, asset, max_score_bps) {
+                    *weight = (*weight as u128 * weight_percent as u128 / 100) as u64;
+                }
+            }
+            vec![]
+        }
+    })
+}
+
+/// Removes the values whose corresponding entry in `excluded` is true.
+fn retain_unexcluded<T>(values: &mut Vec<T>, excluded: &[bool]) {
+    let mut excluded = excluded.iter();
+    values.retain(|_| !excluded.next().copied().unwrap_or_default());
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{config, make_metric_key, with_labeled_gauges, RATE_UNIT};
+    use ic_xrc_types::Config;
+
+    fn source_rates(rates: &[(&str, u64)]) -> Vec<(String, u64)> {
+        rates
+            .iter()
+            .map(|(exchange, rate)| (exchange.to_string(), rate * RATE_UNIT / 1_000))
+            .collect()
+    }
+
+    /// Records `num_calls` calls in which `Biased` is 2% above the consensus for ICP.
+    fn record_biased_calls(num_calls: u64) {
+        for timestamp in 0..num_calls {
+            record_deviations(
+                "ICP",
+                &source_rates(&[
+                    ("Coinbase", 1_000),
+                    ("KuCoin", 1_000),
+                    ("OKX", 1_000),
+                    ("Biased", 1_020),
+                ]),
+                timestamp,
+            );
+        }
+    }
+
+    /// The function verifies that the score is the rolling average deviation from the median
+    /// and that it is exposed as a metric.
+    #[test]
+    fn record_deviations_tracks_rolling_average() {
+        record_biased_calls(1);
+        with_reputation_store(|store| {
+            assert_eq!(
+                store.get("Biased", "ICP"),
+                Some(&ExchangeReputation {
+                    score_ppm: 20_000,
+                    num_samples: 1,
+                    last_update_secs: 0,
+                })
+            );
+            assert_eq!(store.get("Coinbase", "ICP").map(|r| r.score_ppm), Some(0));
+        });
+
+        // The bias disappears and the score decays.
+        for timestamp in 1..=SCORE_WINDOW {
+            record_deviations(
+                "ICP",
+                &source_rates(&[("Coinbase", 1_000), ("KuCoin", 1_000), ("Biased", 1_000)]),
+                timestamp,
+            );
+        }
+        let score_bps =
+            with_reputation_store(|store| store.get("Biased", "ICP").unwrap().score_bps());
+        assert!(score_bps < 100, "score {score_bps} should have decayed");
+        with_labeled_gauges(|gauges| {
+            let key = make_metric_key(
+                MetricName::ExchangeReputationScoreBps,
+                &[(LabelKey::Exchange, "Biased"), (LabelKey::Symbol, "ICP")],
+            );
+            assert_eq!(gauges.get(&key).map(|value| *value as u64), Some(score_bps));
+        });
+
+        // Fewer than MIN_RATES_FOR_SCORING rates are not recorded.
+        record_deviations(
+            "ICP",
+            &source_rates(&[("Coinbase", 1_000), ("OKX", 2_000)]),
+            100,
+        );
+        let num_samples =
+            with_reputation_store(|store| store.get("OKX", "ICP").unwrap().num_samples);
+        assert_eq!(num_samples, 1);
+    }
+
+    /// The function verifies that the rates of an exchange with a poor reputation are only
+    /// discarded once its score is based on sufficiently many samples.
+    #[test]
+    fn apply_policy_excludes_poor_reputation() {
+        config::set_config(Config {
+            exchange_reputation_policy: Some(ExchangeReputationPolicy::Exclude {
+                max_score_bps: 100,
+            }),
+            ..config::default_config()
+        });
+        let apply = || {
+            let mut source_rates = source_rates(&[("Coinbase", 1_000), ("Biased", 1_020)]);
+            let mut rates: Vec<u64> = source_rates.iter().map(|(_, rate)| *rate).collect();
+            let mut weights = vec![RATE_UNIT; rates.len()];
+            let excluded = apply_policy("ICP", &mut source_rates, &mut rates, &mut weights);
+            (excluded, rates.len(), source_rates.len())
+        };
+
+        record_biased_calls(MIN_SAMPLES_FOR_POLICY - 1);
+        assert_eq!(apply(), (vec![], 2, 2));
+
+        record_biased_calls(1);
+        assert_eq!(apply(), (vec!["Biased".to_string()], 1, 1));
+
+        // The only rate is never discarded.
+        let mut source_rates = source_rates(&[("Biased", 1_020)]);
+        let mut rates = vec![source_rates[0].1];
+        assert!(apply_policy("ICP", &mut source_rates, &mut rates, &mut vec![0]).is_empty());
+        assert_eq!(rates.len(), 1);
+    }
+
+    /// The function verifies that the weights of an exchange with a poor reputation are scaled.
+    #[test]
+    fn apply_policy_down_weights_poor_reputation() {
+        config::set_config(Config {
+            exchange_reputation_policy: Some(ExchangeReputationPolicy::DownWeight {
+                max_score_bps: 100,
+                weight_percent: 10,
+            }),
+            ..config::default_config()
+        });
+        record_biased_calls(MIN_SAMPLES_FOR_POLICY);
+
+        let mut source_rates = source_rates(&[("Coinbase", 1_000), ("Biased", 1_020)]);
+        let mut rates: Vec<u64> = source_rates.iter().map(|(_, rate)| *rate).collect();
+        let mut weights = vec![1_000, 5_000];
+        assert!(apply_policy("ICP", &mut source_rates, &mut rates, &mut weights).is_empty());
+        assert_eq!(weights, vec![1_000, 500]);
+        assert_eq!(rates.len(), 2);
+    }
+
+    /// The function verifies that a poor reputation for one asset does not affect the rates
+    /// of the same exchange for another asset.
+    #[test]
+    fn apply_policy_uses_the_reputation_for_the_requested_asset() {
+        config::set_config(Config {
+            exchange_reputation_policy: Some(ExchangeReputationPolicy::Exclude {
+                max_score_bps: 100,
+            }),
+            ..config::default_config()
+        });
+        record_biased_calls(MIN_SAMPLES_FOR_POLICY);
+        let apply = |asset: &str| {
+            let mut source_rates = source_rates(&[("Coinbase", 1_000), ("Biased", 1_020)]);
+            let mut rates: Vec<u64> = source_rates.iter().map(|(_, rate)| *rate).collect();
+            let mut weights = vec![RATE_UNIT; rates.len()];
+            apply_policy(asset, &mut source_rates, &mut rates, &mut weights)
+        };
+
+        assert_eq!(apply("ICP"), vec!["Biased".to_string()]);
+        assert!(apply("BTC").is_empty());
+        let btc_reputation = with_reputation_store(|store| store.get("Biased", "BTC").cloned());
+        assert_eq!(btc_reputation, None);
+    }
+}
@@ -1,37 +1,59 @@
-use std::{cell::RefCell, collections::VecDeque, thread::LocalKey};
+use std::{cell::RefCell, thread::LocalKey};
 
-use candid::Principal;
-use ic_xrc_types::{GetExchangeRateRequest, GetExchangeRateResult};
+use candid::{decode_one, encode_one, Principal};
+use ic_stable_structures::{cell::Cell as StableCell, StableBTreeMap};
+use ic_xrc_types::{
+    ExchangeRateError, GetExchangeRateRequest, GetExchangeRateResult, GetRequestLogRequest,
+    RequestLogEntry, RequestLogFilter, RequestLogPage,
+};
 
-use crate::utils;
+use crate::{stable_memory::Memory, utils, NONPRIVILEGED_REQUEST_LOG, PRIVILEGED_REQUEST_LOG};
 
-/// A single entry in the log containing the timestamp, caller, request, and
-/// result of the request.
-pub(crate) struct RequestLogEntry {
-    pub timestamp: u64,
-    pub caller: Principal,
-    pub request: GetExchangeRateRequest,
-    pub result: GetExchangeRateResult,
-}
+/// The maximum number of entries returned by a single `get_request_log` request.
+pub(crate) const MAX_REQUEST_LOG_PAGE_SIZE: usize = 100;
+
+/// The maximum size of a candid-encoded log entry. Larger entries are not logged.
+const MAX_ENCODED_ENTRY_SIZE: u32 = 2_048;
 
 /// Data structure that contains the most recent requests and results.
+/// The entries are kept in stable memory as a ring buffer, so they survive upgrades without
+/// being serialized in `pre_upgrade`.
 pub(crate) struct RequestLog {
-    entries: VecDeque<RequestLogEntry>,
+    /// The candid-encoded entries keyed by their slot in the ring buffer.
+    entries: StableBTreeMap<Memory, u64, Vec<u8>>,
+    /// The number of entries that have been written to the log. The next entry is written to
+    /// the slot `count % max_entries`.
+    count: StableCell<u64, Memory>,
     /// Max number of entries the log should contain.
-    max_entries: usize,
+    max_entries: u64,
+    /// Whether the log records the requests of privileged callers.
+    privileged: bool,
 }
 
 impl RequestLog {
-    /// Create a new log for recording requests.
-    pub(crate) fn new(max_entries: usize) -> Self {
+    /// Create a new log for recording requests or load the log that is already stored in the
+    /// given memories.
+    pub(crate) fn init(
+        max_entries: u64,
+        privileged: bool,
+        entries_memory: Memory,
+        count_memory: Memory,
+    ) -> Self {
         Self {
-            entries: VecDeque::new(),
+            entries: StableBTreeMap::init(
+                entries_memory,
+                std::mem::size_of::<u64>() as u32,
+                MAX_ENCODED_ENTRY_SIZE,
+            ),
+            count: StableCell::init(count_memory, 0)
+                .expect("Failed to initialize the request log counter."),
             max_entries,
+            privileged,
         }
     }
 
-    /// Writes the provided parameters into a new log entry and if the log
-    /// has more entries than `max_entries`, it prunes off the oldest.
+    /// Writes the provided parameters into a new log entry, overwriting the oldest entry if
+    /// the log already contains `max_entries` entries.
     pub fn log(
         &mut self,
         caller: &Principal,
@@ -39,20 +61,35 @@ impl RequestLog {
         request: &GetExchangeRateRequest,
         result: &GetExchangeRateResult,
     ) {
-        self.entries.push_front(RequestLogEntry {
+        self.push(RequestLogEntry {
             timestamp,
             caller: *caller,
+            privileged: self.privileged,
             request: utils::sanitize_request(request),
             result: result.clone(),
         });
-        if self.entries.len() > self.max_entries {
-            self.entries.pop_back();
+    }
+
+    fn push(&mut self, entry: RequestLogEntry) {
+        let count = *self.count.get();
+        let bytes = encode_one(&entry).expect("Failed to encode a request log entry.");
+        if let Err(err) = self.entries.insert(count % self.max_entries, bytes) {
+            ic_cdk::println!("Failed to log the request: {}", err);
+            return;
         }
+        self.count
+            .set(count + 1)
+            .expect("Failed to update the request log counter.");
     }
 
-    /// Returns a reference to the internal log entries for read purposes.
-    pub fn entries(&self) -> &VecDeque<RequestLogEntry> {
-        &self.entries
+    /// Returns the entries, starting with the most recent entry.
+    pub fn entries(&self) -> impl Iterator<Item = RequestLogEntry> + '_ {
+        let count = *self.count.get();
+        (0..count.min(self.max_entries)).filter_map(move |age| {
+            self.entries
+                .get(&((count - 1 - age) % self.max_entries))
+                .map(|bytes| decode_one(&bytes).expect("Failed to decode a request log entry."))
+        })
     }
 }
 
@@ -68,3 +105,274 @@ pub(crate) fn log(
         cell.borrow_mut().log(caller, timestamp, request, result);
     });
 }
+
+/// Returns the entries of the privileged and the non-privileged request logs that match the
+/// filter, starting with the most recent entry.
+pub(crate) fn get_request_log(request: &GetRequestLogRequest) -> RequestLogPage {
+    PRIVILEGED_REQUEST_LOG.with(|privileged_log| {
+        NONPRIVILEGED_REQUEST_LOG.with(|nonprivileged_log| {
+            let privileged_log = privileged_log.borrow();
+            let nonprivileged_log = nonprivileged_log.borrow();
+            let mut entries = privileged_log
+                .entries()
+                .chain(nonprivileged_log.entries())
+                .filter(|entry| matches_filter(entry, &request.filter))
+                .collect::<Vec<_>>();
+            entries.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+
+            let offset = usize::try_from(request.offset).unwrap_or(usize::MAX);
+            let limit = usize::try_from(request.limit)
+                .unwrap_or(usize::MAX)
+                .min(MAX_REQUEST_LOG_PAGE_SIZE);
+            RequestLogPage {
+                total: entries.len() as u64,
+                entries: entries.into_iter().skip(offset).take(limit).collect(),
+            }
+        })
+    })
+}
+
+fn matches_filter(entry: &RequestLogEntry, filter: &RequestLogFilter) -> bool {
+    let matches_symbol = |symbol: &str, filter_symbol: &Option<String>| {
+        filter_symbol
+            .as_ref()
+            .is_none_or(|filter_symbol| filter_symbol.eq_ignore_ascii_case(symbol))
+    };
+    let matches_error = match (&filter.error, &entry.result) {
+        (None, _) => true,
+        (Some(variant), Err(error)) => error_variant(error) == variant,
+        (Some(_), Ok(_)) => false,
+    };
+
+    filter.caller.is_none_or(|caller| caller == entry.caller)
+        && matches_symbol(&entry.request.base_asset.symbol, &filter.base_asset_symbol)
+        && matches_symbol(
+            &entry.request.quote_asset.symbol,
+            &filter.quote_asset_symbol,
+        )
+        && filter
+            .privileged
+            .is_none_or(|privileged| privileged == entry.privileged)
+        && matches_error
+        && filter
+            .start_timestamp
+            .is_none_or(|start_timestamp| entry.timestamp >= start_timestamp)
+        && filter
+            .end_timestamp
+            .is_none_or(|end_timestamp| entry.timestamp <= end_timestamp)
+}
+
+/// Returns the name of the variant of the error.
+fn error_variant(error: &ExchangeRateError) -> &'static str {
+    match error {
+        ExchangeRateError::AnonymousPrincipalNotAllowed => "AnonymousPrincipalNotAllowed",
+        ExchangeRateError::Pending => "Pending",
+        ExchangeRateError::CryptoBaseAssetNotFound => "CryptoBaseAssetNotFound",
+        ExchangeRateError::CryptoQuoteAssetNotFound => "CryptoQuoteAssetNotFound",
+        ExchangeRateError::StablecoinRateNotFound => "StablecoinRateNotFound",
+        ExchangeRateError::StablecoinRateTooFewRates => "StablecoinRateTooFewRates",
+        ExchangeRateError::StablecoinRateZeroRate => "StablecoinRateZeroRate",
+        ExchangeRateError::ForexInvalidTimestamp => "ForexInvalidTimestamp",
+        ExchangeRateError::ForexBaseAssetNotFound => "ForexBaseAssetNotFound",
+        ExchangeRateError::ForexQuoteAssetNotFound => "ForexQuoteAssetNotFound",
+        ExchangeRateError::ForexAssetsNotFound => "ForexAssetsNotFound",
+        ExchangeRateError::RateLimited => "RateLimited",
+        ExchangeRateError::NotEnoughCycles => "NotEnoughCycles",
+        ExchangeRateError::InconsistentRatesReceived => "InconsistentRatesReceived",
+        ExchangeRateError::Other(_) => "Other",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ic_stable_structures::{
+        memory_manager::{MemoryId, MemoryManager},
+        DefaultMemoryImpl,
+    };
+    use ic_xrc_types::{Asset, AssetClass, OtherError};
+
+    use super::*;
+
+    fn request(base_symbol: &str) -> GetExchangeRateRequest {
+        GetExchangeRateRequest {
+            base_asset: Asset {
+                symbol: base_symbol.to_string(),
+                class: AssetClass::Cryptocurrency,
+            },
+            quote_asset: Asset {
+                symbol: "XDR".to_string(),
+                class: AssetClass::FiatCurrency,
+            },
+            timestamp: None,
+        }
+    }
+
+    fn setup_logs() {
+        let cmc = Principal::from_text("rkp4c-7iaaa-aaaaa-aaaca-cai").unwrap();
+        let user = Principal::from_text("q4eej-kyaaa-aaaaa-aaaha-cai").unwrap();
+        PRIVILEGED_REQUEST_LOG.with(|cell| {
+            let mut log = cell.borrow_mut();
+            log.log(&cmc, 10, &request("ICP"), &Err(ExchangeRateError::Pending));
+            log.log(&cmc, 30, &request("ICP"), &Err(ExchangeRateError::Pending));
+        });
+        NONPRIVILEGED_REQUEST_LOG.with(|cell| {
+            let mut log = cell.borrow_mut();
+            log.log(
+                &user,
+                20,
+                &request("BTC"),
+                &Err(ExchangeRateError::Other(OtherError {
+                    code: 1,
+                    description: "error".to_string(),
+                })),
+            );
+            log.log(
+                &user,
+                40,
+                &request("ICP"),
+                &Err(ExchangeRateError::RateLimited),
+            );
+        });
+    }
+
+    fn timestamps(page: &RequestLogPage) -> Vec<u64> {
+        page.entries.iter().map(|entry| entry.timestamp).collect()
+    }
+
+    /// The function verifies that the entries of both logs are returned from the most recent to
+    /// the oldest entry and are paginated.
+    #[test]
+    fn get_request_log_merges_and_paginates_logs() {
+        setup_logs();
+
+        let page = get_request_log(&GetRequestLogRequest {
+            offset: 0,
+            limit: 10,
+            filter: RequestLogFilter::default(),
+        });
+        assert_eq!(page.total, 4);
+        assert_eq!(timestamps(&page), vec![40, 30, 20, 10]);
+        assert!(page.entries[1].privileged);
+        assert!(!page.entries[0].privileged);
+
+        let page = get_request_log(&GetRequestLogRequest {
+            offset: 1,
+            limit: 2,
+            filter: RequestLogFilter::default(),
+        });
+        assert_eq!(page.total, 4);
+        assert_eq!(timestamps(&page), vec![30, 20]);
+    }
+
+    /// The function verifies that the entries are filtered by all criteria.
+    #[test]
+    fn get_request_log_filters_entries() {
+        setup_logs();
+        let get = |filter: RequestLogFilter| {
+            timestamps(&get_request_log(&GetRequestLogRequest {
+                offset: 0,
+                limit: 10,
+                filter,
+            }))
+        };
+
+        assert_eq!(
+            get(RequestLogFilter {
+                caller: Some(Principal::from_text("rkp4c-7iaaa-aaaaa-aaaca-cai").unwrap()),
+                ..Default::default()
+            }),
+            vec![30, 10]
+        );
+        assert_eq!(
+            get(RequestLogFilter {
+                base_asset_symbol: Some("icp".to_string()),
+                quote_asset_symbol: Some("XDR".to_string()),
+                ..Default::default()
+            }),
+            vec![40, 30, 10]
+        );
+        assert_eq!(
+            get(RequestLogFilter {
+                privileged: Some(false),
+                ..Default::default()
+            }),
+            vec![40, 20]
+        );
+        assert_eq!(
+            get(RequestLogFilter {
+                error: Some("Other".to_string()),
+                ..Default::default()
+            }),
+            vec![20]
+        );
+        assert_eq!(
+            get(RequestLogFilter {
+                start_timestamp: Some(20),
+                end_timestamp: Some(30),
+                ..Default::default()
+            }),
+            vec![30, 20]
+        );
+    }
+
+    fn entry(timestamp: u64) -> RequestLogEntry {
+        RequestLogEntry {
+            timestamp,
+            caller: Principal::anonymous(),
+            privileged: false,
+            request: request("ICP"),
+            result: Err(ExchangeRateError::Pending),
+        }
+    }
+
+    fn log_timestamps(log: &RequestLog) -> Vec<u64> {
+        log.entries().map(|entry| entry.timestamp).collect()
+    }
+
+    /// The function verifies that a page with a large offset or limit is returned without
+    /// truncating the values.
+    #[test]
+    fn get_request_log_saturates_offset_and_limit() {
+        setup_logs();
+
+        let page = get_request_log(&GetRequestLogRequest {
+            offset: 0,
+            limit: u64::MAX,
+            filter: RequestLogFilter::default(),
+        });
+        assert_eq!(timestamps(&page), vec![40, 30, 20, 10]);
+
+        let page = get_request_log(&GetRequestLogRequest {
+            offset: u64::MAX,
+            limit: u64::MAX,
+            filter: RequestLogFilter::default(),
+        });
+        assert_eq!(page.total, 4);
+        assert!(page.entries.is_empty());
+    }
+
+    /// The function verifies that the log overwrites its oldest entry once it is full and that
+    /// the entries are loaded again from the stable memory, e.g., after an upgrade.
+    #[test]
+    fn log_overwrites_oldest_entries_and_survives_reloading() {
+        let memory_manager = MemoryManager::init(DefaultMemoryImpl::default());
+        let init_log = || {
+            RequestLog::init(
+                2,
+                false,
+                memory_manager.get(MemoryId::new(0)),
+                memory_manager.get(MemoryId::new(1)),
+            )
+        };
+        let mut log = init_log();
+        for timestamp in [10, 20, 30] {
+            log.push(entry(timestamp));
+        }
+        assert_eq!(log_timestamps(&log), vec![30, 20]);
+
+        let mut log = init_log();
+        assert_eq!(log_timestamps(&log), vec![30, 20]);
+        log.push(entry(40));
+        assert_eq!(log_timestamps(&log), vec![40, 30]);
+    }
+}
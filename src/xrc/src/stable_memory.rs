@@ -0,0 +1,107 @@
+//! The layout of the canister's stable memory.
+//!
+//! The stable memory is split into virtual memories by a [MemoryManager]. The request logs
+//! live directly in their virtual memories and survive upgrades without being serialized. The
+//! remaining state is kept on the heap and written to [UPGRADES_MEMORY_ID] in `pre_upgrade`.
+//!
+//! Versions before the memory manager was introduced saved the state with
+//! `ic_cdk::storage::stable_save` at the start of the stable memory. [is_legacy_layout]
+//! detects that layout so that `post_upgrade` can read the state before the memory manager
+//! overwrites it.
+
+use std::cell::RefCell;
+
+use ic_stable_structures::{
+    memory_manager::{MemoryId, MemoryManager, VirtualMemory},
+    reader::Reader,
+    writer::Writer,
+    DefaultMemoryImpl, Memory as _,
+};
+
+/// A virtual memory handed out by the memory manager.
+pub(crate) type Memory = VirtualMemory<DefaultMemoryImpl>;
+
+/// Holds the heap state that is serialized in `pre_upgrade`.
+const UPGRADES_MEMORY_ID: MemoryId = MemoryId::new(0);
+/// Holds the entries of the privileged request log.
+pub(crate) const PRIVILEGED_REQUEST_LOG_ENTRIES_MEMORY_ID: MemoryId = MemoryId::new(1);
+/// Holds the number of entries written to the privileged request log.
+pub(crate) const PRIVILEGED_REQUEST_LOG_COUNT_MEMORY_ID: MemoryId = MemoryId::new(2);
+/// Holds the entries of the non-privileged request log.
+pub(crate) const NONPRIVILEGED_REQUEST_LOG_ENTRIES_MEMORY_ID: MemoryId = MemoryId::new(3);
+/// Holds the number of entries written to the non-privileged request log.
+pub(crate) const NONPRIVILEGED_REQUEST_LOG_COUNT_MEMORY_ID: MemoryId = MemoryId::new(4);
+
+/// The magic bytes the memory manager writes at the start of the stable memory.
+const MEMORY_MANAGER_MAGIC: &[u8; 3] = b"MGR";
+
+/// The number of bytes used to store the length of the serialized upgrade state.
+const UPGRADE_STATE_LENGTH_SIZE: usize = std::mem::size_of::<u64>();
+
+thread_local! {
+    static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> =
+        RefCell::new(MemoryManager::init(DefaultMemoryImpl::default()));
+}
+
+/// Returns the virtual memory with the given ID.
+pub(crate) fn get_memory(id: MemoryId) -> Memory {
+    MEMORY_MANAGER.with(|manager| manager.borrow().get(id))
+}
+
+/// Checks if the stable memory was written by `ic_cdk::storage::stable_save` instead of the
+/// memory manager. It must be called before the memory manager is used for the first time.
+pub(crate) fn is_legacy_layout() -> bool {
+    let memory = DefaultMemoryImpl::default();
+    if memory.size() == 0 {
+        return false;
+    }
+    let mut magic = [0; MEMORY_MANAGER_MAGIC.len()];
+    memory.read(0, &mut magic);
+    &magic != MEMORY_MANAGER_MAGIC
+}
+
+/// Writes the serialized upgrade state to its virtual memory.
+pub(crate) fn save_upgrade_state(bytes: &[u8]) {
+    let mut memory = get_memory(UPGRADES_MEMORY_ID);
+    let mut writer = Writer::new(&mut memory, 0);
+    writer
+        .write(&(bytes.len() as u64).to_le_bytes())
+        .and_then(|_| writer.write(bytes))
+        .expect("Writing the upgrade state must succeed.");
+}
+
+/// Reads the serialized upgrade state from its virtual memory. Returns [None] if no state
+/// has been saved.
+pub(crate) fn load_upgrade_state() -> Option<Vec<u8>> {
+    let memory = get_memory(UPGRADES_MEMORY_ID);
+    let mut reader = Reader::new(&memory, 0);
+    let mut length = [0; UPGRADE_STATE_LENGTH_SIZE];
+    if reader.read(&mut length).ok()? < UPGRADE_STATE_LENGTH_SIZE {
+        return None;
+    }
+    let length = usize::try_from(u64::from_le_bytes(length))
+        .expect("The upgrade state must fit into the heap.");
+    let mut bytes = vec![0; length];
+    reader
+        .read(&mut bytes)
+        .expect("Reading the upgrade state must succeed.");
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// The function verifies that the saved upgrade state is read back and that a shorter
+    /// state replaces a longer one.
+    #[test]
+    fn save_and_load_upgrade_state() {
+        assert_eq!(load_upgrade_state(), None);
+
+        save_upgrade_state(b"longer state");
+        assert_eq!(load_upgrade_state(), Some(b"longer state".to_vec()));
+
+        save_upgrade_state(b"state");
+        assert_eq!(load_upgrade_state(), Some(b"state".to_vec()));
+    }
+}
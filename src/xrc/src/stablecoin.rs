@@ -1,7 +1,10 @@
-use ic_xrc_types::{Asset, ExchangeRateError};
+use ic_xrc_types::{Asset, ExchangeRateError, StablecoinDepegPolicy};
 
 use crate::utils::{median, median_in_set};
-use crate::QueriedExchangeRate;
+use crate::{
+    errors, increment_labeled_counter, set_labeled_gauge, with_config, LabelKey, MetricName,
+    QueriedExchangeRate, QueriedSourceRates, RateAggregation, LOG_PREFIX,
+};
 
 /// At least 2 stablecoin rates - each quoted against the same quote asset (USDT
 /// in production) - are needed to determine if a rate is off. The shared quote
@@ -15,6 +18,13 @@ pub(crate) enum StablecoinRateError {
     TooFewRates(usize),
     DifferentQuoteAssets(Asset, Asset),
     ZeroRate,
+    /// No majority of the stablecoins is within the permitted deviation of the chosen one.
+    Depeg {
+        chosen_symbol: String,
+        num_agreeing: usize,
+        num_rates: usize,
+        max_deviation_bps: u64,
+    },
 }
 
 impl From<StablecoinRateError> for ExchangeRateError {
@@ -25,6 +35,7 @@ impl From<StablecoinRateError> for ExchangeRateError {
                 ExchangeRateError::StablecoinRateNotFound
             }
             StablecoinRateError::ZeroRate => ExchangeRateError::StablecoinRateZeroRate,
+            StablecoinRateError::Depeg { .. } => errors::stablecoin_depeg_error(&error.to_string()),
         }
     }
 }
@@ -43,6 +54,16 @@ impl core::fmt::Display for StablecoinRateError {
                 expected_asset.symbol, quote_asset.symbol
             ),
             StablecoinRateError::ZeroRate => write!(f, "Calculated stablecoin rate is zero"),
+            StablecoinRateError::Depeg {
+                chosen_symbol,
+                num_agreeing,
+                num_rates,
+                max_deviation_bps,
+            } => write!(
+                f,
+                "only {} of {} stablecoin rates are within {} basis points of the {} rate",
+                num_agreeing, num_rates, max_deviation_bps, chosen_symbol
+            ),
         }
     }
 }
@@ -100,6 +121,8 @@ pub(crate) fn get_stablecoin_rate(
         .get(*median_index)
         .expect("The stablecoin exchange rate must exist.");
 
+    check_depeg(stablecoin_rates, &median_rates, median_stablecoin_rate)?;
+
     // The returned exchange rate uses the median timestamp.
     let timestamps: Vec<_> = stablecoin_rates.iter().map(|rate| rate.timestamp).collect();
     // The exchange rate canister uses timestamps without seconds.
@@ -118,17 +141,91 @@ pub(crate) fn get_stablecoin_rate(
         median_stablecoin_rate.base_asset_num_queried_sources,
         median_stablecoin_rate.base_asset_num_received_rates,
         None,
+        RateAggregation::Median,
     );
 
-    // Turn the S/Q rate into the Q/S = Q/T rate.
-    Ok(target_to_quote_rate.inverted())
+    // Turn the S/Q rate into the Q/S = Q/T rate and record which stablecoin was chosen.
+    let mut quote_to_target_rate = target_to_quote_rate.inverted();
+    quote_to_target_rate.source_rates = Some(QueriedSourceRates {
+        stablecoin: Some((
+            median_stablecoin_rate.base_asset.symbol.clone(),
+            median_of_median,
+        )),
+        ..Default::default()
+    });
+    Ok(quote_to_target_rate)
+}
+
+/// Checks that the stablecoins agree with the chosen stablecoin, as determined by the
+/// configured [StablecoinDepegPolicy]. The stablecoins agree if a strict majority of their
+/// median rates lies within `max_deviation_bps` basis points of the median rate of the chosen
+/// stablecoin. Hence, if there are only two stablecoins, both of them must agree, as it cannot
+/// be told which of them depegged.
+///
+/// The deviation of every stablecoin is exported as the `xrc_stablecoin_deviation_bps` metric.
+fn check_depeg(
+    stablecoin_rates: &[QueriedExchangeRate],
+    median_rates: &[u64],
+    chosen_rate: &QueriedExchangeRate,
+) -> Result<(), StablecoinRateError> {
+    let policy = with_config(|config| config.stablecoin_depeg_policy)
+        .unwrap_or(StablecoinDepegPolicy::Ignore);
+    let max_deviation_bps = match policy {
+        StablecoinDepegPolicy::Ignore => return Ok(()),
+        StablecoinDepegPolicy::Flag { max_deviation_bps }
+        | StablecoinDepegPolicy::Reject { max_deviation_bps } => max_deviation_bps,
+    };
+
+    let chosen_median_rate = median(&chosen_rate.rates);
+    let mut num_agreeing = 0;
+    for (rate, median_rate) in stablecoin_rates.iter().zip(median_rates) {
+        let difference = median_rate.abs_diff(chosen_median_rate);
+        set_labeled_gauge(
+            MetricName::StablecoinDeviationBps,
+            &[(LabelKey::Symbol, &rate.base_asset.symbol)],
+            difference as f64 * 10_000.0 / chosen_median_rate as f64,
+        );
+        if difference as u128 * 10_000 <= max_deviation_bps as u128 * chosen_median_rate as u128 {
+            num_agreeing += 1;
+        }
+    }
+    if num_agreeing * 2 > median_rates.len() {
+        return Ok(());
+    }
+
+    let error = StablecoinRateError::Depeg {
+        chosen_symbol: chosen_rate.base_asset.symbol.clone(),
+        num_agreeing,
+        num_rates: median_rates.len(),
+        max_deviation_bps,
+    };
+    match policy {
+        StablecoinDepegPolicy::Reject { .. } => {
+            increment_labeled_counter(
+                MetricName::StablecoinDepegTotal,
+                &[(LabelKey::Outcome, "rejected")],
+            );
+            Err(error)
+        }
+        _ => {
+            increment_labeled_counter(
+                MetricName::StablecoinDepegTotal,
+                &[(LabelKey::Outcome, "flagged")],
+            );
+            ic_cdk::println!("{} Stablecoin depeg flagged: {}", LOG_PREFIX, error);
+            Ok(())
+        }
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::{utils, DECIMALS, RATE_UNIT};
-    use ic_xrc_types::AssetClass;
+    use crate::{
+        config, make_metric_key, utils, with_labeled_counters, with_labeled_gauges, DECIMALS,
+        RATE_UNIT,
+    };
+    use ic_xrc_types::{AssetClass, Config};
     use rand::seq::SliceRandom;
     use rand::Rng;
 
@@ -166,6 +263,7 @@ mod test {
                 1,
                 1,
                 None,
+                RateAggregation::Median,
             );
             rates.push(rate);
         }
@@ -189,9 +287,37 @@ mod test {
             1,
             1,
             None,
+            RateAggregation::Median,
         )
     }
 
+    /// Sets the given depeg policy.
+    fn set_depeg_policy(policy: StablecoinDepegPolicy) {
+        config::set_config(Config {
+            stablecoin_depeg_policy: Some(policy),
+            ..config::default_config()
+        });
+    }
+
+    /// The function tests that the chosen stablecoin and its rate are recorded in the
+    /// source rates of the returned rate.
+    #[test]
+    fn stablecoin_rate_records_chosen_stablecoin() {
+        let usdc = stablecoin_rate("USDC", 990_000_000);
+        let usds = stablecoin_rate("USDS", 985_000_000);
+
+        let result = get_stablecoin_rate(&[usdc, usds], &crate::api::usd_asset())
+            .expect("a stablecoin rate should be returned");
+
+        assert_eq!(
+            result.source_rates,
+            Some(QueriedSourceRates {
+                stablecoin: Some(("USDC".to_string(), 990_000_000)),
+                ..Default::default()
+            })
+        );
+    }
+
     /// The function tests that the appropriate error is returned when fewer than
     /// [MIN_NUM_STABLECOIN_RATES] rates are provided.
     #[test]
@@ -268,6 +394,7 @@ mod test {
             1,
             1,
             None,
+            RateAggregation::Median,
         );
         assert!(matches!(stablecoin_rate, Ok(rate) if rate == expected_rate));
     }
@@ -304,6 +431,8 @@ mod test {
     /// The third stablecoin has the median-of-median rate and is used as the rate of the target asset.
     #[test]
     fn stablecoin_median_of_median() {
+        // The rates are too far apart to pass the depeg guard.
+        set_depeg_policy(StablecoinDepegPolicy::Ignore);
         let first_rate = QueriedExchangeRate::new(
             Asset {
                 symbol: "A".to_string(),
@@ -318,6 +447,7 @@ mod test {
             4,
             4,
             None,
+            RateAggregation::Median,
         );
         let second_rate = QueriedExchangeRate::new(
             Asset {
@@ -333,6 +463,7 @@ mod test {
             3,
             3,
             None,
+            RateAggregation::Median,
         );
         let third_rate = QueriedExchangeRate::new(
             Asset {
@@ -348,6 +479,7 @@ mod test {
             5,
             5,
             None,
+            RateAggregation::Median,
         );
         let target_asset = Asset {
             symbol: "T".to_string(),
@@ -369,6 +501,7 @@ mod test {
             5,
             5,
             None,
+            RateAggregation::Median,
         )
         .inverted();
         assert!(matches!(computed_rate, Ok(rate) if rate == expected_rate));
@@ -400,6 +533,7 @@ mod test {
             4,
             4,
             None,
+            RateAggregation::Median,
         );
         let second_rate = QueriedExchangeRate::new(
             Asset {
@@ -415,6 +549,7 @@ mod test {
             3,
             3,
             None,
+            RateAggregation::Median,
         );
         let third_rate = QueriedExchangeRate::new(
             Asset {
@@ -430,6 +565,7 @@ mod test {
             5,
             5,
             None,
+            RateAggregation::Median,
         );
         let fourth_rate = QueriedExchangeRate::new(
             Asset {
@@ -445,6 +581,7 @@ mod test {
             2,
             2,
             None,
+            RateAggregation::Median,
         );
         let target_asset = Asset {
             symbol: "T".to_string(),
@@ -470,13 +607,16 @@ mod test {
             2,
             2,
             None,
+            RateAggregation::Median,
         )
         .inverted();
         assert!(matches!(computed_rate, Ok(rate) if rate == expected_rate));
     }
 
-    /// Documents the ACTUAL selection behaviour with two stablecoin symbols.
-    /// (USDS is the on-chain symbol that replaced DAI.)
+    /// Documents the ACTUAL selection behaviour with two stablecoin symbols when the depeg
+    /// guard is disabled. (USDS is the on-chain symbol that replaced DAI.) With the guard
+    /// enabled, the two stablecoins disagree and the rate is rejected; see
+    /// `depeg_guard_rejects_disagreeing_stablecoins`.
     ///
     /// The original design called for a three-input median over `{median_usdc,
     /// median_usds, median_usdt = 1}` that rejects a single depegged
@@ -494,6 +634,7 @@ mod test {
     /// `two_symbol_set_tolerates_a_usds_depeg_with_usdc_first`.
     #[test]
     fn two_symbol_set_does_not_reject_a_depegged_stablecoin() {
+        set_depeg_policy(StablecoinDepegPolicy::Ignore);
         // Depeg-prone coin listed first (the pre-reorder ordering).
         let depegged_usds = stablecoin_rate("USDS", 800_000_000); // USDS/USDT = 0.80 (depegged)
         let healthy_usdc = stablecoin_rate("USDC", 990_000_000); // USDC/USDT = 0.99
@@ -524,9 +665,11 @@ mod test {
     ///
     /// This is an order-only mitigation, not a true rejection: it relies on USDC
     /// being the more trusted coin and is superseded once the set is odd (>= 3),
-    /// where the true middle is selected by value regardless of order.
+    /// where the true middle is selected by value regardless of order. As above, the depeg
+    /// guard is disabled.
     #[test]
     fn two_symbol_set_tolerates_a_usds_depeg_with_usdc_first() {
+        set_depeg_policy(StablecoinDepegPolicy::Ignore);
         // Order mirrors STABLECOIN_BASES = [USDC, USDS].
         let healthy_usdc = stablecoin_rate("USDC", 990_000_000); // USDC/USDT = 0.99
         let depegged_usds = stablecoin_rate("USDS", 800_000_000); // USDS/USDT = 0.80 (depegged)
@@ -592,4 +735,85 @@ mod test {
         // Selected middle = 1.00 -> USDT/USD = 1/1.00 = RATE_UNIT.
         assert_eq!(median(&a.rates), RATE_UNIT);
     }
+
+    /// The function tests that the rate is rejected if no majority of the stablecoins agrees
+    /// with the chosen stablecoin and that the deviations are exported as metrics.
+    #[test]
+    fn depeg_guard_rejects_disagreeing_stablecoins() {
+        let usd = crate::api::usd_asset();
+        // Two stablecoins that are 3% apart disagree, regardless of their order.
+        let usdc = stablecoin_rate("USDC", 970_000_000);
+        let usds = stablecoin_rate("USDS", 1_000_000_000);
+        assert!(matches!(
+            get_stablecoin_rate(&[usdc.clone(), usds.clone()], &usd),
+            Err(StablecoinRateError::Depeg {
+                num_agreeing: 1,
+                num_rates: 2,
+                max_deviation_bps: 200,
+                ..
+            })
+        ));
+        assert!(matches!(
+            get_stablecoin_rate(&[usds.clone(), usdc.clone()], &usd),
+            Err(StablecoinRateError::Depeg { .. })
+        ));
+        let error = get_stablecoin_rate(&[usdc.clone(), usds.clone()], &usd).unwrap_err();
+        assert!(matches!(
+            ExchangeRateError::from(error),
+            ExchangeRateError::Other(error) if error.code == errors::STABLECOIN_DEPEG_ERROR_CODE
+        ));
+
+        // With a wider basket, the depeg of USDC is outvoted.
+        let rates = [
+            usdc,
+            usds,
+            stablecoin_rate("DAI", 999_000_000),
+            stablecoin_rate("FDUSD", 1_001_000_000),
+            stablecoin_rate("PYUSD", 1_002_000_000),
+        ];
+        let rate = get_stablecoin_rate(&rates, &usd).expect("the majority should agree");
+        assert_eq!(
+            rate.source_rates
+                .and_then(|source_rates| source_rates.stablecoin),
+            Some(("USDS".to_string(), 1_000_000_000))
+        );
+        with_labeled_gauges(|gauges| {
+            let key = make_metric_key(
+                MetricName::StablecoinDeviationBps,
+                &[(LabelKey::Symbol, "USDC")],
+            );
+            assert_eq!(gauges.get(&key).map(|value| value.round()), Some(300.0));
+        });
+        with_labeled_counters(|counters| {
+            let key = make_metric_key(
+                MetricName::StablecoinDepegTotal,
+                &[(LabelKey::Outcome, "rejected")],
+            );
+            assert_eq!(counters.get(&key), Some(&3));
+        });
+    }
+
+    /// The function tests that a flagged disagreement is counted but the rate is still returned.
+    #[test]
+    fn depeg_guard_flags_disagreeing_stablecoins() {
+        set_depeg_policy(StablecoinDepegPolicy::Flag {
+            max_deviation_bps: 100,
+        });
+        let rates = [
+            stablecoin_rate("USDC", 970_000_000),
+            stablecoin_rate("USDS", 1_000_000_000),
+        ];
+
+        let rate = get_stablecoin_rate(&rates, &crate::api::usd_asset())
+            .expect("a flagged rate should be returned");
+
+        assert_eq!(rate.base_asset.symbol, "USDT");
+        with_labeled_counters(|counters| {
+            let key = make_metric_key(
+                MetricName::StablecoinDepegTotal,
+                &[(LabelKey::Outcome, "flagged")],
+            );
+            assert_eq!(counters.get(&key), Some(&1));
+        });
+    }
 }
@@ -1,7 +1,7 @@
 use candid::Principal;
-use ic_xrc_types::{Asset, AssetClass, GetExchangeRateRequest};
+use ic_xrc_types::{Asset, AssetClass, CallerTier, GetExchangeRateRequest};
 
-use crate::{environment::Environment, PRIVILEGED_CANISTER_IDS, PRIVILEGED_CRYPTO_ASSETS, USDT};
+use crate::{config, environment::Environment, USDT};
 
 const NANOS_PER_SEC: u64 = 1_000_000_000;
 
@@ -51,6 +51,31 @@ pub(crate) fn median_in_set(values: &[u64]) -> u64 {
     }
 }
 
+/// The function returns the weighted median of the provided `(value, weight)` pairs, which is
+/// the smallest value such that the values up to and including it carry at least half of the
+/// total weight. If the total weight is zero, the result is identical to the output of [median].
+pub(crate) fn weighted_median(weighted_values: &[(u64, u64)]) -> u64 {
+    let total_weight: u128 = weighted_values
+        .iter()
+        .map(|(_, weight)| *weight as u128)
+        .sum();
+    if total_weight == 0 {
+        let values: Vec<u64> = weighted_values.iter().map(|(value, _)| *value).collect();
+        return median(&values);
+    }
+    let mut sorted_values = weighted_values.to_vec();
+    sorted_values.sort();
+
+    let mut cumulative_weight = 0u128;
+    for (value, weight) in sorted_values {
+        cumulative_weight += weight as u128;
+        if cumulative_weight.saturating_mul(2) >= total_weight {
+            return value;
+        }
+    }
+    0
+}
+
 /// The function computes the integer square root of the given 128-bit number
 /// (the algorithm is decscribed here:
 /// https://en.wikipedia.org/wiki/Methods_of_computing_square_roots#Binary_numeral_system_(base_2)
@@ -154,9 +179,15 @@ pub(crate) fn is_caller_anonymous(caller: &Principal) -> bool {
     *caller == Principal::anonymous()
 }
 
-/// Checks if the caller's principal ID belongs to the Cycles Minting Canister.
+/// Checks if the caller's principal ID belongs to a privileged canister, i.e., a canister
+/// in the [CallerTier::Free] tier.
 pub(crate) fn is_caller_privileged(caller: &Principal) -> bool {
-    PRIVILEGED_CANISTER_IDS.contains(caller)
+    caller_tier(caller) == CallerTier::Free
+}
+
+/// Returns the configured tier of the caller.
+pub(crate) fn caller_tier(caller: &Principal) -> CallerTier {
+    config::caller_tier(caller)
 }
 
 /// Checks if the asset pair is privileged, meaning that it should bypass the rate limiting.
@@ -167,16 +198,15 @@ pub(crate) fn is_privileged_asset_pair(base_asset: &Asset, quote_asset: &Asset)
     match (&base_asset.class, &quote_asset.class) {
         (AssetClass::FiatCurrency, AssetClass::FiatCurrency) => true,
         (AssetClass::FiatCurrency, AssetClass::Cryptocurrency) => {
-            PRIVILEGED_CRYPTO_ASSETS.contains(&quote_asset.symbol.as_ref())
+            config::is_privileged_crypto_asset(&quote_asset.symbol)
         }
         (AssetClass::Cryptocurrency, AssetClass::FiatCurrency) => {
-            PRIVILEGED_CRYPTO_ASSETS.contains(&base_asset.symbol.as_ref())
+            config::is_privileged_crypto_asset(&base_asset.symbol)
         }
         (AssetClass::Cryptocurrency, AssetClass::Cryptocurrency) => {
-            (base_asset.symbol == USDT
-                && PRIVILEGED_CRYPTO_ASSETS.contains(&quote_asset.symbol.as_ref()))
+            (base_asset.symbol == USDT && config::is_privileged_crypto_asset(&quote_asset.symbol))
                 || (quote_asset.symbol == USDT
-                    && PRIVILEGED_CRYPTO_ASSETS.contains(&base_asset.symbol.as_ref()))
+                    && config::is_privileged_crypto_asset(&base_asset.symbol))
         }
     }
 }
@@ -205,6 +235,17 @@ pub(crate) mod test {
             .expect("failed to read file")
     }
 
+    /// The function tests that the weighted median is the smallest value carrying at least half
+    /// of the total weight and that it falls back to the median if all weights are zero.
+    #[test]
+    fn weighted_median_is_computed_correctly() {
+        assert_eq!(weighted_median(&[]), 0);
+        assert_eq!(weighted_median(&[(3, 1), (1, 1), (2, 1)]), 2);
+        assert_eq!(weighted_median(&[(1, 1), (2, 1), (3, 5)]), 3);
+        assert_eq!(weighted_median(&[(1, 1), (2, 1)]), 1);
+        assert_eq!(weighted_median(&[(1, 0), (2, 0), (3, 0), (4, 0)]), 2);
+    }
+
     #[test]
     fn cycles_minting_canister_id_is_correct() {
         let principal_from_text = Principal::from_text("rkp4c-7iaaa-aaaaa-aaaca-cai")
@@ -392,4 +433,26 @@ pub(crate) mod test {
             }
         }
     }
+
+    /// The function tests that the privileged crypto assets are taken from the configuration.
+    #[test]
+    fn is_privileged_asset_pair_uses_configured_assets() {
+        let doge = Asset {
+            symbol: "DOGE".to_string(),
+            class: AssetClass::Cryptocurrency,
+        };
+        let icp = Asset {
+            symbol: "ICP".to_string(),
+            class: AssetClass::Cryptocurrency,
+        };
+        assert!(!is_privileged_asset_pair(&doge, &usdt_asset()));
+
+        config::set_config(ic_xrc_types::Config {
+            privileged_crypto_assets: Some(vec!["DOGE".to_string(), "USDT".to_string()]),
+            ..config::default_config()
+        });
+
+        assert!(is_privileged_asset_pair(&doge, &usdt_asset()));
+        assert!(!is_privileged_asset_pair(&icp, &usdt_asset()));
+    }
 }